@@ -0,0 +1,23 @@
+-- Attendees invited to an appointment, one per invitation
+CREATE TABLE Attendee (
+    id uuid DEFAULT uuid_generate_v4() PRIMARY KEY,
+    appointment_id UUID REFERENCES Appointment(id) ON DELETE CASCADE,
+    name VARCHAR(255) DEFAULT NULL,
+    email VARCHAR(255) NOT NULL,
+    phone VARCHAR(64) DEFAULT NULL,
+    custom_fields JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+ALTER TABLE Invitation ADD COLUMN attendee_id UUID REFERENCES Attendee(id) ON DELETE SET NULL;
+
+-- Invitations sent before attendees existed only kept the receiver in Emails,
+-- so turn each of them into an attendee sharing the invitation id.
+INSERT INTO Attendee (id, appointment_id, email)
+SELECT DISTINCT ON (Emails.invitation_id) Emails.invitation_id, Invitation.appointment_id, Emails.receiver
+FROM Emails
+JOIN Invitation ON Emails.invitation_id = Invitation.id
+ORDER BY Emails.invitation_id, Emails.created_at;
+
+UPDATE Invitation SET attendee_id = id WHERE id IN (SELECT id FROM Attendee);
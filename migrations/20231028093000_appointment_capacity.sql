@@ -0,0 +1,2 @@
+-- Maximum number of invitations of an appointment, NULL meaning unlimited
+ALTER TABLE Appointment ADD COLUMN capacity INTEGER DEFAULT NULL CHECK (capacity IS NULL OR capacity > 0);
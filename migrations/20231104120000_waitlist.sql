@@ -0,0 +1,26 @@
+-- Declined invitations no longer hold a seat of the appointment
+CREATE TYPE invitation_status AS ENUM (
+    'ACTIVE',
+    'DECLINED'
+);
+
+ALTER TABLE Invitation
+    ADD COLUMN status invitation_status NOT NULL DEFAULT 'ACTIVE',
+    ADD COLUMN claim_deadline TIMESTAMP DEFAULT NULL;
+
+-- People waiting for a seat, promoted in position order when one is freed
+CREATE TABLE Waitlist (
+    id uuid DEFAULT uuid_generate_v4() PRIMARY KEY,
+    appointment_id UUID NOT NULL REFERENCES Appointment(id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    name VARCHAR(255) DEFAULT NULL,
+    email VARCHAR(255) NOT NULL,
+    phone VARCHAR(64) DEFAULT NULL,
+    custom_fields JSONB NOT NULL DEFAULT '{}',
+    invitation_id UUID REFERENCES Invitation(id) ON DELETE SET NULL,
+    promoted_at TIMESTAMP DEFAULT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX waitlist_appointment_position ON Waitlist (appointment_id, position) WHERE promoted_at IS NULL;
@@ -0,0 +1,24 @@
+-- Answer of the attendee, given through the links of the invitation email
+CREATE TYPE rsvp_status AS ENUM (
+    'PENDING',
+    'ACCEPTED',
+    'DECLINED'
+);
+
+ALTER TABLE Invitation ADD COLUMN rsvp rsvp_status NOT NULL DEFAULT 'PENDING';
+UPDATE Invitation SET rsvp = 'DECLINED' WHERE status = 'DECLINED';
+
+ALTER TABLE Appointment ADD COLUMN registration_open BOOLEAN NOT NULL DEFAULT false;
+
+-- Self-service registrations, waiting for the email address to be confirmed
+CREATE TABLE Registration (
+    id uuid DEFAULT uuid_generate_v4() PRIMARY KEY,
+    appointment_id UUID NOT NULL REFERENCES Appointment(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    email VARCHAR(255) NOT NULL,
+    token VARCHAR(64) NOT NULL UNIQUE,
+    confirmed_at TIMESTAMP DEFAULT NULL,
+    invitation_id UUID REFERENCES Invitation(id) ON DELETE SET NULL,
+    waitlist_id UUID REFERENCES Waitlist(id) ON DELETE SET NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT timezone('utc', now())
+);
@@ -0,0 +1,22 @@
+-- Revoked invitations no longer validate nor hold a seat
+ALTER TYPE invitation_status ADD VALUE 'REVOKED';
+
+CREATE TYPE invitation_action AS ENUM (
+    'ACCEPTED',
+    'DECLINED',
+    'REVOKED',
+    'REISSUED',
+    'TRANSFERRED',
+    'REPLACEMENT'
+);
+
+-- Every change made to an invitation after it was issued
+CREATE TABLE Invitation_History (
+    id SERIAL PRIMARY KEY,
+    invitation_id UUID NOT NULL REFERENCES Invitation(id) ON DELETE CASCADE,
+    action invitation_action NOT NULL,
+    details JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMP NOT NULL DEFAULT timezone('utc', now())
+);
+
+CREATE INDEX invitation_history_invitation ON Invitation_History (invitation_id, id);
@@ -0,0 +1,5 @@
+-- Number of people an invitation admits, and how many of them have been admitted
+ALTER TABLE Invitation ADD COLUMN max_admissions INTEGER NOT NULL DEFAULT 1 CHECK (max_admissions > 0);
+ALTER TABLE Invitation ADD COLUMN admitted INTEGER NOT NULL DEFAULT 0;
+UPDATE Invitation SET admitted = max_admissions WHERE used;
+ALTER TABLE Invitation ADD CONSTRAINT invitation_admitted_check CHECK (admitted >= 0 AND admitted <= max_admissions);
@@ -0,0 +1,18 @@
+-- Every validation attempt at the door, admitted or not
+CREATE TYPE scan_direction AS ENUM ('IN', 'OUT');
+CREATE TYPE scan_result AS ENUM ('ADMITTED', 'REJECTED');
+
+CREATE TABLE Scan_Events(
+    id SERIAL PRIMARY KEY,
+    -- Not a foreign key, so that scans of unknown codes are kept as well
+    invitation_id UUID NOT NULL,
+    appointment_id UUID REFERENCES Appointment (id) ON DELETE CASCADE,
+    direction scan_direction NOT NULL DEFAULT 'IN',
+    result scan_result NOT NULL,
+    reason TEXT,
+    scanner TEXT,
+    scanned_at TIMESTAMP NOT NULL DEFAULT (CURRENT_TIMESTAMP AT TIME ZONE 'UTC')
+);
+
+CREATE INDEX scan_events_invitation_idx ON Scan_Events (invitation_id);
+CREATE INDEX scan_events_appointment_idx ON Scan_Events (appointment_id) WHERE result = 'ADMITTED';
@@ -0,0 +1,2 @@
+-- ID given by an offline scanner to a check-in, so that a sync can be retried safely
+ALTER TABLE Scan_Events ADD COLUMN client_scan_id UUID UNIQUE;
@@ -0,0 +1,10 @@
+-- Every redirect of an ONLINE invitation to the meeting, for attendance data
+CREATE TABLE Online_Joins(
+    id SERIAL PRIMARY KEY,
+    invitation_id UUID NOT NULL REFERENCES Invitation (id) ON DELETE CASCADE,
+    appointment_id UUID NOT NULL REFERENCES Appointment (id) ON DELETE CASCADE,
+    user_agent TEXT,
+    joined_at TIMESTAMP NOT NULL DEFAULT (CURRENT_TIMESTAMP AT TIME ZONE 'UTC')
+);
+
+CREATE INDEX online_joins_appointment_idx ON Online_Joins (appointment_id);
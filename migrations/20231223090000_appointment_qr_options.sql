@@ -0,0 +1,2 @@
+-- How the QR codes of an appointment are rendered, the defaults when NULL
+ALTER TABLE Appointment ADD COLUMN qr_options JSONB;
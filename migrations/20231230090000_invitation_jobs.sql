@@ -0,0 +1,22 @@
+-- Invitations issued in the background, in batches, for large events
+CREATE TYPE job_status AS ENUM ('PENDING', 'RUNNING', 'COMPLETED', 'FAILED');
+
+CREATE TABLE Invitation_Jobs(
+    id UUID PRIMARY KEY,
+    appointment_id UUID NOT NULL REFERENCES Appointment (id) ON DELETE CASCADE,
+    status job_status NOT NULL DEFAULT 'PENDING',
+    total INT NOT NULL,
+    -- Invitations issued so far, every batch being committed along with it
+    processed INT NOT NULL DEFAULT 0,
+    max_admissions INT NOT NULL DEFAULT 1,
+    -- Attendees to email, one per invitation in order, when any
+    recipients JSONB,
+    errors TEXT[] NOT NULL DEFAULT '{}',
+    created_at TIMESTAMP NOT NULL DEFAULT (CURRENT_TIMESTAMP AT TIME ZONE 'UTC'),
+    updated_at TIMESTAMP NOT NULL DEFAULT (CURRENT_TIMESTAMP AT TIME ZONE 'UTC')
+);
+
+ALTER TABLE Invitation ADD COLUMN job_id UUID REFERENCES Invitation_Jobs (id) ON DELETE SET NULL;
+-- Order of the invitation within its job, the order of the recipients
+ALTER TABLE Invitation ADD COLUMN job_position INT;
+CREATE INDEX invitation_job_idx ON Invitation (job_id, job_position);
@@ -0,0 +1,14 @@
+-- Responses of requests sent with an Idempotency-Key, replayed when the
+-- client retries them
+CREATE TABLE Idempotency_Keys(
+    -- The operation the key was used for, keys are unique within it
+    scope TEXT NOT NULL,
+    idempotency_key TEXT NOT NULL,
+    -- SHA-256 of the request, a key can't be reused for another one
+    request_hash TEXT NOT NULL,
+    -- Both NULL while the first request is still being handled
+    response_status SMALLINT,
+    response_body JSONB,
+    created_at TIMESTAMP NOT NULL DEFAULT (CURRENT_TIMESTAMP AT TIME ZONE 'UTC'),
+    PRIMARY KEY (scope, idempotency_key)
+);
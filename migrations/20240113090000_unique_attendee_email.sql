@@ -0,0 +1,24 @@
+-- An address is invited to an appointment at most once. Attendees sharing an
+-- address within an appointment are merged into the earliest of them first.
+WITH ranked AS (
+    SELECT id, first_value(id) OVER (
+        PARTITION BY appointment_id, lower(email) ORDER BY created_at NULLS LAST, id
+    ) AS kept_id
+    FROM Attendee
+)
+UPDATE Invitation SET attendee_id = ranked.kept_id
+FROM ranked
+WHERE Invitation.attendee_id = ranked.id AND ranked.id <> ranked.kept_id;
+
+DELETE FROM Attendee
+WHERE id IN (
+    SELECT id FROM (
+        SELECT id, row_number() OVER (
+            PARTITION BY appointment_id, lower(email) ORDER BY created_at NULLS LAST, id
+        ) AS position
+        FROM Attendee
+    ) AS ranked
+    WHERE position > 1
+);
+
+CREATE UNIQUE INDEX attendee_appointment_email_idx ON Attendee (appointment_id, lower(email));
@@ -0,0 +1,2 @@
+-- Invitations emailed again without changing their code
+ALTER TYPE invitation_action ADD VALUE 'RESENT';
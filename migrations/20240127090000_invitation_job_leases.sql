@@ -0,0 +1,20 @@
+-- Recipients of a job, one row each, so that batches read only their own
+CREATE TABLE Invitation_Job_Recipients(
+    job_id UUID NOT NULL REFERENCES Invitation_Jobs (id) ON DELETE CASCADE,
+    -- Position of the recipient in the job, from 1
+    position INT NOT NULL,
+    recipient JSONB NOT NULL,
+    PRIMARY KEY (job_id, position)
+);
+
+INSERT INTO Invitation_Job_Recipients (job_id, position, recipient)
+SELECT Invitation_Jobs.id, recipient.position, recipient.value
+FROM Invitation_Jobs, jsonb_array_elements(Invitation_Jobs.recipients) WITH ORDINALITY AS recipient(value, position)
+WHERE Invitation_Jobs.recipients IS NOT NULL;
+
+ALTER TABLE Invitation_Jobs DROP COLUMN recipients;
+
+-- The worker running a job, which holds it until the lease expires
+-- without being renewed. Jobs running before have no lease and can be resumed.
+ALTER TABLE Invitation_Jobs ADD COLUMN worker_id UUID;
+ALTER TABLE Invitation_Jobs ADD COLUMN lease_expires_at TIMESTAMP;
@@ -0,0 +1,3 @@
+-- Renewed while the first request with a key is being handled, so that a
+-- request dropped along the way is told apart from a slow one
+ALTER TABLE Idempotency_Keys ADD COLUMN heartbeat_at TIMESTAMP NOT NULL DEFAULT (CURRENT_TIMESTAMP AT TIME ZONE 'UTC');
@@ -0,0 +1,28 @@
+-- An attendee holds at most one active invitation. Concurrent requests could
+-- issue more before, so the surplus is revoked first. The invitation kept is
+-- the one already let in at the door or online, else the latest one, so that
+-- nobody who came in loses their ticket.
+WITH ranked AS (
+    SELECT id, row_number() OVER (
+        PARTITION BY attendee_id
+        ORDER BY (
+            COALESCE(used, false)
+            OR admitted > 0
+            OR EXISTS (SELECT 1 FROM Scan_Events WHERE Scan_Events.invitation_id = Invitation.id AND Scan_Events.result = 'ADMITTED')
+            OR EXISTS (SELECT 1 FROM Online_Joins WHERE Online_Joins.invitation_id = Invitation.id)
+        ) DESC, created_at DESC, id
+    ) AS position
+    FROM Invitation
+    WHERE status = 'ACTIVE' AND attendee_id IS NOT NULL
+), revoked AS (
+    UPDATE Invitation
+    SET status = 'REVOKED'
+    FROM ranked
+    WHERE Invitation.id = ranked.id AND ranked.position > 1
+    RETURNING Invitation.id
+)
+INSERT INTO Invitation_History (invitation_id, action, details)
+SELECT id, 'REVOKED', '{"reason": "Another active invitation was issued to the attendee"}'
+FROM revoked;
+
+CREATE UNIQUE INDEX invitation_active_attendee ON Invitation (attendee_id) WHERE status = 'ACTIVE';
@@ -0,0 +1,2 @@
+-- Emails of a job that couldn't be sent, whose invitations were issued all the same
+ALTER TABLE Invitation_Jobs ADD COLUMN email_failures JSONB NOT NULL DEFAULT '[]';
@@ -0,0 +1,91 @@
+//! CPU-bound and blocking work kept off the async worker threads.
+
+use std::sync::Arc;
+use anyhow::anyhow;
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
+
+/// Runs `f` on the blocking thread pool of tokio, within the span of the caller.
+pub fn spawn_blocking_with_tracing<F, R>(f: F) -> JoinHandle<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+{
+    let current_span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || current_span.in_scope(f))
+}
+
+/// Bounds the blocking tasks running at the same time, so that a burst of
+/// renders waits its turn instead of taking over the blocking thread pool.
+#[derive(Clone)]
+pub struct BlockingPool {
+    permits: Arc<Semaphore>,
+    size: usize,
+}
+
+impl BlockingPool {
+    pub fn new(size: usize) -> Self {
+        let size = size.max(1);
+        BlockingPool {
+            permits: Arc::new(Semaphore::new(size)),
+            size,
+        }
+    }
+
+    /// Number of tasks running at the same time, at most.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Runs `f` once one of the slots of the pool is free.
+    pub async fn run<F, R>(&self, f: F) -> Result<R, anyhow::Error>
+        where
+            F: FnOnce() -> R + Send + 'static,
+            R: Send + 'static,
+    {
+        let permit = self.permits.clone().acquire_owned().await?;
+        spawn_blocking_with_tracing(move || {
+            let _permit = permit;
+            f()
+        })
+            .await
+            .map_err(|e| anyhow!("Blocking task failed: {}", e))
+    }
+}
+
+impl Default for BlockingPool {
+    /// As many slots as there are cores.
+    fn default() -> Self {
+        BlockingPool::new(std::thread::available_parallelism().map_or(4, |cores| cores.get()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn tasks_running_never_exceed_the_size_of_the_pool() {
+        let pool = BlockingPool::new(2);
+        let running = Arc::new(AtomicUsize::new(0));
+        let most_running = Arc::new(AtomicUsize::new(0));
+
+        let tasks = (0..8).map(|_| {
+            let running = running.clone();
+            let most_running = most_running.clone();
+            pool.run(move || {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                most_running.fetch_max(now, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(50));
+                running.fetch_sub(1, Ordering::SeqCst);
+            })
+        });
+        for result in futures::future::join_all(tasks).await {
+            result.unwrap();
+        }
+
+        assert_eq!(most_running.load(Ordering::SeqCst), 2);
+    }
+}
@@ -2,6 +2,7 @@ use serde::{Deserialize};
 use secrecy::{ExposeSecret, Secret};
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
 use crate::domain::{Email};
+use crate::ticket::{PublicTicketKey, TicketError, TicketSigner};
 use serde_aux::field_attributes::deserialize_number_from_string;
 
 #[derive(Deserialize)]
@@ -18,10 +19,26 @@ pub struct ConsoleClientSettings {
     pub web_url: String,
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, Clone)]
 pub struct ApplicationSettings {
     pub port: u16,
     pub host: String,
+    /// Public URL of the web server, used for links sent by email.
+    pub base_url: String,
+    /// Door scanners allowed to check people in, by their bearer token.
+    #[serde(default)]
+    pub scanners: Vec<ScannerSettings>,
+    /// Reverse proxies whose `X-Forwarded-For` header is trusted for the client address.
+    #[serde(default)]
+    pub trusted_proxies: Vec<std::net::IpAddr>,
+}
+
+/// A door scanner and the bearer token it authenticates with.
+#[derive(serde::Deserialize, Clone)]
+pub struct ScannerSettings {
+    /// Recorded with every check-in made with the token.
+    pub name: String,
+    pub token: Secret<String>,
 }
 
 #[derive(Deserialize, Clone)]
@@ -41,6 +58,8 @@ pub struct EmailClientSettings {
     pub authorization_token: Secret<String>,
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub timeout_milliseconds: u64,
+    #[serde(default)]
+    pub limits: ProviderLimitSettings,
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -51,6 +70,83 @@ pub struct QRClientSettings {
     pub base_image_path: String,
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub timeout_milliseconds: u64,
+    /// Keys of the signed tickets, absent where nothing is signed.
+    pub ticket_keys: Option<TicketKeySettings>,
+    /// Limits on the calls to the URL shortener.
+    #[serde(default)]
+    pub limits: ProviderLimitSettings,
+    /// Where QR codes are rendered and cached.
+    #[serde(default)]
+    pub rendering: QrRenderSettings,
+}
+
+/// QR codes are rendered on the blocking thread pool, a few at a time, and
+/// the images are cached so that the same code isn't encoded twice.
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct QrRenderSettings {
+    /// Codes rendered at the same time, as many as there are cores by default.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_concurrent_renders: usize,
+    /// Bytes of images kept in memory, none when zero.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub cache_memory_bytes: u64,
+    /// Directory images are also kept in, so they outlive restarts.
+    pub cache_directory: Option<String>,
+    /// Bytes of images kept in the directory.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub cache_directory_bytes: u64,
+}
+
+impl Default for QrRenderSettings {
+    fn default() -> Self {
+        QrRenderSettings {
+            max_concurrent_renders: std::thread::available_parallelism().map_or(4, |cores| cores.get()),
+            cache_memory_bytes: 64 * 1024 * 1024,
+            cache_directory: None,
+            cache_directory_bytes: 1024 * 1024 * 1024,
+        }
+    }
+}
+
+/// Limits on the calls made to a third-party provider, to stay within its
+/// rate limits and not to run out of sockets.
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ProviderLimitSettings {
+    /// Requests waiting for a response at the same time.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_concurrent_requests: usize,
+    /// Requests started per second on average, no limit when zero.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub requests_per_second: f64,
+    /// Requests that can be started at once after a quiet period.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub burst: u32,
+    /// Retries of a request answered with `429 Too Many Requests`.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_retries: u32,
+}
+
+impl Default for ProviderLimitSettings {
+    fn default() -> Self {
+        ProviderLimitSettings {
+            max_concurrent_requests: 8,
+            requests_per_second: 10.0,
+            burst: 10,
+            max_retries: 3,
+        }
+    }
+}
+
+/// The key signing new tickets, and the retired keys tickets may still be signed with.
+#[derive(serde::Deserialize, Clone)]
+pub struct TicketKeySettings {
+    pub key_id: String,
+    /// Base64 encoded 32 byte Ed25519 seed.
+    pub signing_key: Secret<String>,
+    #[serde(default)]
+    pub retired_keys: Vec<PublicTicketKey>,
 }
 
 impl QRClientSettings {
@@ -59,6 +155,12 @@ impl QRClientSettings {
     }
 }
 
+impl TicketKeySettings {
+    pub fn signer(&self) -> Result<TicketSigner, TicketError> {
+        TicketSigner::new(self.key_id.clone(), &self.signing_key)
+    }
+}
+
 impl EmailClientSettings {
     pub fn sender(&self) -> Result<Email, String> {
         Email::parse(self.sender_email.clone())
@@ -1,6 +1,7 @@
 use std::str::FromStr;
 use chrono::NaiveDateTime;
-use crate::domain::{Email};
+use crate::domain::{Attendee, DBAttendee, Email, InvitationStatus, NewAttendee};
+use crate::qr_render::QrRenderOptions;
 use url::Url;
 use std::time::Duration;
 use uuid::Uuid;
@@ -15,6 +16,21 @@ pub enum AppointmentFormat {
     OFFLINE
 }
 
+impl FromStr for AppointmentFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "0" | "online" => Ok(AppointmentFormat::ONLINE),
+            "1" | "offline" => Ok(AppointmentFormat::OFFLINE),
+            other => Err(format!(
+                "{} is not a supported format. Use either `online` or `offline`.",
+                other
+            )),
+        }
+    }
+}
+
 fn serialize_duration<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
@@ -41,6 +57,46 @@ pub struct NewAppointment {
     pub date: NaiveDateTime,
     #[serde(serialize_with = "serialize_duration", deserialize_with = "deserialize_duration")]
     pub duration: Duration,
+    /// Maximum number of invitations, unlimited when missing.
+    pub capacity: Option<i32>,
+    /// Whether anyone may register through the public registration page.
+    #[serde(default)]
+    pub registration_open: bool,
+    /// How the QR codes of the invitations are rendered, the defaults when missing.
+    #[serde(default)]
+    pub qr_options: Option<QrRenderOptions>,
+}
+
+impl NewAppointment {
+    /// Checks that the appointment can be stored and later served to attendees:
+    /// text fields are not blank, the duration and capacity are positive and
+    /// the format has its location (an address for OFFLINE, a link for ONLINE).
+    pub fn validate(&self) -> Result<(), String> {
+        if self.title.trim().is_empty() {
+            return Err("title cannot be empty.".to_string());
+        }
+        if self.description.trim().is_empty() {
+            return Err("description cannot be empty.".to_string());
+        }
+        if self.duration.as_secs() == 0 {
+            return Err("duration must be greater than zero.".to_string());
+        }
+        if self.capacity.is_some_and(|capacity| capacity < 1) {
+            return Err("capacity must be greater than zero.".to_string());
+        }
+        if let Some(options) = &self.qr_options {
+            options.validate().map_err(|e| format!("qr_options: {}", e))?;
+        }
+        match self.format {
+            AppointmentFormat::ONLINE if self.link.is_none() => {
+                Err("an ONLINE appointment requires a link.".to_string())
+            }
+            AppointmentFormat::OFFLINE if self.address.as_deref().is_none_or(|a| a.trim().is_empty()) => {
+                Err("an OFFLINE appointment requires an address.".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, FromRow)]
@@ -53,6 +109,10 @@ pub struct DBAppointment {
     pub link: Option<String>,
     pub date: NaiveDateTime,
     pub duration: i32,
+    pub capacity: Option<i32>,
+    pub remaining_seats: Option<i32>,
+    pub registration_open: bool,
+    pub qr_options: Option<sqlx::types::Json<QrRenderOptions>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
@@ -66,6 +126,12 @@ pub struct Appointment {
     pub date: NaiveDateTime,
     #[serde(serialize_with = "serialize_duration", deserialize_with = "deserialize_duration")]
     pub duration: Duration,
+    pub capacity: Option<i32>,
+    /// Invitations that can still be issued, unlimited when missing.
+    pub remaining_seats: Option<i32>,
+    pub registration_open: bool,
+    #[serde(default)]
+    pub qr_options: Option<QrRenderOptions>,
 }
 
 impl From<DBAppointment> for Appointment {
@@ -82,6 +148,10 @@ impl From<DBAppointment> for Appointment {
             link,
             date: db_appointment.date,
             duration: Duration::from_secs(db_appointment.duration as u64), // Convert i64 to Duration
+            capacity: db_appointment.capacity,
+            remaining_seats: db_appointment.remaining_seats,
+            registration_open: db_appointment.registration_open,
+            qr_options: db_appointment.qr_options.map(|options| options.0),
         }
     }
 }
@@ -90,46 +160,99 @@ impl From<DBAppointment> for Appointment {
 pub struct DBAppointmentWithInvitation {
     pub id: Uuid,
     pub appointment_id: Uuid,
+    pub title: String,
     pub used: bool,
+    pub status: InvitationStatus,
+    pub max_admissions: i32,
+    pub admitted: i32,
+    pub claim_deadline: Option<NaiveDateTime>,
     pub short_url: String,
     pub format: AppointmentFormat,
     pub address: Option<String>,
     pub link: Option<String>,
     pub date: NaiveDateTime,
+    pub duration: i32,
+    pub attendee_id: Option<Uuid>,
+    pub attendee_name: Option<String>,
+    pub attendee_email: Option<String>,
+    pub attendee_phone: Option<String>,
+    pub attendee_custom_fields: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppointmentWithInvitation {
     pub id: Uuid,
     pub appointment_id: Uuid,
+    pub title: String,
     pub used: bool,
+    pub status: InvitationStatus,
+    pub max_admissions: i32,
+    pub admitted: i32,
+    /// Admissions left, e.g. for a door scan reporting "2 of 4 admitted".
+    pub remaining_admissions: i32,
+    pub admission: String,
+    /// When a promoted invitation stops being valid unless it is accepted.
+    #[serde(default)]
+    pub claim_deadline: Option<NaiveDateTime>,
     pub short_url: Url,
     pub format: AppointmentFormat,
     pub address: Option<String>,
     pub link: Option<Url>,
     pub date: NaiveDateTime,
+    #[serde(serialize_with = "serialize_duration", deserialize_with = "deserialize_duration")]
+    pub duration: Duration,
+    pub attendee: Option<Attendee>,
 }
 
-impl From<DBAppointmentWithInvitation> for AppointmentWithInvitation {
-    fn from(db_appt_with_invitation: DBAppointmentWithInvitation) -> Self {
+impl TryFrom<DBAppointmentWithInvitation> for AppointmentWithInvitation {
+    type Error = String;
+
+    fn try_from(db_appt_with_invitation: DBAppointmentWithInvitation) -> Result<Self, Self::Error> {
         let link = db_appt_with_invitation
             .link
             .map(|s| Url::parse(&s).expect("Can't convert String to Url"));
 
         let short_url = Url::parse(&db_appt_with_invitation.short_url).expect("Can't convert String to Url");
-        AppointmentWithInvitation {
+        let attendee = db_appt_with_invitation.attendee_id
+            .zip(db_appt_with_invitation.attendee_email)
+            .map(|(id, email)| Attendee::try_from(DBAttendee {
+                id,
+                appointment_id: db_appt_with_invitation.appointment_id,
+                name: db_appt_with_invitation.attendee_name,
+                email,
+                phone: db_appt_with_invitation.attendee_phone,
+                custom_fields: db_appt_with_invitation.attendee_custom_fields.unwrap_or_default(),
+            }))
+            .transpose()?;
+        Ok(AppointmentWithInvitation {
             id: db_appt_with_invitation.id,
             appointment_id: db_appt_with_invitation.appointment_id,
+            title: db_appt_with_invitation.title,
             used: db_appt_with_invitation.used,
+            status: db_appt_with_invitation.status,
+            max_admissions: db_appt_with_invitation.max_admissions,
+            admitted: db_appt_with_invitation.admitted,
+            remaining_admissions: (db_appt_with_invitation.max_admissions - db_appt_with_invitation.admitted).max(0),
+            admission: format!("{} of {} admitted", db_appt_with_invitation.admitted, db_appt_with_invitation.max_admissions),
+            claim_deadline: db_appt_with_invitation.claim_deadline,
             short_url,
             format: db_appt_with_invitation.format,
             address: db_appt_with_invitation.address,
             link,
             date: db_appt_with_invitation.date,
-        }
+            duration: Duration::from_secs(db_appt_with_invitation.duration as u64),
+            attendee,
+        })
     }
 }
 
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct AppointmentFilter {
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+    pub format: Option<AppointmentFormat>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct RemoveAppointment {
     pub id: Vec<Uuid>
@@ -143,5 +266,95 @@ pub struct SendAppointment {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SendAppointmentEmails {
-    pub email: Option<Vec<Email>>
-}
\ No newline at end of file
+    pub email: Option<Vec<Email>>,
+    pub attendees: Option<Vec<NewAttendee>>
+}
+
+impl SendAppointmentEmails {
+    /// Attendees to invite, with plain emails turned into attendees without a name.
+    ///
+    /// Returns `None` when neither attendees nor emails were given.
+    pub fn recipients(self) -> Option<Vec<NewAttendee>> {
+        match (self.attendees, self.email) {
+            (Some(attendees), _) => Some(attendees),
+            (None, Some(emails)) => Some(emails.into_iter().map(NewAttendee::from).collect()),
+            (None, None) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claims::{assert_err, assert_ok};
+
+    fn new_appointment(format: AppointmentFormat) -> NewAppointment {
+        NewAppointment {
+            title: "Title".to_string(),
+            description: "Description".to_string(),
+            format,
+            address: Some("123 Fake St.".to_string()),
+            link: Some(Url::parse("https://meeting-test.com").unwrap()),
+            date: NaiveDateTime::from_timestamp_opt(1672531200, 0).unwrap(),
+            duration: Duration::from_secs(3600),
+            capacity: Some(10),
+            registration_open: false,
+            qr_options: None,
+        }
+    }
+
+    #[test]
+    fn format_is_parsed_case_insensitively() {
+        assert!(matches!("Online".parse::<AppointmentFormat>(), Ok(AppointmentFormat::ONLINE)));
+        assert!(matches!("1".parse::<AppointmentFormat>(), Ok(AppointmentFormat::OFFLINE)));
+        assert_err!("hybrid".parse::<AppointmentFormat>());
+    }
+
+    #[test]
+    fn complete_appointment_is_valid() {
+        assert_ok!(new_appointment(AppointmentFormat::ONLINE).validate());
+        assert_ok!(new_appointment(AppointmentFormat::OFFLINE).validate());
+    }
+
+    #[test]
+    fn blank_title_is_rejected() {
+        let mut appointment = new_appointment(AppointmentFormat::ONLINE);
+        appointment.title = "  ".to_string();
+        assert_err!(appointment.validate());
+    }
+
+    #[test]
+    fn zero_duration_is_rejected() {
+        let mut appointment = new_appointment(AppointmentFormat::ONLINE);
+        appointment.duration = Duration::from_secs(0);
+        assert_err!(appointment.validate());
+    }
+
+    #[test]
+    fn zero_capacity_is_rejected() {
+        let mut appointment = new_appointment(AppointmentFormat::ONLINE);
+        appointment.capacity = Some(0);
+        assert_err!(appointment.validate());
+    }
+
+    #[test]
+    fn online_appointment_without_link_is_rejected() {
+        let mut appointment = new_appointment(AppointmentFormat::ONLINE);
+        appointment.link = None;
+        assert_err!(appointment.validate());
+    }
+
+    #[test]
+    fn offline_appointment_without_address_is_rejected() {
+        let mut appointment = new_appointment(AppointmentFormat::OFFLINE);
+        appointment.address = None;
+        assert_err!(appointment.validate());
+    }
+
+    #[test]
+    fn unreadable_qr_options_are_rejected() {
+        let mut appointment = new_appointment(AppointmentFormat::ONLINE);
+        appointment.qr_options = Some(QrRenderOptions { foreground: "#FFFFFF".to_string(), ..Default::default() });
+        assert_err!(appointment.validate());
+    }
+}
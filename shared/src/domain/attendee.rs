@@ -0,0 +1,92 @@
+use uuid::Uuid;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use crate::domain::Email;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct NewAttendee {
+    pub name: Option<String>,
+    pub email: Email,
+    pub phone: Option<String>,
+    #[serde(default = "empty_custom_fields")]
+    pub custom_fields: serde_json::Value,
+}
+
+fn empty_custom_fields() -> serde_json::Value {
+    serde_json::Value::Object(Default::default())
+}
+
+impl NewAttendee {
+    /// Name to greet the attendee with, falling back to their email address.
+    pub fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or(self.email.as_ref())
+    }
+}
+
+impl From<Email> for NewAttendee {
+    fn from(email: Email) -> Self {
+        NewAttendee {
+            name: None,
+            email,
+            phone: None,
+            custom_fields: empty_custom_fields(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct DBAttendee {
+    pub id: Uuid,
+    pub appointment_id: Uuid,
+    pub name: Option<String>,
+    pub email: String,
+    pub phone: Option<String>,
+    pub custom_fields: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attendee {
+    pub id: Uuid,
+    pub appointment_id: Uuid,
+    pub name: Option<String>,
+    pub email: Email,
+    pub phone: Option<String>,
+    pub custom_fields: serde_json::Value,
+}
+
+impl Attendee {
+    /// Name to greet the attendee with, falling back to their email address.
+    pub fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or(self.email.as_ref())
+    }
+}
+
+impl TryFrom<DBAttendee> for Attendee {
+    type Error = String;
+
+    fn try_from(db_attendee: DBAttendee) -> Result<Self, Self::Error> {
+        Ok(Attendee {
+            id: db_attendee.id,
+            appointment_id: db_attendee.appointment_id,
+            name: db_attendee.name,
+            email: Email::parse(db_attendee.email)?,
+            phone: db_attendee.phone,
+            custom_fields: db_attendee.custom_fields,
+        })
+    }
+}
+
+/// An attendee together with the invitation issued to them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttendeeWithInvitation {
+    #[serde(flatten)]
+    pub attendee: Attendee,
+    pub invitation_id: Option<Uuid>,
+    pub used: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AttendeeSearch {
+    /// Part of the name or email address, matched case-insensitively.
+    pub q: Option<String>,
+}
@@ -1,18 +1,147 @@
+use chrono::NaiveDateTime;
 use uuid::Uuid;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use url::Url;
+use crate::domain::Email;
+use crate::qr_render::{QrErrorCorrection, QrFormat, QrRenderOptions};
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, sqlx::Type)]
+#[sqlx(type_name = "invitation_status")]
+pub enum InvitationStatus {
+    ACTIVE,
+    DECLINED,
+    /// Invalidated by an operator, or replaced by a reissued or transferred invitation.
+    REVOKED
+}
+
+/// Answer of the attendee to their invitation.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, sqlx::Type)]
+#[sqlx(type_name = "rsvp_status")]
+pub enum RsvpStatus {
+    PENDING,
+    ACCEPTED,
+    DECLINED
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct NewInvitation {
     pub id: Uuid,
     pub appointment_id: Uuid,
-    pub short_url: Url
+    pub short_url: Url,
+    pub attendee_id: Option<Uuid>,
+    /// Set on invitations promoted from the waitlist, which are declined when not claimed in time.
+    pub claim_deadline: Option<NaiveDateTime>,
+    /// Number of people the invitation admits.
+    #[serde(default = "single_admission")]
+    pub max_admissions: i32
 }
 
-#[derive(Debug, Deserialize)]
+fn single_admission() -> i32 {
+    1
+}
+
+/// Whether an invitation was issued for a recipient, or is the one they
+/// already held for the appointment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum IssueOutcome {
+    CREATED,
+    EXISTING
+}
+
+/// An invitation as answered to the request issuing it.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct IssuedInvitation {
+    #[serde(flatten)]
+    pub invitation: NewInvitation,
+    /// Address of the recipient, absent on invitations issued without one.
+    pub email: Option<Email>,
+    pub outcome: IssueOutcome,
+    /// Why the invitation couldn't be emailed, absent when it was, or wasn't
+    /// meant to be.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email_error: Option<String>,
+}
+
+/// Number of invitations issued for an appointment.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct InvitationCounts {
+    pub total: i64,
+    pub used: i64,
+    pub unused: i64,
+    pub declined: i64,
+}
+
+/// Body of the revoke and reissue requests.
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct EditInvitation {
-    pub used: String,
+    /// Why the invitation is changed, kept in its history.
+    pub reason: Option<String>,
+}
+
+/// Body of the transfer request.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TransferInvitation {
+    /// Email address of the new holder.
+    pub email: Email,
+    pub name: Option<String>,
+    pub reason: Option<String>,
+}
+
+/// Body of the resend request.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ResendInvitation {
+    /// Corrected email address of the attendee, the one on file otherwise.
+    pub email: Option<Email>,
+}
+
+/// Change made to an invitation.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, sqlx::Type)]
+#[sqlx(type_name = "invitation_action")]
+pub enum InvitationAction {
+    ACCEPTED,
+    DECLINED,
+    REVOKED,
+    /// Replaced by a fresh code for the same attendee.
+    REISSUED,
+    /// Replaced by a code for another attendee.
+    TRANSFERRED,
+    /// Issued as the replacement of another invitation.
+    REPLACEMENT,
+    /// Emailed again, to the same or a corrected address.
+    RESENT,
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct DBInvitationHistory {
+    pub id: i32,
+    pub invitation_id: Uuid,
+    pub action: InvitationAction,
+    pub details: serde_json::Value,
+    pub created_at: NaiveDateTime,
+}
+
+/// A recorded change of an invitation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvitationHistory {
+    pub id: i32,
+    pub invitation_id: Uuid,
+    pub action: InvitationAction,
+    /// Action specific data, e.g. the reason or the invitation replacing this one.
+    pub details: serde_json::Value,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<DBInvitationHistory> for InvitationHistory {
+    fn from(db_history: DBInvitationHistory) -> Self {
+        InvitationHistory {
+            id: db_history.id,
+            invitation_id: db_history.invitation_id,
+            action: db_history.action,
+            details: db_history.details,
+            created_at: db_history.created_at,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, FromRow)]
@@ -20,7 +149,13 @@ pub struct DBInvitation {
     pub id: Uuid,
     pub appointment_id: Uuid,
     pub used: bool,
-    pub short_url: String
+    pub short_url: String,
+    pub attendee_id: Option<Uuid>,
+    pub status: InvitationStatus,
+    pub rsvp: RsvpStatus,
+    pub claim_deadline: Option<NaiveDateTime>,
+    pub max_admissions: i32,
+    pub admitted: i32
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,7 +163,13 @@ pub struct Invitation {
     pub id: Uuid,
     pub appointment_id: Uuid,
     pub used: bool,
-    pub short_url: Url
+    pub short_url: Url,
+    pub attendee_id: Option<Uuid>,
+    pub status: InvitationStatus,
+    pub rsvp: RsvpStatus,
+    pub claim_deadline: Option<NaiveDateTime>,
+    pub max_admissions: i32,
+    pub admitted: i32
 }
 
 
@@ -39,12 +180,70 @@ impl From<DBInvitation> for Invitation {
             id: db_invitation.id,
             appointment_id: db_invitation.appointment_id,
             used: db_invitation.used,
-            short_url: Url::parse(&db_invitation.short_url).expect("Can't convert String to Url")
+            short_url: Url::parse(&db_invitation.short_url).expect("Can't convert String to Url"),
+            attendee_id: db_invitation.attendee_id,
+            status: db_invitation.status,
+            rsvp: db_invitation.rsvp,
+            claim_deadline: db_invitation.claim_deadline,
+            max_admissions: db_invitation.max_admissions,
+            admitted: db_invitation.admitted
         }
     }
 }
 
+impl Invitation {
+    /// People who can still be admitted with the invitation.
+    pub fn remaining_admissions(&self) -> i32 {
+        (self.max_admissions - self.admitted).max(0)
+    }
+}
+
+/// Response submitted from the RSVP page.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RsvpForm {
+    /// `accept` or `decline`.
+    pub response: String,
+}
+
+/// Overrides of the QR options of the appointment, for one QR code request.
+#[derive(Debug, Default, Deserialize)]
+pub struct QrParams {
+    /// Answers with the image itself in this format instead of the QR page.
+    pub format: Option<QrFormat>,
+    pub size: Option<u32>,
+    pub quiet_zone: Option<u32>,
+    pub error_correction: Option<QrErrorCorrection>,
+    pub foreground: Option<String>,
+    pub background: Option<String>,
+}
+
+impl QrParams {
+    /// The appointment options with the given overrides.
+    pub fn apply(self, mut options: QrRenderOptions) -> QrRenderOptions {
+        options.format = self.format.unwrap_or(options.format);
+        options.size = self.size.or(options.size);
+        options.quiet_zone = self.quiet_zone.unwrap_or(options.quiet_zone);
+        options.error_correction = self.error_correction.unwrap_or(options.error_correction);
+        options.foreground = self.foreground.unwrap_or(options.foreground);
+        options.background = self.background.unwrap_or(options.background);
+        options
+    }
+}
+
+/// Contents of the QR code archive of an appointment.
+#[derive(Debug, Default, Deserialize)]
+pub struct QrArchiveParams {
+    /// `png`, `svg` or `jpeg` for QR codes, `pdf` for printable tickets,
+    /// the QR format of the appointment by default.
+    pub format: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct InvitationParams {
-    pub count: Option<i32>
+    pub count: Option<i32>,
+    /// Number of people each issued invitation admits, one by default.
+    pub max_admissions: Option<i32>,
+    /// Emails their invitation again to recipients who already hold one.
+    #[serde(default)]
+    pub resend_existing: bool
 }
\ No newline at end of file
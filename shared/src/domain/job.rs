@@ -0,0 +1,53 @@
+use chrono::NaiveDateTime;
+use uuid::Uuid;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use crate::domain::Email;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, sqlx::Type)]
+#[sqlx(type_name = "job_status")]
+pub enum JobStatus {
+    /// Waiting for a worker.
+    PENDING,
+    RUNNING,
+    COMPLETED,
+    /// Stopped on an error, the invitations issued so far are kept and the
+    /// job can be resumed.
+    FAILED
+}
+
+impl JobStatus {
+    /// Whether the job won't make any more progress on its own.
+    pub fn is_finished(&self) -> bool {
+        matches!(self, JobStatus::COMPLETED | JobStatus::FAILED)
+    }
+}
+
+/// Invitations issued in the background, batch after batch.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct InvitationJob {
+    pub id: Uuid,
+    pub appointment_id: Uuid,
+    pub status: JobStatus,
+    /// Number of invitations to issue.
+    pub total: i32,
+    /// Number of invitations issued, and emailed when there are recipients.
+    pub processed: i32,
+    pub max_admissions: i32,
+    /// Every error the job stopped on, oldest first.
+    pub errors: Vec<String>,
+    /// Invitations the job issued but couldn't email, in the order of their
+    /// recipients.
+    #[serde(default)]
+    pub email_failures: sqlx::types::Json<Vec<JobEmailFailure>>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// An invitation of a job whose email couldn't be sent, with the reason.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JobEmailFailure {
+    pub invitation_id: Uuid,
+    pub email: Email,
+    pub error: String,
+}
@@ -1,7 +1,17 @@
 mod email;
 mod appointment;
+mod attendee;
 mod invitation;
+mod waitlist;
+mod registration;
+mod scan;
+mod job;
 
 pub use email::Email;
 pub use appointment::*;
-pub use invitation::*;
\ No newline at end of file
+pub use attendee::*;
+pub use invitation::*;
+pub use waitlist::*;
+pub use registration::*;
+pub use scan::*;
+pub use job::*;
\ No newline at end of file
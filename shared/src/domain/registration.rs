@@ -0,0 +1,121 @@
+use chrono::NaiveDateTime;
+use uuid::Uuid;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use crate::domain::{Email, NewAttendee};
+
+/// Fields posted by the public registration form.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RegistrationForm {
+    pub name: String,
+    pub email: String,
+    /// Honeypot field hidden from people; only bots fill it in.
+    #[serde(default)]
+    pub website: String,
+}
+
+impl RegistrationForm {
+    /// Checks the form and turns it into the attendee to register.
+    pub fn parse(&self) -> Result<NewAttendee, String> {
+        let name = self.name.trim();
+        if name.is_empty() {
+            return Err("name cannot be empty.".to_string());
+        }
+        if name.chars().count() > 255 {
+            return Err("name is too long.".to_string());
+        }
+        let email = Email::parse(self.email.trim().to_string())?;
+        Ok(NewAttendee {
+            name: Some(name.to_string()),
+            ..NewAttendee::from(email)
+        })
+    }
+
+    /// Whether the honeypot field was filled in.
+    pub fn is_spam(&self) -> bool {
+        !self.website.trim().is_empty()
+    }
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct DBRegistration {
+    pub id: Uuid,
+    pub appointment_id: Uuid,
+    pub name: String,
+    pub email: String,
+    pub created_at: NaiveDateTime,
+    pub confirmed_at: Option<NaiveDateTime>,
+    pub invitation_id: Option<Uuid>,
+    pub waitlist_id: Option<Uuid>,
+}
+
+/// A self-service registration, confirmed once the email link is opened.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Registration {
+    pub id: Uuid,
+    pub appointment_id: Uuid,
+    pub name: String,
+    pub email: Email,
+    pub created_at: NaiveDateTime,
+    pub confirmed_at: Option<NaiveDateTime>,
+    /// Invitation issued on confirmation.
+    pub invitation_id: Option<Uuid>,
+    /// Waitlist entry created on confirmation when the appointment was full.
+    pub waitlist_id: Option<Uuid>,
+}
+
+impl Registration {
+    pub fn attendee(&self) -> NewAttendee {
+        NewAttendee {
+            name: Some(self.name.clone()),
+            ..NewAttendee::from(self.email.clone())
+        }
+    }
+}
+
+impl From<DBRegistration> for Registration {
+    fn from(db_registration: DBRegistration) -> Self {
+        Registration {
+            id: db_registration.id,
+            appointment_id: db_registration.appointment_id,
+            name: db_registration.name,
+            email: Email::parse(db_registration.email).expect("Can't convert String to Email"),
+            created_at: db_registration.created_at,
+            confirmed_at: db_registration.confirmed_at,
+            invitation_id: db_registration.invitation_id,
+            waitlist_id: db_registration.waitlist_id,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn form(name: &str, email: &str, website: &str) -> RegistrationForm {
+        RegistrationForm {
+            name: name.to_string(),
+            email: email.to_string(),
+            website: website.to_string(),
+        }
+    }
+
+    #[test]
+    fn valid_form_becomes_named_attendee() {
+        let attendee = form("  Ada  ", "ada@example.com", "").parse().unwrap();
+        assert_eq!(attendee.name.as_deref(), Some("Ada"));
+        assert_eq!(attendee.email.as_ref(), "ada@example.com");
+    }
+
+    #[test]
+    fn blank_name_and_bad_email_are_rejected() {
+        assert!(form(" ", "ada@example.com", "").parse().is_err());
+        assert!(form("Ada", "not-an-email", "").parse().is_err());
+    }
+
+    #[test]
+    fn filled_honeypot_is_spam() {
+        assert!(form("Ada", "ada@example.com", "http://spam.example").is_spam());
+        assert!(!form("Ada", "ada@example.com", "").is_spam());
+    }
+}
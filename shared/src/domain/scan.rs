@@ -0,0 +1,152 @@
+use chrono::NaiveDateTime;
+use uuid::Uuid;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use crate::domain::{AppointmentFormat, InvitationStatus};
+
+/// Whether a scan lets someone in or out of the venue.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize, sqlx::Type)]
+#[sqlx(type_name = "scan_direction")]
+pub enum ScanDirection {
+    #[default]
+    IN,
+    OUT
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, sqlx::Type)]
+#[sqlx(type_name = "scan_result")]
+pub enum ScanResult {
+    ADMITTED,
+    REJECTED
+}
+
+/// Body of a check-in; the scanner is known from its token.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ScanParams {
+    #[serde(default)]
+    pub direction: ScanDirection,
+}
+
+/// A validation attempt to record.
+#[derive(Debug, Clone)]
+pub struct NewScanEvent {
+    pub invitation_id: Uuid,
+    pub appointment_id: Option<Uuid>,
+    pub direction: ScanDirection,
+    pub result: ScanResult,
+    pub reason: Option<String>,
+    pub scanner: Option<String>,
+    /// When the scan happened, now unless it was made offline.
+    pub scanned_at: Option<NaiveDateTime>,
+    pub client_scan_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct DBScanEvent {
+    pub id: i32,
+    pub invitation_id: Uuid,
+    pub appointment_id: Option<Uuid>,
+    pub direction: ScanDirection,
+    pub result: ScanResult,
+    pub reason: Option<String>,
+    pub scanner: Option<String>,
+    pub scanned_at: NaiveDateTime,
+}
+
+/// A recorded validation attempt.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanEvent {
+    pub id: i32,
+    pub invitation_id: Uuid,
+    /// `None` when the scanned code doesn't belong to any invitation.
+    pub appointment_id: Option<Uuid>,
+    pub direction: ScanDirection,
+    pub result: ScanResult,
+    /// Why the scan was rejected.
+    pub reason: Option<String>,
+    pub scanner: Option<String>,
+    pub scanned_at: NaiveDateTime,
+}
+
+impl From<DBScanEvent> for ScanEvent {
+    fn from(db_event: DBScanEvent) -> Self {
+        ScanEvent {
+            id: db_event.id,
+            invitation_id: db_event.invitation_id,
+            appointment_id: db_event.appointment_id,
+            direction: db_event.direction,
+            result: db_event.result,
+            reason: db_event.reason,
+            scanner: db_event.scanner,
+            scanned_at: db_event.scanned_at,
+        }
+    }
+}
+
+/// People currently inside the venue of an appointment.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Occupancy {
+    pub appointment_id: Uuid,
+    /// Admitted check-ins minus check-outs.
+    pub inside: i64,
+    pub checked_in: i64,
+    pub checked_out: i64,
+}
+
+/// How often an invitation was used to join an ONLINE appointment.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct OnlineAttendance {
+    pub invitation_id: Uuid,
+    pub attendee_name: Option<String>,
+    pub attendee_email: Option<String>,
+    pub joins: i64,
+    pub first_joined_at: NaiveDateTime,
+    pub last_joined_at: NaiveDateTime,
+}
+
+/// A check-in made by a scanner while offline, pushed once it's back online.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OfflineScan {
+    /// Given by the scanner, so that pushing the same check-in twice is harmless.
+    pub id: Uuid,
+    pub invitation_id: Uuid,
+    pub scanned_at: NaiveDateTime,
+}
+
+/// How the server took an offline check-in.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum SyncStatus {
+    ACCEPTED,
+    /// Pushed before, nothing changed.
+    DUPLICATE,
+    /// The admissions were used up by other scanners first.
+    CONFLICT,
+    /// The invitation isn't valid for the appointment.
+    REJECTED
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncedScan {
+    pub id: Uuid,
+    pub invitation_id: Uuid,
+    pub status: SyncStatus,
+    pub reason: Option<String>,
+}
+
+/// What a validation link tells whoever opens it, without checking anyone in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidationPreview {
+    pub invitation_id: Uuid,
+    pub appointment_id: Uuid,
+    pub title: String,
+    pub format: AppointmentFormat,
+    pub address: Option<String>,
+    pub date: NaiveDateTime,
+    pub status: InvitationStatus,
+    pub admission: String,
+    pub remaining_admissions: i32,
+    pub attendee_name: Option<String>,
+    /// Whether a check-in would be admitted right now.
+    pub valid: bool,
+    pub reason: Option<String>,
+}
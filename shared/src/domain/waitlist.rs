@@ -0,0 +1,68 @@
+use chrono::NaiveDateTime;
+use uuid::Uuid;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use crate::domain::{Email, NewAttendee};
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct DBWaitlistEntry {
+    pub id: Uuid,
+    pub appointment_id: Uuid,
+    pub position: i32,
+    pub name: Option<String>,
+    pub email: String,
+    pub phone: Option<String>,
+    pub custom_fields: serde_json::Value,
+    pub invitation_id: Option<Uuid>,
+    pub promoted_at: Option<NaiveDateTime>,
+}
+
+/// A person waiting for a seat of a full appointment.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WaitlistEntry {
+    pub id: Uuid,
+    pub appointment_id: Uuid,
+    /// 1-based place in the queue; promoted entries keep their last position.
+    pub position: i32,
+    pub name: Option<String>,
+    pub email: Email,
+    pub phone: Option<String>,
+    pub custom_fields: serde_json::Value,
+    /// Invitation issued on promotion.
+    pub invitation_id: Option<Uuid>,
+    pub promoted_at: Option<NaiveDateTime>,
+}
+
+impl WaitlistEntry {
+    /// The attendee to create once the entry is promoted.
+    pub fn attendee(&self) -> NewAttendee {
+        NewAttendee {
+            name: self.name.clone(),
+            email: self.email.clone(),
+            phone: self.phone.clone(),
+            custom_fields: self.custom_fields.clone(),
+        }
+    }
+}
+
+impl From<DBWaitlistEntry> for WaitlistEntry {
+    fn from(db_entry: DBWaitlistEntry) -> Self {
+        WaitlistEntry {
+            id: db_entry.id,
+            appointment_id: db_entry.appointment_id,
+            position: db_entry.position,
+            name: db_entry.name,
+            email: Email::parse(db_entry.email).expect("Can't convert String to Email"),
+            phone: db_entry.phone,
+            custom_fields: db_entry.custom_fields,
+            invitation_id: db_entry.invitation_id,
+            promoted_at: db_entry.promoted_at,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct WaitlistPosition {
+    /// New 1-based place in the queue, clamped to its length.
+    pub position: i32,
+}
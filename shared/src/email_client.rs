@@ -1,12 +1,27 @@
+use crate::configuration::ProviderLimitSettings;
 use crate::domain::Email;
+use crate::throttle::Throttle;
 use reqwest::Client;
 use secrecy::{ExposeSecret, Secret};
 
+/// Largest number of messages Postmark takes in a single batch.
+pub const MAX_BATCH_SIZE: usize = 500;
+
 pub struct EmailClient {
     http_client: Client,
     base_url: String,
     sender: Email,
     authorization_token: Secret<String>,
+    throttle: Throttle,
+}
+
+/// A message to send with [`EmailClient::send_email_batch`].
+#[derive(Debug, Clone)]
+pub struct OutgoingEmail {
+    pub recipient: Email,
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
 }
 
 impl EmailClient {
@@ -22,9 +37,21 @@ impl EmailClient {
             base_url,
             sender,
             authorization_token,
+            throttle: Throttle::new(&ProviderLimitSettings::default()),
         }
     }
 
+    /// Limits the calls to Postmark, the defaults of `ProviderLimitSettings` otherwise.
+    pub fn with_limits(mut self, limits: &ProviderLimitSettings) -> Self {
+        self.throttle = Throttle::new(limits);
+        self
+    }
+
+    /// Number of requests sent to Postmark at the same time, at most.
+    pub fn max_concurrent_requests(&self) -> usize {
+        self.throttle.max_concurrent_requests()
+    }
+
     pub async fn send_email(
         &self,
         recipient: &Email,
@@ -40,18 +67,73 @@ impl EmailClient {
             html_body: html_content,
             text_body: text_content,
         };
-        self.http_client
-            .post(&url)
-            .header(
-                "X-Postmark-Server-Token",
-                self.authorization_token.expose_secret(),
-            )
-            .json(&request_body)
-            .send()
+        self.throttle
+            .send(|| {
+                self.http_client
+                    .post(&url)
+                    .header(
+                        "X-Postmark-Server-Token",
+                        self.authorization_token.expose_secret(),
+                    )
+                    .json(&request_body)
+            })
             .await?
             .error_for_status()?;
         Ok(())
     }
+
+    /// Sends many messages with Postmark's batch endpoint, `MAX_BATCH_SIZE`
+    /// per request.
+    ///
+    /// Postmark accepts or rejects every message of a batch on its own, so the
+    /// outcome of each message is returned, in order. A request that failed as
+    /// a whole fails the messages it carried, and the next ones are sent all
+    /// the same, so the messages of the requests that went through are never
+    /// reported as failed.
+    pub async fn send_email_batch(&self, emails: &[OutgoingEmail]) -> Vec<Result<(), String>> {
+        let mut outcomes = Vec::with_capacity(emails.len());
+        for batch in emails.chunks(MAX_BATCH_SIZE) {
+            match self.send_batch_request(batch).await {
+                Ok(responses) => {
+                    let mut responses = responses.into_iter();
+                    outcomes.extend(batch.iter().map(|email| match responses.next() {
+                        Some(response) if response.error_code == 0 => Ok(()),
+                        Some(response) => Err(format!("{}: {}", email.recipient.as_ref(), response.message)),
+                        None => Err(format!("{}: no answer from the email service", email.recipient.as_ref())),
+                    }));
+                }
+                Err(e) => outcomes.extend(batch.iter().map(|email| Err(format!("{}: {}", email.recipient.as_ref(), e)))),
+            }
+        }
+        outcomes
+    }
+
+    async fn send_batch_request(&self, batch: &[OutgoingEmail]) -> Result<Vec<BatchEmailResponse>, reqwest::Error> {
+        let url = format!("{}/email/batch", self.base_url);
+        let request_body: Vec<SendEmailRequest> = batch.iter()
+            .map(|email| SendEmailRequest {
+                from: self.sender.as_ref(),
+                to: email.recipient.as_ref(),
+                subject: &email.subject,
+                html_body: &email.html_body,
+                text_body: &email.text_body,
+            })
+            .collect();
+        self.throttle
+            .send(|| {
+                self.http_client
+                    .post(&url)
+                    .header(
+                        "X-Postmark-Server-Token",
+                        self.authorization_token.expose_secret(),
+                    )
+                    .json(&request_body)
+            })
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
 }
 
 #[derive(serde::Serialize)]
@@ -64,10 +146,18 @@ struct SendEmailRequest<'a> {
     text_body: &'a str,
 }
 
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BatchEmailResponse {
+    error_code: i64,
+    #[serde(default)]
+    message: String,
+}
+
 #[cfg(test)]
 mod tests {
     use crate::domain::Email;
-    use crate::email_client::EmailClient;
+    use crate::email_client::{EmailClient, OutgoingEmail};
     use claims::{assert_err, assert_ok};
     use fake::faker::internet::en::SafeEmail;
     use fake::faker::lorem::en::{Paragraph, Sentence};
@@ -185,6 +275,45 @@ mod tests {
         assert_err!(outcome);
     }
 
+    #[tokio::test]
+    async fn send_email_batch_reports_every_message() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let emails: Vec<OutgoingEmail> = (0..3)
+            .map(|_| OutgoingEmail {
+                recipient: email(),
+                subject: subject(),
+                html_body: content(),
+                text_body: content(),
+            })
+            .collect();
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(path("/email/batch"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "ErrorCode": 0, "Message": "OK" },
+                { "ErrorCode": 406, "Message": "Inactive recipient" },
+                { "ErrorCode": 0, "Message": "OK" }
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcomes = email_client.send_email_batch(&emails).await;
+
+        // Assert
+        let requests = mock_server.received_requests().await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert_eq!(body.as_array().unwrap().len(), 3);
+        assert_eq!(body[1]["To"], emails[1].recipient.as_ref());
+        assert_ok!(&outcomes[0]);
+        assert!(outcomes[1].as_ref().unwrap_err().contains("Inactive recipient"));
+        assert_ok!(&outcomes[2]);
+    }
+
     #[tokio::test]
     async fn send_email_times_out_if_the_server_takes_too_long() {
         // Arrange
@@ -206,4 +335,42 @@ mod tests {
         // Assert
         assert_err!(outcome);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn send_email_batch_only_fails_the_messages_of_a_failed_request() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let emails: Vec<OutgoingEmail> = (0..501)
+            .map(|_| OutgoingEmail {
+                recipient: email(),
+                subject: subject(),
+                html_body: content(),
+                text_body: content(),
+            })
+            .collect();
+
+        let accepted: Vec<serde_json::Value> = (0..500)
+            .map(|_| serde_json::json!({ "ErrorCode": 0, "Message": "OK" }))
+            .collect();
+        Mock::given(path("/email/batch"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(accepted))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/email/batch"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcomes = email_client.send_email_batch(&emails).await;
+
+        // Assert
+        assert_eq!(outcomes.len(), 501);
+        assert!(outcomes[..500].iter().all(|outcome| outcome.is_ok()));
+        assert!(outcomes[500].as_ref().unwrap_err().contains(emails[500].recipient.as_ref()));
+    }
+}
@@ -0,0 +1,294 @@
+//! Subsets of TrueType fonts holding only the glyphs a document prints, so
+//! that PDFs embedding a font with thousands of glyphs stay small.
+//!
+//! Glyphs keep the order of their original IDs, with the missing glyph
+//! first. Tables that refer to glyphs but aren't needed to draw them, such
+//! as kerning and substitutions, are left out.
+
+use std::collections::{BTreeMap, BTreeSet};
+use anyhow::anyhow;
+use ttf_parser::{Face, Tag};
+
+/// Tables that don't refer to glyphs, copied as they are when the font has them.
+const COPIED_TABLES: [&[u8; 4]; 5] = [b"OS/2", b"cvt ", b"fpgm", b"name", b"prep"];
+
+/// Makes a font holding the glyphs of the characters of `text`, and those
+/// they are composed of.
+///
+/// Characters the font has no glyph for are left out; printed, they show
+/// as blanks.
+pub fn subset_font(font: &[u8], text: &str) -> Result<Vec<u8>, anyhow::Error> {
+    let face = Face::from_slice(font, 0)
+        .map_err(|e| anyhow!("Failed to parse font: {}", e))?;
+    let table = |tag: &[u8; 4]| face.table_data(Tag::from_bytes(tag))
+        .ok_or_else(|| anyhow!("Font has no {} table", String::from_utf8_lossy(tag)));
+    let (head, hhea, maxp, hmtx, loca, glyf, post) = (
+        table(b"head")?, table(b"hhea")?, table(b"maxp")?, table(b"hmtx")?,
+        table(b"loca")?, table(b"glyf")?, table(b"post")?,
+    );
+
+    let long_offsets = read_u16(head, 50)? == 1;
+    let glyph = |id: u16| -> Result<&[u8], anyhow::Error> {
+        let id = id as usize;
+        let (start, end) = match long_offsets {
+            true => (read_u32(loca, 4 * id)? as usize, read_u32(loca, 4 * id + 4)? as usize),
+            false => (2 * read_u16(loca, 2 * id)? as usize, 2 * read_u16(loca, 2 * id + 2)? as usize),
+        };
+        glyf.get(start..end)
+            .ok_or_else(|| anyhow!("Glyph {} is out of the glyf table", id))
+    };
+
+    let characters: BTreeMap<char, u16> = text.chars()
+        .filter_map(|character| face.glyph_index(character).map(|id| (character, id.0)))
+        .collect();
+    let mut glyphs: BTreeSet<u16> = std::iter::once(0)
+        .chain(characters.values().copied())
+        .collect();
+    let mut pending: Vec<u16> = glyphs.iter().copied().collect();
+    while let Some(id) = pending.pop() {
+        for (_, component) in components(glyph(id)?)? {
+            if glyphs.insert(component) {
+                pending.push(component);
+            }
+        }
+    }
+    let new_ids: BTreeMap<u16, u16> = glyphs.iter()
+        .enumerate()
+        .map(|(new_id, old_id)| (*old_id, new_id as u16))
+        .collect();
+
+    let mut new_glyf = vec![];
+    let mut new_loca = vec![];
+    let mut new_hmtx = vec![];
+    let metrics = read_u16(hhea, 34)?;
+    for &old_id in &glyphs {
+        new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+        let mut data = glyph(old_id)?.to_vec();
+        for (offset, component) in components(&data)? {
+            data[offset..offset + 2].copy_from_slice(&new_ids[&component].to_be_bytes());
+        }
+        new_glyf.extend_from_slice(&data);
+        new_glyf.resize(new_glyf.len().next_multiple_of(4), 0);
+
+        // Glyphs past the last full metric share its advance width.
+        let (advance, left_side_bearing) = match old_id < metrics {
+            true => (read_u16(hmtx, 4 * old_id as usize)?, read_u16(hmtx, 4 * old_id as usize + 2)?),
+            false => (
+                read_u16(hmtx, 4 * (metrics as usize - 1))?,
+                read_u16(hmtx, 4 * metrics as usize + 2 * (old_id - metrics) as usize)?,
+            ),
+        };
+        new_hmtx.extend_from_slice(&advance.to_be_bytes());
+        new_hmtx.extend_from_slice(&left_side_bearing.to_be_bytes());
+    }
+    new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+
+    let count = glyphs.len() as u16;
+    let mut new_head = head.to_vec();
+    write_u32(&mut new_head, 8, 0)?;
+    write_u16(&mut new_head, 50, 1)?;
+    let mut new_hhea = hhea.to_vec();
+    write_u16(&mut new_hhea, 34, count)?;
+    let mut new_maxp = maxp.to_vec();
+    write_u16(&mut new_maxp, 4, count)?;
+    // Version 3 of the post table leaves the glyph names out.
+    let mut new_post = post.get(..32)
+        .ok_or_else(|| anyhow!("The post table is too short"))?
+        .to_vec();
+    write_u32(&mut new_post, 0, 0x0003_0000)?;
+
+    let mut tables: BTreeMap<[u8; 4], Vec<u8>> = BTreeMap::from([
+        (*b"cmap", character_map(&characters, &new_ids)),
+        (*b"glyf", new_glyf),
+        (*b"head", new_head),
+        (*b"hhea", new_hhea),
+        (*b"hmtx", new_hmtx),
+        (*b"loca", new_loca),
+        (*b"maxp", new_maxp),
+        (*b"post", new_post),
+    ]);
+    for tag in COPIED_TABLES {
+        if let Some(data) = face.table_data(Tag::from_bytes(tag)) {
+            tables.insert(*tag, data.to_vec());
+        }
+    }
+    write_font(tables)
+}
+
+/// Offsets of the glyph IDs of the components of a composite glyph, with the IDs.
+fn components(glyph: &[u8]) -> Result<Vec<(usize, u16)>, anyhow::Error> {
+    const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+    const WE_HAVE_A_SCALE: u16 = 0x0008;
+    const MORE_COMPONENTS: u16 = 0x0020;
+    const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+    const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+
+    // Empty glyphs have no data, simple ones a positive number of contours.
+    if glyph.is_empty() || read_u16(glyph, 0)? as i16 >= 0 {
+        return Ok(vec![]);
+    }
+    let mut components = vec![];
+    let mut offset = 10;
+    loop {
+        let flags = read_u16(glyph, offset)?;
+        components.push((offset + 2, read_u16(glyph, offset + 2)?));
+        offset += match flags & ARG_1_AND_2_ARE_WORDS {
+            0 => 6,
+            _ => 8,
+        };
+        if flags & WE_HAVE_A_SCALE != 0 {
+            offset += 2;
+        } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+            offset += 4;
+        } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+            offset += 8;
+        }
+        if flags & MORE_COMPONENTS == 0 {
+            return Ok(components);
+        }
+    }
+}
+
+/// A cmap table with a single Windows Unicode subtable, in format 12 so that
+/// characters beyond the Basic Multilingual Plane fit in.
+fn character_map(characters: &BTreeMap<char, u16>, new_ids: &BTreeMap<u16, u16>) -> Vec<u8> {
+    let groups = characters.len() as u32;
+    let mut cmap = vec![];
+    cmap.extend_from_slice(&0u16.to_be_bytes());
+    cmap.extend_from_slice(&1u16.to_be_bytes());
+    cmap.extend_from_slice(&3u16.to_be_bytes());
+    cmap.extend_from_slice(&10u16.to_be_bytes());
+    cmap.extend_from_slice(&12u32.to_be_bytes());
+
+    cmap.extend_from_slice(&12u16.to_be_bytes());
+    cmap.extend_from_slice(&0u16.to_be_bytes());
+    cmap.extend_from_slice(&(16 + 12 * groups).to_be_bytes());
+    cmap.extend_from_slice(&0u32.to_be_bytes());
+    cmap.extend_from_slice(&groups.to_be_bytes());
+    for (character, old_id) in characters {
+        let code = *character as u32;
+        cmap.extend_from_slice(&code.to_be_bytes());
+        cmap.extend_from_slice(&code.to_be_bytes());
+        cmap.extend_from_slice(&(new_ids[old_id] as u32).to_be_bytes());
+    }
+    cmap
+}
+
+/// Lays the tables out in a font file, ordered by tag as the format requires.
+fn write_font(tables: BTreeMap<[u8; 4], Vec<u8>>) -> Result<Vec<u8>, anyhow::Error> {
+    let count = tables.len() as u16;
+    let entry_selector = 15 - count.leading_zeros() as u16;
+    let search_range: u16 = 16 << entry_selector;
+
+    let mut font = vec![];
+    font.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+    font.extend_from_slice(&count.to_be_bytes());
+    font.extend_from_slice(&search_range.to_be_bytes());
+    font.extend_from_slice(&entry_selector.to_be_bytes());
+    font.extend_from_slice(&(count * 16 - search_range).to_be_bytes());
+
+    let mut offset = 12 + 16 * tables.len();
+    let mut head_offset = None;
+    for (tag, data) in &tables {
+        if tag == b"head" {
+            head_offset = Some(offset);
+        }
+        font.extend_from_slice(tag);
+        font.extend_from_slice(&checksum(data).to_be_bytes());
+        font.extend_from_slice(&(offset as u32).to_be_bytes());
+        font.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        offset += data.len().next_multiple_of(4);
+    }
+    for data in tables.values() {
+        font.extend_from_slice(data);
+        font.resize(font.len().next_multiple_of(4), 0);
+    }
+
+    let head_offset = head_offset.ok_or_else(|| anyhow!("Font has no head table"))?;
+    let adjustment = 0xB1B0_AFBAu32.wrapping_sub(checksum(&font));
+    write_u32(&mut font, head_offset + 8, adjustment)?;
+    Ok(font)
+}
+
+/// Sum of the big-endian 32-bit words of a table, padded with zeros.
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4)
+        .map(|chunk| {
+            let mut word = [0; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            u32::from_be_bytes(word)
+        })
+        .fold(0, u32::wrapping_add)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, anyhow::Error> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+        .ok_or_else(|| anyhow!("Font data ends before offset {}", offset + 2))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, anyhow::Error> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or_else(|| anyhow!("Font data ends before offset {}", offset + 4))
+}
+
+fn write_u16(data: &mut [u8], offset: usize, value: u16) -> Result<(), anyhow::Error> {
+    data.get_mut(offset..offset + 2)
+        .ok_or_else(|| anyhow!("Font data ends before offset {}", offset + 2))?
+        .copy_from_slice(&value.to_be_bytes());
+    Ok(())
+}
+
+fn write_u32(data: &mut [u8], offset: usize, value: u32) -> Result<(), anyhow::Error> {
+    data.get_mut(offset..offset + 4)
+        .ok_or_else(|| anyhow!("Font data ends before offset {}", offset + 4))?
+        .copy_from_slice(&value.to_be_bytes());
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FONT: &[u8] = include_bytes!("../assets/fonts/DejaVuSans.ttf");
+
+    #[test]
+    fn subset_keeps_the_glyphs_of_the_text() {
+        let subset = subset_font(FONT, "Añ Ж").unwrap();
+
+        let original = Face::from_slice(FONT, 0).unwrap();
+        let face = Face::from_slice(&subset, 0).unwrap();
+        for character in ['A', 'ñ', ' ', 'Ж'] {
+            let id = face.glyph_index(character).unwrap();
+            let original_id = original.glyph_index(character).unwrap();
+            assert_eq!(face.glyph_hor_advance(id), original.glyph_hor_advance(original_id));
+            assert_eq!(face.glyph_bounding_box(id), original.glyph_bounding_box(original_id));
+        }
+        assert!(face.glyph_index('B').is_none());
+        assert!(subset.len() < FONT.len() / 10);
+    }
+
+    #[test]
+    fn subset_holds_the_components_of_composite_glyphs() {
+        let subset = subset_font(FONT, "ñ").unwrap();
+
+        let face = Face::from_slice(&subset, 0).unwrap();
+        let glyf = face.table_data(Tag::from_bytes(b"glyf")).unwrap();
+        let id = face.glyph_index('ñ').unwrap().0 as usize;
+        let loca = face.table_data(Tag::from_bytes(b"loca")).unwrap();
+        let start = read_u32(loca, 4 * id).unwrap() as usize;
+        let end = read_u32(loca, 4 * id + 4).unwrap() as usize;
+        let components = components(&glyf[start..end]).unwrap();
+        assert!(!components.is_empty());
+        assert!(components.iter().all(|(_, component)| *component < face.number_of_glyphs()));
+    }
+
+    #[test]
+    fn subset_checksums_add_up() {
+        let subset = subset_font(FONT, "Ticket").unwrap();
+
+        assert_eq!(checksum(&subset), 0xB1B0_AFBA);
+    }
+}
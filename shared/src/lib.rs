@@ -2,4 +2,12 @@ pub mod db;
 pub mod configuration;
 pub mod domain;
 pub mod email_client;
-pub mod qr_client;
\ No newline at end of file
+pub mod qr_client;
+pub mod ticket;
+pub mod ticket_pdf;
+pub mod font_subset;
+pub mod qr_render;
+pub mod qr_cache;
+pub mod throttle;
+pub mod blocking;
+
@@ -0,0 +1,293 @@
+//! Cache of rendered QR codes and tickets, keyed by their content and render options.
+//!
+//! The most recently used files stay in memory, up to `cache_memory_bytes`.
+//! With a directory configured, every file is also kept on disk, up to
+//! `cache_directory_bytes`, so renders survive restarts and the entries pushed
+//! out of memory are read back instead of rendered again.
+
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use lru::LruCache;
+use sha2::{Digest, Sha256};
+use tokio::fs;
+use crate::configuration::QrRenderSettings;
+use crate::qr_render::{QrRenderOptions, RenderedQrCode};
+
+pub struct QrCache {
+    memory: Option<Mutex<SizedLru<String, Vec<u8>>>>,
+    directory: Option<PathBuf>,
+    /// Files kept in the directory, by size.
+    files: Mutex<SizedLru<PathBuf, ()>>,
+}
+
+/// Least recently used entries, evicted once their sizes add up to more than
+/// the capacity.
+struct SizedLru<K: Hash + Eq, V> {
+    entries: LruCache<K, (V, u64)>,
+    bytes: u64,
+    capacity: u64,
+}
+
+impl<K: Hash + Eq, V> SizedLru<K, V> {
+    fn new(capacity: u64) -> Self {
+        SizedLru { entries: LruCache::unbounded(), bytes: 0, capacity }
+    }
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.entries.get(key).map(|(value, _)| value)
+    }
+
+    /// Adds an entry, returning the keys evicted to make room for it.
+    fn insert(&mut self, key: K, value: V, size: u64) -> Vec<K> {
+        if let Some((_, previous)) = self.entries.put(key, (value, size)) {
+            self.bytes -= previous;
+        }
+        self.bytes += size;
+        let mut evicted = vec![];
+        while self.bytes > self.capacity {
+            let Some((key, (_, size))) = self.entries.pop_lru() else {
+                break;
+            };
+            self.bytes -= size;
+            evicted.push(key);
+        }
+        evicted
+    }
+
+    fn remove(&mut self, key: &K) {
+        if let Some((_, size)) = self.entries.pop(key) {
+            self.bytes -= size;
+        }
+    }
+}
+
+impl QrCache {
+    /// Opens the cache, taking over the files already in its directory.
+    pub fn new(settings: &QrRenderSettings) -> Self {
+        let directory = settings.cache_directory.as_ref().map(PathBuf::from);
+        let mut files = SizedLru::new(settings.cache_directory_bytes);
+        let mut evicted = vec![];
+        if let Some(directory) = &directory {
+            for (path, size) in cached_files(directory) {
+                evicted.extend(files.insert(path, (), size));
+            }
+        }
+        for path in evicted {
+            let _ = std::fs::remove_file(path);
+        }
+        QrCache {
+            memory: (settings.cache_memory_bytes > 0).then(|| Mutex::new(SizedLru::new(settings.cache_memory_bytes))),
+            directory,
+            files: Mutex::new(files),
+        }
+    }
+
+    /// Key of the QR code of `content` rendered with `options`.
+    pub fn key(content: &[u8], options: &QrRenderOptions) -> String {
+        let options = serde_json::to_vec(options).expect("Failed to serialize QR code options");
+        let mut hasher = Sha256::new();
+        hasher.update((content.len() as u64).to_be_bytes());
+        hasher.update(content);
+        hasher.update(options);
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// The cached QR code, from memory or else from disk.
+    pub async fn get(&self, key: &str, options: &QrRenderOptions) -> Option<RenderedQrCode> {
+        let bytes = self.get_file(key, options.format.extension()).await?;
+        Some(RenderedQrCode { format: options.format, bytes })
+    }
+
+    /// Caches a QR code. Failing to write it to disk only costs a render later on.
+    pub async fn put(&self, key: &str, options: &QrRenderOptions, qr_code: &RenderedQrCode) {
+        self.put_file(key, options.format.extension(), &qr_code.bytes).await
+    }
+
+    /// The cached file `key.extension`, from memory or else from disk.
+    pub async fn get_file(&self, key: &str, extension: &str) -> Option<Vec<u8>> {
+        let name = format!("{}.{}", key, extension);
+        if let Some(memory) = &self.memory {
+            if let Some(bytes) = memory.lock().expect("QR cache lock is poisoned").get(&name) {
+                return Some(bytes.clone());
+            }
+        }
+        let path = self.path(key, &name)?;
+        let bytes = match fs::read(&path).await {
+            Ok(bytes) => bytes,
+            Err(_) => {
+                self.files.lock().expect("QR cache lock is poisoned").remove(&path);
+                return None;
+            }
+        };
+        // Read files are the last used ones.
+        self.files.lock().expect("QR cache lock is poisoned").get(&path);
+        self.remember(name, &bytes);
+        Some(bytes)
+    }
+
+    /// Caches a file as `key.extension`. Failing to write it to disk only
+    /// costs a render later on.
+    pub async fn put_file(&self, key: &str, extension: &str, bytes: &[u8]) {
+        let name = format!("{}.{}", key, extension);
+        self.remember(name.clone(), bytes);
+        let Some(path) = self.path(key, &name) else {
+            return;
+        };
+        // Written aside and renamed, so that a reader never gets half a file.
+        let partial = path.with_extension(format!("{}.part", uuid::Uuid::new_v4()));
+        let written = async {
+            if let Some(directory) = path.parent() {
+                fs::create_dir_all(directory).await?;
+            }
+            fs::write(&partial, bytes).await?;
+            fs::rename(&partial, &path).await
+        };
+        if let Err(e) = written.await {
+            tracing::warn!("Failed to cache {} in {}: {}", name, path.display(), e);
+            let _ = fs::remove_file(&partial).await;
+            return;
+        }
+
+        let evicted = self.files.lock().expect("QR cache lock is poisoned")
+            .insert(path, (), bytes.len() as u64);
+        for path in evicted {
+            // Another instance on the same directory may have dropped it already.
+            let _ = fs::remove_file(path).await;
+        }
+    }
+
+    fn remember(&self, name: String, bytes: &[u8]) {
+        if let Some(memory) = &self.memory {
+            let mut memory = memory.lock().expect("QR cache lock is poisoned");
+            // A file larger than the whole cache would only evict everything else.
+            if bytes.len() as u64 <= memory.capacity {
+                memory.insert(name, bytes.to_vec(), bytes.len() as u64);
+            }
+        }
+    }
+
+    fn path(&self, key: &str, name: &str) -> Option<PathBuf> {
+        // Spread over subdirectories, so that none holds too many files.
+        self.directory.as_ref()
+            .map(|directory| directory.join(&key[..2]).join(name))
+    }
+}
+
+/// Files already cached in `directory`, least recently modified first.
+fn cached_files(directory: &Path) -> Vec<(PathBuf, u64)> {
+    let mut files = vec![];
+    let subdirectories = std::fs::read_dir(directory).into_iter().flatten().flatten();
+    for entry in subdirectories.flat_map(|subdirectory| std::fs::read_dir(subdirectory.path()).into_iter().flatten().flatten()) {
+        let path = entry.path();
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        // Files still being written aren't cached yet.
+        let partial = path.extension().is_some_and(|extension| extension == "part");
+        if metadata.is_file() && !partial {
+            files.push((metadata.modified().ok(), path, metadata.len()));
+        }
+    }
+    files.sort();
+    files.into_iter().map(|(_, path, size)| (path, size)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qr_render::QrFormat;
+
+    fn settings(cache_memory_bytes: u64, cache_directory: Option<String>) -> QrRenderSettings {
+        QrRenderSettings { cache_memory_bytes, cache_directory, ..QrRenderSettings::default() }
+    }
+
+    fn qr_code(bytes: &[u8]) -> RenderedQrCode {
+        RenderedQrCode { format: QrFormat::Png, bytes: bytes.to_vec() }
+    }
+
+    #[test]
+    fn keys_differ_by_content_and_options() {
+        let options = QrRenderOptions::default();
+        let svg = QrRenderOptions { format: QrFormat::Svg, ..QrRenderOptions::default() };
+
+        assert_eq!(QrCache::key(b"https://short.url/a", &options), QrCache::key(b"https://short.url/a", &options));
+        assert_ne!(QrCache::key(b"https://short.url/a", &options), QrCache::key(b"https://short.url/b", &options));
+        assert_ne!(QrCache::key(b"https://short.url/a", &options), QrCache::key(b"https://short.url/a", &svg));
+    }
+
+    #[tokio::test]
+    async fn least_recently_used_codes_are_evicted() {
+        let cache = QrCache::new(&settings(2, None));
+        let options = QrRenderOptions::default();
+
+        cache.put("a", &options, &qr_code(b"a")).await;
+        cache.put("b", &options, &qr_code(b"b")).await;
+        cache.get("a", &options).await.unwrap();
+        cache.put("c", &options, &qr_code(b"c")).await;
+
+        assert!(cache.get("a", &options).await.is_some());
+        assert!(cache.get("b", &options).await.is_none());
+        assert!(cache.get("c", &options).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn codes_evicted_from_memory_are_read_back_from_disk() {
+        let directory = std::env::temp_dir().join(format!("qr-cache-{}", uuid::Uuid::new_v4()));
+        let cache = QrCache::new(&settings(12, Some(directory.to_str().unwrap().to_string())));
+        let options = QrRenderOptions::default();
+        let first = QrCache::key(b"first", &options);
+        let second = QrCache::key(b"second", &options);
+
+        cache.put(&first, &options, &qr_code(b"first image")).await;
+        cache.put(&second, &options, &qr_code(b"second image")).await;
+
+        assert_eq!(cache.get(&first, &options).await.unwrap().bytes, b"first image");
+        // Another cache on the same directory, as after a restart.
+        let restarted = QrCache::new(&settings(12, Some(directory.to_str().unwrap().to_string())));
+        assert_eq!(restarted.get(&second, &options).await.unwrap().bytes, b"second image");
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn memory_is_bounded_by_bytes() {
+        let cache = QrCache::new(&settings(10, None));
+        let options = QrRenderOptions::default();
+
+        cache.put("a", &options, &qr_code(b"aaaa")).await;
+        cache.put("b", &options, &qr_code(b"bbbb")).await;
+        cache.put("c", &options, &qr_code(b"cccccc")).await;
+        // Larger than the whole cache, so not kept at all.
+        cache.put("d", &options, &qr_code(b"ddddddddddd")).await;
+
+        assert!(cache.get("a", &options).await.is_none());
+        assert!(cache.get("b", &options).await.is_some());
+        assert!(cache.get("c", &options).await.is_some());
+        assert!(cache.get("d", &options).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn directory_is_bounded_by_bytes() {
+        let directory = std::env::temp_dir().join(format!("qr-cache-{}", uuid::Uuid::new_v4()));
+        let cache = QrCache::new(&QrRenderSettings {
+            cache_memory_bytes: 0,
+            cache_directory: Some(directory.to_str().unwrap().to_string()),
+            cache_directory_bytes: 25,
+            ..QrRenderSettings::default()
+        });
+        let keys: Vec<String> = ["first", "second", "third"].iter()
+            .map(|content| QrCache::key(content.as_bytes(), &QrRenderOptions::default()))
+            .collect();
+
+        cache.put_file(&keys[0], "pdf", b"0123456789").await;
+        cache.put_file(&keys[1], "pdf", b"0123456789").await;
+        cache.get_file(&keys[0], "pdf").await.unwrap();
+        cache.put_file(&keys[2], "pdf", b"0123456789").await;
+
+        assert!(cache.get_file(&keys[0], "pdf").await.is_some());
+        assert!(cache.get_file(&keys[1], "pdf").await.is_none());
+        assert!(!directory.join(&keys[1][..2]).join(format!("{}.pdf", keys[1])).exists());
+        assert!(cache.get_file(&keys[2], "pdf").await.is_some());
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -1,4 +1,3 @@
-use std::io::Cursor;
 use std::path::Path;
 use reqwest::{Client};
 use secrecy::{ExposeSecret, Secret};
@@ -6,9 +5,17 @@ use serde::Deserialize;
 use qrcode::QrCode;
 use tokio::fs;
 use uuid::Uuid;
-use base64::encode;
-use image::{Luma, DynamicImage};
+use image::Luma;
 use url::Url;
+use chrono::{NaiveDateTime, Utc};
+use crate::blocking::BlockingPool;
+use crate::configuration::{ProviderLimitSettings, QrRenderSettings};
+use crate::qr_cache::QrCache;
+use crate::qr_render::{render_qr_code, QrRenderOptions, RenderedQrCode};
+use crate::throttle::Throttle;
+use crate::ticket::{PublicTicketKey, TicketSigner};
+use crate::ticket_pdf::{render_ticket_pdf, render_ticket_sheet_pdf, PrintableTicket};
+use sha2::{Digest, Sha256};
 
 pub struct QRClient {
     http_client: Client,
@@ -16,6 +23,11 @@ pub struct QRClient {
     api_key: Secret<String>,
     base_url: Url,
     base_image_path: String,
+    ticket_signer: Option<TicketSigner>,
+    retired_ticket_keys: Vec<PublicTicketKey>,
+    throttle: Throttle,
+    render_pool: BlockingPool,
+    cache: QrCache,
 }
 
 #[derive(Deserialize, Debug)]
@@ -42,19 +54,81 @@ impl QRClient {
             api_key,
             base_url,
             base_image_path,
+            ticket_signer: None,
+            retired_ticket_keys: vec![],
+            throttle: Throttle::new(&ProviderLimitSettings::default()),
+            render_pool: BlockingPool::default(),
+            cache: QrCache::new(&QrRenderSettings::default()),
         }
     }
 
+    /// Renders QR codes on a pool of `max_concurrent_renders` blocking threads
+    /// and caches them as configured, the defaults of `QrRenderSettings` otherwise.
+    pub fn with_rendering(mut self, settings: &QrRenderSettings) -> Self {
+        self.render_pool = BlockingPool::new(settings.max_concurrent_renders);
+        self.cache = QrCache::new(settings);
+        self
+    }
+
+    /// Limits the calls to the URL shortener, the defaults of `ProviderLimitSettings` otherwise.
+    pub fn with_limits(mut self, limits: &ProviderLimitSettings) -> Self {
+        self.throttle = Throttle::new(limits);
+        self
+    }
+
+    /// Number of requests sent to the URL shortener at the same time, at most.
+    pub fn max_concurrent_requests(&self) -> usize {
+        self.throttle.max_concurrent_requests()
+    }
+
+    /// The pool QR codes and tickets are rendered on, for other CPU-bound
+    /// work that should wait its turn along with them.
+    pub fn render_pool(&self) -> &BlockingPool {
+        &self.render_pool
+    }
+
+    /// Signs tickets with `signer`, while tickets signed with the `retired`
+    /// keys are still accepted by scanners.
+    pub fn with_ticket_keys(mut self, signer: TicketSigner, retired: Vec<PublicTicketKey>) -> Self {
+        self.ticket_signer = Some(signer);
+        self.retired_ticket_keys = retired;
+        self
+    }
+
+    /// Public keys scanners need to verify tickets, the current one first.
+    pub fn public_ticket_keys(&self) -> Vec<PublicTicketKey> {
+        self.ticket_signer.iter()
+            .map(|signer| signer.public_key())
+            .chain(self.retired_ticket_keys.iter().cloned())
+            .collect()
+    }
+
+    /// Signs a ticket for an invitation, valid between `not_before` and `not_after`.
+    pub fn sign_ticket(
+        &self,
+        invitation_id: Uuid,
+        appointment_id: Uuid,
+        not_before: NaiveDateTime,
+        not_after: NaiveDateTime
+    ) -> Result<String, anyhow::Error> {
+        let signer = self.ticket_signer.as_ref()
+            .ok_or_else(|| anyhow::anyhow!("No ticket signing key is configured."))?;
+        Ok(signer.sign(invitation_id, appointment_id, not_before, not_after))
+    }
+
     pub async fn get_short_url(&self, token: String) -> Result<ShortUrlResponse, anyhow::Error> {
-        let response = self.http_client.post(self.api_url.as_ref())
-            .header("apikey", self.api_key.expose_secret())
-            .body(format!("{}/{}", self.base_url, token))
-            .send()
+        let response = self.throttle
+            .send(|| {
+                self.http_client.post(self.api_url.as_ref())
+                    .header("apikey", self.api_key.expose_secret())
+                    .body(format!("{}/{}", self.base_url, token))
+            })
             .await
             .map_err(|e| { println!("{}", e); anyhow::anyhow!(e) })
             ?;
 
-        let result: ShortUrlResponse = response.json().await?;
+        // Still `429 Too Many Requests` once the retries are used up.
+        let result: ShortUrlResponse = response.error_for_status()?.json().await?;
         println!("{:?}", result);
 
         Ok(result)
@@ -73,33 +147,120 @@ impl QRClient {
         // Asynchronously ensure the directory exists
         fs::create_dir_all(path.parent().unwrap()).await?;
 
-        let code = QrCode::new(short_url.as_str().as_bytes())?;
-        let image = code.render::<Luma<u8>>().build();
-
-        image.save(&path)?;
+        let path = self.render_pool.run(move || -> Result<_, anyhow::Error> {
+            let code = QrCode::new(short_url.as_str().as_bytes())?;
+            let image = code.render::<Luma<u8>>().build();
+            image.save(&path)?;
+            Ok(path)
+        }).await??;
 
         Ok(path.to_str().unwrap().to_string())
     }
 
 
+    /// Saves tickets as A4 sheets of `per_page` tickets next to the QR codes
+    /// of the appointment, returning the path of the PDF.
+    pub async fn save_ticket_sheet_pdf(
+        &self,
+        appt_id: Uuid,
+        tickets: &[PrintableTicket],
+        per_page: usize,
+    ) -> Result<String, anyhow::Error> {
+        let filename = format!("tickets-{}.pdf", Utc::now().format("%Y%m%d%H%M%S"));
+        let path_str = format!("{}/{}", self.base_image_path, appt_id);
+        let path = Path::new(&path_str).join(filename);
+
+        fs::create_dir_all(path.parent().unwrap()).await?;
+        let tickets = tickets.to_vec();
+        let pdf = self.render_pool.run(move || render_ticket_sheet_pdf(&tickets, per_page)).await??;
+        fs::write(&path, pdf).await?;
+
+        Ok(path.to_str().unwrap().to_string())
+    }
+
     pub async fn generate_qr_code_base64(
         &self,
         short_url: Url,
     ) -> Result<String, anyhow::Error> {
+        Ok(self.render_cached(short_url.as_str().as_bytes(), &QrRenderOptions::default()).await?.to_base64())
+    }
 
-        let code = QrCode::new(short_url.as_str().as_bytes()).unwrap();
-        let image = code.render::<Luma<u8>>().build();
+    /// Renders the QR code of a short URL with the given options.
+    pub async fn render_qr_code(
+        &self,
+        short_url: &Url,
+        options: &QrRenderOptions,
+    ) -> Result<RenderedQrCode, anyhow::Error> {
+        self.render_cached(short_url.as_str().as_bytes(), options).await
+    }
 
-        // Convert the image into a byte vector using a Cursor
-        let mut buffer = Cursor::new(Vec::new());
-        let dynamic_image = DynamicImage::ImageLuma8(image);
-        dynamic_image.write_to(&mut buffer, image::ImageOutputFormat::Png)?;
+    /// Encodes a signed ticket, as made by `sign_ticket`, into a QR code.
+    pub async fn generate_ticket_qr_code_base64(
+        &self,
+        ticket: &str,
+    ) -> Result<String, anyhow::Error> {
+        // Every ticket is signed anew, so it wouldn't be found in the cache.
+        let content = ticket.as_bytes().to_vec();
+        let qr_code = self.render_pool
+            .run(move || render_qr_code(&content, &QrRenderOptions::default()))
+            .await??;
+        Ok(qr_code.to_base64())
+    }
+
+    /// Renders the PDF ticket of an invitation, unless it is cached.
+    pub async fn render_ticket_pdf(
+        &self,
+        ticket: &PrintableTicket,
+    ) -> Result<Vec<u8>, anyhow::Error> {
+        let key = ticket_key(ticket);
+        if let Some(pdf) = self.cache.get_file(&key, "pdf").await {
+            return Ok(pdf);
+        }
 
-        // Encode the byte vector into a Base64 string
-        let base64_image = encode(buffer.into_inner());
+        let render_ticket = ticket.clone();
+        let pdf = self.render_pool
+            .run(move || render_ticket_pdf(&render_ticket))
+            .await??;
+        self.cache.put_file(&key, "pdf", &pdf).await;
 
-        Ok(base64_image)
+        Ok(pdf)
     }
+
+    // Renders content into a QR code on the render pool, unless it is cached.
+    async fn render_cached(
+        &self,
+        content: &[u8],
+        options: &QrRenderOptions,
+    ) -> Result<RenderedQrCode, anyhow::Error> {
+        let key = QrCache::key(content, options);
+        if let Some(qr_code) = self.cache.get(&key, options).await {
+            return Ok(qr_code);
+        }
+
+        let (content, render_options) = (content.to_vec(), options.clone());
+        let qr_code = self.render_pool
+            .run(move || render_qr_code(&content, &render_options))
+            .await??;
+        self.cache.put(&key, options, &qr_code).await;
+
+        Ok(qr_code)
+    }
+}
+
+// Key of a ticket in the cache. The version is part of it, as the layout of
+// tickets may change between releases while the cache outlives them.
+fn ticket_key(ticket: &PrintableTicket) -> String {
+    let printed = serde_json::to_vec(&(
+        env!("CARGO_PKG_VERSION"),
+        ticket.invitation_id,
+        &ticket.title,
+        ticket.date,
+        &ticket.location,
+        &ticket.attendee_name,
+        ticket.max_admissions,
+        &ticket.qr_content,
+    )).expect("Failed to serialize ticket");
+    format!("{:x}", Sha256::digest(printed))
 }
 
 #[cfg(test)]
@@ -148,6 +309,55 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_render_qr_code_is_cached() {
+        let directory = std::env::temp_dir().join(format!("qr-cache-{}", Uuid::new_v4()));
+        let qr_client = get_qr_client().with_rendering(&QrRenderSettings {
+            max_concurrent_renders: 2,
+            cache_directory: Some(directory.to_str().unwrap().to_string()),
+            ..QrRenderSettings::default()
+        });
+        let short_url = Url::parse("https://short.url/abc").unwrap();
+        let options = QrRenderOptions::default();
+
+        let rendered = qr_client.render_qr_code(&short_url, &options).await.unwrap();
+        let cached = qr_client.render_qr_code(&short_url, &options).await.unwrap();
+
+        assert_eq!(rendered.bytes, cached.bytes);
+        assert_eq!(rendered.bytes, render_qr_code(short_url.as_str().as_bytes(), &options).unwrap().bytes);
+        let key = QrCache::key(short_url.as_str().as_bytes(), &options);
+        assert!(directory.join(&key[..2]).join(format!("{}.png", key)).exists());
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ticket_pdf_is_cached() {
+        let directory = std::env::temp_dir().join(format!("qr-cache-{}", Uuid::new_v4()));
+        let qr_client = get_qr_client().with_rendering(&QrRenderSettings {
+            cache_directory: Some(directory.to_str().unwrap().to_string()),
+            ..QrRenderSettings::default()
+        });
+        let ticket = PrintableTicket {
+            invitation_id: Uuid::new_v4(),
+            title: "Conference".to_string(),
+            date: chrono::NaiveDate::from_ymd_opt(2024, 10, 10).unwrap().and_hms_opt(10, 10, 0).unwrap(),
+            location: "123 Fake St.".to_string(),
+            attendee_name: Some("Ada".to_string()),
+            max_admissions: 1,
+            qr_content: "https://short.url/abc".to_string(),
+        };
+
+        let rendered = qr_client.render_ticket_pdf(&ticket).await.unwrap();
+        let cached = qr_client.render_ticket_pdf(&ticket).await.unwrap();
+
+        assert_eq!(rendered, cached);
+        let key = ticket_key(&ticket);
+        assert!(directory.join(&key[..2]).join(format!("{}.pdf", key)).exists());
+        let renamed = PrintableTicket { attendee_name: Some("Grace".to_string()), ..ticket.clone() };
+        assert_ne!(ticket_key(&renamed), key);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
     #[tokio::test]
     async fn test_get_short_url() {
         // Setup the mock server
@@ -0,0 +1,489 @@
+//! Rendering of QR codes as PNG, JPEG or SVG images, with configurable
+//! module size, quiet zone, error correction, colors and a centered logo.
+//!
+//! The defaults match the plain black on white PNG the `qrcode` crate renders.
+
+use std::fmt;
+use std::io::Cursor;
+use std::str::FromStr;
+use anyhow::anyhow;
+use image::{DynamicImage, ImageFormat, ImageOutputFormat, Rgba, RgbaImage};
+use image::imageops::FilterType;
+use qrcode::{EcLevel, QrCode};
+use serde::{Deserialize, Serialize};
+
+/// Largest module, in pixels.
+pub const MAX_MODULE_SIZE: u32 = 40;
+/// Widest quiet zone, in modules.
+pub const MAX_QUIET_ZONE: u32 = 16;
+/// Smallest and largest image width, in pixels.
+pub const MIN_IMAGE_SIZE: u32 = 32;
+pub const MAX_IMAGE_SIZE: u32 = 2048;
+/// Largest logo, in bytes once decoded from base64.
+pub const MAX_LOGO_BYTES: usize = 256 * 1024;
+
+/// Share of the code width covered by the logo and its padding. High error
+/// correction restores up to 30% of the code, the logo hides about 5% of it.
+const LOGO_RATIO: f64 = 0.22;
+/// Contrast scanners need between the modules and the background.
+const MIN_CONTRAST: f64 = 3.0;
+
+/// Image format of a rendered QR code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QrFormat {
+    #[default]
+    Png,
+    Svg,
+    #[serde(alias = "jpg")]
+    Jpeg,
+}
+
+impl QrFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            QrFormat::Png => "image/png",
+            QrFormat::Svg => "image/svg+xml",
+            QrFormat::Jpeg => "image/jpeg",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            QrFormat::Png => "png",
+            QrFormat::Svg => "svg",
+            QrFormat::Jpeg => "jpg",
+        }
+    }
+}
+
+impl FromStr for QrFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "png" => Ok(QrFormat::Png),
+            "svg" => Ok(QrFormat::Svg),
+            "jpeg" | "jpg" => Ok(QrFormat::Jpeg),
+            other => Err(format!("{} is not a supported format. Use `png`, `svg` or `jpeg`.", other)),
+        }
+    }
+}
+
+impl fmt::Display for QrFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+/// Share of the code that can be damaged and still be read:
+/// about 7%, 15%, 25% and 30%.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum QrErrorCorrection {
+    L,
+    #[default]
+    M,
+    Q,
+    H,
+}
+
+impl From<QrErrorCorrection> for EcLevel {
+    fn from(level: QrErrorCorrection) -> Self {
+        match level {
+            QrErrorCorrection::L => EcLevel::L,
+            QrErrorCorrection::M => EcLevel::M,
+            QrErrorCorrection::Q => EcLevel::Q,
+            QrErrorCorrection::H => EcLevel::H,
+        }
+    }
+}
+
+/// How QR codes are rendered. Missing fields take their defaults.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QrRenderOptions {
+    pub format: QrFormat,
+    /// Width of a module, in pixels.
+    pub module_size: u32,
+    /// Width of the blank border around the code, in modules.
+    pub quiet_zone: u32,
+    pub error_correction: QrErrorCorrection,
+    /// Colors as `#RRGGBB`, the foreground darker than the background.
+    pub foreground: String,
+    pub background: String,
+    /// Width of the image in pixels, overriding `module_size`. Raster images
+    /// are made of whole pixel modules, so they may come out a little smaller.
+    pub size: Option<u32>,
+    /// Base64 encoded PNG or JPEG drawn in the center of the code.
+    /// A logo forces high error correction.
+    pub logo: Option<String>,
+}
+
+impl Default for QrRenderOptions {
+    fn default() -> Self {
+        Self {
+            format: QrFormat::Png,
+            module_size: 8,
+            quiet_zone: 4,
+            error_correction: QrErrorCorrection::M,
+            foreground: "#000000".to_string(),
+            background: "#FFFFFF".to_string(),
+            size: None,
+            logo: None,
+        }
+    }
+}
+
+impl QrRenderOptions {
+    /// Checks that codes rendered with these options can be scanned and
+    /// that the images stay reasonably small.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=MAX_MODULE_SIZE).contains(&self.module_size) {
+            return Err(format!("module_size must be between 1 and {} pixels.", MAX_MODULE_SIZE));
+        }
+        if self.quiet_zone > MAX_QUIET_ZONE {
+            return Err(format!("quiet_zone must be at most {} modules.", MAX_QUIET_ZONE));
+        }
+        if self.size.is_some_and(|size| !(MIN_IMAGE_SIZE..=MAX_IMAGE_SIZE).contains(&size)) {
+            return Err(format!("size must be between {} and {} pixels.", MIN_IMAGE_SIZE, MAX_IMAGE_SIZE));
+        }
+        let foreground = parse_color(&self.foreground).map_err(|e| format!("foreground {}", e))?;
+        let background = parse_color(&self.background).map_err(|e| format!("background {}", e))?;
+        if contrast(foreground, background) < MIN_CONTRAST {
+            return Err("foreground must be clearly darker than background.".to_string());
+        }
+        if let Some(logo) = &self.logo {
+            decode_logo(logo)?;
+        }
+        Ok(())
+    }
+
+    /// The error correction codes are rendered with.
+    pub fn effective_error_correction(&self) -> QrErrorCorrection {
+        match self.logo {
+            Some(_) => QrErrorCorrection::H,
+            None => self.error_correction,
+        }
+    }
+
+    /// The same options in a format email clients display, which leaves out SVG.
+    pub fn for_email(&self) -> Self {
+        let mut options = self.clone();
+        if options.format == QrFormat::Svg {
+            options.format = QrFormat::Png;
+        }
+        options
+    }
+}
+
+/// A QR code image.
+#[derive(Debug, Clone)]
+pub struct RenderedQrCode {
+    pub format: QrFormat,
+    pub bytes: Vec<u8>,
+}
+
+impl RenderedQrCode {
+    pub fn content_type(&self) -> &'static str {
+        self.format.content_type()
+    }
+
+    pub fn to_base64(&self) -> String {
+        base64::encode(&self.bytes)
+    }
+}
+
+/// Renders content into a QR code image. Fails when the options are invalid
+/// or the content doesn't fit a QR code.
+pub fn render_qr_code(content: &[u8], options: &QrRenderOptions) -> Result<RenderedQrCode, anyhow::Error> {
+    options.validate().map_err(|e| anyhow!("Invalid QR code options: {}", e))?;
+
+    let code = QrCode::with_error_correction_level(content, options.effective_error_correction().into())?;
+    let foreground = parse_color(&options.foreground).map_err(|e| anyhow!(e))?;
+    let background = parse_color(&options.background).map_err(|e| anyhow!(e))?;
+    let logo = options.logo.as_deref().map(decode_logo).transpose().map_err(|e| anyhow!(e))?;
+
+    let bytes = match options.format {
+        QrFormat::Svg => render_svg(&code, options, foreground, background, logo.as_ref()).into_bytes(),
+        format => render_raster(&code, options, format, foreground, background, logo.as_ref())?,
+    };
+    Ok(RenderedQrCode { format: options.format, bytes })
+}
+
+struct Logo {
+    bytes: Vec<u8>,
+    format: ImageFormat,
+}
+
+fn decode_logo(logo: &str) -> Result<Logo, String> {
+    // Data URLs are accepted as well, since that is how logos are usually copied around.
+    let encoded = logo.split_once("base64,").map_or(logo, |(_, data)| data);
+    let bytes = base64::decode(encoded.trim()).map_err(|_| "logo must be base64 encoded.".to_string())?;
+    if bytes.len() > MAX_LOGO_BYTES {
+        return Err(format!("logo must be at most {} KiB.", MAX_LOGO_BYTES / 1024));
+    }
+    match image::guess_format(&bytes) {
+        Ok(format @ (ImageFormat::Png | ImageFormat::Jpeg)) => {
+            image::load_from_memory_with_format(&bytes, format)
+                .map_err(|e| format!("logo can't be decoded: {}.", e))?;
+            Ok(Logo { bytes, format })
+        }
+        _ => Err("logo must be a PNG or JPEG image.".to_string()),
+    }
+}
+
+// Width of the code with its quiet zone and the size of a module, in pixels.
+fn layout(code: &QrCode, options: &QrRenderOptions) -> (u32, u32) {
+    let modules = code.width() as u32 + 2 * options.quiet_zone;
+    let module_size = options.size.map_or(options.module_size, |size| size / modules)
+        .min(MAX_IMAGE_SIZE / modules)
+        .max(1);
+    (modules, module_size)
+}
+
+fn render_raster(
+    code: &QrCode,
+    options: &QrRenderOptions,
+    format: QrFormat,
+    foreground: [u8; 3],
+    background: [u8; 3],
+    logo: Option<&Logo>
+) -> Result<Vec<u8>, anyhow::Error> {
+    let (modules, module_size) = layout(code, options);
+    let width = code.width();
+    let colors = code.to_colors();
+    let [r, g, b] = background;
+    let mut canvas = RgbaImage::from_pixel(modules * module_size, modules * module_size, Rgba([r, g, b, 255]));
+    let [r, g, b] = foreground;
+    let dark = Rgba([r, g, b, 255]);
+
+    for (index, color) in colors.iter().enumerate() {
+        if *color != qrcode::Color::Dark {
+            continue;
+        }
+        let left = (options.quiet_zone + (index % width) as u32) * module_size;
+        let top = (options.quiet_zone + (index / width) as u32) * module_size;
+        for y in top..top + module_size {
+            for x in left..left + module_size {
+                canvas.put_pixel(x, y, dark);
+            }
+        }
+    }
+
+    if let Some(logo) = logo {
+        let code_size = width as u32 * module_size;
+        let pad = (code_size as f64 * LOGO_RATIO) as u32;
+        let origin = options.quiet_zone * module_size + (code_size - pad) / 2;
+        let [r, g, b] = background;
+        for y in origin..origin + pad {
+            for x in origin..origin + pad {
+                canvas.put_pixel(x, y, Rgba([r, g, b, 255]));
+            }
+        }
+        let inner = pad.saturating_sub(2 * module_size).max(1);
+        let image = image::load_from_memory_with_format(&logo.bytes, logo.format)?
+            .resize(inner, inner, FilterType::Lanczos3)
+            .to_rgba8();
+        let x = origin + (pad - image.width()) / 2;
+        let y = origin + (pad - image.height()) / 2;
+        image::imageops::overlay(&mut canvas, &image, x, y);
+    }
+
+    let output = match format {
+        QrFormat::Jpeg => ImageOutputFormat::Jpeg(90),
+        _ => ImageOutputFormat::Png,
+    };
+    let mut buffer = Cursor::new(Vec::new());
+    DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(canvas).to_rgb8()).write_to(&mut buffer, output)?;
+    Ok(buffer.into_inner())
+}
+
+fn render_svg(
+    code: &QrCode,
+    options: &QrRenderOptions,
+    foreground: [u8; 3],
+    background: [u8; 3],
+    logo: Option<&Logo>
+) -> String {
+    let (modules, module_size) = layout(code, options);
+    let image_size = options.size.unwrap_or(modules * module_size);
+    let width = code.width();
+    let colors = code.to_colors();
+
+    // The view box counts modules, so the code scales to any size without blur.
+    let mut path = String::new();
+    for row in 0..width {
+        let mut column = 0;
+        while column < width {
+            if colors[row * width + column] != qrcode::Color::Dark {
+                column += 1;
+                continue;
+            }
+            let start = column;
+            while column < width && colors[row * width + column] == qrcode::Color::Dark {
+                column += 1;
+            }
+            path.push_str(&format!(
+                "M{} {}h{}v1h-{}z",
+                options.quiet_zone as usize + start,
+                options.quiet_zone as usize + row,
+                column - start,
+                column - start
+            ));
+        }
+    }
+
+    let mut svg = format!(
+        concat!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" version="1.1" width="{size}" height="{size}" viewBox="0 0 {modules} {modules}" shape-rendering="crispEdges">"#,
+            r#"<rect width="{modules}" height="{modules}" fill="{background}"/>"#,
+            r#"<path d="{path}" fill="{foreground}"/>"#
+        ),
+        size = image_size,
+        modules = modules,
+        background = hex(background),
+        foreground = hex(foreground),
+        path = path
+    );
+
+    if let Some(logo) = logo {
+        let pad = width as f64 * LOGO_RATIO;
+        let origin = options.quiet_zone as f64 + (width as f64 - pad) / 2.0;
+        let mime = match logo.format {
+            ImageFormat::Jpeg => "image/jpeg",
+            _ => "image/png",
+        };
+        svg.push_str(&format!(
+            r#"<rect x="{x:.2}" y="{x:.2}" width="{pad:.2}" height="{pad:.2}" fill="{background}"/><image x="{ix:.2}" y="{ix:.2}" width="{inner:.2}" height="{inner:.2}" href="data:{mime};base64,{data}"/>"#,
+            x = origin,
+            pad = pad,
+            background = hex(background),
+            ix = origin + 1.0,
+            inner = (pad - 2.0).max(1.0),
+            mime = mime,
+            data = base64::encode(&logo.bytes)
+        ));
+    }
+
+    svg.push_str("</svg>");
+    svg
+}
+
+/// Parses a `#RRGGBB` color, the `#` being optional.
+fn parse_color(color: &str) -> Result<[u8; 3], String> {
+    let hex = color.trim().trim_start_matches('#');
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("must be a #RRGGBB color, got {}.", color));
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or_default();
+    Ok([channel(0), channel(2), channel(4)])
+}
+
+fn hex([r, g, b]: [u8; 3]) -> String {
+    format!("#{:02X}{:02X}{:02X}", r, g, b)
+}
+
+// WCAG contrast ratio, below one when the foreground is the lighter color.
+fn contrast(foreground: [u8; 3], background: [u8; 3]) -> f64 {
+    fn luminance(color: [u8; 3]) -> f64 {
+        let [r, g, b] = color.map(|channel| {
+            let c = channel as f64 / 255.0;
+            if c <= 0.03928 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+        });
+        0.2126 * r + 0.7152 * g + 0.0722 * b
+    }
+    (luminance(background) + 0.05) / (luminance(foreground) + 0.05)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use image::GenericImageView;
+    use super::*;
+
+    const CONTENT: &[u8] = b"https://short.url/testhash";
+
+    fn logo() -> String {
+        let mut buffer = Cursor::new(Vec::new());
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(64, 32, Rgba([200, 0, 0, 255])))
+            .write_to(&mut buffer, ImageOutputFormat::Png)
+            .unwrap();
+        base64::encode(buffer.into_inner())
+    }
+
+    #[test]
+    fn default_options_render_a_png() {
+        let rendered = render_qr_code(CONTENT, &QrRenderOptions::default()).unwrap();
+
+        assert_eq!(rendered.content_type(), "image/png");
+        let image = image::load_from_memory(&rendered.bytes).unwrap();
+        let code = QrCode::new(CONTENT).unwrap();
+        assert_eq!(image.width(), (code.width() as u32 + 8) * 8);
+    }
+
+    #[test]
+    fn size_sets_the_module_size() {
+        let options = QrRenderOptions { format: QrFormat::Jpeg, size: Some(300), quiet_zone: 2, ..Default::default() };
+
+        let rendered = render_qr_code(CONTENT, &options).unwrap();
+
+        let image = image::load_from_memory_with_format(&rendered.bytes, ImageFormat::Jpeg).unwrap();
+        assert!(image.width() <= 300 && image.width() > 250);
+    }
+
+    #[test]
+    fn svg_uses_the_colors_and_size() {
+        let options = QrRenderOptions {
+            format: QrFormat::Svg,
+            foreground: "#1a237e".to_string(),
+            background: "fffde7".to_string(),
+            size: Some(512),
+            ..Default::default()
+        };
+
+        let svg = String::from_utf8(render_qr_code(CONTENT, &options).unwrap().bytes).unwrap();
+
+        assert!(svg.contains(r#"width="512""#));
+        assert!(svg.contains(r##"fill="#1A237E""##));
+        assert!(svg.contains(r##"fill="#FFFDE7""##));
+    }
+
+    #[test]
+    fn logo_forces_high_error_correction() {
+        let options = QrRenderOptions { error_correction: QrErrorCorrection::L, logo: Some(logo()), ..Default::default() };
+        assert_eq!(options.effective_error_correction(), QrErrorCorrection::H);
+
+        assert!(render_qr_code(CONTENT, &options).is_ok());
+        let svg = render_qr_code(CONTENT, &QrRenderOptions { format: QrFormat::Svg, ..options }).unwrap();
+        assert!(String::from_utf8(svg.bytes).unwrap().contains("data:image/png;base64,"));
+    }
+
+    #[test]
+    fn unreadable_options_are_rejected() {
+        let invalid = [
+            QrRenderOptions { module_size: 0, ..Default::default() },
+            QrRenderOptions { quiet_zone: MAX_QUIET_ZONE + 1, ..Default::default() },
+            QrRenderOptions { size: Some(MAX_IMAGE_SIZE + 1), ..Default::default() },
+            QrRenderOptions { foreground: "black".to_string(), ..Default::default() },
+            QrRenderOptions { foreground: "#FFFFFF".to_string(), background: "#000000".to_string(), ..Default::default() },
+            QrRenderOptions { foreground: "#AAAAAA".to_string(), ..Default::default() },
+            QrRenderOptions { logo: Some("bm90IGFuIGltYWdl".to_string()), ..Default::default() },
+        ];
+
+        for options in invalid {
+            assert!(options.validate().is_err(), "{:?} should be invalid", options);
+            assert!(render_qr_code(CONTENT, &options).is_err());
+        }
+    }
+
+    #[test]
+    fn options_deserialize_with_defaults() {
+        let options: QrRenderOptions = serde_json::from_str(r#"{"format": "jpg", "error_correction": "Q"}"#).unwrap();
+
+        assert_eq!(options.format, QrFormat::Jpeg);
+        assert_eq!(options.error_correction, QrErrorCorrection::Q);
+        assert_eq!(options.module_size, 8);
+        assert_eq!("SVG".parse::<QrFormat>().unwrap(), QrFormat::Svg);
+    }
+}
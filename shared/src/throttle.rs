@@ -0,0 +1,279 @@
+//! Limits on the calls made to third-party providers.
+//!
+//! Each client owns a [`Throttle`] bounding the requests in flight with a
+//! semaphore and the request rate with a token bucket, and retrying requests
+//! the provider answers with `429 Too Many Requests`.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use reqwest::header::RETRY_AFTER;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use tokio::sync::Semaphore;
+use crate::configuration::ProviderLimitSettings;
+
+/// Longest wait before retrying, whatever `Retry-After` asks for.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// First wait before retrying when the provider doesn't say how long to wait,
+/// doubled on every retry.
+const DEFAULT_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+pub struct Throttle {
+    permits: Semaphore,
+    max_concurrent_requests: usize,
+    bucket: Option<Mutex<TokenBucket>>,
+    max_retries: u32,
+}
+
+impl Throttle {
+    pub fn new(limits: &ProviderLimitSettings) -> Self {
+        let max_concurrent_requests = limits.max_concurrent_requests.max(1);
+        let bucket = (limits.requests_per_second > 0.0)
+            .then(|| Mutex::new(TokenBucket::new(limits.requests_per_second, limits.burst.max(1))));
+        Throttle {
+            permits: Semaphore::new(max_concurrent_requests),
+            max_concurrent_requests,
+            bucket,
+            max_retries: limits.max_retries,
+        }
+    }
+
+    /// Number of requests sent at the same time, at most.
+    pub fn max_concurrent_requests(&self) -> usize {
+        self.max_concurrent_requests
+    }
+
+    /// Sends the request built by `request` once a slot and a token are
+    /// available, retrying it while the provider answers `429 Too Many Requests`.
+    ///
+    /// The response of the last attempt is returned as is, so a `429` comes
+    /// back once the retries are used up.
+    pub async fn send<F>(&self, request: F) -> Result<Response, reqwest::Error>
+        where
+            F: Fn() -> RequestBuilder,
+    {
+        let mut attempt = 0;
+        loop {
+            let response = {
+                let _permit = self.permits.acquire().await.expect("Throttle semaphore is never closed");
+                self.wait_for_token().await;
+                request().send().await?
+            };
+            if response.status() != StatusCode::TOO_MANY_REQUESTS || attempt >= self.max_retries {
+                return Ok(response);
+            }
+            let delay = retry_after(&response, Utc::now())
+                .unwrap_or_else(|| backoff(attempt))
+                .min(MAX_RETRY_DELAY);
+            attempt += 1;
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    async fn wait_for_token(&self) {
+        let Some(bucket) = &self.bucket else {
+            return;
+        };
+        loop {
+            let wait = bucket.lock().expect("Token bucket lock is poisoned").take(Instant::now());
+            match wait {
+                None => return,
+                Some(wait) => tokio::time::sleep(wait).await,
+            }
+        }
+    }
+}
+
+/// Wait before the retry following `attempt`, doubled on every attempt up to
+/// `MAX_RETRY_DELAY`, however many retries are configured.
+fn backoff(attempt: u32) -> Duration {
+    2u32.checked_pow(attempt)
+        .and_then(|factor| DEFAULT_RETRY_DELAY.checked_mul(factor))
+        .map_or(MAX_RETRY_DELAY, |delay| delay.min(MAX_RETRY_DELAY))
+}
+
+/// Refills `rate` tokens per second, up to `capacity`.
+struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: f64, capacity: u32) -> Self {
+        TokenBucket {
+            rate,
+            capacity: capacity as f64,
+            tokens: capacity as f64,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    /// Takes a token, or returns how long to wait for the next one.
+    fn take(&mut self, now: Instant) -> Option<Duration> {
+        let elapsed = now.saturating_duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.refilled_at = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+        }
+    }
+}
+
+/// How long `Retry-After` asks to wait, given in seconds or as an HTTP date.
+fn retry_after(response: &Response, now: DateTime<Utc>) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some((date.with_timezone(&Utc) - now).to_std().unwrap_or(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+    fn limits(max_concurrent_requests: usize, requests_per_second: f64) -> ProviderLimitSettings {
+        ProviderLimitSettings {
+            max_concurrent_requests,
+            requests_per_second,
+            burst: 1,
+            max_retries: 2,
+        }
+    }
+
+    /// Answers slowly, noting when every request came in.
+    struct SlowResponder {
+        arrivals: Arc<Mutex<Vec<Instant>>>,
+        delay: Duration,
+    }
+
+    impl Respond for SlowResponder {
+        fn respond(&self, _: &Request) -> ResponseTemplate {
+            self.arrivals.lock().unwrap().push(Instant::now());
+            ResponseTemplate::new(200).set_delay(self.delay)
+        }
+    }
+
+    #[tokio::test]
+    async fn requests_in_flight_never_exceed_the_limit() {
+        let mock_server = MockServer::start().await;
+        let arrivals = Arc::new(Mutex::new(vec![]));
+        let delay = Duration::from_millis(200);
+        Mock::given(method("GET"))
+            .respond_with(SlowResponder { arrivals: arrivals.clone(), delay })
+            .expect(12)
+            .mount(&mock_server)
+            .await;
+
+        let throttle = Throttle::new(&limits(3, 0.0));
+        let client = reqwest::Client::new();
+        let requests = (0..12).map(|_| throttle.send(|| client.get(mock_server.uri())));
+        for response in futures::future::join_all(requests).await {
+            assert_eq!(response.unwrap().status(), StatusCode::OK);
+        }
+
+        // A request only starts once another one got its response, so no more
+        // than 3 of them can come in within the time it takes to answer one.
+        let mut arrivals = arrivals.lock().unwrap().clone();
+        arrivals.sort();
+        let in_flight = arrivals.iter()
+            .map(|start| arrivals.iter().filter(|other| **other >= *start && **other - *start < delay).count())
+            .max()
+            .unwrap();
+        assert_eq!(in_flight, 3);
+    }
+
+    #[tokio::test]
+    async fn requests_are_spread_by_the_token_bucket() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let throttle = Throttle::new(&limits(10, 20.0));
+        let client = reqwest::Client::new();
+        let started = Instant::now();
+        let requests = (0..5).map(|_| throttle.send(|| client.get(mock_server.uri())));
+        futures::future::join_all(requests).await;
+
+        // One token right away, then one every 50ms.
+        assert!(started.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn too_many_requests_are_retried_after_the_delay_asked_for() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/email"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/email"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let throttle = Throttle::new(&limits(1, 0.0));
+        let client = reqwest::Client::new();
+        let started = Instant::now();
+        let response = throttle.send(|| client.post(format!("{}/email", mock_server.uri()))).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(started.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn too_many_requests_come_back_once_the_retries_are_used_up() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "0"))
+            .expect(3)
+            .mount(&mock_server)
+            .await;
+
+        let throttle = Throttle::new(&limits(1, 0.0));
+        let client = reqwest::Client::new();
+        let response = throttle.send(|| client.post(mock_server.uri())).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_longest_delay() {
+        assert_eq!(backoff(0), Duration::from_secs(1));
+        assert_eq!(backoff(3), Duration::from_secs(8));
+        assert_eq!(backoff(6), MAX_RETRY_DELAY);
+        assert_eq!(backoff(32), MAX_RETRY_DELAY);
+        assert_eq!(backoff(u32::MAX), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn token_bucket_refills_at_its_rate() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket { rate: 2.0, capacity: 2.0, tokens: 2.0, refilled_at: start };
+
+        assert_eq!(bucket.take(start), None);
+        assert_eq!(bucket.take(start), None);
+        assert_eq!(bucket.take(start), Some(Duration::from_millis(500)));
+        assert_eq!(bucket.take(start + Duration::from_millis(500)), None);
+        // Never more than the capacity, however long it stays unused.
+        bucket.take(start + Duration::from_secs(60));
+        bucket.take(start + Duration::from_secs(60));
+        assert!(bucket.take(start + Duration::from_secs(60)).is_some());
+    }
+}
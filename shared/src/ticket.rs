@@ -0,0 +1,244 @@
+//! Signed tickets, verifiable at the door with nothing but a public key.
+//!
+//! A ticket is `TKT1.<claims>.<signature>`, both parts base64url encoded
+//! without padding. The claims name the key that signed them, so that keys
+//! can be rotated while tickets signed with a retired key stay valid.
+
+use std::collections::HashMap;
+use chrono::NaiveDateTime;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Version prefix of the ticket format.
+pub const TICKET_PREFIX: &str = "TKT1";
+
+#[derive(Debug, Error, PartialEq)]
+pub enum TicketError {
+    #[error("The ticket is malformed.")]
+    Malformed,
+    #[error("The ticket was signed with the unknown key `{0}`.")]
+    UnknownKey(String),
+    #[error("The ticket signature is invalid.")]
+    InvalidSignature,
+    #[error("The ticket is not valid before {0}.")]
+    NotYetValid(NaiveDateTime),
+    #[error("The ticket expired at {0}.")]
+    Expired(NaiveDateTime),
+    #[error("The ticket key `{0}` is invalid.")]
+    InvalidKey(String),
+}
+
+/// What a ticket vouches for.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TicketClaims {
+    /// ID of the key the ticket is signed with.
+    pub kid: String,
+    pub invitation_id: Uuid,
+    pub appointment_id: Uuid,
+    pub not_before: NaiveDateTime,
+    pub not_after: NaiveDateTime,
+}
+
+impl TicketClaims {
+    /// Reads the claims of a ticket without checking its signature.
+    ///
+    /// Only meant to tell which invitation a ticket is about, never to admit anyone.
+    pub fn read_unverified(ticket: &str) -> Result<TicketClaims, TicketError> {
+        let (claims, _, _) = split(ticket)?;
+        Ok(claims)
+    }
+}
+
+/// A signed ticket handed out for an invitation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssuedTicket {
+    pub ticket: String,
+    pub claims: TicketClaims,
+    /// Base64 encoded PNG of the QR code holding the ticket.
+    pub qr_code: String,
+}
+
+/// A public key scanners verify tickets with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PublicTicketKey {
+    pub key_id: String,
+    /// Base64 encoded Ed25519 public key.
+    pub public_key: String,
+}
+
+/// The key new tickets are signed with.
+pub struct TicketSigner {
+    key_id: String,
+    signing_key: SigningKey,
+}
+
+impl TicketSigner {
+    /// Builds a signer from a base64 encoded 32 byte Ed25519 seed.
+    pub fn new(key_id: String, seed: &Secret<String>) -> Result<Self, TicketError> {
+        let bytes: [u8; 32] = base64::decode(seed.expose_secret())
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| TicketError::InvalidKey(key_id.clone()))?;
+        Ok(Self { key_id, signing_key: SigningKey::from_bytes(&bytes) })
+    }
+
+    pub fn public_key(&self) -> PublicTicketKey {
+        PublicTicketKey {
+            key_id: self.key_id.clone(),
+            public_key: base64::encode(self.signing_key.verifying_key().to_bytes()),
+        }
+    }
+
+    /// Signs a ticket for an invitation, valid between `not_before` and `not_after`.
+    pub fn sign(
+        &self,
+        invitation_id: Uuid,
+        appointment_id: Uuid,
+        not_before: NaiveDateTime,
+        not_after: NaiveDateTime
+    ) -> String {
+        let claims = TicketClaims {
+            kid: self.key_id.clone(),
+            invitation_id,
+            appointment_id,
+            not_before,
+            not_after,
+        };
+        let claims = base64::encode_config(
+            serde_json::to_vec(&claims).expect("Failed to serialize ticket claims"),
+            base64::URL_SAFE_NO_PAD
+        );
+        let signed = format!("{}.{}", TICKET_PREFIX, claims);
+        let signature = self.signing_key.sign(signed.as_bytes());
+        format!("{}.{}", signed, base64::encode_config(signature.to_bytes(), base64::URL_SAFE_NO_PAD))
+    }
+}
+
+/// Verifies tickets against a set of public keys, current and retired.
+#[derive(Debug, Clone, Default)]
+pub struct TicketVerifier {
+    keys: HashMap<String, VerifyingKey>,
+}
+
+impl TicketVerifier {
+    pub fn new(keys: &[PublicTicketKey]) -> Result<Self, TicketError> {
+        let mut verifier = Self::default();
+        for key in keys {
+            let bytes: [u8; 32] = base64::decode(&key.public_key)
+                .ok()
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or_else(|| TicketError::InvalidKey(key.key_id.clone()))?;
+            let verifying_key = VerifyingKey::from_bytes(&bytes)
+                .map_err(|_| TicketError::InvalidKey(key.key_id.clone()))?;
+            verifier.keys.insert(key.key_id.clone(), verifying_key);
+        }
+        Ok(verifier)
+    }
+
+    /// Checks the signature and the validity window of a ticket at `now`.
+    pub fn verify(&self, ticket: &str, now: NaiveDateTime) -> Result<TicketClaims, TicketError> {
+        let (claims, signed, signature) = split(ticket)?;
+        let key = self.keys.get(&claims.kid)
+            .ok_or_else(|| TicketError::UnknownKey(claims.kid.clone()))?;
+        key.verify_strict(signed.as_bytes(), &signature)
+            .map_err(|_| TicketError::InvalidSignature)?;
+
+        if now < claims.not_before {
+            return Err(TicketError::NotYetValid(claims.not_before));
+        }
+        if now > claims.not_after {
+            return Err(TicketError::Expired(claims.not_after));
+        }
+        Ok(claims)
+    }
+}
+
+/// Whether scanned text is a signed ticket rather than a URL.
+pub fn is_signed_ticket(text: &str) -> bool {
+    text.starts_with(&format!("{}.", TICKET_PREFIX))
+}
+
+// Splits a ticket into its claims, the signed part and the signature.
+fn split(ticket: &str) -> Result<(TicketClaims, &str, Signature), TicketError> {
+    let ticket = ticket.trim();
+    let (signed, signature) = ticket.rsplit_once('.').ok_or(TicketError::Malformed)?;
+    let (prefix, claims) = signed.split_once('.').ok_or(TicketError::Malformed)?;
+    if prefix != TICKET_PREFIX {
+        return Err(TicketError::Malformed);
+    }
+
+    let claims: TicketClaims = base64::decode_config(claims, base64::URL_SAFE_NO_PAD)
+        .ok()
+        .and_then(|claims| serde_json::from_slice(&claims).ok())
+        .ok_or(TicketError::Malformed)?;
+    let signature = base64::decode_config(signature, base64::URL_SAFE_NO_PAD)
+        .ok()
+        .and_then(|signature| Signature::from_slice(&signature).ok())
+        .ok_or(TicketError::Malformed)?;
+
+    Ok((claims, signed, signature))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+
+    fn signer(key_id: &str, seed: u8) -> TicketSigner {
+        TicketSigner::new(key_id.to_string(), &Secret::new(base64::encode([seed; 32]))).unwrap()
+    }
+
+    fn sign(signer: &TicketSigner) -> String {
+        let now = Utc::now().naive_utc();
+        signer.sign(Uuid::new_v4(), Uuid::new_v4(), now - Duration::hours(1), now + Duration::hours(1))
+    }
+
+    #[test]
+    fn ticket_signed_with_a_retired_key_is_still_valid() {
+        let retired = signer("2023-10", 1);
+        let current = signer("2023-11", 2);
+        let verifier = TicketVerifier::new(&[current.public_key(), retired.public_key()]).unwrap();
+
+        let ticket = sign(&retired);
+        let claims = verifier.verify(&ticket, Utc::now().naive_utc()).unwrap();
+
+        assert!(is_signed_ticket(&ticket));
+        assert_eq!(claims.kid, "2023-10");
+        assert_eq!(TicketClaims::read_unverified(&ticket).unwrap(), claims);
+    }
+
+    #[test]
+    fn tampered_ticket_is_rejected() {
+        let signer = signer("2023-11", 2);
+        let verifier = TicketVerifier::new(&[signer.public_key()]).unwrap();
+        let ticket = sign(&signer);
+
+        let other = sign(&signer);
+        let forged = format!(
+            "{}.{}",
+            other.rsplit_once('.').unwrap().0,
+            ticket.rsplit_once('.').unwrap().1
+        );
+
+        assert_eq!(verifier.verify(&forged, Utc::now().naive_utc()), Err(TicketError::InvalidSignature));
+        assert_eq!(verifier.verify("TKT1.garbage", Utc::now().naive_utc()), Err(TicketError::Malformed));
+    }
+
+    #[test]
+    fn ticket_is_only_valid_within_its_window() {
+        let signer = signer("2023-11", 2);
+        let verifier = TicketVerifier::new(&[signer.public_key()]).unwrap();
+        let ticket = sign(&signer);
+        let now = Utc::now().naive_utc();
+
+        assert!(matches!(verifier.verify(&ticket, now + Duration::hours(2)), Err(TicketError::Expired(_))));
+        assert!(matches!(verifier.verify(&ticket, now - Duration::hours(2)), Err(TicketError::NotYetValid(_))));
+        assert_eq!(
+            TicketVerifier::default().verify(&ticket, now),
+            Err(TicketError::UnknownKey("2023-11".to_string()))
+        );
+    }
+}
@@ -0,0 +1,386 @@
+//! Printable tickets: the QR code of an invitation together with the event
+//! and attendee details, rendered as PDF.
+//!
+//! Texts use DejaVu Sans, embedded as a subset of the glyphs the tickets
+//! print, so that names and titles in any script it covers come out right.
+//! The QR code is drawn as vector squares so that it stays sharp at any
+//! print size. The same tickets always render to
+//! the same bytes, so that downloads of them can be resumed.
+
+use anyhow::anyhow;
+use chrono::NaiveDateTime;
+use printpdf::lopdf;
+use printpdf::{Color, Greyscale, IndirectFontRef, Line, Mm, OffsetDateTime, PdfDocument, PdfDocumentReference, PdfLayerReference, Point};
+use qrcode::QrCode;
+use uuid::Uuid;
+use crate::font_subset::subset_font;
+
+/// What is printed on a ticket.
+#[derive(Debug, Clone)]
+pub struct PrintableTicket {
+    pub invitation_id: Uuid,
+    pub title: String,
+    pub date: NaiveDateTime,
+    /// Address of an OFFLINE appointment, `Online` otherwise.
+    pub location: String,
+    pub attendee_name: Option<String>,
+    /// Number of people the ticket admits.
+    pub max_admissions: i32,
+    /// Text of the QR code, the short URL of the invitation.
+    pub qr_content: String,
+}
+
+/// Upper bound of tickets printed on one A4 sheet.
+pub const MAX_TICKETS_PER_PAGE: usize = 10;
+
+/// A6 landscape, the size of a single ticket.
+const TICKET_PAGE: (f64, f64) = (148.0, 105.0);
+const A4_PAGE: (f64, f64) = (210.0, 297.0);
+/// Margin of an A4 sheet, most printers can't print closer to the edge.
+const SHEET_MARGIN: f64 = 10.0;
+
+/// Renders a single ticket on an A6 landscape page.
+pub fn render_ticket_pdf(ticket: &PrintableTicket) -> Result<Vec<u8>, anyhow::Error> {
+    let (width, height) = TICKET_PAGE;
+    let (document, page, layer) = PdfDocument::new(&ticket.title, Mm(width), Mm(height), "Ticket");
+    let document = reproducible(document, ticket);
+    let fonts = Fonts::load(&document, std::slice::from_ref(ticket))?;
+
+    let layer = document.get_page(page).get_layer(layer);
+    draw_ticket(&layer, &fonts, ticket, Cell { x: 0.0, y: 0.0, width, height })?;
+
+    save(document, ticket)
+}
+
+/// Renders tickets on A4 sheets holding `per_page` tickets each, separated
+/// by cut lines.
+pub fn render_ticket_sheet_pdf(tickets: &[PrintableTicket], per_page: usize) -> Result<Vec<u8>, anyhow::Error> {
+    if !(1..=MAX_TICKETS_PER_PAGE).contains(&per_page) {
+        return Err(anyhow!("Tickets per page must be between 1 and {}, got {}", MAX_TICKETS_PER_PAGE, per_page));
+    }
+    let first = tickets.first()
+        .ok_or_else(|| anyhow!("No tickets to print"))?;
+
+    let (width, height) = A4_PAGE;
+    let (document, first_page, first_layer) = PdfDocument::new(&first.title, Mm(width), Mm(height), "Tickets");
+    let document = reproducible(document, first);
+    let fonts = Fonts::load(&document, tickets)?;
+
+    // Two columns once the tickets get too flat to fit their QR code next to the text.
+    let columns = if per_page <= 3 { 1 } else { 2 };
+    let rows = per_page.div_ceil(columns);
+    let cell_width = (width - 2.0 * SHEET_MARGIN) / columns as f64;
+    let cell_height = (height - 2.0 * SHEET_MARGIN) / rows as f64;
+
+    for (index, page_tickets) in tickets.chunks(per_page).enumerate() {
+        let layer = if index == 0 {
+            document.get_page(first_page).get_layer(first_layer)
+        } else {
+            let (page, layer) = document.add_page(Mm(width), Mm(height), "Tickets");
+            document.get_page(page).get_layer(layer)
+        };
+
+        for (position, ticket) in page_tickets.iter().enumerate() {
+            let (row, column) = (position / columns, position % columns);
+            let cell = Cell {
+                x: SHEET_MARGIN + column as f64 * cell_width,
+                // PDF coordinates start at the bottom left corner.
+                y: height - SHEET_MARGIN - (row + 1) as f64 * cell_height,
+                width: cell_width,
+                height: cell_height,
+            };
+            draw_cut_lines(&layer, &cell);
+            draw_ticket(&layer, &fonts, ticket, cell)?;
+        }
+    }
+
+    save(document, first)
+}
+
+// Takes the document ID and dates from the ticket instead of a random
+// generator and the clock.
+fn reproducible(document: PdfDocumentReference, ticket: &PrintableTicket) -> PdfDocumentReference {
+    let date = OffsetDateTime::from_unix_timestamp(ticket.date.timestamp());
+    document
+        .with_document_id(document_id(ticket))
+        .with_creation_date(date)
+        .with_mod_date(date)
+        .with_metadata_date(date)
+}
+
+fn save(document: PdfDocumentReference, ticket: &PrintableTicket) -> Result<Vec<u8>, anyhow::Error> {
+    let saved = document.save_to_bytes()?;
+
+    // printpdf draws a random instance ID for the trailer on every save and
+    // has no way to set it, so the document is loaded back to set both IDs.
+    let mut document = lopdf::Document::load_mem(&saved)?;
+    let id = lopdf::Object::String(document_id(ticket).into_bytes(), lopdf::StringFormat::Literal);
+    document.trailer.set("ID", lopdf::Object::Array(vec![id.clone(), id]));
+    let mut bytes = vec![];
+    document.save_to(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn document_id(ticket: &PrintableTicket) -> String {
+    ticket.invitation_id.simple().to_string().to_uppercase()
+}
+
+const REGULAR_FONT: &[u8] = include_bytes!("../assets/fonts/DejaVuSans.ttf");
+const BOLD_FONT: &[u8] = include_bytes!("../assets/fonts/DejaVuSans-Bold.ttf");
+
+struct Fonts {
+    regular: IndirectFontRef,
+    bold: IndirectFontRef,
+}
+
+impl Fonts {
+    /// Embeds the glyphs `tickets` print: printable ASCII for the fixed
+    /// texts, dates and IDs, and whatever their titles, locations and
+    /// names use.
+    fn load(document: &printpdf::PdfDocumentReference, tickets: &[PrintableTicket]) -> Result<Self, anyhow::Error> {
+        let mut text: String = (' '..='~').collect();
+        for ticket in tickets {
+            text.push_str(&ticket.title);
+            text.push_str(&ticket.location);
+            text.push_str(ticket.attendee_name.as_deref().unwrap_or_default());
+        }
+        Ok(Self {
+            regular: document.add_external_font(subset_font(REGULAR_FONT, &text)?.as_slice())?,
+            bold: document.add_external_font(subset_font(BOLD_FONT, &text)?.as_slice())?,
+        })
+    }
+}
+
+/// Area of a page a ticket is drawn in, in millimetres from the bottom left corner.
+#[derive(Debug, Clone, Copy)]
+struct Cell {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+fn draw_ticket(layer: &PdfLayerReference, fonts: &Fonts, ticket: &PrintableTicket, cell: Cell) -> Result<(), anyhow::Error> {
+    let padding = (cell.height * 0.08).clamp(3.0, 8.0);
+    let qr_size = (cell.height - 2.0 * padding).min(cell.width * 0.45);
+    draw_qr_code(layer, &ticket.qr_content, cell.x + padding, cell.y + (cell.height - qr_size) / 2.0, qr_size)?;
+
+    // Font sizes follow the height of the ticket, so that a full A4 sheet stays readable.
+    let scale = (cell.height / TICKET_PAGE.1).clamp(0.5, 1.0);
+    let text_x = cell.x + 2.0 * padding + qr_size;
+    let text_width = cell.x + cell.width - padding - text_x;
+    let mut baseline = cell.y + cell.height - padding - 16.0 * scale * PT_TO_MM;
+
+    layer.set_fill_color(black());
+    for line in wrap(&ticket.title, text_width, 16.0 * scale).into_iter().take(2) {
+        layer.use_text(line, 16.0 * scale, Mm(text_x), Mm(baseline), &fonts.bold);
+        baseline -= 16.0 * scale * PT_TO_MM * 1.3;
+    }
+
+    let admits = match ticket.max_admissions {
+        1 => "Admits one person".to_string(),
+        count => format!("Admits {} people", count),
+    };
+    let details = [
+        format!("{} UTC", ticket.date.format("%Y/%m/%d %H:%M")),
+        ticket.location.clone(),
+        ticket.attendee_name.clone().unwrap_or_default(),
+        admits,
+    ];
+    baseline -= 2.0 * scale;
+    for detail in details.iter().filter(|detail| !detail.is_empty()) {
+        let line = wrap(detail, text_width, 11.0 * scale).into_iter().next().unwrap_or_default();
+        layer.use_text(line, 11.0 * scale, Mm(text_x), Mm(baseline), &fonts.regular);
+        baseline -= 11.0 * scale * PT_TO_MM * 1.5;
+    }
+
+    layer.use_text(ticket.invitation_id.to_string(), 6.0 * scale, Mm(text_x), Mm(cell.y + padding), &fonts.regular);
+    Ok(())
+}
+
+/// Draws a QR code as a square of `size` millimetres, with its quiet zone.
+fn draw_qr_code(layer: &PdfLayerReference, content: &str, x: f64, y: f64, size: f64) -> Result<(), anyhow::Error> {
+    const QUIET_ZONE: usize = 4;
+
+    let code = QrCode::new(content.as_bytes())?;
+    let modules = code.width();
+    let colors = code.to_colors();
+    let module_size = size / (modules + 2 * QUIET_ZONE) as f64;
+
+    layer.set_fill_color(black());
+    for row in 0..modules {
+        // Runs of dark modules are drawn as a single rectangle.
+        let mut column = 0;
+        while column < modules {
+            if colors[row * modules + column] != qrcode::Color::Dark {
+                column += 1;
+                continue;
+            }
+            let start = column;
+            while column < modules && colors[row * modules + column] == qrcode::Color::Dark {
+                column += 1;
+            }
+            let left = x + (QUIET_ZONE + start) as f64 * module_size;
+            let top = y + size - (QUIET_ZONE + row) as f64 * module_size;
+            layer.add_shape(rectangle(left, top - module_size, (column - start) as f64 * module_size, module_size));
+        }
+    }
+    Ok(())
+}
+
+fn draw_cut_lines(layer: &PdfLayerReference, cell: &Cell) {
+    layer.set_outline_color(Color::Greyscale(Greyscale::new(0.7, None)));
+    layer.set_outline_thickness(0.3);
+    layer.add_shape(Line {
+        points: corners(cell.x, cell.y, cell.width, cell.height),
+        is_closed: true,
+        has_stroke: true,
+        ..Default::default()
+    });
+}
+
+fn rectangle(x: f64, y: f64, width: f64, height: f64) -> Line {
+    Line {
+        points: corners(x, y, width, height),
+        is_closed: true,
+        has_fill: true,
+        ..Default::default()
+    }
+}
+
+fn corners(x: f64, y: f64, width: f64, height: f64) -> Vec<(Point, bool)> {
+    vec![
+        (Point::new(Mm(x), Mm(y)), false),
+        (Point::new(Mm(x + width), Mm(y)), false),
+        (Point::new(Mm(x + width), Mm(y + height)), false),
+        (Point::new(Mm(x), Mm(y + height)), false),
+    ]
+}
+
+fn black() -> Color {
+    Color::Greyscale(Greyscale::new(0.0, None))
+}
+
+const PT_TO_MM: f64 = 0.3528;
+
+/// Splits text into lines fitting `width` millimetres at `font_size` points.
+///
+/// DejaVu Sans glyphs are a little wider than half the font size on
+/// average, which is close enough for ticket texts.
+fn wrap(text: &str, width: f64, font_size: f64) -> Vec<String> {
+    let max_chars = ((width / (font_size * PT_TO_MM * 0.55)) as usize).max(1);
+    let mut lines: Vec<String> = vec![];
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > max_chars {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines.into_iter()
+        .map(|line| match line.chars().count() > max_chars {
+            true => line.chars().take(max_chars.saturating_sub(3)).collect::<String>() + "...",
+            false => line,
+        })
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ticket(title: &str) -> PrintableTicket {
+        PrintableTicket {
+            invitation_id: Uuid::new_v4(),
+            title: title.to_string(),
+            date: NaiveDateTime::parse_from_str("2023-12-24 18:00", "%Y-%m-%d %H:%M").unwrap(),
+            location: "123 Fake St.".to_string(),
+            attendee_name: Some("Ada Lovelace".to_string()),
+            max_admissions: 2,
+            qr_content: "https://short.url/testhash".to_string(),
+        }
+    }
+
+    fn page_count(pdf: &[u8]) -> usize {
+        String::from_utf8_lossy(pdf).matches("/Type/Page/").count()
+    }
+
+    #[test]
+    fn single_ticket_is_a_pdf() {
+        let pdf = render_ticket_pdf(&ticket("Christmas concert")).unwrap();
+
+        assert!(pdf.starts_with(b"%PDF"));
+        assert_eq!(page_count(&pdf), 1);
+    }
+
+    #[test]
+    fn sheet_holds_tickets_per_page() {
+        let tickets: Vec<_> = (0..9).map(|_| ticket("Christmas concert")).collect();
+
+        let pdf = render_ticket_sheet_pdf(&tickets, 4).unwrap();
+
+        assert!(pdf.starts_with(b"%PDF"));
+        assert_eq!(page_count(&pdf), 3);
+    }
+
+    #[test]
+    fn same_tickets_render_the_same_bytes() {
+        let ticket = ticket("Christmas concert");
+
+        assert_eq!(render_ticket_pdf(&ticket).unwrap(), render_ticket_pdf(&ticket).unwrap());
+        assert_eq!(
+            render_ticket_sheet_pdf(&[ticket.clone(), ticket.clone()], 4).unwrap(),
+            render_ticket_sheet_pdf(&[ticket.clone(), ticket], 4).unwrap()
+        );
+    }
+
+    #[test]
+    fn texts_beyond_windows_1252_are_embedded() {
+        let mut ticket = ticket("Зимний концерт");
+        ticket.attendee_name = Some("Đorđe Łukasz".to_string());
+
+        let pdf = render_ticket_pdf(&ticket).unwrap();
+
+        // The glyphs are mapped back to their characters, so that the text can be copied.
+        let text = String::from_utf8_lossy(&pdf);
+        for character in ['З', 'к', 'Đ', 'Ł'] {
+            assert!(text.contains(&format!("<{:04x}>\n", character as u32)), "{} is missing", character);
+        }
+        assert!(pdf.len() < 200_000);
+    }
+
+    #[test]
+    fn documents_are_identified_by_the_invitation() {
+        let ticket = ticket("Christmas concert");
+
+        let pdf = render_ticket_pdf(&ticket).unwrap();
+
+        let id = document_id(&ticket);
+        let trailer = lopdf::Document::load_mem(&pdf).unwrap().trailer;
+        let ids = trailer.get(b"ID").unwrap().as_array().unwrap();
+        assert!(ids.iter().all(|object| object.as_str().unwrap() == id.as_bytes()));
+    }
+
+    #[test]
+    fn sheet_rejects_invalid_layouts() {
+        assert!(render_ticket_sheet_pdf(&[ticket("Concert")], 0).is_err());
+        assert!(render_ticket_sheet_pdf(&[ticket("Concert")], MAX_TICKETS_PER_PAGE + 1).is_err());
+        assert!(render_ticket_sheet_pdf(&[], 4).is_err());
+    }
+
+    #[test]
+    fn long_titles_are_wrapped_and_shortened() {
+        let lines = wrap("An evening of chamber music with a very long title", 40.0, 16.0);
+
+        assert!(lines.len() > 1);
+        assert!(lines.iter().all(|line| line.chars().count() <= 28));
+        assert_eq!(wrap("Supercalifragilisticexpialidocious", 10.0, 16.0)[0], "...");
+    }
+}
@@ -0,0 +1,187 @@
+//! Reading attendee lists exported from spreadsheets.
+
+use std::collections::HashSet;
+use std::io::Read;
+use anyhow::{anyhow, Error};
+use serde::Serialize;
+use serde_json::json;
+use shared::domain::{Email, NewAttendee};
+
+/// Names of the CSV columns holding attendee data.
+#[derive(Debug, Clone)]
+pub struct ColumnMapping {
+    pub email: String,
+    pub name: Option<String>,
+    pub phone: Option<String>,
+    pub locale: Option<String>,
+}
+
+/// A valid attendee row of a CSV file.
+#[derive(Debug, Clone, Serialize)]
+pub struct AttendeeRow {
+    /// Line of the row in the file, the header being line 1.
+    pub line: u64,
+    pub email: Email,
+    pub name: Option<String>,
+    pub phone: Option<String>,
+    pub locale: Option<String>,
+}
+
+impl From<AttendeeRow> for NewAttendee {
+    fn from(row: AttendeeRow) -> Self {
+        let mut attendee = NewAttendee::from(row.email);
+        attendee.name = row.name;
+        attendee.phone = row.phone;
+        if let Some(locale) = row.locale {
+            attendee.custom_fields = json!({ "locale": locale });
+        }
+        attendee
+    }
+}
+
+/// A row that was rejected while reading a CSV file.
+#[derive(Debug, Clone, Serialize)]
+pub struct RowError {
+    pub line: u64,
+    pub value: String,
+    pub reason: String,
+}
+
+/// Attendees read from a CSV file, split into valid and rejected rows.
+#[derive(Debug, Default)]
+pub struct AttendeeImport {
+    pub attendees: Vec<AttendeeRow>,
+    pub errors: Vec<RowError>,
+}
+
+/// Reads attendees from CSV data with a header row.
+///
+/// Rows with an invalid email, or an email already seen in an earlier row,
+/// are reported as errors rather than failing the whole import. Extra columns
+/// are ignored and empty name or locale cells are read as missing.
+///
+/// # Parameters
+///
+/// - `reader`: The CSV data.
+/// - `mapping`: The columns to read the attendee fields from.
+///
+/// # Returns
+///
+/// - The valid and rejected rows, or an error if a mapped column is missing
+///   or the data is not valid CSV.
+pub fn read_attendees_csv<R: Read>(reader: R, mapping: &ColumnMapping) -> Result<AttendeeImport, Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(reader);
+
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| -> Result<usize, Error> {
+        headers.iter()
+            .position(|header| header.eq_ignore_ascii_case(name))
+            .ok_or_else(|| anyhow!("Column `{}` not found, the file has: {}", name, headers.iter().collect::<Vec<_>>().join(", ")))
+    };
+    let email_column = column(&mapping.email)?;
+    let name_column = mapping.name.as_deref().map(column).transpose()?;
+    let phone_column = mapping.phone.as_deref().map(column).transpose()?;
+    let locale_column = mapping.locale.as_deref().map(column).transpose()?;
+
+    let mut import = AttendeeImport::default();
+    let mut seen = HashSet::new();
+
+    for record in reader.records() {
+        let record = record?;
+        let line = record.position().map_or(0, |position| position.line());
+        let cell = |index: Option<usize>| {
+            index.and_then(|index| record.get(index))
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+
+        let value = record.get(email_column).unwrap_or_default().to_string();
+        let email = match Email::parse(value.clone()) {
+            Ok(email) => email,
+            Err(reason) => {
+                import.errors.push(RowError { line, value, reason });
+                continue;
+            }
+        };
+        if !seen.insert(value.to_lowercase()) {
+            import.errors.push(RowError { line, value, reason: "duplicate of an earlier row.".to_string() });
+            continue;
+        }
+
+        import.attendees.push(AttendeeRow {
+            line,
+            email,
+            name: cell(name_column),
+            phone: cell(phone_column),
+            locale: cell(locale_column),
+        });
+    }
+
+    Ok(import)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping() -> ColumnMapping {
+        ColumnMapping {
+            email: "E-mail".to_string(),
+            name: Some("Full name".to_string()),
+            phone: None,
+            locale: Some("locale".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_read_attendees_csv() {
+        let data = "\
+Full name,E-mail,Company,Locale
+Ada Lovelace,ada@example.com,Analytical,en
+Bad Row,not-an-email,Nowhere,
+Grace Hopper,grace@example.com,Navy,
+Ada Again,ADA@example.com,Analytical,en
+";
+        let import = read_attendees_csv(data.as_bytes(), &mapping()).unwrap();
+
+        assert_eq!(import.attendees.len(), 2);
+        assert_eq!(import.attendees[0].line, 2);
+        assert_eq!(import.attendees[0].name.as_deref(), Some("Ada Lovelace"));
+        assert_eq!(import.attendees[0].locale.as_deref(), Some("en"));
+        assert_eq!(import.attendees[1].email.as_ref(), "grace@example.com");
+        assert_eq!(import.attendees[1].locale, None);
+
+        assert_eq!(import.errors.len(), 2);
+        assert_eq!((import.errors[0].line, import.errors[0].value.as_str()), (3, "not-an-email"));
+        assert_eq!(import.errors[1].line, 5);
+    }
+
+    #[test]
+    fn test_missing_column_is_rejected() {
+        let data = "name,mail\nAda,ada@example.com\n";
+
+        let error = read_attendees_csv(data.as_bytes(), &mapping()).unwrap_err();
+
+        assert!(error.to_string().contains("E-mail"));
+    }
+
+    #[test]
+    fn test_row_becomes_attendee_with_locale_field() {
+        let row = AttendeeRow {
+            line: 2,
+            email: Email::parse("ada@example.com".to_string()).unwrap(),
+            name: Some("Ada".to_string()),
+            phone: Some("+380000000000".to_string()),
+            locale: Some("uk".to_string()),
+        };
+
+        let attendee = NewAttendee::from(row);
+
+        assert_eq!(attendee.name.as_deref(), Some("Ada"));
+        assert_eq!(attendee.phone.as_deref(), Some("+380000000000"));
+        assert_eq!(attendee.custom_fields, json!({ "locale": "uk" }));
+    }
+}
@@ -1,12 +1,35 @@
-use std::io::{self, BufRead};
+use std::io::{self, BufRead, IsTerminal};
+use std::path::Path;
 use tokio::task;
 use chrono::{NaiveDateTime, DateTime};
 use shared::domain::{AppointmentFormat, NewAppointment};
 use std::time::Duration;
-use anyhow::{anyhow, Error};
+use anyhow::{anyhow, Context, Error};
 use reqwest::Url;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use shared::configuration::get_configuration;
+use crate::cli::CreateAppointment;
+use crate::error::{bulk_result, ensure_success, CliError};
+use crate::output::{render, OutputFormat, Tabular};
+use crate::retry::send_idempotent;
+
+/// ID of a created appointment, rendered as a bare string in JSON.
+#[derive(Debug, Serialize)]
+#[serde(transparent)]
+pub struct CreatedAppointment {
+    pub id: Uuid,
+}
+
+impl Tabular for CreatedAppointment {
+    fn headers() -> Vec<&'static str> {
+        vec!["id"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![self.id.to_string()]
+    }
+}
 
 /// Reads a single line of input from stdin asynchronously.
 ///
@@ -34,13 +57,12 @@ async fn read_trimmed_line() -> io::Result<String> {
 /// - The selected `AppointmentFormat`.
 async fn read_appointment_format() -> AppointmentFormat {
     loop {
-        println!("Enter format [0 || online ] - ONLINE or [ 1 || offline ] - OFFLINE):");
+        eprintln!("Enter format [0 || online ] - ONLINE or [ 1 || offline ] - OFFLINE):");
         let type_str = read_trimmed_line().await.expect("Failed to read format");
-        match type_str.as_str() {
-            "0" | "online" => return AppointmentFormat::ONLINE,
-            "1" | "offline" => return AppointmentFormat::OFFLINE,
-            _ => {
-                println!("Invalid format. Please enter either `online` or `offline`.");
+        match type_str.parse::<AppointmentFormat>() {
+            Ok(format) => return format,
+            Err(_) => {
+                eprintln!("Invalid format. Please enter either `online` or `offline`.");
                 continue;
             },
         };
@@ -60,12 +82,12 @@ async fn read_appointment_format() -> AppointmentFormat {
 /// - The user's non-empty input as a `String`.
 async fn read_non_empty_input(prompt: &str) -> String {
     loop {
-        println!("{}", prompt);
+        eprintln!("{}", prompt);
         let input = read_trimmed_line().await.expect("Failed to read input");
         if !input.is_empty() {
             return input;
         }
-        println!("Input cannot be empty. Please try again.");
+        eprintln!("Input cannot be empty. Please try again.");
     }
 }
 
@@ -88,7 +110,7 @@ async fn read_optional_address(format: &AppointmentFormat, prompt: &str) -> Opti
                 return None;
             }
             AppointmentFormat::OFFLINE => {
-                println!("{}", prompt);
+                eprintln!("{}", prompt);
                 let input = read_trimmed_line().await.expect("Failed to read input");
                 return match input.as_str() {
                     "none" => continue,
@@ -118,7 +140,7 @@ async fn read_uri_input(format: &AppointmentFormat, prompt: &str) -> Option<Url>
                 return None;
             }
             AppointmentFormat::ONLINE => {
-                println!("{}", prompt);
+                eprintln!("{}", prompt);
                 let input = read_trimmed_line().await.expect("Failed to read input");
                 match input.as_str().trim() {
                     "none" => return None,
@@ -126,8 +148,8 @@ async fn read_uri_input(format: &AppointmentFormat, prompt: &str) -> Option<Url>
                         match Url::parse(input.as_str()) {
                             Ok(uri) => return Some(uri),
                             Err(e) => {
-                                println!("Error: {}", e);
-                                println!("Please enter a valid URI or 'none'.");
+                                eprintln!("Error: {}", e);
+                                eprintln!("Please enter a valid URI or 'none'.");
                             }
                         }
                     }
@@ -164,22 +186,40 @@ async fn read_uri_input(format: &AppointmentFormat, prompt: &str) -> Option<Url>
 /// ```
 async fn read_duration_in_minutes_input(prompt: &str, error_msg: &str) -> Duration {
     loop {
-        println!("{}", prompt);
+        eprintln!("{}", prompt);
         let input = read_trimmed_line().await.expect("Failed to read input");
-        match input.parse::<u64>() {
-            Ok(value) => return Duration::from_secs(value * 60), // Convert minutes to seconds
-            Err(_) => println!("{}", error_msg),
+        match input.parse::<u64>().ok().and_then(minutes_to_duration) {
+            Some(duration) => return duration,
+            None => println!("{}", error_msg),
         }
     }
 }
 
+/// Parses a datetime given either as "yyyy/mm/dd hh:mm" or in RFC3339 format.
+///
+/// The timezone of an RFC3339 input is stripped after converting it to UTC.
+///
+/// # Returns
+///
+/// - The parsed `NaiveDateTime` or a message describing the accepted formats.
+pub fn parse_datetime(input: &str) -> Result<NaiveDateTime, String> {
+    // Try parsing it directly as "yyyy/mm/dd hh:mm" format
+    if let Ok(naive_dt) = NaiveDateTime::parse_from_str(input, "%Y/%m/%d %H:%M") {
+        return Ok(naive_dt);
+    }
+
+    // If the above parsing failed, try parsing it with timezone information (RFC3339 format) and then strip the timezone
+    if let Ok(dt) = DateTime::parse_from_rfc3339(input) {
+        return Ok(dt.naive_utc()); // Extract NaiveDateTime from DateTime
+    }
+
+    Err(format!("{} is not a valid datetime. Use 'yyyy/mm/dd hh:mm' or RFC3339 format.", input))
+}
+
 /// Asynchronously reads a datetime input from the user.
 ///
-/// The user will be repeatedly prompted until they provide a valid datetime input.
-/// The function supports two formats for datetime:
-/// 1. Direct "yyyy/mm/dd hh:mm" format.
-/// 2. RFC3339 format, which includes timezone information. The timezone will be stripped
-///    and a `NaiveDateTime` representation will be returned.
+/// The user will be repeatedly prompted until they provide a valid datetime input
+/// accepted by [`parse_datetime`].
 ///
 /// # Parameters
 ///
@@ -201,75 +241,208 @@ async fn read_duration_in_minutes_input(prompt: &str, error_msg: &str) -> Durati
 /// ```
 async fn read_datetime_input(prompt: &str, error_msg: &str) -> NaiveDateTime {
     loop {
-        println!("{}", prompt);
+        eprintln!("{}", prompt);
         let input = read_trimmed_line().await.expect("Failed to read input");
 
-        // Try parsing it directly as "yyyy/mm/dd hh:mm" format
-        if let Ok(naive_dt) = NaiveDateTime::parse_from_str(&input, "%Y/%m/%d %H:%M") {
+        if let Ok(naive_dt) = parse_datetime(&input) {
             return naive_dt;
         }
 
-        // If the above parsing failed, try parsing it with timezone information (RFC3339 format) and then strip the timezone
-        if let Ok(dt) = DateTime::parse_from_rfc3339(&input) {
-            return dt.naive_utc(); // Extract NaiveDateTime from DateTime
-        }
-
-        // If both parsing attempts failed, print an error message
-        println!("{}", error_msg);
+        eprintln!("{}", error_msg);
     }
 }
 
-/// Asynchronously constructs a new appointment by reading input from stdin.
+/// Converts minutes to a `Duration`, `None` when the seconds overflow.
+fn minutes_to_duration(minutes: u64) -> Option<Duration> {
+    minutes.checked_mul(60).map(Duration::from_secs)
+}
+
+/// Builds the error returned when a field is neither given as a flag nor can be prompted for.
+fn missing_field(flag: &str) -> Error {
+    CliError::Usage(format!("Missing `--{}`: stdin is not a terminal, so it can't be prompted for.", flag)).into()
+}
+
+/// Asynchronously completes a new appointment from command-line flags.
+///
+/// Fields given as flags are used as-is. Missing fields are read from stdin
+/// when `interactive` is set, otherwise they are reported as an error.
+/// The address is only asked for OFFLINE appointments and the link only for
+/// ONLINE ones.
+///
+/// # Parameters
+///
+/// - `args`: The flags given to the `create` command.
+/// - `interactive`: Whether missing fields may be prompted for.
 ///
 /// # Returns
 ///
-/// - A `NewAppointment` constructed from user's input.
-pub async fn read_new_appointment_from_stdin() -> NewAppointment {
-    let title = read_non_empty_input("Enter title:").await;
-    let description = read_non_empty_input("Enter description:").await;
-    let format = read_appointment_format().await;
-    let address = read_optional_address(&format, "Enter address:").await;
-    let link = read_uri_input(&format, "Enter uri:").await;
-    let date = read_datetime_input("Enter start date in 'yyyy/mm/dd hh:mm' UTC time format:", "Please enter a valid datetime.").await;
-    let duration = read_duration_in_minutes_input("Enter duration in minutes:", "Please enter a valid number for duration.").await;
-
-    NewAppointment {
+/// - A `NewAppointment` or an error naming the first missing field.
+pub async fn complete_new_appointment(
+    args: CreateAppointment,
+    interactive: bool
+) -> Result<NewAppointment, Error> {
+    let title = match args.title {
+        Some(title) => title,
+        None if interactive => read_non_empty_input("Enter title:").await,
+        None => return Err(missing_field("title")),
+    };
+    let description = match args.description {
+        Some(description) => description,
+        None if interactive => read_non_empty_input("Enter description:").await,
+        None => return Err(missing_field("description")),
+    };
+    let format = match args.format {
+        Some(format) => format,
+        None if interactive => read_appointment_format().await,
+        None => return Err(missing_field("format")),
+    };
+    let address = match (args.address, &format) {
+        (Some(address), _) => Some(address),
+        (None, AppointmentFormat::OFFLINE) if interactive => read_optional_address(&format, "Enter address:").await,
+        (None, AppointmentFormat::OFFLINE) => return Err(missing_field("address")),
+        (None, AppointmentFormat::ONLINE) => None,
+    };
+    let link = match (args.link, &format) {
+        (Some(link), _) => Some(link),
+        (None, AppointmentFormat::ONLINE) if interactive => read_uri_input(&format, "Enter uri:").await,
+        (None, AppointmentFormat::ONLINE) => return Err(missing_field("link")),
+        (None, AppointmentFormat::OFFLINE) => None,
+    };
+    let date = match args.date {
+        Some(date) => date,
+        None if interactive => read_datetime_input("Enter start date in 'yyyy/mm/dd hh:mm' UTC time format:", "Please enter a valid datetime.").await,
+        None => return Err(missing_field("date")),
+    };
+    let duration = match args.duration {
+        Some(minutes) => minutes_to_duration(minutes)
+            .ok_or_else(|| CliError::Usage(format!("`--duration` of {} minutes is too long.", minutes)))?,
+        None if interactive => read_duration_in_minutes_input("Enter duration in minutes:", "Please enter a valid number for duration.").await,
+        None => return Err(missing_field("duration")),
+    };
+
+    Ok(NewAppointment {
         title,
         description,
         format,
         address,
         link,
         date,
-        duration
-    }
+        duration,
+        capacity: args.capacity,
+        registration_open: args.registration_open,
+        qr_options: None,
+    })
 }
 
-/// Asynchronously handles the process of creating a new appointment.
+/// Parses appointments from the content of a JSON or YAML file.
 ///
-/// This function reads a new appointment from stdin, sends it to the server,
-/// and returns the server's response.
+/// A document may hold a single `NewAppointment` or a list of them, and YAML
+/// content may contain several `---` separated documents. The `duration` field
+/// is given in seconds, as in the HTTP API.
+///
+/// # Parameters
+///
+/// - `content`: The file content.
+/// - `is_json`: Whether the content is JSON rather than YAML.
 ///
 /// # Returns
 ///
-/// - A `Result<String, Error>` representing the server's response or an error.
-pub async fn new_appointment_handler() -> Result<String, Error> {
-    let appointment: NewAppointment = read_new_appointment_from_stdin().await;
-    send_new_appointment_handler(appointment).await
+/// - All appointments found in the content, in order.
+pub fn parse_appointments(content: &str, is_json: bool) -> Result<Vec<NewAppointment>, Error> {
+    if is_json {
+        let value: serde_json::Value = serde_json::from_str(content)?;
+        return if value.is_array() {
+            Ok(serde_json::from_value(value)?)
+        } else {
+            Ok(vec![serde_json::from_value(value)?])
+        };
+    }
+
+    let mut appointments = vec![];
+    for document in serde_yaml::Deserializer::from_str(content) {
+        let value = serde_yaml::Value::deserialize(document)?;
+        if value.is_sequence() {
+            appointments.extend(serde_yaml::from_value::<Vec<NewAppointment>>(value)?);
+        } else {
+            appointments.push(serde_yaml::from_value(value)?);
+        }
+    }
+    Ok(appointments)
 }
 
-async fn send_new_appointment_handler(appointment: NewAppointment) -> Result<String, Error> {
-    let client = reqwest::Client::new();
+/// Reads appointments from a `.json`, `.yaml` or `.yml` file.
+pub fn read_appointments_from_file(path: &Path) -> Result<Vec<NewAppointment>, Error> {
+    let is_json = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => true,
+        Some("yaml") | Some("yml") => false,
+        _ => return Err(anyhow!("{} must have a .json, .yaml or .yml extension.", path.display())),
+    };
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    parse_appointments(&content, is_json)
+        .with_context(|| format!("Failed to parse appointments from {}", path.display()))
+}
+
+/// Asynchronously handles the process of creating new appointments.
+///
+/// Appointments are read from `--from-file` or completed from the flags,
+/// prompting only for missing fields and only when stdin is a terminal.
+/// Every appointment is validated before any of them is sent to the server.
+///
+/// # Parameters
+///
+/// - `args`: The flags given to the `create` command.
+/// - `output`: The format the created IDs are rendered in.
+///
+/// # Returns
+///
+/// - A `Result<String, Error>` holding the rendered IDs of the created appointments,
+///   or a `CliError::Partial` when only some of them could be created.
+pub async fn new_appointment_handler(args: CreateAppointment, output: OutputFormat) -> Result<String, Error> {
+    let appointments = match &args.from_file {
+        Some(path) => read_appointments_from_file(path)
+            .map_err(|e| CliError::Usage(format!("{:#}", e)))?,
+        None => vec![complete_new_appointment(args, io::stdin().is_terminal()).await?],
+    };
+
+    if appointments.is_empty() {
+        return Err(CliError::Usage("No appointments to create.".to_string()).into());
+    }
+
+    let errors: Vec<String> = appointments.iter().enumerate()
+        .filter_map(|(index, appointment)| {
+            appointment.validate().err().map(|e| format!("appointment #{}: {}", index + 1, e))
+        })
+        .collect();
+    if !errors.is_empty() {
+        return Err(CliError::Usage(format!("Invalid appointments:\n{}", errors.join("\n"))).into());
+    }
+
     let configuration = get_configuration()
         .map_err(|e| anyhow!("Failed to get configuration: {}", e))?;
 
-    println!("{:?}", appointment);
+    let mut created = vec![];
+    let mut failures = vec![];
+    for (index, appointment) in appointments.iter().enumerate() {
+        match send_new_appointment(&configuration.console_cli.web_url, appointment).await {
+            Ok(id) => created.push(CreatedAppointment { id }),
+            Err(e) => failures.push((format!("appointment #{}", index + 1), e)),
+        }
+    }
+
+    bulk_result(render(&created, output)?, created.len(), failures)
+}
 
-    let response = client.post(format!("{}/api/appointment", configuration.console_cli.web_url))
-        .json(&appointment)
-        .send()
-        .await?;
+async fn send_new_appointment(web_url: &str, appointment: &NewAppointment) -> Result<Uuid, Error> {
+    let client = reqwest::Client::new();
 
-    Ok(format!("Response: {:?}", response.json::<Uuid>().await?))
+    let response = send_idempotent(
+        client.post(format!("{}/api/appointment", web_url))
+            .json(appointment)
+    ).await?;
+
+    Ok(ensure_success(response).await?.json::<Uuid>().await?)
 }
 
 
@@ -281,7 +454,7 @@ mod tests {
     use mockito::{mock, Matcher, server_url};
     use crate::appointment::ENV_VAR_LOCK_TEST;
 
-    fn mock_read_new_appointment_from_stdin() -> NewAppointment {
+    fn mock_new_appointment() -> NewAppointment {
         let date = NaiveDateTime::from_timestamp_opt(1672531200, 0).expect("Invalid timestamp provided");
         NewAppointment {
             title: "Test Title".to_string(),
@@ -291,14 +464,15 @@ mod tests {
             link: Some(Url::from_str("http://www.rust.com/").unwrap()),
             date, // Arbitrary date
             duration: Duration::from_secs(3600), // 1 hour
+            capacity: None,
+            registration_open: false,
+            qr_options: None,
         }
     }
 
     #[tokio::test]
-    async fn test_new_appointment_handler() {
-        let _guard = ENV_VAR_LOCK_TEST.lock().unwrap();
-        env::set_var("APP_CONSOLE_CLI__WEB_URL", server_url().as_str());
-        // env::set_var::<&str, &str>("APP_CONSOLE_CLI__WEB_URL", server_url().as_str());
+    async fn test_send_new_appointment() {
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
 
         // Mock the HTTP POST endpoint for creating a new appointment
         let _mock = mock("POST", Matcher::Exact("/api/appointment".to_string()))
@@ -306,13 +480,152 @@ mod tests {
             .with_body(r#""6ba7b812-9dad-11d1-80b4-00c04fd430c9""#)
             .create();
 
-        // This assumes the user will input data correctly on the first attempt for all prompts.
-        // In a real-world scenario, you'd refactor your handler to accept a custom input function for easier testing.
-        let response = send_new_appointment_handler(mock_read_new_appointment_from_stdin()).await;
+        let response = send_new_appointment(&server_url(), &mock_new_appointment()).await;
 
         // Check the results.
-        assert_eq!(response.unwrap(), r#"Response: 6ba7b812-9dad-11d1-80b4-00c04fd430c9"#);
+        assert_eq!(response.unwrap(), Uuid::parse_str("6ba7b812-9dad-11d1-80b4-00c04fd430c9").unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_new_appointment_handler_from_flags() {
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
+        env::set_var("APP_CONSOLE_CLI__WEB_URL", server_url().as_str());
+
+        let _mock = mock("POST", Matcher::Exact("/api/appointment".to_string()))
+            .with_status(200)
+            .with_body(r#""6ba7b812-9dad-11d1-80b4-00c04fd430c9""#)
+            .create();
+
+        let args = CreateAppointment {
+            title: Some("Test Title".to_string()),
+            description: Some("Test Description".to_string()),
+            format: Some(AppointmentFormat::OFFLINE),
+            address: Some("123 Fake St.".to_string()),
+            date: Some(parse_datetime("2024/10/10 10:10").unwrap()),
+            duration: Some(90),
+            ..CreateAppointment::default()
+        };
+
+        let response = new_appointment_handler(args, OutputFormat::Json).await;
 
         env::remove_var("APP_CONSOLE_CLI__WEB_URL");
+
+        assert_eq!(response.unwrap(), r#"["6ba7b812-9dad-11d1-80b4-00c04fd430c9"]"#);
+    }
+
+    #[tokio::test]
+    async fn test_partial_failure_keeps_created_ids() {
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
+        env::set_var("APP_CONSOLE_CLI__WEB_URL", server_url().as_str());
+
+        let _created = mock("POST", "/api/appointment")
+            .match_body(Matcher::PartialJsonString(r#"{"title":"A"}"#.to_string()))
+            .with_status(200)
+            .with_body(r#""6ba7b812-9dad-11d1-80b4-00c04fd430c9""#)
+            .create();
+        let _failed = mock("POST", "/api/appointment")
+            .match_body(Matcher::PartialJsonString(r#"{"title":"B"}"#.to_string()))
+            .with_status(500)
+            .with_body("An internal error occurred. Please try again later.")
+            .create();
+
+        let file = env::temp_dir().join(format!("{}.json", Uuid::new_v4()));
+        std::fs::write(&file, r#"[
+            {"title":"A","description":"D","format":"ONLINE","link":"https://meet.com","date":"2024-10-10T10:10:00","duration":3600},
+            {"title":"B","description":"D","format":"ONLINE","link":"https://meet.com","date":"2024-10-10T10:10:00","duration":3600}
+        ]"#).unwrap();
+        let args = CreateAppointment { from_file: Some(file.clone()), ..CreateAppointment::default() };
+
+        let response = new_appointment_handler(args, OutputFormat::Json).await;
+
+        env::remove_var("APP_CONSOLE_CLI__WEB_URL");
+        std::fs::remove_file(file).unwrap();
+
+        match response.unwrap_err().downcast::<CliError>().unwrap() {
+            CliError::Partial { output, failures } => {
+                assert_eq!(output, r#"["6ba7b812-9dad-11d1-80b4-00c04fd430c9"]"#);
+                assert_eq!(failures.len(), 1);
+                assert!(failures[0].starts_with("appointment #2"));
+            }
+            other => panic!("Unexpected error: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_missing_flag_is_reported_when_not_interactive() {
+        let args = CreateAppointment {
+            title: Some("Test Title".to_string()),
+            ..CreateAppointment::default()
+        };
+
+        let error = complete_new_appointment(args, false).await.unwrap_err();
+
+        assert!(error.to_string().contains("--description"));
+        assert!(matches!(error.downcast_ref::<CliError>(), Some(CliError::Usage(_))));
+    }
+
+    #[tokio::test]
+    async fn test_overflowing_duration_is_rejected() {
+        let args = CreateAppointment {
+            title: Some("Test Title".to_string()),
+            description: Some("Test Description".to_string()),
+            format: Some(AppointmentFormat::OFFLINE),
+            address: Some("123 Test St".to_string()),
+            date: Some(parse_datetime("2024/10/10 10:10").unwrap()),
+            duration: Some(u64::MAX),
+            ..CreateAppointment::default()
+        };
+
+        let error = complete_new_appointment(args, false).await.unwrap_err();
+
+        assert!(error.to_string().contains("--duration"));
+        assert!(matches!(error.downcast_ref::<CliError>(), Some(CliError::Usage(_))));
+    }
+
+    #[test]
+    fn test_parse_datetime() {
+        let expected = NaiveDateTime::parse_from_str("2024/10/10 10:10", "%Y/%m/%d %H:%M").unwrap();
+        assert_eq!(parse_datetime("2024/10/10 10:10").unwrap(), expected);
+        assert_eq!(parse_datetime("2024-10-10T12:10:00+02:00").unwrap(), expected);
+        assert!(parse_datetime("10.10.2024").is_err());
+    }
+
+    #[test]
+    fn test_parse_appointments_from_json() {
+        let single = r#"{"title":"A","description":"B","format":"ONLINE","address":null,"link":"https://meet.com","date":"2024-10-10T10:10:00","duration":3600}"#;
+        assert_eq!(parse_appointments(single, true).unwrap().len(), 1);
+
+        let many = format!("[{},{}]", single, single);
+        assert_eq!(parse_appointments(&many, true).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_parse_appointments_from_yaml_documents() {
+        let content = r#"
+title: A
+description: B
+format: OFFLINE
+address: 123 Fake St.
+date: 2024-10-10T10:10:00
+duration: 3600
+---
+- title: C
+  description: D
+  format: ONLINE
+  link: https://meet.com
+  date: 2024-10-11T10:10:00
+  duration: 1800
+- title: E
+  description: F
+  format: ONLINE
+  link: https://meet.com
+  date: 2024-10-12T10:10:00
+  duration: 1800
+"#;
+        let appointments = parse_appointments(content, false).unwrap();
+
+        assert_eq!(appointments.len(), 3);
+        assert_eq!(appointments[1].title, "C");
+        assert_eq!(appointments[2].duration, Duration::from_secs(1800));
     }
 }
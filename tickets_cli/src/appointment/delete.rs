@@ -1,11 +1,33 @@
 use std::io::BufRead;
-use anyhow::Error;
+use anyhow::{anyhow, Error};
+use serde::Serialize;
 use tokio::{io, task};
 use uuid::Uuid;
+use shared::configuration::get_configuration;
 use shared::domain::RemoveAppointment;
+use crate::error::{bulk_result, ensure_success, CliError};
+use crate::output::{render, OutputFormat, Tabular};
+
+/// Outcome of deleting a single appointment.
+#[derive(Debug, Serialize)]
+pub struct DeletedAppointment {
+    pub id: Uuid,
+    /// `false` when no appointment had this ID.
+    pub deleted: bool,
+}
+
+impl Tabular for DeletedAppointment {
+    fn headers() -> Vec<&'static str> {
+        vec!["id", "deleted"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![self.id.to_string(), self.deleted.to_string()]
+    }
+}
 
 pub async fn read_uuids() -> io::Result<Vec<Uuid>> {
-    println!("Please list id's (Uuid) Appointment to delete (EOL format)':");
+    eprintln!("Please list id's (Uuid) Appointment to delete (EOL format)':");
     task::spawn_blocking(|| {
         let stdin = std::io::stdin();
         let locked = stdin.lock();
@@ -26,12 +48,32 @@ pub async fn read_uuids() -> io::Result<Vec<Uuid>> {
         Ok(uuids)
     })
         .await
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+        .map_err(io::Error::other)?
+}
+
+// Sends a request to the server to delete an appointment.
+async fn send_delete_appointment(web_url: &str, appt_id: Uuid) -> Result<bool, Error> {
+    let client = reqwest::Client::new();
+
+    let response = client.delete(format!("{}/api/appointment/{}", web_url, appt_id))
+        .send()
+        .await?;
+
+    Ok(ensure_success(response).await?.json::<bool>().await?)
 }
 
-pub async fn delete_appointment_handler(uuids: Vec<Uuid>) -> Result<bool, Error> {
+/// Asynchronously deletes the given appointments.
+///
+/// If no IDs are provided, they are read from standard input.
+///
+/// # Returns
+///
+/// - A `Result<String, Error>` with the rendered outcome per appointment,
+///   or a `CliError::Partial` when some requests failed.
+pub async fn delete_appointment_handler(uuids: Vec<Uuid>, output: OutputFormat) -> Result<String, Error> {
     let mut payload = if uuids.is_empty() {
-        let uuids = read_uuids().await?;
+        let uuids = read_uuids().await
+            .map_err(|e| CliError::Usage(e.to_string()))?;
         RemoveAppointment { id: uuids }
     } else {
         RemoveAppointment { id: uuids }
@@ -39,7 +81,17 @@ pub async fn delete_appointment_handler(uuids: Vec<Uuid>) -> Result<bool, Error>
     payload.id.sort();
     payload.id.dedup();
 
+    let configuration = get_configuration()
+        .map_err(|e| anyhow!("Failed to get configuration: {}", e))?;
 
-    println!("{:?}", payload);
-    Ok(true)
-}
\ No newline at end of file
+    let mut results = vec![];
+    let mut failures = vec![];
+    for id in payload.id {
+        match send_delete_appointment(&configuration.console_cli.web_url, id).await {
+            Ok(deleted) => results.push(DeletedAppointment { id, deleted }),
+            Err(e) => failures.push((id.to_string(), e)),
+        }
+    }
+
+    bulk_result(render(&results, output)?, results.len(), failures)
+}
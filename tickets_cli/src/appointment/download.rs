@@ -0,0 +1,225 @@
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Error};
+use reqwest::header::{CONTENT_RANGE, ETAG, IF_RANGE, RANGE};
+use reqwest::StatusCode;
+use serde::Serialize;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+use shared::configuration::get_configuration;
+use crate::error::ensure_success;
+use crate::output::{render_one, OutputFormat, Tabular};
+
+/// A QR code archive saved on disk.
+#[derive(Debug, Serialize)]
+pub struct DownloadedArchive {
+    pub appointment_id: Uuid,
+    pub path: String,
+    /// Size of the archive, in bytes.
+    pub size: u64,
+    /// Bytes kept from an interrupted download, zero for a fresh one.
+    pub resumed_from: u64,
+}
+
+impl Tabular for DownloadedArchive {
+    fn headers() -> Vec<&'static str> {
+        vec!["appointment_id", "path", "size", "resumed_from"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.appointment_id.to_string(),
+            self.path.clone(),
+            self.size.to_string(),
+            self.resumed_from.to_string(),
+        ]
+    }
+}
+
+/// Downloads the QR code archive of an appointment into a directory.
+///
+/// The archive is written to `<name>.part`, along with its ETag in
+/// `<name>.etag`, and only renamed once complete. When a download is
+/// interrupted, the next one asks the server for the missing bytes only,
+/// provided the archive hasn't changed in the meantime.
+///
+/// # Parameters
+///
+/// - `web_url`: The base URL of the server.
+/// - `appt_id`: The UUID of the appointment.
+/// - `format`: `png`, `svg` or `jpeg` for QR codes, `pdf` for tickets, the
+///   QR format of the appointment when missing.
+/// - `directory`: Where to save the archive, created when missing.
+///
+/// # Returns
+///
+/// - A `Result<DownloadedArchive, Error>` with the path of the archive.
+pub(crate) async fn download_archive(
+    web_url: &str,
+    appt_id: Uuid,
+    format: Option<&str>,
+    directory: &Path
+) -> Result<DownloadedArchive, Error> {
+    fs::create_dir_all(directory).await?;
+    let name = match format {
+        Some(format) => format!("qr-{}-{}.zip", appt_id, format.to_lowercase()),
+        None => format!("qr-{}.zip", appt_id),
+    };
+    let path = directory.join(&name);
+    let part_path = directory.join(format!("{}.part", name));
+    let etag_path = directory.join(format!("{}.etag", name));
+
+    // Without the ETag there is no telling whether the bytes kept still
+    // belong to the archive on the server.
+    let etag = fs::read_to_string(&etag_path).await.ok();
+    let offset = match (&etag, fs::metadata(&part_path).await) {
+        (Some(_), Ok(metadata)) => metadata.len(),
+        _ => 0,
+    };
+
+    let client = reqwest::Client::new();
+    let mut request = client.get(format!("{}/api/appointment/{}/qr.zip", web_url, appt_id));
+    if let Some(format) = format {
+        request = request.query(&[("format", format)]);
+    }
+    if let (Some(etag), true) = (&etag, offset > 0) {
+        request = request
+            .header(RANGE, format!("bytes={}-", offset))
+            .header(IF_RANGE, etag.trim());
+    }
+    let response = request.send().await?;
+
+    // Nothing is missing: the last download stopped right before the rename.
+    let resumed_from = if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        let complete = response.headers().get(CONTENT_RANGE)
+            .and_then(|range| range.to_str().ok())
+            .is_some_and(|range| range == format!("bytes */{}", offset));
+        if !complete {
+            fs::remove_file(&part_path).await?;
+            return Err(anyhow!("The partial download of {} is broken and was removed, run the command again.", name));
+        }
+        offset
+    } else {
+        let mut response = ensure_success(response).await?;
+        let resuming = response.status() == StatusCode::PARTIAL_CONTENT;
+
+        match response.headers().get(ETAG).and_then(|etag| etag.to_str().ok()) {
+            Some(etag) => fs::write(&etag_path, etag).await?,
+            None => { let _ = fs::remove_file(&etag_path).await; }
+        }
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(resuming)
+            .truncate(!resuming)
+            .open(&part_path)
+            .await?;
+
+        while let Some(chunk) = response.chunk().await
+            .map_err(|e| anyhow!("Download of {} interrupted, run the command again to resume: {}", name, e))?
+        {
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        // The server sends the whole archive again when it changed.
+        if resuming { offset } else { 0 }
+    };
+
+    fs::rename(&part_path, &path).await?;
+    let _ = fs::remove_file(&etag_path).await;
+
+    Ok(DownloadedArchive {
+        appointment_id: appt_id,
+        path: path.to_string_lossy().to_string(),
+        size: fs::metadata(&path).await?.len(),
+        resumed_from,
+    })
+}
+
+/// Asynchronously downloads the ZIP archive of the QR codes, or PDF tickets,
+/// of an appointment, resuming an interrupted download.
+///
+/// # Parameters
+///
+/// - `appt_id`: The UUID of the appointment.
+/// - `format`: `png`, `svg`, `jpeg` or `pdf`, the QR format of the appointment when missing.
+/// - `directory`: Where to save the archive, the QR code directory of the appointment by default.
+/// - `output`: The format the result is rendered in.
+///
+/// # Returns
+///
+/// - A `Result<String, Error>` with the rendered path and size of the archive.
+pub async fn download_archive_handler(
+    appt_id: Uuid,
+    format: Option<String>,
+    directory: Option<PathBuf>,
+    output: OutputFormat
+) -> Result<String, Error> {
+    let configuration = get_configuration()
+        .map_err(|e| anyhow!("Failed to get configuration: {}", e))?;
+    let directory = directory.unwrap_or_else(|| {
+        Path::new(&configuration.qr_client.base_image_path).join(appt_id.to_string())
+    });
+
+    let downloaded = download_archive(&configuration.console_cli.web_url, appt_id, format.as_deref(), &directory).await?;
+    render_one(&downloaded, output)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{mock, server_url, Matcher};
+    use crate::appointment::ENV_VAR_LOCK_TEST;
+
+    const ARCHIVE: &[u8] = b"PK\x03\x04 pretend this is a whole archive";
+
+    #[tokio::test]
+    async fn test_download_archive() {
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
+        let appt_id = Uuid::new_v4();
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+
+        let _mock = mock("GET", format!("/api/appointment/{}/qr.zip", appt_id).as_str())
+            .match_query(Matcher::UrlEncoded("format".into(), "pdf".into()))
+            .with_status(200)
+            .with_header("etag", "\"v1\"")
+            .with_body(ARCHIVE)
+            .create();
+
+        let downloaded = download_archive(&server_url(), appt_id, Some("pdf"), &directory).await.unwrap();
+
+        assert_eq!(downloaded.resumed_from, 0);
+        assert_eq!(std::fs::read(&downloaded.path).unwrap(), ARCHIVE);
+        assert!(downloaded.path.ends_with(&format!("qr-{}-pdf.zip", appt_id)));
+        assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 1);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_download_is_resumed() {
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
+        let appt_id = Uuid::new_v4();
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join(format!("qr-{}.zip.part", appt_id)), &ARCHIVE[..10]).unwrap();
+        std::fs::write(directory.join(format!("qr-{}.zip.etag", appt_id)), "\"v1\"").unwrap();
+
+        let _mock = mock("GET", format!("/api/appointment/{}/qr.zip", appt_id).as_str())
+            .match_header("range", "bytes=10-")
+            .match_header("if-range", "\"v1\"")
+            .with_status(206)
+            .with_header("etag", "\"v1\"")
+            .with_header("content-range", &format!("bytes 10-{}/{}", ARCHIVE.len() - 1, ARCHIVE.len()))
+            .with_body(&ARCHIVE[10..])
+            .create();
+
+        let downloaded = download_archive(&server_url(), appt_id, None, &directory).await.unwrap();
+
+        assert_eq!(downloaded.resumed_from, 10);
+        assert_eq!(downloaded.size, ARCHIVE.len() as u64);
+        assert_eq!(std::fs::read(&downloaded.path).unwrap(), ARCHIVE);
+        assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 1);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
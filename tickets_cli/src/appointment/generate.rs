@@ -1,59 +1,204 @@
+use std::str::FromStr;
 use anyhow::{anyhow, Error};
+use serde::Serialize;
+use url::Url;
 use uuid::Uuid;
 use shared::configuration::{get_configuration};
-use shared::domain::{NewInvitation, SendAppointmentEmails};
+use shared::domain::{Appointment, AppointmentFormat, NewInvitation, SendAppointmentEmails};
+use shared::ticket_pdf::PrintableTicket;
+use crate::error::{bulk_result, ensure_success};
+use crate::job::{issue_invitations_in_job, MAX_INVITATIONS_PER_REQUEST};
+use crate::output::{render, OutputFormat, Tabular};
+use crate::retry::send_idempotent;
 use crate::QR_CLIENT;
 
-// Sends a request to the server to create invitations
-// and returns the invitations.
+/// What `generate` saves for the invitations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TicketFormat {
+    /// A QR code image per invitation.
+    #[default]
+    Png,
+    /// A4 sheets of printable tickets for all invitations.
+    Pdf,
+}
+
+impl FromStr for TicketFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "png" => Ok(TicketFormat::Png),
+            "pdf" => Ok(TicketFormat::Pdf),
+            other => Err(format!("{} is not a supported format. Use `png` or `pdf`.", other)),
+        }
+    }
+}
+
+/// A generated invitation and the path of its QR code image or ticket sheet.
+#[derive(Debug, Serialize)]
+pub struct GeneratedQrCode {
+    pub invitation_id: Uuid,
+    pub appointment_id: Uuid,
+    pub short_url: Url,
+    pub path: String,
+}
+
+impl Tabular for GeneratedQrCode {
+    fn headers() -> Vec<&'static str> {
+        vec!["invitation_id", "appointment_id", "short_url", "path"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.invitation_id.to_string(),
+            self.appointment_id.to_string(),
+            self.short_url.to_string(),
+            self.path.clone(),
+        ]
+    }
+}
+
+// Sends a request to the server to create invitations, or starts a job
+// for more than a request can take, and returns the invitations.
 async fn fetch_invitations(
     web_url: String,
     appt_id: Uuid,
-    count: i32
+    count: i32,
+    max_admissions: Option<i32>
 ) -> Result<Vec<NewInvitation>, Error> {
+    let data = SendAppointmentEmails { email: None, attendees: None };
+    if count > MAX_INVITATIONS_PER_REQUEST {
+        return issue_invitations_in_job(&web_url, appt_id, count, max_admissions, &data).await;
+    }
     let client = reqwest::Client::new();
-    let data = SendAppointmentEmails { email: None };
 
-    let response = client.post(format!("{}/api/appointment/{}/invitation?count={}", web_url, appt_id, count))
-        .json(&data)
-        .send()
-        .await?;
+    let mut url = format!("{}/api/appointment/{}/invitation?count={}", web_url, appt_id, count);
+    if let Some(max_admissions) = max_admissions {
+        url.push_str(&format!("&max_admissions={}", max_admissions));
+    }
+
+    let response = send_idempotent(client.post(url).json(&data)).await?;
 
-    let invitations: Vec<NewInvitation> = response.json::<Vec<NewInvitation>>().await?;
+    let invitations: Vec<NewInvitation> = ensure_success(response).await?.json::<Vec<NewInvitation>>().await?;
     Ok(invitations)
 }
 
-// Generates QR codes for the given list of invitations.
+// Generates QR codes for the given list of invitations,
+// returning the generated codes and the invitations that failed.
 async fn generate_qr_codes_for_invitations(
     invitations: Vec<NewInvitation>
-) -> Result<Vec<String>, Error> {
-    let mut results: Vec<String> = vec![];
+) -> (Vec<GeneratedQrCode>, Vec<(String, Error)>) {
+    let mut results = vec![];
+    let mut failures = vec![];
 
     for invitation in invitations {
-        let result: String = QR_CLIENT.generate_qr_code(
-            invitation.short_url,
+        let generated = QR_CLIENT.generate_qr_code(
+            invitation.short_url.clone(),
             invitation.appointment_id,
             invitation.id
-        ).await?;
-        results.push(result);
+        ).await;
+        match generated {
+            Ok(path) => results.push(GeneratedQrCode {
+                invitation_id: invitation.id,
+                appointment_id: invitation.appointment_id,
+                short_url: invitation.short_url,
+                path,
+            }),
+            Err(e) => failures.push((invitation.id.to_string(), e)),
+        }
     }
 
-    Ok(results)
+    (results, failures)
 }
 
+// Fetches the appointment printed on the tickets.
+async fn fetch_appointment(web_url: &str, appt_id: Uuid) -> Result<Appointment, Error> {
+    let client = reqwest::Client::new();
+
+    let response = client.get(format!("{}/api/appointment/{}", web_url, appt_id))
+        .send()
+        .await?;
+
+    Ok(ensure_success(response).await?.json::<Appointment>().await?)
+}
+
+// Prints the invitations on A4 sheets of `per_page` tickets.
+async fn generate_ticket_sheet_for_invitations(
+    appointment: &Appointment,
+    invitations: Vec<NewInvitation>,
+    per_page: usize
+) -> Result<Vec<GeneratedQrCode>, Error> {
+    let location = match appointment.format {
+        AppointmentFormat::OFFLINE => appointment.address.clone().unwrap_or_default(),
+        AppointmentFormat::ONLINE => "Online".to_string(),
+    };
+    let tickets: Vec<PrintableTicket> = invitations.iter()
+        .map(|invitation| PrintableTicket {
+            invitation_id: invitation.id,
+            title: appointment.title.clone(),
+            date: appointment.date,
+            location: location.clone(),
+            attendee_name: None,
+            max_admissions: invitation.max_admissions,
+            qr_content: invitation.short_url.to_string(),
+        })
+        .collect();
+
+    let path = QR_CLIENT.save_ticket_sheet_pdf(appointment.id, &tickets, per_page).await?;
+
+    Ok(invitations.into_iter()
+        .map(|invitation| GeneratedQrCode {
+            invitation_id: invitation.id,
+            appointment_id: invitation.appointment_id,
+            short_url: invitation.short_url,
+            path: path.clone(),
+        })
+        .collect())
+}
+
+/// Asynchronously generates invitations for an appointment and saves their QR codes,
+/// as images or as printable ticket sheets.
+///
+/// # Parameters
+///
+/// - `appt_id`: The UUID of the appointment to generate invitations for.
+/// - `count`: The number of invitations, one by default.
+/// - `max_admissions`: The number of people each invitation admits, one by default.
+/// - `format`: Whether to save a PNG per invitation or a PDF of tickets.
+/// - `per_page`: The number of tickets on each A4 page of the PDF.
+/// - `output`: The format the generated codes are rendered in.
+///
+/// # Returns
+///
+/// - A `Result<String, Error>` with the rendered codes, or a `CliError::Partial`
+///   when some QR codes couldn't be saved.
 pub async fn generate_invitation_handler(
     appt_id: Uuid,
-    count: Option<i32>
+    count: Option<i32>,
+    max_admissions: Option<i32>,
+    format: TicketFormat,
+    per_page: usize,
+    output: OutputFormat
 ) -> Result<String, Error> {
     let configuration = get_configuration()
         .map_err(|e| anyhow!("Failed to get configuration: {}", e))?;
+    let web_url = configuration.console_cli.web_url;
 
     let count: i32 = count.unwrap_or(1);
 
-    let invitations = fetch_invitations(configuration.console_cli.web_url, appt_id, count).await?;
-    let qr_codes = generate_qr_codes_for_invitations(invitations).await?;
+    // Fetched first, so that no invitations are issued for a missing appointment.
+    let appointment = match format {
+        TicketFormat::Pdf => Some(fetch_appointment(&web_url, appt_id).await?),
+        TicketFormat::Png => None,
+    };
 
-    Ok(format!("Generated to: {:?}", qr_codes))
+    let invitations = fetch_invitations(web_url, appt_id, count, max_admissions).await?;
+    let (qr_codes, failures) = match appointment {
+        Some(appointment) => (generate_ticket_sheet_for_invitations(&appointment, invitations, per_page).await?, vec![]),
+        None => generate_qr_codes_for_invitations(invitations).await,
+    };
+
+    bulk_result(render(&qr_codes, output)?, qr_codes.len(), failures)
 }
 
 
@@ -63,10 +208,11 @@ mod tests {
     use mockito::{mock, server_url};
     use std::env;
     use crate::appointment::ENV_VAR_LOCK_TEST;
+    use crate::error::CliError;
 
     #[tokio::test]
     async fn test_fetch_invitations() {
-        let _guard = ENV_VAR_LOCK_TEST.lock().unwrap();
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
 
         // Set the environment variable to override the web_url to point to the mockito server
         env::set_var("APP_CONSOLE_CLI__WEB_URL", server_url().as_str());
@@ -80,7 +226,7 @@ mod tests {
             .with_body(r#"[{"id":"6ba7b810-9dad-11d1-80b4-00c04fd430c8","appointment_id":"6ba7b812-9dad-11d1-80b4-00c04fd430c9","short_url":"https://example.com/shorturl"}]"#)
             .create();
 
-        let result = fetch_invitations(server_url(), test_appt_id, 1).await;
+        let result = fetch_invitations(server_url(), test_appt_id, 1, None).await;
 
         println!("{:?}", result);
 
@@ -94,4 +240,33 @@ mod tests {
         assert_eq!(invitations[0].appointment_id, test_appt_id);
         assert_eq!(invitations[0].short_url.to_string(), "https://example.com/shorturl".to_string());
     }
+
+    #[test]
+    fn test_parse_ticket_format() {
+        assert_eq!("PDF".parse::<TicketFormat>().unwrap(), TicketFormat::Pdf);
+        assert_eq!("png".parse::<TicketFormat>().unwrap(), TicketFormat::Png);
+        assert!("jpeg".parse::<TicketFormat>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_invitations_surfaces_server_errors() {
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
+
+        let test_appt_id = Uuid::new_v4();
+        let mock_endpoint = format!("/api/appointment/{}/invitation?count=1", test_appt_id);
+        let _mock = mock("POST", mock_endpoint.as_str())
+            .with_status(404)
+            .with_body("Not found")
+            .create();
+
+        let error = fetch_invitations(server_url(), test_appt_id, 1, None).await.unwrap_err();
+
+        match error.downcast_ref::<CliError>() {
+            Some(CliError::Server { status, message }) => {
+                assert_eq!(status.as_u16(), 404);
+                assert_eq!(message, "Not found");
+            }
+            other => panic!("Unexpected error: {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,99 @@
+use anyhow::{anyhow, Error};
+use shared::configuration::get_configuration;
+use shared::domain::{Appointment, AppointmentFilter};
+use crate::error::ensure_success;
+use crate::output::{render, OutputFormat, Tabular};
+
+impl Tabular for Appointment {
+    fn headers() -> Vec<&'static str> {
+        vec!["id", "title", "format", "date", "duration_minutes", "address", "link", "capacity", "remaining_seats"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.title.clone(),
+            format!("{:?}", self.format),
+            self.date.format("%Y/%m/%d %H:%M").to_string(),
+            (self.duration.as_secs() / 60).to_string(),
+            self.address.clone().unwrap_or_default(),
+            self.link.as_ref().map(|link| link.to_string()).unwrap_or_default(),
+            self.capacity.map(|capacity| capacity.to_string()).unwrap_or_default(),
+            self.remaining_seats.map(|remaining| remaining.to_string()).unwrap_or_default(),
+        ]
+    }
+}
+
+// Sends a request to the server to list the appointments
+// matching the given filter.
+async fn fetch_appointments(
+    web_url: &str,
+    filter: &AppointmentFilter
+) -> Result<Vec<Appointment>, Error> {
+    let client = reqwest::Client::new();
+
+    let response = client.get(format!("{}/api/appointment", web_url))
+        .query(filter)
+        .send()
+        .await?;
+
+    Ok(ensure_success(response).await?.json::<Vec<Appointment>>().await?)
+}
+
+/// Asynchronously lists appointments matching the given filter.
+///
+/// # Parameters
+///
+/// - `filter`: Date range and format the appointments must match.
+/// - `output`: The format the appointments are rendered in.
+///
+/// # Returns
+///
+/// - A `Result<String, Error>` with the rendered appointments or an error.
+pub async fn list_appointments_handler(
+    filter: AppointmentFilter,
+    output: OutputFormat
+) -> Result<String, Error> {
+    let configuration = get_configuration()
+        .map_err(|e| anyhow!("Failed to get configuration: {}", e))?;
+
+    let appointments = fetch_appointments(&configuration.console_cli.web_url, &filter).await?;
+
+    render(&appointments, output)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{mock, server_url, Matcher};
+    use shared::domain::AppointmentFormat;
+    use crate::appointment::ENV_VAR_LOCK_TEST;
+
+    #[tokio::test]
+    async fn test_fetch_appointments_with_filter() {
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
+
+        let _mock = mock("GET", "/api/appointment")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("format".into(), "OFFLINE".into()),
+                Matcher::UrlEncoded("from".into(), "2024-10-10T10:10:00".into()),
+            ]))
+            .with_status(200)
+            .with_body(r#"[{"id":"6ba7b812-9dad-11d1-80b4-00c04fd430c9","title":"Meetup","description":"Monthly","format":"OFFLINE","address":"123 Fake St.","link":null,"date":"2024-10-10T10:10:00","duration":5400,"capacity":50,"remaining_seats":48,"registration_open":false}]"#)
+            .create();
+
+        let filter = AppointmentFilter {
+            from: Some(crate::appointment::parse_datetime("2024/10/10 10:10").unwrap()),
+            to: None,
+            format: Some(AppointmentFormat::OFFLINE),
+        };
+        let appointments = fetch_appointments(&server_url(), &filter).await.unwrap();
+
+        assert_eq!(appointments.len(), 1);
+        assert_eq!(
+            render(&appointments, OutputFormat::Csv).unwrap(),
+            "id,title,format,date,duration_minutes,address,link,capacity,remaining_seats\n6ba7b812-9dad-11d1-80b4-00c04fd430c9,Meetup,OFFLINE,2024/10/10 10:10,90,123 Fake St.,,50,48"
+        );
+    }
+}
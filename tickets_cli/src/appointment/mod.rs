@@ -1,16 +1,23 @@
+pub mod attendees;
 pub mod create;
 pub mod delete;
+pub mod download;
 pub mod generate;
+pub mod list;
 pub mod send;
+pub mod show;
 
 pub use create::*;
 pub use delete::*;
+pub use download::*;
 pub use generate::*;
+pub use list::*;
 pub use send::*;
+pub use show::*;
 
 #[cfg(test)]
-use std::sync::Mutex;
+use tokio::sync::Mutex;
 #[cfg(test)]
 use once_cell::sync::Lazy;
 #[cfg(test)]
-static ENV_VAR_LOCK_TEST: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+pub(crate) static ENV_VAR_LOCK_TEST: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
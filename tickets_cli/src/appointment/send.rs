@@ -1,10 +1,114 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::fs::File;
 use anyhow::{anyhow, Error};
+use serde::Serialize;
+use url::Url;
 use uuid::Uuid;
-use shared::domain::{Email, NewInvitation, SendAppointment, SendAppointmentEmails};
+use shared::domain::{AttendeeWithInvitation, Email, Invitation, InvitationStatus, IssueOutcome, IssuedInvitation, NewAttendee, NewInvitation, SendAppointment, SendAppointmentEmails};
 use tokio::{io};
 use tokio::io::{AsyncBufReadExt, BufReader};
 use std::io::ErrorKind;
 use shared::configuration::get_configuration;
+use crate::appointment::attendees::{read_attendees_csv, ColumnMapping};
+use crate::cli::SendInvitations;
+use crate::error::{ensure_success, CliError};
+use crate::job::{issue_invitations_in_job, MAX_INVITATIONS_PER_REQUEST};
+use crate::output::{render, OutputFormat, Tabular};
+use crate::retry::send_idempotent;
+
+/// An invitation sent to an email address, or the one the address already held.
+#[derive(Debug, Serialize)]
+pub struct SentInvitation {
+    pub email: Email,
+    pub id: Uuid,
+    pub appointment_id: Uuid,
+    pub short_url: Url,
+    pub outcome: IssueOutcome,
+}
+
+impl SentInvitation {
+    /// The invitation as sent, `None` when it was issued without an address.
+    pub fn from_issued(issued: IssuedInvitation) -> Option<Self> {
+        Some(SentInvitation {
+            email: issued.email?,
+            id: issued.invitation.id,
+            appointment_id: issued.invitation.appointment_id,
+            short_url: issued.invitation.short_url,
+            outcome: issued.outcome,
+        })
+    }
+}
+
+impl Tabular for SentInvitation {
+    fn headers() -> Vec<&'static str> {
+        vec!["email", "id", "appointment_id", "short_url", "outcome"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.email.to_string(),
+            self.id.to_string(),
+            self.appointment_id.to_string(),
+            self.short_url.to_string(),
+            format!("{:?}", self.outcome),
+        ]
+    }
+}
+
+/// What happened to an attendee row of a CSV import.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttendeeStatus {
+    Sent,
+    Resent,
+    WouldSend,
+    Skipped,
+    Invalid,
+}
+
+impl Display for AttendeeStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let status = match self {
+            AttendeeStatus::Sent => "sent",
+            AttendeeStatus::Resent => "resent",
+            AttendeeStatus::WouldSend => "would_send",
+            AttendeeStatus::Skipped => "skipped",
+            AttendeeStatus::Invalid => "invalid",
+        };
+        write!(f, "{}", status)
+    }
+}
+
+/// Outcome of a single row of a CSV import.
+#[derive(Debug, Serialize)]
+pub struct AttendeeReport {
+    pub line: u64,
+    pub email: String,
+    pub name: Option<String>,
+    pub locale: Option<String>,
+    pub status: AttendeeStatus,
+    pub reason: Option<String>,
+    pub invitation_id: Option<Uuid>,
+}
+
+impl Tabular for AttendeeReport {
+    fn headers() -> Vec<&'static str> {
+        vec!["line", "email", "name", "locale", "status", "reason", "invitation_id"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.line.to_string(),
+            self.email.clone(),
+            self.name.clone().unwrap_or_default(),
+            self.locale.clone().unwrap_or_default(),
+            self.status.to_string(),
+            self.reason.clone().unwrap_or_default(),
+            self.invitation_id.map(|id| id.to_string()).unwrap_or_default(),
+        ]
+    }
+}
 
 /// Asynchronously reads a list of emails from the standard input.
 ///
@@ -16,7 +120,7 @@ use shared::configuration::get_configuration;
 /// - A `Result<Vec<Email>, io::Error>` representing the list of emails
 ///   read from the standard input or an error.
 pub async fn read_emails() -> io::Result<Vec<Email>> {
-    println!("Please list emails to send Appointment invitation':");
+    eprintln!("Please list emails to send Appointment invitation':");
 
     let mut lines = BufReader::new(io::stdin()).lines();
     let mut emails = Vec::new();
@@ -41,25 +145,33 @@ pub async fn read_emails() -> io::Result<Vec<Email>> {
 ///
 /// After ensuring the list of emails is sorted and deduplicated, an HTTP request
 /// is made to send the invitations, and the resulting invitations are returned.
+/// Addresses already invited keep their invitation, reported as `EXISTING`.
 ///
 /// # Parameters
 ///
 /// - `appt_id`: The UUID of the appointment for which the invitation letters are to be sent.
 /// - `email`: An optional list of emails to which the invitations will be sent.
+/// - `max_admissions`: The number of people each invitation admits, one by default.
+/// - `resend_existing`: Whether addresses already invited get their invitation emailed again.
+/// - `output`: The format the sent invitations are rendered in.
 ///
 /// # Returns
 ///
-/// - A `Result<String, Error>` containing a string representation of generated invitations
+/// - A `Result<String, Error>` containing the rendered invitations, one per email,
 ///   or an error.
 pub async fn send_invitation_letter_handler(
     appt_id: Uuid,
-    email: Vec<Email>
+    email: Vec<Email>,
+    max_admissions: Option<i32>,
+    resend_existing: bool,
+    output: OutputFormat
 ) -> Result<String, Error> {
     let configuration = get_configuration()
         .map_err(|e| anyhow!("Failed to get configuration: {}", e))?;
 
     let email_list = if email.is_empty() {
-        read_emails().await?
+        read_emails().await
+            .map_err(|e| CliError::Usage(e.to_string()))?
     } else {
         email
     };
@@ -73,17 +185,245 @@ pub async fn send_invitation_letter_handler(
         email: sorted_emails
     };
 
+    let data = SendAppointmentEmails { email: Some(payload.email), attendees: None };
+    let invitations = post_invitations(&configuration.console_cli.web_url, payload.id, &data, max_admissions, resend_existing).await?;
+
+    let sent: Vec<SentInvitation> = invitations.into_iter()
+        .filter_map(SentInvitation::from_issued)
+        .collect();
+
+    render(&sent, output)
+}
+
+// Sends a request to the server to create and email an invitation per recipient,
+// or starts a job for more recipients than a request can take.
+async fn post_invitations(
+    web_url: &str,
+    appt_id: Uuid,
+    data: &SendAppointmentEmails,
+    max_admissions: Option<i32>,
+    resend_existing: bool
+) -> Result<Vec<IssuedInvitation>, Error> {
+    let emails: Vec<Email> = match (&data.attendees, &data.email) {
+        (Some(attendees), _) => attendees.iter().map(|attendee| attendee.email.clone()).collect(),
+        (None, Some(emails)) => emails.clone(),
+        (None, None) => vec![],
+    };
+    if emails.len() as i32 > MAX_INVITATIONS_PER_REQUEST {
+        return post_invitations_in_job(web_url, appt_id, data, emails, max_admissions, resend_existing).await;
+    }
     let client = reqwest::Client::new();
-    let data = SendAppointmentEmails { email: Some(payload.email) };
 
-    let response = client.post(format!("{}/api/appointment/{}/invitation", configuration.console_cli.web_url, payload.id))
-        .json(&data)
+    let mut request = client.post(format!("{}/api/appointment/{}/invitation", web_url, appt_id));
+    if let Some(max_admissions) = max_admissions {
+        request = request.query(&[("max_admissions", max_admissions)]);
+    }
+    if resend_existing {
+        request = request.query(&[("resend_existing", true)]);
+    }
+
+    let response = send_idempotent(request.json(data)).await?;
+
+    Ok(ensure_success(response).await?.json::<Vec<IssuedInvitation>>().await?)
+}
+
+// Issues the invitations of the recipients not invited yet with a job.
+// A job only lists the invitations it issued, so addresses already holding an
+// active invitation are left out of it and reported with that invitation.
+async fn post_invitations_in_job(
+    web_url: &str,
+    appt_id: Uuid,
+    data: &SendAppointmentEmails,
+    emails: Vec<Email>,
+    max_admissions: Option<i32>,
+    resend_existing: bool
+) -> Result<Vec<IssuedInvitation>, Error> {
+    if resend_existing {
+        return Err(CliError::Usage(format!(
+            "`--resend-existing` takes at most {} recipients.", MAX_INVITATIONS_PER_REQUEST
+        )).into());
+    }
+    let invited = fetch_active_invitations(web_url, appt_id).await?;
+    let is_new = |email: &Email| !invited.contains_key(&email.as_ref().to_lowercase());
+
+    let data = SendAppointmentEmails {
+        email: data.email.as_ref().map(|emails| emails.iter().filter(|email| is_new(email)).cloned().collect()),
+        attendees: data.attendees.as_ref().map(|attendees| attendees.iter().filter(|attendee| is_new(&attendee.email)).cloned().collect()),
+    };
+    let count = emails.iter().filter(|email| is_new(email)).count();
+    let mut created = match count {
+        0 => vec![],
+        count => issue_invitations_in_job(web_url, appt_id, count as i32, max_admissions, &data).await?,
+    }.into_iter();
+
+    // The job issues the invitations in the order of its recipients.
+    Ok(emails.into_iter()
+        .filter_map(|email| {
+            let (invitation, outcome) = match invited.get(&email.as_ref().to_lowercase()) {
+                Some(invitation) => (invitation.clone(), IssueOutcome::EXISTING),
+                None => (created.next()?, IssueOutcome::CREATED),
+            };
+            Some(IssuedInvitation { invitation, email: Some(email), outcome, email_error: None })
+        })
+        .collect())
+}
+
+// Fetches the active invitations of the appointment, by the lowercase address
+// of their attendee.
+async fn fetch_active_invitations(web_url: &str, appt_id: Uuid) -> Result<HashMap<String, NewInvitation>, Error> {
+    let client = reqwest::Client::new();
+
+    let response = client.get(format!("{}/api/appointment/{}/attendee", web_url, appt_id))
+        .send()
+        .await?;
+    let attendees = ensure_success(response).await?.json::<Vec<AttendeeWithInvitation>>().await?;
+    let response = client.get(format!("{}/api/appointment/{}/invitation", web_url, appt_id))
+        .send()
+        .await?;
+    let invitations = ensure_success(response).await?.json::<Vec<Invitation>>().await?;
+
+    let emails: HashMap<Uuid, String> = attendees.into_iter()
+        .map(|attendee| (attendee.attendee.id, attendee.attendee.email.as_ref().to_lowercase()))
+        .collect();
+    Ok(invitations.into_iter()
+        .filter(|invitation| invitation.status == InvitationStatus::ACTIVE)
+        .filter_map(|invitation| {
+            let email = emails.get(&invitation.attendee_id?)?.clone();
+            Some((email, NewInvitation {
+                id: invitation.id,
+                appointment_id: invitation.appointment_id,
+                short_url: invitation.short_url,
+                attendee_id: invitation.attendee_id,
+                claim_deadline: invitation.claim_deadline,
+                max_admissions: invitation.max_admissions,
+            }))
+        })
+        .collect())
+}
+
+// Fetches the addresses that already received an invitation for the appointment.
+async fn fetch_recipients(web_url: &str, appt_id: Uuid) -> Result<Vec<Email>, Error> {
+    let client = reqwest::Client::new();
+
+    let response = client.get(format!("{}/api/appointment/{}/recipients", web_url, appt_id))
         .send()
         .await?;
 
-    let invitations = response.json::<Vec<NewInvitation>>().await?;
+    Ok(ensure_success(response).await?.json::<Vec<Email>>().await?)
+}
+
+/// Asynchronously sends invitations to the attendees listed in a CSV file.
+///
+/// Rows with an invalid or repeated email are reported with their line number
+/// and attendees that already received an invitation for the appointment are
+/// skipped. In dry-run mode nothing is sent and the remaining attendees are
+/// reported as `would_send`.
+///
+/// # Parameters
+///
+/// - `args`: The `send` flags, with `csv` set.
+/// - `output`: The format the per-row report is rendered in.
+///
+/// # Returns
+///
+/// - A `Result<String, Error>` with the rendered report, or a `CliError::Partial`
+///   holding it when some rows were invalid.
+pub async fn send_invitations_from_csv_handler(
+    args: SendInvitations,
+    output: OutputFormat
+) -> Result<String, Error> {
+    let path = args.csv.as_ref().ok_or_else(|| CliError::Usage("Missing `--csv`.".to_string()))?;
+    let mapping = ColumnMapping {
+        email: args.email_column.clone(),
+        name: args.name_column.clone(),
+        phone: args.phone_column.clone(),
+        locale: args.locale_column.clone(),
+    };
+    let import = File::open(path)
+        .map_err(Error::from)
+        .and_then(|file| read_attendees_csv(file, &mapping))
+        .map_err(|e| CliError::Usage(format!("{}: {:#}", path.display(), e)))?;
+
+    let configuration = get_configuration()
+        .map_err(|e| anyhow!("Failed to get configuration: {}", e))?;
+    let web_url = configuration.console_cli.web_url;
+
+    // The server reports the addresses it resends to.
+    let invited: HashSet<String> = match args.resend_existing {
+        true => HashSet::new(),
+        false => fetch_recipients(&web_url, args.appt_id).await?
+            .into_iter()
+            .map(|email| email.as_ref().to_lowercase())
+            .collect(),
+    };
+
+    let mut reports: Vec<AttendeeReport> = import.errors.iter()
+        .map(|error| AttendeeReport {
+            line: error.line,
+            email: error.value.clone(),
+            name: None,
+            locale: None,
+            status: AttendeeStatus::Invalid,
+            reason: Some(error.reason.clone()),
+            invitation_id: None,
+        })
+        .collect();
+
+    let (skipped, to_send): (Vec<_>, Vec<_>) = import.attendees.into_iter()
+        .partition(|attendee| invited.contains(&attendee.email.as_ref().to_lowercase()));
+
+    reports.extend(skipped.into_iter().map(|attendee| AttendeeReport {
+        line: attendee.line,
+        email: attendee.email.into_inner(),
+        name: attendee.name,
+        locale: attendee.locale,
+        status: AttendeeStatus::Skipped,
+        reason: Some("already invited.".to_string()),
+        invitation_id: None,
+    }));
 
-    Ok(format!("Generated invitations: {:?}", invitations))
+    // Invitations are matched to their rows by address, whatever order the server returns them in.
+    let mut issued: HashMap<String, IssuedInvitation> = if args.dry_run || to_send.is_empty() {
+        HashMap::new()
+    } else {
+        let attendees: Vec<NewAttendee> = to_send.iter().cloned().map(NewAttendee::from).collect();
+        let data = SendAppointmentEmails { email: None, attendees: Some(attendees) };
+        post_invitations(&web_url, args.appt_id, &data, args.max_admissions, args.resend_existing).await?
+            .into_iter()
+            .filter_map(|issued| Some((issued.email.as_ref()?.as_ref().to_lowercase(), issued)))
+            .collect()
+    };
+
+    reports.extend(to_send.into_iter().map(|attendee| {
+        let issued = issued.remove(&attendee.email.as_ref().to_lowercase());
+        let (status, reason) = match issued.as_ref().map(|issued| issued.outcome) {
+            None if args.dry_run => (AttendeeStatus::WouldSend, None),
+            None => (AttendeeStatus::Skipped, Some("no invitation returned.".to_string())),
+            Some(IssueOutcome::EXISTING) if args.resend_existing => (AttendeeStatus::Resent, Some("already invited.".to_string())),
+            Some(IssueOutcome::EXISTING) => (AttendeeStatus::Skipped, Some("already invited.".to_string())),
+            _ => (AttendeeStatus::Sent, None),
+        };
+        AttendeeReport {
+            line: attendee.line,
+            email: attendee.email.into_inner(),
+            name: attendee.name,
+            locale: attendee.locale,
+            status,
+            reason,
+            invitation_id: issued.map(|issued| issued.invitation.id),
+        }
+    }));
+    reports.sort_by_key(|report| report.line);
+
+    let rendered = render(&reports, output)?;
+    if import.errors.is_empty() {
+        Ok(rendered)
+    } else {
+        let failures = import.errors.iter()
+            .map(|error| format!("line {}: {}", error.line, error.reason))
+            .collect();
+        Err(CliError::Partial { output: rendered, failures }.into())
+    }
 }
 
 
@@ -95,11 +435,33 @@ mod tests {
     use mockito::{mock, server_url};
     use std::env;
     use crate::appointment::ENV_VAR_LOCK_TEST; // Import the environment variable handling module
+    use mockito::Matcher;
+
+    fn send_args(csv: std::path::PathBuf, appt_id: Uuid, dry_run: bool) -> SendInvitations {
+        SendInvitations {
+            appt_id,
+            email: vec![],
+            csv: Some(csv),
+            email_column: "email".to_string(),
+            name_column: Some("name".to_string()),
+            phone_column: None,
+            locale_column: None,
+            dry_run,
+            max_admissions: None,
+            resend_existing: false,
+        }
+    }
+
+    fn write_csv() -> std::path::PathBuf {
+        let file = env::temp_dir().join(format!("{}.csv", Uuid::new_v4()));
+        std::fs::write(&file, "name,email\nAda,ada@example.com\nBad,bad-email\nGrace,grace@example.com\n").unwrap();
+        file
+    }
 
     #[tokio::test]
     async fn test_send_invitation_letter_handler() {
         // Set the environment variable to override the web_url to point to the mockito server
-        let _guard = ENV_VAR_LOCK_TEST.lock().unwrap();
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
         env::set_var("APP_CONSOLE_CLI__WEB_URL", server_url().as_str());
 
         let test_appt_id = Uuid::parse_str("6ba7b812-9dad-11d1-80b4-00c04fd430c9").unwrap();
@@ -109,10 +471,10 @@ mod tests {
         let mock_endpoint = format!("/api/appointment/{}/invitation", test_appt_id);
         let _mock = mock("POST", mock_endpoint.as_str())
             .with_status(200)
-            .with_body(r#"[{"id":"6ba7b810-9dad-11d1-80b4-00c04fd430c8","appointment_id":"6ba7b812-9dad-11d1-80b4-00c04fd430c9","short_url":"https://example.com/shorturl"}]"#)
+            .with_body(r#"[{"id":"6ba7b810-9dad-11d1-80b4-00c04fd430c8","appointment_id":"6ba7b812-9dad-11d1-80b4-00c04fd430c9","short_url":"https://example.com/shorturl","email":"test@gmail.com","outcome":"CREATED"}]"#)
             .create();
 
-        let result = send_invitation_letter_handler(test_appt_id, test_email).await;
+        let result = send_invitation_letter_handler(test_appt_id, test_email, None, false, OutputFormat::Json).await;
 
         println!("{:?}", result);
 
@@ -120,6 +482,167 @@ mod tests {
         env::remove_var("APP_CONSOLE_CLI__WEB_URL");
 
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), "Generated invitations: [NewInvitation { id: 6ba7b810-9dad-11d1-80b4-00c04fd430c8, appointment_id: 6ba7b812-9dad-11d1-80b4-00c04fd430c9, short_url: Url { scheme: \"https\", cannot_be_a_base: false, username: \"\", password: None, host: Some(Domain(\"example.com\")), port: None, path: \"/shorturl\", query: None, fragment: None } }]");
+        assert_eq!(result.unwrap(), r#"[{"email":"test@gmail.com","id":"6ba7b810-9dad-11d1-80b4-00c04fd430c8","appointment_id":"6ba7b812-9dad-11d1-80b4-00c04fd430c9","short_url":"https://example.com/shorturl","outcome":"CREATED"}]"#);
+    }
+
+    #[tokio::test]
+    async fn test_send_from_csv_skips_invited_and_reports_invalid_rows() {
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
+        env::set_var("APP_CONSOLE_CLI__WEB_URL", server_url().as_str());
+
+        let test_appt_id = Uuid::parse_str("6ba7b812-9dad-11d1-80b4-00c04fd430c9").unwrap();
+        let _recipients = mock("GET", format!("/api/appointment/{}/recipients", test_appt_id).as_str())
+            .with_status(200)
+            .with_body(r#"["ADA@example.com"]"#)
+            .create();
+        let send = mock("POST", format!("/api/appointment/{}/invitation", test_appt_id).as_str())
+            .match_body(Matcher::PartialJson(serde_json::json!({ "attendees": [{ "name": "Grace", "email": "grace@example.com" }] })))
+            .with_status(200)
+            .with_body(r#"[{"id":"6ba7b810-9dad-11d1-80b4-00c04fd430c8","appointment_id":"6ba7b812-9dad-11d1-80b4-00c04fd430c9","short_url":"https://example.com/shorturl","email":"grace@example.com","outcome":"CREATED"}]"#)
+            .create();
+
+        let file = write_csv();
+        let result = send_invitations_from_csv_handler(send_args(file.clone(), test_appt_id, false), OutputFormat::Csv).await;
+
+        env::remove_var("APP_CONSOLE_CLI__WEB_URL");
+        std::fs::remove_file(file).unwrap();
+
+        send.assert();
+        match result.unwrap_err().downcast::<CliError>().unwrap() {
+            CliError::Partial { output, failures } => {
+                assert_eq!(output, "line,email,name,locale,status,reason,invitation_id\n\
+                    2,ada@example.com,Ada,,skipped,already invited.,\n\
+                    3,bad-email,,,invalid,bad-email is not a valid email string.,\n\
+                    4,grace@example.com,Grace,,sent,,6ba7b810-9dad-11d1-80b4-00c04fd430c8");
+                assert_eq!(failures, vec!["line 3: bad-email is not a valid email string."]);
+            }
+            other => panic!("Unexpected error: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_from_csv_dry_run_sends_nothing() {
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
+        env::set_var("APP_CONSOLE_CLI__WEB_URL", server_url().as_str());
+
+        let test_appt_id = Uuid::new_v4();
+        let _recipients = mock("GET", format!("/api/appointment/{}/recipients", test_appt_id).as_str())
+            .with_status(200)
+            .with_body("[]")
+            .create();
+        let send = mock("POST", format!("/api/appointment/{}/invitation", test_appt_id).as_str())
+            .expect(0)
+            .create();
+
+        let file = write_csv();
+        let result = send_invitations_from_csv_handler(send_args(file.clone(), test_appt_id, true), OutputFormat::Json).await;
+
+        env::remove_var("APP_CONSOLE_CLI__WEB_URL");
+        std::fs::remove_file(file).unwrap();
+
+        send.assert();
+        let output = match result.unwrap_err().downcast::<CliError>().unwrap() {
+            CliError::Partial { output, .. } => output,
+            other => panic!("Unexpected error: {:?}", other),
+        };
+        let reports: Vec<serde_json::Value> = serde_json::from_str(&output).unwrap();
+        let statuses: Vec<&str> = reports.iter().map(|report| report["status"].as_str().unwrap()).collect();
+        assert_eq!(statuses, vec!["would_send", "invalid", "would_send"]);
+    }
+
+    #[tokio::test]
+    async fn test_send_from_csv_resends_to_invited_attendees() {
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
+        env::set_var("APP_CONSOLE_CLI__WEB_URL", server_url().as_str());
+
+        let test_appt_id = Uuid::new_v4();
+        let (ada_id, grace_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let recipients = mock("GET", format!("/api/appointment/{}/recipients", test_appt_id).as_str())
+            .expect(0)
+            .create();
+        let send = mock("POST", format!("/api/appointment/{}/invitation", test_appt_id).as_str())
+            .match_query(Matcher::UrlEncoded("resend_existing".into(), "true".into()))
+            .with_status(200)
+            .with_body(serde_json::json!([
+                {"id": grace_id, "appointment_id": test_appt_id, "short_url": "https://example.com/grace", "email": "Grace@example.com", "outcome": "CREATED"},
+                {"id": ada_id, "appointment_id": test_appt_id, "short_url": "https://example.com/ada", "email": "ada@example.com", "outcome": "EXISTING"}
+            ]).to_string())
+            .create();
+
+        let file = write_csv();
+        let mut args = send_args(file.clone(), test_appt_id, false);
+        args.resend_existing = true;
+        let result = send_invitations_from_csv_handler(args, OutputFormat::Json).await;
+
+        env::remove_var("APP_CONSOLE_CLI__WEB_URL");
+        std::fs::remove_file(file).unwrap();
+
+        recipients.assert();
+        send.assert();
+        let output = match result.unwrap_err().downcast::<CliError>().unwrap() {
+            CliError::Partial { output, .. } => output,
+            other => panic!("Unexpected error: {:?}", other),
+        };
+        let reports: Vec<serde_json::Value> = serde_json::from_str(&output).unwrap();
+        let statuses: Vec<&str> = reports.iter().map(|report| report["status"].as_str().unwrap()).collect();
+        assert_eq!(statuses, vec!["resent", "invalid", "sent"]);
+        let invitation_ids: Vec<&str> = reports.iter().map(|report| report["invitation_id"].as_str().unwrap_or_default()).collect();
+        assert_eq!(invitation_ids, vec![ada_id.to_string().as_str(), "", grace_id.to_string().as_str()]);
+    }
+
+    #[tokio::test]
+    async fn test_job_reports_addresses_already_invited() {
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
+        let test_appt_id = Uuid::new_v4();
+        let (job_id, ada_id, ada_invitation_id, grace_invitation_id) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let job = |status: &str, processed: i32| serde_json::json!({
+            "id": job_id,
+            "appointment_id": test_appt_id,
+            "status": status,
+            "total": 1,
+            "processed": processed,
+            "max_admissions": 1,
+            "errors": [],
+            "created_at": "2024-10-10T10:10:00",
+            "updated_at": "2024-10-10T10:10:00"
+        }).to_string();
+
+        let _attendees = mock("GET", format!("/api/appointment/{}/attendee", test_appt_id).as_str())
+            .with_status(200)
+            .with_body(serde_json::json!([
+                {"id": ada_id, "appointment_id": test_appt_id, "name": "Ada", "email": "ADA@example.com", "phone": null, "custom_fields": {}, "invitation_id": ada_invitation_id, "used": false}
+            ]).to_string())
+            .create();
+        let _invitations = mock("GET", format!("/api/appointment/{}/invitation", test_appt_id).as_str())
+            .with_status(200)
+            .with_body(serde_json::json!([
+                {"id": Uuid::new_v4(), "appointment_id": test_appt_id, "used": false, "short_url": "https://example.com/revoked", "attendee_id": ada_id, "status": "REVOKED", "rsvp": "PENDING", "claim_deadline": null, "max_admissions": 1, "admitted": 0},
+                {"id": ada_invitation_id, "appointment_id": test_appt_id, "used": false, "short_url": "https://example.com/ada", "attendee_id": ada_id, "status": "ACTIVE", "rsvp": "PENDING", "claim_deadline": null, "max_admissions": 1, "admitted": 0}
+            ]).to_string())
+            .create();
+        let start = mock("POST", format!("/api/appointment/{}/invitation/jobs", test_appt_id).as_str())
+            .match_query(Matcher::UrlEncoded("count".into(), "1".into()))
+            .match_body(Matcher::Json(serde_json::json!({ "email": ["grace@example.com"], "attendees": null })))
+            .with_status(202)
+            .with_body(job("RUNNING", 0))
+            .create();
+        let _poll = mock("GET", format!("/api/jobs/{}", job_id).as_str())
+            .with_status(200)
+            .with_body(job("COMPLETED", 1))
+            .create();
+        let _created = mock("GET", format!("/api/jobs/{}/invitations", job_id).as_str())
+            .with_status(200)
+            .with_body(serde_json::json!([
+                {"id": grace_invitation_id, "appointment_id": test_appt_id, "short_url": "https://example.com/grace"}
+            ]).to_string())
+            .create();
+
+        let emails = vec![Email::from_str("ada@example.com").unwrap(), Email::from_str("grace@example.com").unwrap()];
+        let data = SendAppointmentEmails { email: Some(emails.clone()), attendees: None };
+        let issued = post_invitations_in_job(&server_url(), test_appt_id, &data, emails, None, false).await.unwrap();
+
+        start.assert();
+        let outcomes: Vec<(Uuid, IssueOutcome)> = issued.iter().map(|issued| (issued.invitation.id, issued.outcome)).collect();
+        assert_eq!(outcomes, vec![(ada_invitation_id, IssueOutcome::EXISTING), (grace_invitation_id, IssueOutcome::CREATED)]);
     }
 }
@@ -0,0 +1,128 @@
+use anyhow::{anyhow, Error};
+use serde::Serialize;
+use uuid::Uuid;
+use shared::configuration::get_configuration;
+use shared::domain::{Appointment, InvitationCounts};
+use crate::error::ensure_success;
+use crate::output::{render_one, OutputFormat, Tabular};
+
+/// An appointment together with the counts of its invitations.
+#[derive(Debug, Serialize)]
+pub struct AppointmentDetails {
+    #[serde(flatten)]
+    pub appointment: Appointment,
+    pub invitations: InvitationCounts,
+}
+
+impl Tabular for AppointmentDetails {
+    fn headers() -> Vec<&'static str> {
+        let mut headers = Appointment::headers();
+        headers.extend(["description", "invitations_total", "invitations_used", "invitations_unused", "invitations_declined"]);
+        headers
+    }
+
+    fn row(&self) -> Vec<String> {
+        let mut row = self.appointment.row();
+        row.extend([
+            self.appointment.description.clone(),
+            self.invitations.total.to_string(),
+            self.invitations.used.to_string(),
+            self.invitations.unused.to_string(),
+            self.invitations.declined.to_string(),
+        ]);
+        row
+    }
+}
+
+// Fetches an appointment and the counts of its invitations from the server.
+async fn fetch_appointment_details(
+    web_url: &str,
+    appt_id: Uuid
+) -> Result<AppointmentDetails, Error> {
+    let client = reqwest::Client::new();
+
+    let response = client.get(format!("{}/api/appointment/{}", web_url, appt_id))
+        .send()
+        .await?;
+    let appointment = ensure_success(response).await?.json::<Appointment>().await?;
+
+    let response = client.get(format!("{}/api/appointment/{}/invitation/counts", web_url, appt_id))
+        .send()
+        .await?;
+    let invitations = ensure_success(response).await?.json::<InvitationCounts>().await?;
+
+    Ok(AppointmentDetails {
+        appointment,
+        invitations,
+    })
+}
+
+/// Asynchronously shows an appointment with its total, used, unused and declined invitation counts.
+///
+/// # Parameters
+///
+/// - `appt_id`: The UUID of the appointment to show.
+/// - `output`: The format the appointment is rendered in.
+///
+/// # Returns
+///
+/// - A `Result<String, Error>` with the rendered appointment or an error.
+pub async fn show_appointment_handler(
+    appt_id: Uuid,
+    output: OutputFormat
+) -> Result<String, Error> {
+    let configuration = get_configuration()
+        .map_err(|e| anyhow!("Failed to get configuration: {}", e))?;
+
+    let details = fetch_appointment_details(&configuration.console_cli.web_url, appt_id).await?;
+
+    render_one(&details, output)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{mock, server_url};
+    use crate::appointment::ENV_VAR_LOCK_TEST;
+
+    #[tokio::test]
+    async fn test_fetch_appointment_details() {
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
+
+        let appt_id = Uuid::parse_str("6ba7b812-9dad-11d1-80b4-00c04fd430c9").unwrap();
+
+        let _appointment_mock = mock("GET", format!("/api/appointment/{}", appt_id).as_str())
+            .with_status(200)
+            .with_body(r#"{"id":"6ba7b812-9dad-11d1-80b4-00c04fd430c9","title":"Meetup","description":"Monthly","format":"OFFLINE","address":"123 Fake St.","link":null,"date":"2024-10-10T10:10:00","duration":5400,"capacity":null,"remaining_seats":null,"registration_open":false}"#)
+            .create();
+        let _counts_mock = mock("GET", format!("/api/appointment/{}/invitation/counts", appt_id).as_str())
+            .with_status(200)
+            .with_body(r#"{"total":3,"used":1,"unused":1,"declined":1}"#)
+            .create();
+
+        let details = fetch_appointment_details(&server_url(), appt_id).await.unwrap();
+
+        assert_eq!(details.appointment.id, appt_id);
+        assert_eq!(details.invitations.total, 3);
+        assert_eq!(details.invitations.used, 1);
+        assert_eq!(details.invitations.unused, 1);
+        assert_eq!(details.invitations.declined, 1);
+
+        let json: serde_json::Value = serde_json::from_str(&render_one(&details, OutputFormat::Json).unwrap()).unwrap();
+        assert_eq!(json["title"], "Meetup");
+        assert_eq!(json["invitations"]["unused"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_missing_appointment_fails() {
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
+
+        let appt_id = Uuid::new_v4();
+        let _mock = mock("GET", format!("/api/appointment/{}", appt_id).as_str())
+            .with_status(404)
+            .create();
+
+        assert!(fetch_appointment_details(&server_url(), appt_id).await.is_err());
+    }
+}
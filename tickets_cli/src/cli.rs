@@ -1,23 +1,46 @@
+use std::path::PathBuf;
+use chrono::NaiveDateTime;
 use structopt::StructOpt;
+use url::Url;
 use uuid::Uuid;
-use shared::domain::Email;
+use shared::domain::{AppointmentFormat, Email};
+use crate::appointment::{parse_datetime, TicketFormat};
+use crate::output::OutputFormat;
 
 /// Command-line arguments for the application.
 #[derive(Debug, StructOpt)]
 pub struct CliArgs {
-    /// Optional authentication token to authorize certain operations.
+    /// Optional authentication token to authorize certain operations, e.g. the scanner token of `scan`.
     #[structopt(short, long = "auth-token", env = "AUTH_TOKEN", hide_env_values = true)]
-    pub(crate) _auth_token: Option<String>,
+    pub(crate) auth_token: Option<String>,
+
+    /// Output format of the results: `table`, `json` or `csv`.
+    #[structopt(short, long, default_value = "table")]
+    pub(crate) output: OutputFormat,
 
     /// The main command to execute.
     #[structopt(subcommand)]
     pub(crate) cmd: Command,
 }
 
+// Parsed once per run, so the size of the `create` flags doesn't matter.
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, StructOpt)]
 pub(crate) enum Command {
     /// Manages appointment interactions.
     Appointment(Appointment),
+
+    /// Inspects invitations.
+    Invitation(Invitation),
+
+    /// Inspects and reorders the waitlist of an appointment.
+    Waitlist(Waitlist),
+
+    /// Follows and resumes invitations issued in the background.
+    Job(Job),
+
+    /// Checks people in at the door, keeping working while offline.
+    Scan(ScanArgs),
 }
 
 
@@ -30,8 +53,29 @@ pub(crate) struct Appointment {
 
 #[derive(Debug, StructOpt)]
 pub(crate) enum AppointmentCommand {
-    /// Creates a new appointment.
-    Create,
+    /// Creates new appointments from flags or a file, prompting for missing fields.
+    Create(CreateAppointment),
+
+    /// Lists appointments, optionally filtered by date and format.
+    List {
+        /// Only list appointments starting at or after this date.
+        #[structopt(long, parse(try_from_str = parse_datetime))]
+        from: Option<NaiveDateTime>,
+
+        /// Only list appointments starting at or before this date.
+        #[structopt(long, parse(try_from_str = parse_datetime))]
+        to: Option<NaiveDateTime>,
+
+        /// Only list appointments of this format: `online` or `offline`.
+        #[structopt(long)]
+        format: Option<AppointmentFormat>,
+    },
+
+    /// Shows an appointment with its invitation counts.
+    Show {
+        /// ID of the appointment to show.
+        id: Uuid,
+    },
 
     /// Deletes a specified appointment.
     Delete {
@@ -49,16 +93,299 @@ pub(crate) enum AppointmentCommand {
         /// Number of QR codes to generate.
         #[structopt(short)]
         count: Option<i32>,
+
+        /// Number of people each invitation admits, one by default.
+        #[structopt(long)]
+        max_admissions: Option<i32>,
+
+        /// What to save: a QR code image per invitation (`png`) or printable A4 ticket sheets (`pdf`).
+        #[structopt(long, default_value = "png")]
+        format: TicketFormat,
+
+        /// Number of tickets on each page of a PDF, from 1 to 10.
+        #[structopt(long, default_value = "8")]
+        per_page: usize,
     },
 
     /// Sends emails containing QR codes for a specified appointment.
-    Send {
-        /// ID of the appointment for which to send QR codes.
+    Send(SendInvitations),
+
+    /// Downloads a ZIP of the QR codes, or PDF tickets, of all active invitations,
+    /// resuming an interrupted download.
+    Download {
+        /// ID of the appointment to download the QR codes of.
+        #[structopt(short)]
+        appt_id: Uuid,
+
+        /// `png`, `svg` or `jpeg` for QR codes, `pdf` for tickets; the QR format of the appointment by default.
+        #[structopt(long)]
+        format: Option<String>,
+
+        /// Directory to save the archive in, the QR code directory of the appointment by default.
+        #[structopt(long, parse(from_os_str))]
+        dir: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub(crate) struct SendInvitations {
+    /// ID of the appointment for which to send QR codes.
+    #[structopt(short)]
+    pub(crate) appt_id: Uuid,
+
+    /// Email addresses to send QR codes to.
+    #[structopt(short)]
+    pub(crate) email: Vec<Email>,
+
+    /// CSV file of attendees with a header row; attendees already invited are
+    /// skipped unless `--resend-existing` is given.
+    #[structopt(long, parse(from_os_str), conflicts_with = "email")]
+    pub(crate) csv: Option<PathBuf>,
+
+    /// CSV column holding the email address.
+    #[structopt(long, default_value = "email", requires = "csv")]
+    pub(crate) email_column: String,
+
+    /// CSV column holding the attendee name.
+    #[structopt(long, requires = "csv")]
+    pub(crate) name_column: Option<String>,
+
+    /// CSV column holding the attendee phone number.
+    #[structopt(long, requires = "csv")]
+    pub(crate) phone_column: Option<String>,
+
+    /// CSV column holding the attendee locale.
+    #[structopt(long, requires = "csv")]
+    pub(crate) locale_column: Option<String>,
+
+    /// Only report what would be sent, without sending anything.
+    #[structopt(long, requires = "csv")]
+    pub(crate) dry_run: bool,
+
+    /// Number of people each invitation admits, one by default.
+    #[structopt(long)]
+    pub(crate) max_admissions: Option<i32>,
+
+    /// Email addresses already invited their invitation again, instead of
+    /// only reporting it.
+    #[structopt(long)]
+    pub(crate) resend_existing: bool,
+}
+
+#[derive(Debug, StructOpt)]
+pub(crate) struct Invitation {
+    /// Specifies the specific invitation action to perform.
+    #[structopt(subcommand)]
+    pub(crate) invitation_command: InvitationCommand,
+}
+
+#[derive(Debug, StructOpt)]
+pub(crate) enum InvitationCommand {
+    /// Shows an invitation.
+    Show {
+        /// ID of the invitation to show.
+        id: Uuid,
+    },
+
+    /// Revokes an invitation, so its QR code no longer validates.
+    Revoke {
+        /// ID of the invitation to revoke.
+        id: Uuid,
+
+        /// Why the invitation is revoked, kept in its history.
+        #[structopt(long)]
+        reason: Option<String>,
+    },
+
+    /// Replaces an invitation with a fresh code for the same attendee.
+    Reissue {
+        /// ID of the invitation to reissue.
+        id: Uuid,
+
+        /// Why the invitation is reissued, kept in its history.
+        #[structopt(long)]
+        reason: Option<String>,
+    },
+
+    /// Hands an invitation over to someone else, notifying both parties.
+    Transfer {
+        /// ID of the invitation to transfer.
+        id: Uuid,
+
+        /// Email address of the new holder.
+        #[structopt(long)]
+        email: Email,
+
+        /// Name of the new holder.
+        #[structopt(long)]
+        name: Option<String>,
+
+        /// Why the invitation is transferred, kept in its history.
+        #[structopt(long)]
+        reason: Option<String>,
+    },
+
+    /// Emails an invitation again with its current QR code. With `-a` instead
+    /// of an ID, resends every invitation of the appointment whose holder
+    /// hasn't checked in or joined online yet.
+    Resend {
+        /// ID of the invitation to resend.
+        #[structopt(required_unless = "appt-id")]
+        id: Option<Uuid>,
+
+        /// Corrected email address of the attendee, replacing the one on file.
+        #[structopt(long, conflicts_with = "appt-id")]
+        email: Option<Email>,
+
+        /// ID of the appointment whose pending invitations are all resent.
+        #[structopt(short, conflicts_with = "id")]
+        appt_id: Option<Uuid>,
+    },
+
+    /// Lists the changes made to an invitation.
+    History {
+        /// ID of the invitation.
+        id: Uuid,
+    },
+
+    /// Tells which invitation a QR code screenshot belongs to, without using it up.
+    Inspect {
+        /// Image holding the QR code.
+        #[structopt(parse(from_os_str))]
+        image: PathBuf,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub(crate) struct Waitlist {
+    /// Specifies the specific waitlist action to perform.
+    #[structopt(subcommand)]
+    pub(crate) waitlist_command: WaitlistCommand,
+}
+
+#[derive(Debug, StructOpt)]
+pub(crate) enum WaitlistCommand {
+    /// Lists the people waiting for a seat, in queue order.
+    List {
+        /// ID of the appointment.
         #[structopt(short)]
         appt_id: Uuid,
+    },
 
-        /// Email addresses to send QR codes to.
+    /// Moves a person to another place of the waitlist.
+    Move {
+        /// ID of the appointment.
         #[structopt(short)]
-        email: Vec<Email>
+        appt_id: Uuid,
+
+        /// ID of the waitlist entry to move.
+        id: Uuid,
+
+        /// New 1-based place in the waitlist.
+        #[structopt(short, long)]
+        position: i32,
+    },
+
+    /// Invites people from the waitlist to the free seats.
+    Promote {
+        /// ID of the appointment.
+        #[structopt(short)]
+        appt_id: Uuid,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub(crate) struct Job {
+    /// Specifies the specific job action to perform.
+    #[structopt(subcommand)]
+    pub(crate) job_command: JobCommand,
+}
+
+#[derive(Debug, StructOpt)]
+pub(crate) enum JobCommand {
+    /// Shows an invitation job with its progress and errors.
+    Show {
+        /// ID of the job.
+        id: Uuid,
     },
-}
\ No newline at end of file
+
+    /// Resumes a failed invitation job and shows its progress until it finishes.
+    Resume {
+        /// ID of the job.
+        id: Uuid,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub(crate) struct ScanArgs {
+    /// ID of the appointment to check people in for.
+    #[structopt(short, long = "appt-id")]
+    pub(crate) appt_id: Uuid,
+
+    /// Scanned codes: invitation IDs, validation or short URLs, or signed tickets.
+    /// Read line by line from stdin when neither codes nor images are given.
+    pub(crate) codes: Vec<String>,
+
+    /// Images of QR codes to decode and validate.
+    #[structopt(long, parse(from_os_str))]
+    pub(crate) image: Vec<PathBuf>,
+
+    /// Name of this scanner, shown with the check-ins of the local store.
+    #[structopt(long)]
+    pub(crate) scanner: Option<String>,
+
+    /// Local store of invitations and check-ins, `scanner-<appt-id>.json` by default.
+    #[structopt(long, parse(from_os_str))]
+    pub(crate) store: Option<PathBuf>,
+
+    /// Only uses the local store, without contacting the server.
+    #[structopt(long)]
+    pub(crate) offline: bool,
+}
+
+#[derive(Debug, Default, StructOpt)]
+pub(crate) struct CreateAppointment {
+    /// Title of the appointment.
+    #[structopt(long)]
+    pub(crate) title: Option<String>,
+
+    /// Description of the appointment.
+    #[structopt(long)]
+    pub(crate) description: Option<String>,
+
+    /// Format of the appointment: `online` or `offline`.
+    #[structopt(long)]
+    pub(crate) format: Option<AppointmentFormat>,
+
+    /// Address of an OFFLINE appointment.
+    #[structopt(long)]
+    pub(crate) address: Option<String>,
+
+    /// Meeting link of an ONLINE appointment.
+    #[structopt(long)]
+    pub(crate) link: Option<Url>,
+
+    /// Start date in 'yyyy/mm/dd hh:mm' UTC time or RFC3339 format.
+    #[structopt(long, parse(try_from_str = parse_datetime))]
+    pub(crate) date: Option<NaiveDateTime>,
+
+    /// Duration in minutes.
+    #[structopt(long)]
+    pub(crate) duration: Option<u64>,
+
+    /// Maximum number of invitations; unlimited when omitted.
+    #[structopt(long)]
+    pub(crate) capacity: Option<i32>,
+
+    /// Lets anyone register through the public registration page.
+    #[structopt(long)]
+    pub(crate) registration_open: bool,
+
+    /// JSON or YAML file holding one or many appointments; excludes the other flags.
+    #[structopt(
+        long,
+        parse(from_os_str),
+        conflicts_with_all = &["title", "description", "format", "address", "link", "date", "duration", "capacity", "registration_open"]
+    )]
+    pub(crate) from_file: Option<PathBuf>,
+}
@@ -0,0 +1,197 @@
+//! Errors that map to the process exit code of the CLI.
+
+use std::process::ExitCode;
+use anyhow::Error;
+use reqwest::{Response, StatusCode};
+use serde::Serialize;
+use serde_json::json;
+
+/// Unexpected failure, e.g. configuration or network errors.
+pub const EXIT_FAILURE: u8 = 1;
+/// Invalid arguments or input.
+pub const EXIT_USAGE: u8 = 2;
+/// The server answered with a non-2xx status.
+pub const EXIT_SERVER: u8 = 3;
+/// Some items of a bulk command failed while others succeeded.
+pub const EXIT_PARTIAL: u8 = 4;
+
+/// Errors with a dedicated exit code.
+///
+/// Handlers return `anyhow::Error`, so these are wrapped into it and
+/// recovered with `downcast_ref` when the process exits. Any other error
+/// exits with [`EXIT_FAILURE`].
+#[derive(Debug, thiserror::Error)]
+pub enum CliError {
+    #[error("{0}")]
+    Usage(String),
+    #[error("Server responded with {status}: {message}")]
+    Server {
+        status: StatusCode,
+        message: String,
+    },
+    #[error("{} item(s) failed:\n{}", failures.len(), failures.join("\n"))]
+    Partial {
+        /// Rendered result of the items that succeeded.
+        output: String,
+        failures: Vec<String>,
+    },
+}
+
+impl CliError {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            CliError::Usage(_) => EXIT_USAGE,
+            CliError::Server { .. } => EXIT_SERVER,
+            CliError::Partial { .. } => EXIT_PARTIAL,
+        }
+    }
+}
+
+/// Machine-readable form of an error, printed to stderr with `--output json`.
+#[derive(Debug, Serialize)]
+pub struct ErrorReport {
+    pub kind: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub failures: Vec<String>,
+}
+
+impl From<&Error> for ErrorReport {
+    fn from(error: &Error) -> Self {
+        match error.downcast_ref::<CliError>() {
+            Some(CliError::Usage(message)) => ErrorReport {
+                kind: "usage",
+                message: message.clone(),
+                status: None,
+                failures: vec![],
+            },
+            Some(CliError::Server { status, message }) => ErrorReport {
+                kind: "server",
+                message: message.clone(),
+                status: Some(status.as_u16()),
+                failures: vec![],
+            },
+            Some(CliError::Partial { failures, .. }) => ErrorReport {
+                kind: "partial",
+                message: format!("{} item(s) failed", failures.len()),
+                status: None,
+                failures: failures.clone(),
+            },
+            None => ErrorReport {
+                kind: "failure",
+                message: format!("{:#}", error),
+                status: None,
+                failures: vec![],
+            },
+        }
+    }
+}
+
+/// Prints an error and returns the exit code matching it.
+///
+/// The output of a partial failure goes to stdout so it can still be piped,
+/// everything else goes to stderr, as JSON when `json` is set.
+pub fn report_error(error: &Error, json: bool) -> ExitCode {
+    let cli_error = error.downcast_ref::<CliError>();
+
+    if let Some(CliError::Partial { output, .. }) = cli_error {
+        println!("{}", output);
+    }
+
+    if json {
+        eprintln!("{}", json!({ "error": ErrorReport::from(error) }));
+    } else {
+        eprintln!("Error: {:#}", error);
+    }
+
+    ExitCode::from(cli_error.map_or(EXIT_FAILURE, CliError::exit_code))
+}
+
+/// Combines the outcome of a command that processes several items.
+///
+/// Succeeds when no item failed and returns a [`CliError::Partial`] when only
+/// some did. When every item failed, the first error is returned so that it
+/// keeps its own exit code.
+///
+/// # Parameters
+///
+/// - `rendered`: Rendered result of the items that succeeded.
+/// - `succeeded`: Number of items that succeeded.
+/// - `failures`: Label and error of every item that failed.
+pub fn bulk_result(
+    rendered: String,
+    succeeded: usize,
+    failures: Vec<(String, Error)>
+) -> Result<String, Error> {
+    if failures.is_empty() {
+        return Ok(rendered);
+    }
+    if succeeded == 0 {
+        let (label, error) = failures.into_iter().next().expect("failures is not empty");
+        return Err(error.context(label));
+    }
+
+    let failures = failures.into_iter()
+        .map(|(label, error)| format!("{}: {:#}", label, error))
+        .collect();
+    Err(CliError::Partial { output: rendered, failures }.into())
+}
+
+/// Turns a non-2xx response into a [`CliError::Server`] carrying the response body.
+pub async fn ensure_success(response: Response) -> Result<Response, Error> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let message = response.text().await.unwrap_or_default();
+    let message = if message.trim().is_empty() {
+        status.canonical_reason().unwrap_or("unknown error").to_string()
+    } else {
+        message
+    };
+
+    Err(CliError::Server { status, message }.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn test_exit_codes() {
+        let usage: Error = CliError::Usage("bad flag".to_string()).into();
+        let server: Error = CliError::Server { status: StatusCode::NOT_FOUND, message: "missing".to_string() }.into();
+        let partial: Error = CliError::Partial { output: "[]".to_string(), failures: vec!["one".to_string()] }.into();
+
+        assert_eq!(usage.downcast_ref::<CliError>().unwrap().exit_code(), EXIT_USAGE);
+        assert_eq!(server.downcast_ref::<CliError>().unwrap().exit_code(), EXIT_SERVER);
+        assert_eq!(partial.downcast_ref::<CliError>().unwrap().exit_code(), EXIT_PARTIAL);
+        assert!(anyhow!("other").downcast_ref::<CliError>().is_none());
+    }
+
+    #[test]
+    fn test_bulk_result() {
+        assert_eq!(bulk_result("[]".to_string(), 0, vec![]).unwrap(), "[]");
+
+        let server = || -> Error { CliError::Server { status: StatusCode::BAD_GATEWAY, message: "down".to_string() }.into() };
+
+        let all_failed = bulk_result("[]".to_string(), 0, vec![("#1".to_string(), server())]).unwrap_err();
+        assert_eq!(all_failed.downcast_ref::<CliError>().unwrap().exit_code(), EXIT_SERVER);
+
+        let some_failed = bulk_result("[1]".to_string(), 1, vec![("#2".to_string(), server())]).unwrap_err();
+        assert_eq!(some_failed.downcast_ref::<CliError>().unwrap().exit_code(), EXIT_PARTIAL);
+    }
+
+    #[test]
+    fn test_error_report_schema() {
+        let server: Error = CliError::Server { status: StatusCode::NOT_FOUND, message: "missing".to_string() }.into();
+
+        let report = serde_json::to_value(ErrorReport::from(&server)).unwrap();
+
+        assert_eq!(report, json!({ "kind": "server", "message": "missing", "status": 404 }));
+    }
+}
@@ -0,0 +1,179 @@
+use anyhow::{anyhow, Error};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use uuid::Uuid;
+use shared::configuration::get_configuration;
+use shared::domain::{EditInvitation, Email, Invitation, NewInvitation, TransferInvitation};
+use crate::error::ensure_success;
+use crate::output::{render_one, OutputFormat};
+
+// Posts a change of an invitation to the server, returning the affected invitation.
+pub(crate) async fn send_invitation_change<B: Serialize, T: DeserializeOwned>(
+    web_url: &str,
+    invitation_id: Uuid,
+    action: &str,
+    body: &B
+) -> Result<T, Error> {
+    let client = reqwest::Client::new();
+
+    let response = client.post(format!("{}/api/invitation/{}/{}", web_url, invitation_id, action))
+        .json(body)
+        .send()
+        .await?;
+
+    Ok(ensure_success(response).await?.json::<T>().await?)
+}
+
+/// Asynchronously revokes an invitation.
+///
+/// # Parameters
+///
+/// - `invitation_id`: The UUID of the invitation to revoke.
+/// - `reason`: Why the invitation is revoked.
+/// - `output`: The format the revoked invitation is rendered in.
+///
+/// # Returns
+///
+/// - A `Result<String, Error>` with the rendered invitation or an error.
+pub async fn revoke_invitation_handler(
+    invitation_id: Uuid,
+    reason: Option<String>,
+    output: OutputFormat
+) -> Result<String, Error> {
+    let configuration = get_configuration()
+        .map_err(|e| anyhow!("Failed to get configuration: {}", e))?;
+
+    let invitation: Invitation = send_invitation_change(
+        &configuration.console_cli.web_url,
+        invitation_id,
+        "revoke",
+        &EditInvitation { reason }
+    ).await?;
+
+    render_one(&invitation, output)
+}
+
+/// Asynchronously replaces an invitation with a fresh code for the same attendee.
+///
+/// # Parameters
+///
+/// - `invitation_id`: The UUID of the invitation to reissue.
+/// - `reason`: Why the invitation is reissued.
+/// - `output`: The format the new invitation is rendered in.
+///
+/// # Returns
+///
+/// - A `Result<String, Error>` with the rendered new invitation or an error.
+pub async fn reissue_invitation_handler(
+    invitation_id: Uuid,
+    reason: Option<String>,
+    output: OutputFormat
+) -> Result<String, Error> {
+    let configuration = get_configuration()
+        .map_err(|e| anyhow!("Failed to get configuration: {}", e))?;
+
+    let invitation: NewInvitation = send_invitation_change(
+        &configuration.console_cli.web_url,
+        invitation_id,
+        "reissue",
+        &EditInvitation { reason }
+    ).await?;
+
+    render_one(&invitation, output)
+}
+
+/// Asynchronously transfers an invitation to someone else.
+///
+/// # Parameters
+///
+/// - `invitation_id`: The UUID of the invitation to transfer.
+/// - `email`: The email address of the new holder.
+/// - `name`: The name of the new holder.
+/// - `reason`: Why the invitation is transferred.
+/// - `output`: The format the new invitation is rendered in.
+///
+/// # Returns
+///
+/// - A `Result<String, Error>` with the rendered new invitation or an error.
+pub async fn transfer_invitation_handler(
+    invitation_id: Uuid,
+    email: Email,
+    name: Option<String>,
+    reason: Option<String>,
+    output: OutputFormat
+) -> Result<String, Error> {
+    let configuration = get_configuration()
+        .map_err(|e| anyhow!("Failed to get configuration: {}", e))?;
+
+    let invitation: NewInvitation = send_invitation_change(
+        &configuration.console_cli.web_url,
+        invitation_id,
+        "transfer",
+        &TransferInvitation { email, name, reason }
+    ).await?;
+
+    render_one(&invitation, output)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{mock, server_url, Matcher};
+    use crate::appointment::ENV_VAR_LOCK_TEST;
+
+    #[tokio::test]
+    async fn test_revoke_inactive_invitation_fails() {
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
+
+        let invitation_id = Uuid::new_v4();
+        let _mock = mock("POST", format!("/api/invitation/{}/revoke", invitation_id).as_str())
+            .match_body(Matcher::Json(serde_json::json!({"reason": "duplicate"})))
+            .with_status(409)
+            .with_body(format!("Invitation {} is not active", invitation_id))
+            .create();
+
+        let error = send_invitation_change::<_, Invitation>(
+            &server_url(),
+            invitation_id,
+            "revoke",
+            &EditInvitation { reason: Some("duplicate".to_string()) }
+        ).await.unwrap_err();
+
+        assert!(error.to_string().contains("is not active"));
+    }
+
+    #[tokio::test]
+    async fn test_transfer_returns_new_invitation() {
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
+
+        let invitation_id = Uuid::new_v4();
+        let new_id = Uuid::new_v4();
+        let appointment_id = Uuid::new_v4();
+        let _mock = mock("POST", format!("/api/invitation/{}/transfer", invitation_id).as_str())
+            .match_body(Matcher::PartialJson(serde_json::json!({"email": "new@example.com"})))
+            .with_status(200)
+            .with_body(serde_json::json!({
+                "id": new_id,
+                "appointment_id": appointment_id,
+                "short_url": "https://short.url/xyz",
+                "attendee_id": Uuid::new_v4(),
+                "claim_deadline": null
+            }).to_string())
+            .create();
+
+        let invitation: NewInvitation = send_invitation_change(
+            &server_url(),
+            invitation_id,
+            "transfer",
+            &TransferInvitation {
+                email: Email::parse("new@example.com".to_string()).unwrap(),
+                name: None,
+                reason: None
+            }
+        ).await.unwrap();
+
+        assert_eq!(invitation.id, new_id);
+        assert_eq!(invitation.appointment_id, appointment_id);
+    }
+}
@@ -0,0 +1,88 @@
+use anyhow::{anyhow, Error};
+use uuid::Uuid;
+use shared::configuration::get_configuration;
+use shared::domain::InvitationHistory;
+use crate::error::ensure_success;
+use crate::output::{render, OutputFormat, Tabular};
+
+impl Tabular for InvitationHistory {
+    fn headers() -> Vec<&'static str> {
+        vec!["id", "invitation_id", "action", "details", "created_at"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.invitation_id.to_string(),
+            format!("{:?}", self.action),
+            self.details.to_string(),
+            self.created_at.format("%Y/%m/%d %H:%M").to_string(),
+        ]
+    }
+}
+
+// Fetches the history of an invitation from the server.
+async fn fetch_invitation_history(
+    web_url: &str,
+    invitation_id: Uuid
+) -> Result<Vec<InvitationHistory>, Error> {
+    let client = reqwest::Client::new();
+
+    let response = client.get(format!("{}/api/invitation/{}/history", web_url, invitation_id))
+        .send()
+        .await?;
+
+    Ok(ensure_success(response).await?.json::<Vec<InvitationHistory>>().await?)
+}
+
+/// Asynchronously lists the changes made to an invitation.
+///
+/// # Parameters
+///
+/// - `invitation_id`: The UUID of the invitation.
+/// - `output`: The format the history is rendered in.
+///
+/// # Returns
+///
+/// - A `Result<String, Error>` with the rendered history or an error.
+pub async fn invitation_history_handler(
+    invitation_id: Uuid,
+    output: OutputFormat
+) -> Result<String, Error> {
+    let configuration = get_configuration()
+        .map_err(|e| anyhow!("Failed to get configuration: {}", e))?;
+
+    let history = fetch_invitation_history(&configuration.console_cli.web_url, invitation_id).await?;
+
+    render(&history, output)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{mock, server_url};
+    use crate::appointment::ENV_VAR_LOCK_TEST;
+
+    #[tokio::test]
+    async fn test_fetch_invitation_history() {
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
+
+        let invitation_id = Uuid::new_v4();
+        let _mock = mock("GET", format!("/api/invitation/{}/history", invitation_id).as_str())
+            .with_status(200)
+            .with_body(serde_json::json!([{
+                "id": 1,
+                "invitation_id": invitation_id,
+                "action": "REVOKED",
+                "details": {"reason": "lost"},
+                "created_at": "2023-11-18T10:00:00"
+            }]).to_string())
+            .create();
+
+        let history = fetch_invitation_history(&server_url(), invitation_id).await.unwrap();
+
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].details["reason"], "lost");
+    }
+}
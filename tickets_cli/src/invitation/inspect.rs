@@ -0,0 +1,235 @@
+use std::path::Path;
+use anyhow::{anyhow, Error};
+use chrono::{NaiveDateTime, Utc};
+use reqwest::header::LOCATION;
+use reqwest::redirect::Policy;
+use serde::Serialize;
+use url::Url;
+use uuid::Uuid;
+use shared::configuration::get_configuration;
+use shared::domain::{Appointment, InvitationStatus};
+use shared::ticket::{PublicTicketKey, TicketClaims, TicketError, TicketVerifier};
+use crate::error::ensure_success;
+use crate::invitation::fetch_invitation;
+use crate::output::{render, OutputFormat, Tabular};
+use crate::scan::{decode_qr_image, ScannedCode};
+
+/// What a QR code says about its invitation, read without validating it.
+#[derive(Debug, Serialize)]
+pub struct InvitationInspection {
+    pub code: String,
+    pub invitation_id: Uuid,
+    pub appointment_id: Uuid,
+    pub title: String,
+    pub date: NaiveDateTime,
+    pub status: InvitationStatus,
+    pub used: bool,
+    pub admission: String,
+    /// Whether the appointment is over, or the signed ticket no longer valid.
+    pub expired: bool,
+    /// Outcome of the signature check, for signed tickets only.
+    pub ticket: Option<String>,
+    /// `unused`, `partially used`, `used`, `expired`, `declined` or `revoked`.
+    pub state: String,
+}
+
+impl Tabular for InvitationInspection {
+    fn headers() -> Vec<&'static str> {
+        vec!["invitation_id", "appointment_id", "title", "date", "state", "admitted", "ticket", "code"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.invitation_id.to_string(),
+            self.appointment_id.to_string(),
+            self.title.clone(),
+            self.date.format("%Y/%m/%d %H:%M").to_string(),
+            self.state.clone(),
+            self.admission.clone(),
+            self.ticket.clone().unwrap_or_default(),
+            self.code.clone(),
+        ]
+    }
+}
+
+// Asks the shortener where a short URL points to, without following the redirect,
+// so the validation endpoint behind it is never hit.
+async fn resolve_short_url(short_url: &Url) -> Result<Uuid, Error> {
+    let client = reqwest::Client::builder()
+        .redirect(Policy::none())
+        .build()?;
+
+    let response = client.get(short_url.clone()).send().await?;
+    let location = response.headers()
+        .get(LOCATION)
+        .and_then(|location| location.to_str().ok())
+        .ok_or_else(|| anyhow!("{} doesn't redirect anywhere ({}).", short_url, response.status()))?;
+
+    match ScannedCode::parse(location)? {
+        ScannedCode::Invitation(id) => Ok(id),
+        _ => Err(anyhow!("{} doesn't point to an invitation but to {}.", short_url, location)),
+    }
+}
+
+// Checks the signature and validity window of a ticket with the keys of the server.
+async fn check_ticket(web_url: &str, ticket: &str, now: NaiveDateTime) -> Result<Result<TicketClaims, TicketError>, Error> {
+    let client = reqwest::Client::new();
+
+    let response = client.get(format!("{}/api/ticket/keys", web_url))
+        .send()
+        .await?;
+    let keys = ensure_success(response).await?.json::<Vec<PublicTicketKey>>().await?;
+
+    Ok(TicketVerifier::new(&keys)?.verify(ticket, now))
+}
+
+// Works out the invitation of a code and gathers its state from the server.
+async fn inspect_code(web_url: &str, code: &str, now: NaiveDateTime) -> Result<InvitationInspection, Error> {
+    let (invitation_id, ticket) = match ScannedCode::parse(code)? {
+        ScannedCode::Invitation(id) => (id, None),
+        ScannedCode::ShortUrl(url) => (resolve_short_url(&url).await?, None),
+        ScannedCode::Ticket(ticket) => {
+            // A tampered ticket still tells support which invitation it claims to be.
+            let claims = TicketClaims::read_unverified(&ticket)?;
+            (claims.invitation_id, Some(check_ticket(web_url, &ticket, now).await?))
+        }
+    };
+
+    let invitation = fetch_invitation(web_url, invitation_id).await?;
+    let response = reqwest::Client::new()
+        .get(format!("{}/api/appointment/{}", web_url, invitation.appointment_id))
+        .send()
+        .await?;
+    let appointment = ensure_success(response).await?.json::<Appointment>().await?;
+
+    let ticket_expired = matches!(ticket, Some(Err(TicketError::Expired(_))));
+    let ends_at = appointment.date + chrono::Duration::seconds(appointment.duration.as_secs() as i64);
+    let expired = ticket_expired || ends_at < now;
+    let ticket = ticket.map(|check| match check {
+        Ok(claims) => format!("valid until {}", claims.not_after.format("%Y/%m/%d %H:%M")),
+        Err(e) => e.to_string(),
+    });
+    let state = match invitation.status {
+        InvitationStatus::REVOKED => "revoked",
+        InvitationStatus::DECLINED => "declined",
+        InvitationStatus::ACTIVE if invitation.used => "used",
+        InvitationStatus::ACTIVE if expired => "expired",
+        InvitationStatus::ACTIVE if invitation.admitted > 0 => "partially used",
+        InvitationStatus::ACTIVE => "unused",
+    };
+
+    Ok(InvitationInspection {
+        code: code.trim().to_string(),
+        invitation_id,
+        appointment_id: appointment.id,
+        title: appointment.title,
+        date: appointment.date,
+        used: invitation.used,
+        admission: format!("{} of {}", invitation.admitted, invitation.max_admissions),
+        status: invitation.status,
+        expired,
+        ticket,
+        state: state.to_string(),
+    })
+}
+
+/// Asynchronously tells which invitation a QR code image belongs to.
+///
+/// The code is never validated, so inspecting a ticket doesn't use it up.
+///
+/// # Parameters
+///
+/// - `image`: Path of a screenshot or photo of the QR code.
+/// - `output`: The format the inspection is rendered in.
+///
+/// # Returns
+///
+/// - A `Result<String, Error>` with an inspection per QR code in the image, or an error.
+pub async fn inspect_invitation_handler(
+    image: &Path,
+    output: OutputFormat
+) -> Result<String, Error> {
+    let configuration = get_configuration()
+        .map_err(|e| anyhow!("Failed to get configuration: {}", e))?;
+    let now = Utc::now().naive_utc();
+
+    let mut inspections = vec![];
+    for code in decode_qr_image(image)? {
+        inspections.push(inspect_code(&configuration.console_cli.web_url, &code, now).await?);
+    }
+
+    render(&inspections, output)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{mock, server_url};
+    use crate::appointment::ENV_VAR_LOCK_TEST;
+
+    #[tokio::test]
+    async fn test_short_url_is_resolved_without_following_it() {
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
+
+        let invitation_id = Uuid::new_v4();
+        let _short = mock("GET", "/short/abc")
+            .with_status(302)
+            .with_header("location", &format!("{}/api/validations/{}", server_url(), invitation_id))
+            .create();
+        let validation = mock("GET", format!("/api/validations/{}", invitation_id).as_str())
+            .expect(0)
+            .create();
+
+        let short_url = Url::parse(&format!("{}/short/abc", server_url())).unwrap();
+        assert_eq!(resolve_short_url(&short_url).await.unwrap(), invitation_id);
+        validation.assert();
+    }
+
+    #[tokio::test]
+    async fn test_inspect_reports_revoked_invitation() {
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
+
+        let invitation_id = Uuid::new_v4();
+        let appointment_id = Uuid::new_v4();
+        let _invitation = mock("GET", format!("/api/invitation/{}", invitation_id).as_str())
+            .with_status(200)
+            .with_body(serde_json::json!({
+                "id": invitation_id,
+                "appointment_id": appointment_id,
+                "used": false,
+                "short_url": "https://short.url/abc",
+                "attendee_id": null,
+                "status": "REVOKED",
+                "rsvp": "PENDING",
+                "claim_deadline": null,
+                "max_admissions": 2,
+                "admitted": 1
+            }).to_string())
+            .create();
+        let _appointment = mock("GET", format!("/api/appointment/{}", appointment_id).as_str())
+            .with_status(200)
+            .with_body(serde_json::json!({
+                "id": appointment_id,
+                "title": "Test Appointment",
+                "description": "Test Description",
+                "format": "OFFLINE",
+                "address": "123 Fake St.",
+                "link": null,
+                "date": "2023-12-01T10:00:00",
+                "duration": 3600,
+                "capacity": null,
+                "remaining_seats": null,
+                "registration_open": false
+            }).to_string())
+            .create();
+
+        let now = NaiveDateTime::parse_from_str("2023-11-20 10:00", "%Y-%m-%d %H:%M").unwrap();
+        let inspection = inspect_code(&server_url(), &invitation_id.to_string(), now).await.unwrap();
+
+        assert_eq!(inspection.state, "revoked");
+        assert_eq!(inspection.admission, "1 of 2");
+        assert!(!inspection.expired);
+        assert_eq!(inspection.ticket, None);
+    }
+}
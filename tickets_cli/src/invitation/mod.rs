@@ -0,0 +1,11 @@
+pub mod show;
+pub mod change;
+pub mod history;
+pub mod inspect;
+pub mod resend;
+
+pub use show::*;
+pub use change::*;
+pub use history::*;
+pub use inspect::*;
+pub use resend::*;
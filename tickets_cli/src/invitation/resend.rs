@@ -0,0 +1,148 @@
+use anyhow::{anyhow, Error};
+use uuid::Uuid;
+use shared::configuration::get_configuration;
+use shared::domain::{Email, IssuedInvitation, ResendInvitation};
+use crate::appointment::SentInvitation;
+use crate::error::ensure_success;
+use crate::invitation::send_invitation_change;
+use crate::output::{render, render_one, OutputFormat};
+use crate::retry::send_idempotent;
+
+// Asks the server to email every pending invitation of an appointment again.
+// Retries share the idempotency key, so nobody gets the email twice.
+pub(crate) async fn post_pending_resend(
+    web_url: &str,
+    appt_id: Uuid
+) -> Result<Vec<IssuedInvitation>, Error> {
+    let client = reqwest::Client::new();
+
+    let response = send_idempotent(
+        client.post(format!("{}/api/appointment/{}/invitation/resend", web_url, appt_id))
+    ).await?;
+
+    Ok(ensure_success(response).await?.json::<Vec<IssuedInvitation>>().await?)
+}
+
+/// Asynchronously emails an invitation again with its current QR code.
+///
+/// # Parameters
+///
+/// - `invitation_id`: The UUID of the invitation to resend.
+/// - `email`: A corrected email address of the attendee, replacing the one on file.
+/// - `output`: The format the resent invitation is rendered in.
+///
+/// # Returns
+///
+/// - A `Result<String, Error>` with the rendered invitation or an error.
+pub async fn resend_invitation_handler(
+    invitation_id: Uuid,
+    email: Option<Email>,
+    output: OutputFormat
+) -> Result<String, Error> {
+    let configuration = get_configuration()
+        .map_err(|e| anyhow!("Failed to get configuration: {}", e))?;
+
+    let issued: IssuedInvitation = send_invitation_change(
+        &configuration.console_cli.web_url,
+        invitation_id,
+        "resend",
+        &ResendInvitation { email }
+    ).await?;
+    let sent = SentInvitation::from_issued(issued)
+        .ok_or_else(|| anyhow!("Invitation {} was resent without an address", invitation_id))?;
+
+    render_one(&sent, output)
+}
+
+/// Asynchronously emails every invitation of an appointment whose holder
+/// hasn't checked in or joined online yet once more.
+///
+/// # Parameters
+///
+/// - `appt_id`: The UUID of the appointment.
+/// - `output`: The format the resent invitations are rendered in.
+///
+/// # Returns
+///
+/// - A `Result<String, Error>` with the rendered invitations or an error.
+pub async fn resend_pending_invitations_handler(
+    appt_id: Uuid,
+    output: OutputFormat
+) -> Result<String, Error> {
+    let configuration = get_configuration()
+        .map_err(|e| anyhow!("Failed to get configuration: {}", e))?;
+
+    let sent: Vec<SentInvitation> = post_pending_resend(&configuration.console_cli.web_url, appt_id).await?
+        .into_iter()
+        .filter_map(SentInvitation::from_issued)
+        .collect();
+
+    render(&sent, output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{mock, server_url, Matcher};
+    use shared::domain::IssueOutcome;
+    use crate::appointment::ENV_VAR_LOCK_TEST;
+
+    #[tokio::test]
+    async fn test_resend_to_corrected_address() {
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
+        std::env::set_var("APP_CONSOLE_CLI__WEB_URL", server_url());
+
+        let invitation_id = Uuid::new_v4();
+        let _mock = mock("POST", format!("/api/invitation/{}/resend", invitation_id).as_str())
+            .match_body(Matcher::Json(serde_json::json!({"email": "fixed@example.com"})))
+            .with_status(200)
+            .with_body(serde_json::json!({
+                "id": invitation_id,
+                "appointment_id": Uuid::new_v4(),
+                "short_url": "https://short.url/xyz",
+                "attendee_id": Uuid::new_v4(),
+                "claim_deadline": null,
+                "max_admissions": 1,
+                "email": "fixed@example.com",
+                "outcome": "EXISTING"
+            }).to_string())
+            .create();
+
+        let result = resend_invitation_handler(
+            invitation_id,
+            Some(Email::parse("fixed@example.com".to_string()).unwrap()),
+            OutputFormat::Json
+        ).await.unwrap();
+
+        let sent: serde_json::Value = serde_json::from_str(&result).unwrap();
+        assert_eq!(sent["id"], invitation_id.to_string());
+        assert_eq!(sent["email"], "fixed@example.com");
+    }
+
+    #[tokio::test]
+    async fn test_resend_to_pending_attendees() {
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
+
+        let appt_id = Uuid::new_v4();
+        let _mock = mock("POST", format!("/api/appointment/{}/invitation/resend", appt_id).as_str())
+            .match_header("Idempotency-Key", Matcher::Any)
+            .with_status(200)
+            .with_body(serde_json::json!([{
+                "id": Uuid::new_v4(),
+                "appointment_id": appt_id,
+                "short_url": "https://short.url/abc",
+                "attendee_id": Uuid::new_v4(),
+                "claim_deadline": null,
+                "max_admissions": 1,
+                "email": "late@example.com",
+                "outcome": "EXISTING"
+            }]).to_string())
+            .create();
+
+        let issued = post_pending_resend(&server_url(), appt_id).await.unwrap();
+
+        assert_eq!(issued.len(), 1);
+        assert_eq!(issued[0].email.as_ref().unwrap().as_ref(), "late@example.com");
+        assert_eq!(issued[0].outcome, IssueOutcome::EXISTING);
+    }
+}
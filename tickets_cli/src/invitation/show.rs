@@ -0,0 +1,61 @@
+use anyhow::{anyhow, Error};
+use uuid::Uuid;
+use shared::configuration::get_configuration;
+use shared::domain::Invitation;
+use crate::error::ensure_success;
+use crate::output::{render_one, OutputFormat, Tabular};
+
+impl Tabular for Invitation {
+    fn headers() -> Vec<&'static str> {
+        vec!["id", "appointment_id", "used", "admitted", "status", "rsvp", "short_url", "claim_deadline"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.appointment_id.to_string(),
+            self.used.to_string(),
+            format!("{} of {}", self.admitted, self.max_admissions),
+            format!("{:?}", self.status),
+            format!("{:?}", self.rsvp),
+            self.short_url.to_string(),
+            self.claim_deadline.map(|deadline| deadline.format("%Y/%m/%d %H:%M").to_string()).unwrap_or_default(),
+        ]
+    }
+}
+
+// Fetches an invitation from the server.
+pub(crate) async fn fetch_invitation(
+    web_url: &str,
+    invitation_id: Uuid
+) -> Result<Invitation, Error> {
+    let client = reqwest::Client::new();
+
+    let response = client.get(format!("{}/api/invitation/{}", web_url, invitation_id))
+        .send()
+        .await?;
+
+    Ok(ensure_success(response).await?.json::<Invitation>().await?)
+}
+
+/// Asynchronously shows an invitation.
+///
+/// # Parameters
+///
+/// - `invitation_id`: The UUID of the invitation to show.
+/// - `output`: The format the invitation is rendered in.
+///
+/// # Returns
+///
+/// - A `Result<String, Error>` with the rendered invitation or an error.
+pub async fn show_invitation_handler(
+    invitation_id: Uuid,
+    output: OutputFormat
+) -> Result<String, Error> {
+    let configuration = get_configuration()
+        .map_err(|e| anyhow!("Failed to get configuration: {}", e))?;
+
+    let invitation = fetch_invitation(&configuration.console_cli.web_url, invitation_id).await?;
+
+    render_one(&invitation, output)
+}
@@ -0,0 +1,5 @@
+pub mod run;
+pub mod show;
+
+pub use run::*;
+pub use show::*;
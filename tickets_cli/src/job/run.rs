@@ -0,0 +1,207 @@
+use std::io::{self, IsTerminal, Write};
+use std::time::Duration;
+use anyhow::{anyhow, Error};
+use uuid::Uuid;
+use shared::domain::{InvitationJob, JobStatus, NewInvitation, SendAppointmentEmails};
+use crate::error::ensure_success;
+
+/// Largest number of invitations the server issues within a single request,
+/// more are issued by an invitation job.
+pub(crate) const MAX_INVITATIONS_PER_REQUEST: i32 = 1000;
+
+/// How often a running job is polled for its progress.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+const PROGRESS_BAR_WIDTH: usize = 40;
+
+// Asks the server to issue invitations in the background.
+async fn start_invitation_job(
+    web_url: &str,
+    appt_id: Uuid,
+    count: i32,
+    max_admissions: Option<i32>,
+    data: &SendAppointmentEmails
+) -> Result<InvitationJob, Error> {
+    let client = reqwest::Client::new();
+
+    let mut request = client.post(format!("{}/api/appointment/{}/invitation/jobs", web_url, appt_id))
+        .query(&[("count", count)]);
+    if let Some(max_admissions) = max_admissions {
+        request = request.query(&[("max_admissions", max_admissions)]);
+    }
+    let response = request.json(data).send().await?;
+
+    Ok(ensure_success(response).await?.json::<InvitationJob>().await?)
+}
+
+// Fetches a job with its progress.
+pub(crate) async fn fetch_job(web_url: &str, job_id: Uuid) -> Result<InvitationJob, Error> {
+    let client = reqwest::Client::new();
+
+    let response = client.get(format!("{}/api/jobs/{}", web_url, job_id))
+        .send()
+        .await?;
+
+    Ok(ensure_success(response).await?.json::<InvitationJob>().await?)
+}
+
+// Asks the server to carry on with a failed job.
+pub(crate) async fn send_resume_job(web_url: &str, job_id: Uuid) -> Result<InvitationJob, Error> {
+    let client = reqwest::Client::new();
+
+    let response = client.post(format!("{}/api/jobs/{}/resume", web_url, job_id))
+        .send()
+        .await?;
+
+    Ok(ensure_success(response).await?.json::<InvitationJob>().await?)
+}
+
+// Fetches the invitations issued by a job, in the order of its recipients.
+pub(crate) async fn fetch_job_invitations(web_url: &str, job_id: Uuid) -> Result<Vec<NewInvitation>, Error> {
+    let client = reqwest::Client::new();
+
+    let response = client.get(format!("{}/api/jobs/{}/invitations", web_url, job_id))
+        .send()
+        .await?;
+
+    Ok(ensure_success(response).await?.json::<Vec<NewInvitation>>().await?)
+}
+
+/// Polls a job until it completes or fails, drawing its progress on stderr
+/// when it is a terminal.
+///
+/// # Parameters
+///
+/// - `web_url`: The base URL of the server.
+/// - `job`: The job as last seen.
+///
+/// # Returns
+///
+/// - A `Result<InvitationJob, Error>` with the finished job, failed or not.
+pub(crate) async fn follow_job(web_url: &str, mut job: InvitationJob) -> Result<InvitationJob, Error> {
+    let progress = io::stderr().is_terminal();
+    while !job.status.is_finished() {
+        if progress {
+            draw_progress(&job);
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+        job = fetch_job(web_url, job.id).await?;
+    }
+    if progress {
+        draw_progress(&job);
+        eprintln!();
+    }
+    Ok(job)
+}
+
+fn draw_progress(job: &InvitationJob) {
+    let done = match job.total {
+        0 => PROGRESS_BAR_WIDTH,
+        total => PROGRESS_BAR_WIDTH * job.processed.max(0) as usize / total as usize,
+    };
+    eprint!(
+        "\r[{}{}] {}/{} invitations",
+        "#".repeat(done),
+        "-".repeat(PROGRESS_BAR_WIDTH.saturating_sub(done)),
+        job.processed,
+        job.total
+    );
+    let _ = io::stderr().flush();
+}
+
+/// Error for a job that stopped, telling how to resume it.
+pub(crate) fn job_failed(job: &InvitationJob) -> Error {
+    anyhow!(
+        "Invitation job {} stopped after {} of {} invitations: {}. Resume it with `tickets_cli job resume {}`.",
+        job.id,
+        job.processed,
+        job.total,
+        job.errors.last().map(String::as_str).unwrap_or("unknown error"),
+        job.id
+    )
+}
+
+/// Issues invitations with an invitation job, following its progress, and
+/// returns them once all are issued.
+///
+/// # Parameters
+///
+/// - `web_url`: The base URL of the server.
+/// - `appt_id`: The UUID of the appointment.
+/// - `count`: The number of invitations, ignored by the server when recipients are given.
+/// - `max_admissions`: The number of people each invitation admits, one by default.
+/// - `data`: The recipients to email the invitations to, when any.
+///
+/// # Returns
+///
+/// - A `Result<Vec<NewInvitation>, Error>` with the invitations in the order of
+///   the recipients, or an error telling how to resume the job when it failed.
+pub async fn issue_invitations_in_job(
+    web_url: &str,
+    appt_id: Uuid,
+    count: i32,
+    max_admissions: Option<i32>,
+    data: &SendAppointmentEmails
+) -> Result<Vec<NewInvitation>, Error> {
+    let job = start_invitation_job(web_url, appt_id, count, max_admissions, data).await?;
+    let job = follow_job(web_url, job).await?;
+    match job.status {
+        JobStatus::COMPLETED => fetch_job_invitations(web_url, job.id).await,
+        _ => Err(job_failed(&job)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{mock, server_url, Matcher};
+    use serde_json::json;
+    use crate::appointment::ENV_VAR_LOCK_TEST;
+
+    #[tokio::test]
+    async fn test_issue_invitations_in_job_returns_them_once_completed() {
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
+        let appt_id = Uuid::new_v4();
+        let job_id = Uuid::new_v4();
+        let job = |status: &str, processed: i32| json!({
+            "id": job_id,
+            "appointment_id": appt_id,
+            "status": status,
+            "total": 2,
+            "processed": processed,
+            "max_admissions": 3,
+            "errors": [],
+            "created_at": "2024-10-10T10:10:00",
+            "updated_at": "2024-10-10T10:10:00"
+        }).to_string();
+
+        let start = mock("POST", format!("/api/appointment/{}/invitation/jobs", appt_id).as_str())
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("count".into(), "2".into()),
+                Matcher::UrlEncoded("max_admissions".into(), "3".into()),
+            ]))
+            .with_status(202)
+            .with_body(job("RUNNING", 0))
+            .create();
+        let poll = mock("GET", format!("/api/jobs/{}", job_id).as_str())
+            .with_status(200)
+            .with_body(job("COMPLETED", 2))
+            .create();
+        let invitations = mock("GET", format!("/api/jobs/{}/invitations", job_id).as_str())
+            .with_status(200)
+            .with_body(json!([
+                {"id": Uuid::new_v4(), "appointment_id": appt_id, "short_url": "https://short.url/a", "max_admissions": 3},
+                {"id": Uuid::new_v4(), "appointment_id": appt_id, "short_url": "https://short.url/b", "max_admissions": 3}
+            ]).to_string())
+            .create();
+
+        let data = SendAppointmentEmails { email: None, attendees: None };
+        let issued = issue_invitations_in_job(&server_url(), appt_id, 2, Some(3), &data).await.unwrap();
+
+        start.assert();
+        poll.assert();
+        invitations.assert();
+        assert_eq!(issued.len(), 2);
+        assert_eq!(issued[1].short_url.as_str(), "https://short.url/b");
+    }
+}
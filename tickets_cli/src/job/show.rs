@@ -0,0 +1,141 @@
+use anyhow::{anyhow, Error};
+use uuid::Uuid;
+use shared::configuration::get_configuration;
+use shared::domain::{InvitationJob, JobStatus};
+use crate::job::{fetch_job, follow_job, job_failed, send_resume_job};
+use crate::output::{render_one, OutputFormat, Tabular};
+
+impl Tabular for InvitationJob {
+    fn headers() -> Vec<&'static str> {
+        vec!["id", "appointment_id", "status", "progress", "max_admissions", "last_error", "email_failures", "updated_at"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.appointment_id.to_string(),
+            format!("{:?}", self.status),
+            format!("{} of {}", self.processed, self.total),
+            self.max_admissions.to_string(),
+            self.errors.last().cloned().unwrap_or_default(),
+            self.email_failures.len().to_string(),
+            self.updated_at.format("%Y/%m/%d %H:%M:%S").to_string(),
+        ]
+    }
+}
+
+/// Asynchronously shows an invitation job with its progress and errors.
+///
+/// # Parameters
+///
+/// - `job_id`: The UUID of the job to show.
+/// - `output`: The format the job is rendered in.
+///
+/// # Returns
+///
+/// - A `Result<String, Error>` with the rendered job or an error.
+pub async fn show_job_handler(
+    job_id: Uuid,
+    output: OutputFormat
+) -> Result<String, Error> {
+    let configuration = get_configuration()
+        .map_err(|e| anyhow!("Failed to get configuration: {}", e))?;
+
+    let job = fetch_job(&configuration.console_cli.web_url, job_id).await?;
+
+    render_one(&job, output)
+}
+
+/// Asynchronously resumes a failed invitation job from the invitations it
+/// already issued, showing its progress until it finishes.
+///
+/// # Parameters
+///
+/// - `job_id`: The UUID of the job to resume.
+/// - `output`: The format the finished job is rendered in.
+///
+/// # Returns
+///
+/// - A `Result<String, Error>` with the rendered job, or an error when it
+///   failed again.
+pub async fn resume_job_handler(
+    job_id: Uuid,
+    output: OutputFormat
+) -> Result<String, Error> {
+    let configuration = get_configuration()
+        .map_err(|e| anyhow!("Failed to get configuration: {}", e))?;
+    let web_url = configuration.console_cli.web_url;
+
+    let job = send_resume_job(&web_url, job_id).await?;
+    let job = follow_job(&web_url, job).await?;
+    match job.status {
+        JobStatus::FAILED => Err(job_failed(&job)),
+        _ => render_one(&job, output),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{mock, server_url};
+    use serde_json::json;
+    use std::env;
+    use crate::appointment::ENV_VAR_LOCK_TEST;
+
+    fn job(id: Uuid, status: &str, processed: i32, errors: Vec<&str>) -> serde_json::Value {
+        json!({
+            "id": id,
+            "appointment_id": Uuid::nil(),
+            "status": status,
+            "total": 2500,
+            "processed": processed,
+            "max_admissions": 1,
+            "errors": errors,
+            "created_at": "2024-10-10T10:10:00",
+            "updated_at": "2024-10-10T10:12:00"
+        })
+    }
+
+    #[tokio::test]
+    async fn test_resume_job_follows_it_until_it_completes() {
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
+        env::set_var("APP_CONSOLE_CLI__WEB_URL", server_url());
+        let job_id = Uuid::new_v4();
+
+        let resume = mock("POST", format!("/api/jobs/{}/resume", job_id).as_str())
+            .with_status(202)
+            .with_body(job(job_id, "RUNNING", 1000, vec!["Shortener timed out"]).to_string())
+            .create();
+        let poll = mock("GET", format!("/api/jobs/{}", job_id).as_str())
+            .with_status(200)
+            .with_body(job(job_id, "COMPLETED", 2500, vec!["Shortener timed out"]).to_string())
+            .create();
+
+        let rendered = resume_job_handler(job_id, OutputFormat::Json).await.unwrap();
+        let resumed: InvitationJob = serde_json::from_str(&rendered).unwrap();
+
+        resume.assert();
+        poll.assert();
+        assert_eq!(resumed.status, JobStatus::COMPLETED);
+        assert_eq!(resumed.processed, 2500);
+        env::remove_var("APP_CONSOLE_CLI__WEB_URL");
+    }
+
+    #[tokio::test]
+    async fn test_failed_job_tells_how_to_resume_it() {
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
+        env::set_var("APP_CONSOLE_CLI__WEB_URL", server_url());
+        let job_id = Uuid::new_v4();
+
+        let _resume = mock("POST", format!("/api/jobs/{}/resume", job_id).as_str())
+            .with_status(202)
+            .with_body(job(job_id, "FAILED", 1200, vec!["Shortener timed out", "Email service unavailable"]).to_string())
+            .create();
+
+        let error = resume_job_handler(job_id, OutputFormat::Table).await.unwrap_err().to_string();
+
+        assert!(error.contains("stopped after 1200 of 2500 invitations: Email service unavailable"));
+        assert!(error.contains(&format!("tickets_cli job resume {}", job_id)));
+        env::remove_var("APP_CONSOLE_CLI__WEB_URL");
+    }
+}
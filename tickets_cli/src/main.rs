@@ -2,18 +2,38 @@
 
 mod cli;
 mod appointment;
+mod error;
+mod invitation;
+mod job;
+mod output;
+mod retry;
+mod scan;
+mod waitlist;
 
+use std::process::ExitCode;
 use once_cell::sync::Lazy;
+use structopt::clap::ErrorKind;
 use structopt::StructOpt;
 use shared::configuration::get_configuration;
 use shared::qr_client::QRClient;
+use shared::domain::AppointmentFilter;
 use crate::appointment::{
     delete_appointment_handler,
+    download_archive_handler,
     new_appointment_handler,
     generate_invitation_handler,
-    send_invitation_letter_handler
+    list_appointments_handler,
+    send_invitation_letter_handler,
+    send_invitations_from_csv_handler,
+    show_appointment_handler
 };
-use crate::cli::{AppointmentCommand, CliArgs, Command};
+use crate::cli::{AppointmentCommand, CliArgs, Command, InvitationCommand, JobCommand, WaitlistCommand};
+use crate::error::{report_error, CliError, EXIT_USAGE};
+use crate::invitation::{inspect_invitation_handler, invitation_history_handler, reissue_invitation_handler, resend_invitation_handler, resend_pending_invitations_handler, revoke_invitation_handler, show_invitation_handler, transfer_invitation_handler};
+use crate::job::{resume_job_handler, show_job_handler};
+use crate::output::OutputFormat;
+use crate::scan::scan_handler;
+use crate::waitlist::{list_waitlist_handler, move_waitlist_entry_handler, promote_waitlist_handler};
 
 /// QRClient instance initialized lazily based on the application's configuration.
 static QR_CLIENT: Lazy<QRClient> = Lazy::new(|| {
@@ -26,33 +46,49 @@ static QR_CLIENT: Lazy<QRClient> = Lazy::new(|| {
         configuration.qr_client.base_url.clone(),
         configuration.qr_client.base_image_path.clone(),
         configuration.qr_client.timeout(),
-    )
+    ).with_rendering(&configuration.qr_client.rendering)
 });
 
 /// Entry point of the application.
 ///
 /// Parses the command line arguments and executes the corresponding command.
-/// Outputs the result or an error message to the console.
+/// Outputs the result to stdout and errors to stderr.
 ///
 /// # Returns
 ///
-/// * `anyhow::Result<()>` - Indicates successful execution or contains an error.
+/// * `ExitCode` - `0` on success, `1` on unexpected failures, `2` on usage errors,
+///   `3` when the server answered with an error and `4` on partial failures.
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let args: CliArgs = cli::CliArgs::from_args();
+async fn main() -> ExitCode {
+    let args: CliArgs = match CliArgs::from_args_safe() {
+        Ok(args) => args,
+        Err(e) if matches!(e.kind, ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed) => {
+            println!("{}", e.message);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("{}", e.message);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+    let json = args.output == OutputFormat::Json;
 
     match execute_cmd(args).await {
-        Ok(response) => println!("{}", response),
-        Err(e) => eprintln!("Error: {}", e),
+        Ok(response) => {
+            println!("{}", response);
+            ExitCode::SUCCESS
+        }
+        Err(e) => report_error(&e, json),
     }
-
-    Ok(())
 }
 
 /// Executes the command specified in the given `CliArgs`.
 ///
 /// Based on the parsed command line arguments, it performs various actions such as:
-/// - Appointment-related tasks including creation, deletion, generating QR invitations, and sending email invitations.
+/// - Appointment-related tasks including creation, listing, inspection, deletion, generating QR invitations, and sending email invitations.
+/// - Invitation inspection, QR image decoding, revocation, reissue and transfer.
+/// - Waitlist inspection, reordering and promotion.
+/// - Door scanning, online or offline.
 ///
 /// # Parameters
 ///
@@ -62,23 +98,94 @@ async fn main() -> anyhow::Result<()> {
 ///
 /// * `Result<String, Box<dyn std::error::Error>>` - The result of the command's execution or an error.
 async fn execute_cmd(args: CliArgs) -> Result<String, anyhow::Error> {
+    let output = args.output;
+    let auth_token = args.auth_token;
     let response = match args.cmd {
         Command::Appointment(appt_cmd) => {
             match appt_cmd.appt_command {
-                AppointmentCommand::Create => {
-                    new_appointment_handler().await?
+                AppointmentCommand::Create(args) => {
+                    new_appointment_handler(args, output).await?
+                }
+                AppointmentCommand::List { from, to, format } => {
+                    list_appointments_handler(AppointmentFilter { from, to, format }, output).await?
+                }
+                AppointmentCommand::Show { id } => {
+                    show_appointment_handler(id, output).await?
                 }
                 AppointmentCommand::Delete { uuids } => {
-                    delete_appointment_handler(uuids).await?.to_string()
+                    delete_appointment_handler(uuids, output).await?
+                }
+                AppointmentCommand::Generate { appt_id, count, max_admissions, format, per_page } => {
+                    generate_invitation_handler(appt_id, count, max_admissions, format, per_page, output).await?
+                }
+                AppointmentCommand::Download { appt_id, format, dir } => {
+                    download_archive_handler(appt_id, format, dir, output).await?
+                }
+                AppointmentCommand::Send(args) if args.csv.is_some() => {
+                    send_invitations_from_csv_handler(args, output).await?
+                }
+                AppointmentCommand::Send(args) => {
+                    send_invitation_letter_handler(args.appt_id, args.email, args.max_admissions, args.resend_existing, output).await?
+                }
+            }
+        }
+        Command::Invitation(invitation_cmd) => {
+            match invitation_cmd.invitation_command {
+                InvitationCommand::Show { id } => {
+                    show_invitation_handler(id, output).await?
+                }
+                InvitationCommand::Revoke { id, reason } => {
+                    revoke_invitation_handler(id, reason, output).await?
                 }
-                AppointmentCommand::Generate { appt_id, count } => {
-                    generate_invitation_handler(appt_id, count).await?
+                InvitationCommand::Reissue { id, reason } => {
+                    reissue_invitation_handler(id, reason, output).await?
                 }
-                AppointmentCommand::Send { appt_id, email } => {
-                    send_invitation_letter_handler(appt_id, email).await?
+                InvitationCommand::Transfer { id, email, name, reason } => {
+                    transfer_invitation_handler(id, email, name, reason, output).await?
+                }
+                InvitationCommand::Resend { id: Some(id), email, appt_id: None } => {
+                    resend_invitation_handler(id, email, output).await?
+                }
+                InvitationCommand::Resend { appt_id: Some(appt_id), .. } => {
+                    resend_pending_invitations_handler(appt_id, output).await?
+                }
+                InvitationCommand::Resend { .. } => {
+                    return Err(CliError::Usage("Give either an invitation ID or `-a <appt_id>`.".to_string()).into());
+                }
+                InvitationCommand::History { id } => {
+                    invitation_history_handler(id, output).await?
+                }
+                InvitationCommand::Inspect { image } => {
+                    inspect_invitation_handler(&image, output).await?
                 }
             }
         }
+        Command::Waitlist(waitlist_cmd) => {
+            match waitlist_cmd.waitlist_command {
+                WaitlistCommand::List { appt_id } => {
+                    list_waitlist_handler(appt_id, output).await?
+                }
+                WaitlistCommand::Move { appt_id, id, position } => {
+                    move_waitlist_entry_handler(appt_id, id, position, output).await?
+                }
+                WaitlistCommand::Promote { appt_id } => {
+                    promote_waitlist_handler(appt_id, output).await?
+                }
+            }
+        }
+        Command::Job(job_cmd) => {
+            match job_cmd.job_command {
+                JobCommand::Show { id } => {
+                    show_job_handler(id, output).await?
+                }
+                JobCommand::Resume { id } => {
+                    resume_job_handler(id, output).await?
+                }
+            }
+        }
+        Command::Scan(args) => {
+            scan_handler(args, auth_token, output).await?
+        }
     };
 
     Ok(response)
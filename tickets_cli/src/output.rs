@@ -0,0 +1,155 @@
+//! Rendering of command results as a table, JSON or CSV.
+
+use std::str::FromStr;
+use anyhow::Error;
+use serde::Serialize;
+
+/// Output format selected with `--output`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    Json,
+    Csv,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            other => Err(format!(
+                "{} is not a supported output. Use `table`, `json` or `csv`.",
+                other
+            )),
+        }
+    }
+}
+
+/// A record that can be printed as a row of a table or a CSV file.
+pub trait Tabular {
+    /// Column names, in the order of the values returned by [`Tabular::row`].
+    fn headers() -> Vec<&'static str>;
+
+    /// Column values of this record.
+    fn row(&self) -> Vec<String>;
+}
+
+/// Renders records in the requested format.
+///
+/// JSON is rendered from the `Serialize` implementation, table and CSV from
+/// the `Tabular` one.
+///
+/// # Parameters
+///
+/// - `records`: The records to render.
+/// - `format`: The output format.
+///
+/// # Returns
+///
+/// - The rendered records, without a trailing newline.
+pub fn render<T: Serialize + Tabular>(records: &[T], format: OutputFormat) -> Result<String, Error> {
+    match format {
+        OutputFormat::Json => Ok(serde_json::to_string(records)?),
+        OutputFormat::Csv => render_csv(records),
+        OutputFormat::Table => Ok(render_table(records)),
+    }
+}
+
+/// Renders a single record in the requested format.
+///
+/// Same as [`render`], except that JSON output is an object rather than a list.
+pub fn render_one<T: Serialize + Tabular>(record: &T, format: OutputFormat) -> Result<String, Error> {
+    match format {
+        OutputFormat::Json => Ok(serde_json::to_string(record)?),
+        _ => render(std::slice::from_ref(record), format),
+    }
+}
+
+fn render_csv<T: Tabular>(records: &[T]) -> Result<String, Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(T::headers())?;
+    for record in records {
+        writer.write_record(record.row())?;
+    }
+    let output = String::from_utf8(writer.into_inner()?)?;
+    Ok(output.trim_end().to_string())
+}
+
+fn render_table<T: Tabular>(records: &[T]) -> String {
+    let headers: Vec<String> = T::headers().into_iter().map(|header| header.to_uppercase()).collect();
+    let rows: Vec<Vec<String>> = records.iter().map(Tabular::row).collect();
+
+    let mut widths: Vec<usize> = headers.iter().map(|header| header.chars().count()).collect();
+    for row in &rows {
+        for (width, value) in widths.iter_mut().zip(row) {
+            *width = (*width).max(value.chars().count());
+        }
+    }
+
+    std::iter::once(&headers)
+        .chain(rows.iter())
+        .map(|row| {
+            row.iter()
+                .zip(&widths)
+                .map(|(value, width)| format!("{:<width$}", value, width = width))
+                .collect::<Vec<_>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Record {
+        name: String,
+        count: u32,
+    }
+
+    impl Tabular for Record {
+        fn headers() -> Vec<&'static str> {
+            vec!["name", "count"]
+        }
+
+        fn row(&self) -> Vec<String> {
+            vec![self.name.clone(), self.count.to_string()]
+        }
+    }
+
+    fn records() -> Vec<Record> {
+        vec![
+            Record { name: "first, with comma".to_string(), count: 1 },
+            Record { name: "second".to_string(), count: 20 },
+        ]
+    }
+
+    #[test]
+    fn test_render_table() {
+        let output = render(&records(), OutputFormat::Table).unwrap();
+        assert_eq!(output, "NAME               COUNT\nfirst, with comma  1\nsecond             20");
+    }
+
+    #[test]
+    fn test_render_csv() {
+        let output = render(&records(), OutputFormat::Csv).unwrap();
+        assert_eq!(output, "name,count\n\"first, with comma\",1\nsecond,20");
+    }
+
+    #[test]
+    fn test_render_json() {
+        let output = render(&records(), OutputFormat::Json).unwrap();
+        assert_eq!(output, r#"[{"name":"first, with comma","count":1},{"name":"second","count":20}]"#);
+
+        let output = render_one(&records()[1], OutputFormat::Json).unwrap();
+        assert_eq!(output, r#"{"name":"second","count":20}"#);
+    }
+}
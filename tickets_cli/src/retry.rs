@@ -0,0 +1,148 @@
+//! Retries of the requests creating appointments and invitations.
+
+use std::time::Duration;
+use anyhow::{anyhow, Error};
+use reqwest::header::RETRY_AFTER;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use uuid::Uuid;
+
+/// Header the server recognizes retries of the same request by.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Retries while an earlier attempt is still being handled, which takes as
+/// long as emailing a large batch.
+const MAX_IN_PROGRESS_ATTEMPTS: u32 = 20;
+
+/// Wait before the first retry, longer before every other one.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Longest wait between two attempts.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// Sends a request with a new `Idempotency-Key`, retrying it with the same key
+/// when the connection fails or the server is briefly unavailable.
+///
+/// The server replays the response to an attempt that got through instead of
+/// handling the request again, so nothing is created or emailed twice. While
+/// that attempt is still being handled, the server answers `409 Conflict`
+/// with `Retry-After`, and the request is retried a while longer.
+///
+/// # Parameters
+///
+/// - `request`: The request to send, with a body that can be cloned.
+///
+/// # Returns
+///
+/// - A `Result<Response, Error>` with the response to the last attempt, or the
+///   error it failed with.
+pub async fn send_idempotent(request: RequestBuilder) -> Result<Response, Error> {
+    let request = request.header(IDEMPOTENCY_KEY_HEADER, Uuid::new_v4().to_string());
+    let mut attempt = 1;
+    let mut in_progress = 0;
+    loop {
+        let retry = request.try_clone()
+            .ok_or_else(|| anyhow!("Failed to retry a request with a streamed body"))?;
+        let delay = match retry.send().await {
+            Ok(response) if is_in_progress(&response) && in_progress < MAX_IN_PROGRESS_ATTEMPTS => {
+                in_progress += 1;
+                retry_after(&response).max(RETRY_DELAY * in_progress)
+            }
+            Ok(response) if !is_transient(response.status()) || attempt >= MAX_ATTEMPTS => return Ok(response),
+            Err(e) if !(e.is_connect() || e.is_timeout() || e.is_request()) || attempt >= MAX_ATTEMPTS => {
+                return Err(e.into())
+            },
+            _ => {
+                attempt += 1;
+                RETRY_DELAY * (attempt - 1)
+            }
+        };
+        tokio::time::sleep(delay.min(MAX_RETRY_DELAY)).await;
+    }
+}
+
+// The conflict of a retry sent while the first attempt is being handled, told
+// apart from a key reused for another request by its `Retry-After`.
+fn is_in_progress(response: &Response) -> bool {
+    response.status() == StatusCode::CONFLICT && response.headers().contains_key(RETRY_AFTER)
+}
+
+// Seconds to wait as asked by the server, none when it didn't say.
+fn retry_after(response: &Response) -> Duration {
+    response.headers().get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or_default()
+}
+
+// Statuses of a server or proxy that may answer differently in a moment.
+fn is_transient(status: StatusCode) -> bool {
+    matches!(status, StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{mock, server_url, Matcher};
+    use crate::appointment::ENV_VAR_LOCK_TEST;
+
+    #[tokio::test]
+    async fn test_unavailable_server_is_retried_with_the_same_key() {
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
+        let unavailable = mock("POST", "/api/appointment/retried")
+            .match_header(IDEMPOTENCY_KEY_HEADER, Matcher::Regex("^[0-9a-f-]{36}$".to_string()))
+            .with_status(503)
+            .expect(MAX_ATTEMPTS as usize)
+            .create();
+
+        let client = reqwest::Client::new();
+        let response = send_idempotent(client.post(format!("{}/api/appointment/retried", server_url())).body("{}"))
+            .await
+            .unwrap();
+
+        unavailable.assert();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_client_errors_are_not_retried() {
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
+        let conflict = mock("POST", "/api/appointment/conflict")
+            .with_status(409)
+            .expect(1)
+            .create();
+
+        let client = reqwest::Client::new();
+        let response = send_idempotent(client.post(format!("{}/api/appointment/conflict", server_url())).body("{}"))
+            .await
+            .unwrap();
+
+        conflict.assert();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn test_request_in_progress_is_retried_until_answered() {
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
+        let in_progress = mock("POST", "/api/appointment/in-progress")
+            .with_status(409)
+            .with_header("Retry-After", "0")
+            .expect(2)
+            .create();
+        let answered = mock("POST", "/api/appointment/in-progress")
+            .with_status(200)
+            .expect(1)
+            .create();
+
+        let client = reqwest::Client::new();
+        let response = send_idempotent(client.post(format!("{}/api/appointment/in-progress", server_url())).body("{}"))
+            .await
+            .unwrap();
+
+        in_progress.assert();
+        answered.assert();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
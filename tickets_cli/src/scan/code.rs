@@ -0,0 +1,108 @@
+use std::path::Path;
+use anyhow::{anyhow, Error};
+use url::Url;
+use uuid::Uuid;
+use shared::ticket::is_signed_ticket;
+
+/// What a scanned or typed code turned out to be.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScannedCode {
+    /// A bare invitation ID, or a validation URL ending with one.
+    Invitation(Uuid),
+    /// A short URL, only known to the server and the downloaded invitation list.
+    ShortUrl(Url),
+    /// A signed ticket, verifiable with the public ticket keys.
+    Ticket(String),
+}
+
+impl ScannedCode {
+    pub fn parse(text: &str) -> Result<ScannedCode, Error> {
+        let text = text.trim();
+        if is_signed_ticket(text) {
+            return Ok(ScannedCode::Ticket(text.to_string()));
+        }
+        if let Ok(id) = Uuid::parse_str(text) {
+            return Ok(ScannedCode::Invitation(id));
+        }
+
+        let url = Url::parse(text).map_err(|_| anyhow!("{} is neither an invitation ID, a URL nor a signed ticket.", text))?;
+        let id = url.path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .and_then(|segment| Uuid::parse_str(segment).ok());
+        Ok(match id {
+            Some(id) => ScannedCode::Invitation(id),
+            None => ScannedCode::ShortUrl(url),
+        })
+    }
+}
+
+/// Decodes the QR codes found in an image file.
+///
+/// # Returns
+///
+/// - The text of every QR code in the image, or an error when the image
+///   can't be read or holds no readable QR code.
+pub fn decode_qr_image(path: &Path) -> Result<Vec<String>, Error> {
+    let image = image::open(path)
+        .map_err(|e| anyhow!("Failed to open {}: {}", path.display(), e))?
+        .to_luma8();
+    let mut prepared = rqrr::PreparedImage::prepare_from_greyscale(
+        image.width() as usize,
+        image.height() as usize,
+        |x, y| image.get_pixel(x as u32, y as u32)[0]
+    );
+
+    let contents: Vec<String> = prepared.detect_grids()
+        .into_iter()
+        .filter_map(|grid| grid.decode().ok())
+        .map(|(_, content)| content)
+        .collect();
+    if contents.is_empty() {
+        return Err(anyhow!("No QR code found in {}.", path.display()));
+    }
+    Ok(contents)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secrecy::Secret;
+    use shared::qr_client::QRClient;
+
+    #[test]
+    fn test_parse_codes() {
+        let id = Uuid::new_v4();
+
+        assert_eq!(ScannedCode::parse(&id.to_string()).unwrap(), ScannedCode::Invitation(id));
+        assert_eq!(
+            ScannedCode::parse(&format!("http://127.0.0.1/api/validations/{}", id)).unwrap(),
+            ScannedCode::Invitation(id)
+        );
+        assert_eq!(
+            ScannedCode::parse("https://short.url/testhash").unwrap(),
+            ScannedCode::ShortUrl(Url::parse("https://short.url/testhash").unwrap())
+        );
+        assert!(matches!(ScannedCode::parse(" TKT1.abc.def\n").unwrap(), ScannedCode::Ticket(_)));
+        assert!(ScannedCode::parse("not a code").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_decode_generated_qr_code() {
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let qr_client = QRClient::new(
+            "http://127.0.0.1/short_url/hash".to_string(),
+            Secret::new("xxx".to_string()),
+            "http://127.0.0.1/api/validations".to_string(),
+            directory.to_str().unwrap().to_string(),
+            std::time::Duration::from_millis(1000),
+        );
+        let url = Url::parse("https://short.url/testhash").unwrap();
+
+        let path = qr_client.generate_qr_code(url.clone(), Uuid::new_v4(), Uuid::new_v4()).await.unwrap();
+        let decoded = decode_qr_image(Path::new(&path));
+        std::fs::remove_dir_all(directory).unwrap();
+
+        assert_eq!(decoded.unwrap(), vec![url.to_string()]);
+    }
+}
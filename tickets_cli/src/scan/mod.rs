@@ -0,0 +1,7 @@
+pub mod code;
+pub mod store;
+pub mod scanner;
+
+pub use code::*;
+pub use store::*;
+pub use scanner::*;
@@ -0,0 +1,200 @@
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Error};
+use chrono::Utc;
+use uuid::Uuid;
+use shared::configuration::get_configuration;
+use shared::domain::{Invitation, OfflineScan, SyncedScan};
+use shared::ticket::PublicTicketKey;
+use crate::cli::ScanArgs;
+use crate::error::ensure_success;
+use crate::output::{render, OutputFormat};
+use crate::scan::{decode_qr_image, ScannerStore};
+
+/// Validates codes at the door, online or offline.
+///
+/// The invitation list and the ticket keys of the appointment are kept in a
+/// local store, refreshed from the server when it can be reached. Codes are
+/// checked against the store, so the door keeps working when the network
+/// drops, and the check-ins are pushed to the server whenever it's back.
+///
+/// # Parameters
+///
+/// - `args`: The codes to validate and the store options.
+/// - `auth_token`: The scanner token check-ins are pushed with.
+/// - `output`: The format the scans are rendered in.
+///
+/// # Returns
+///
+/// - A `Result<String, Error>` with the rendered scans of this run, or an error
+///   when the server can't be reached and there's no local store yet.
+pub async fn scan_handler(args: ScanArgs, auth_token: Option<String>, output: OutputFormat) -> Result<String, Error> {
+    let configuration = get_configuration()
+        .map_err(|e| anyhow!("Failed to get configuration: {}", e))?;
+    let web_url = configuration.console_cli.web_url;
+    let path = args.store.clone()
+        .unwrap_or_else(|| PathBuf::from(format!("scanner-{}.json", args.appt_id)));
+
+    let stored = ScannerStore::load(&path)?;
+    let mut store = if args.offline {
+        stored
+    } else {
+        let mut store = stored.clone().unwrap_or_else(|| ScannerStore::new(args.appt_id));
+        match refresh_store(&web_url, auth_token.as_deref(), &mut store).await {
+            Ok(()) => {
+                store.save(&path)?;
+                Some(store)
+            }
+            Err(e) => {
+                eprintln!("Working offline, {}", e);
+                stored
+            }
+        }
+    }.ok_or_else(|| anyhow!(
+        "No scanner store at {}, run once online to download the invitations.",
+        path.display()
+    ))?;
+    if store.appointment_id != args.appt_id {
+        return Err(anyhow!("{} is the store of appointment {}.", path.display(), store.appointment_id));
+    }
+
+    let mut scanned = vec![];
+    for code in read_codes(&args)? {
+        let scan = store.check_in(&code, args.scanner.clone(), Utc::now().naive_utc());
+        store.save(&path)?;
+        scanned.push(scan);
+    }
+
+    if !args.offline {
+        match push_scans(&web_url, auth_token.as_deref(), &store).await {
+            Ok(synced) => {
+                let updated = store.apply_sync(&synced, Utc::now().naive_utc());
+                store.save(&path)?;
+                for scan in updated {
+                    if let Some(entry) = scanned.iter_mut().find(|entry| entry.id == scan.id) {
+                        *entry = scan;
+                    }
+                }
+            }
+            Err(e) => eprintln!("Check-ins kept for a later sync, {}", e),
+        }
+    }
+
+    render(&scanned, output)
+}
+
+// Pushes pending check-ins, then downloads the invitation list and the ticket keys.
+async fn refresh_store(web_url: &str, auth_token: Option<&str>, store: &mut ScannerStore) -> Result<(), Error> {
+    let synced = push_scans(web_url, auth_token, store).await?;
+    store.apply_sync(&synced, Utc::now().naive_utc());
+
+    let client = reqwest::Client::new();
+    let invitations: Vec<Invitation> = ensure_success(
+        client.get(format!("{}/api/appointment/{}/invitation", web_url, store.appointment_id))
+            .send()
+            .await?
+    ).await?.json().await?;
+    let keys: Vec<PublicTicketKey> = ensure_success(
+        client.get(format!("{}/api/ticket/keys", web_url))
+            .send()
+            .await?
+    ).await?.json().await?;
+
+    store.refresh(invitations, keys, Utc::now().naive_utc());
+    Ok(())
+}
+
+// Sends the pending check-ins of a store to the server.
+async fn push_scans(web_url: &str, auth_token: Option<&str>, store: &ScannerStore) -> Result<Vec<SyncedScan>, Error> {
+    sync_scans(web_url, auth_token, store.appointment_id, &store.pending()).await
+}
+
+async fn sync_scans(
+    web_url: &str,
+    auth_token: Option<&str>,
+    appointment_id: Uuid,
+    scans: &[OfflineScan]
+) -> Result<Vec<SyncedScan>, Error> {
+    if scans.is_empty() {
+        return Ok(vec![]);
+    }
+    let auth_token = auth_token
+        .ok_or_else(|| anyhow!("pushing check-ins requires the scanner token, set --auth-token"))?;
+    let client = reqwest::Client::new();
+
+    let response = client.post(format!("{}/api/appointment/{}/scans", web_url, appointment_id))
+        .bearer_auth(auth_token)
+        .json(scans)
+        .send()
+        .await?;
+
+    Ok(ensure_success(response).await?.json::<Vec<SyncedScan>>().await?)
+}
+
+// Codes given as arguments and decoded from images, or read line by line from stdin.
+fn read_codes(args: &ScanArgs) -> Result<Vec<String>, Error> {
+    let mut codes = args.codes.clone();
+    for image in &args.image {
+        codes.extend(decode_qr_image(Path::new(image))?);
+    }
+    if codes.is_empty() && args.image.is_empty() {
+        for line in std::io::stdin().lock().lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                codes.push(line);
+            }
+        }
+    }
+    Ok(codes)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDateTime;
+    use mockito::{mock, server_url, Matcher};
+    use shared::domain::SyncStatus;
+    use crate::appointment::ENV_VAR_LOCK_TEST;
+
+    #[tokio::test]
+    async fn test_sync_posts_pending_check_ins() {
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
+
+        let appointment_id = Uuid::new_v4();
+        let scan = OfflineScan {
+            id: Uuid::new_v4(),
+            invitation_id: Uuid::new_v4(),
+            scanned_at: NaiveDateTime::parse_from_str("2023/12/09 10:00:00", "%Y/%m/%d %H:%M:%S").unwrap(),
+        };
+        let _mock = mock("POST", format!("/api/appointment/{}/scans", appointment_id).as_str())
+            .match_header("authorization", "Bearer scanner-token")
+            .match_body(Matcher::Json(serde_json::json!([{
+                "id": scan.id,
+                "invitation_id": scan.invitation_id,
+                "scanned_at": "2023-12-09T10:00:00"
+            }])))
+            .with_status(200)
+            .with_body(serde_json::json!([{
+                "id": scan.id,
+                "invitation_id": scan.invitation_id,
+                "status": "CONFLICT",
+                "reason": "all 1 admission(s) were used by other scans first"
+            }]).to_string())
+            .create();
+
+        let synced = sync_scans(&server_url(), Some("scanner-token"), appointment_id, std::slice::from_ref(&scan)).await.unwrap();
+
+        assert_eq!(synced.len(), 1);
+        assert_eq!(synced[0].id, scan.id);
+        assert_eq!(synced[0].status, SyncStatus::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn test_nothing_is_posted_without_pending_check_ins() {
+        // No server behind this URL, so any request would fail.
+        let synced = sync_scans("http://127.0.0.1:9", None, Uuid::new_v4(), &[]).await.unwrap();
+
+        assert!(synced.is_empty());
+    }
+}
@@ -0,0 +1,287 @@
+use std::path::Path;
+use anyhow::{anyhow, Error};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use shared::domain::{Invitation, InvitationStatus, OfflineScan, SyncStatus, SyncedScan};
+use shared::ticket::{PublicTicketKey, TicketVerifier};
+use crate::output::Tabular;
+use crate::scan::ScannedCode;
+
+/// A code validated by this scanner, admitted or not.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocalScan {
+    pub id: Uuid,
+    pub code: String,
+    pub invitation_id: Option<Uuid>,
+    pub scanned_at: NaiveDateTime,
+    pub scanner: Option<String>,
+    pub admitted: bool,
+    /// Why the code was rejected, or why the server didn't take the check-in.
+    pub reason: Option<String>,
+    /// How the server took the check-in, `None` until pushed. Rejected codes are never pushed.
+    pub sync: Option<SyncStatus>,
+    pub synced_at: Option<NaiveDateTime>,
+}
+
+impl Tabular for LocalScan {
+    fn headers() -> Vec<&'static str> {
+        vec!["code", "invitation_id", "scanned_at", "admitted", "reason", "sync"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.code.clone(),
+            self.invitation_id.map(|id| id.to_string()).unwrap_or_default(),
+            self.scanned_at.format("%Y/%m/%d %H:%M:%S").to_string(),
+            self.admitted.to_string(),
+            self.reason.clone().unwrap_or_default(),
+            match (self.admitted, self.sync) {
+                (false, _) => String::new(),
+                (true, None) => "PENDING".to_string(),
+                (true, Some(status)) => format!("{:?}", status),
+            },
+        ]
+    }
+}
+
+/// Invitation list of an appointment and the check-ins made against it,
+/// kept in a JSON file so that codes can be validated without the server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScannerStore {
+    pub appointment_id: Uuid,
+    pub downloaded_at: NaiveDateTime,
+    pub keys: Vec<PublicTicketKey>,
+    pub invitations: Vec<Invitation>,
+    pub scans: Vec<LocalScan>,
+}
+
+impl ScannerStore {
+    pub fn new(appointment_id: Uuid) -> Self {
+        Self {
+            appointment_id,
+            downloaded_at: NaiveDateTime::MIN,
+            keys: vec![],
+            invitations: vec![],
+            scans: vec![],
+        }
+    }
+
+    /// Reads a store, `None` when the file doesn't exist yet.
+    pub fn load(path: &Path) -> Result<Option<Self>, Error> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(path)?;
+        let store = serde_json::from_str(&content)
+            .map_err(|e| anyhow!("{} is not a scanner store: {}", path.display(), e))?;
+        Ok(Some(store))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        // Written aside and renamed, so that a crash never leaves half a store.
+        let temporary = path.with_extension("tmp");
+        std::fs::write(&temporary, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(temporary, path)?;
+        Ok(())
+    }
+
+    /// Replaces the invitation list and the keys with fresh ones from the server.
+    pub fn refresh(&mut self, invitations: Vec<Invitation>, keys: Vec<PublicTicketKey>, now: NaiveDateTime) {
+        self.invitations = invitations;
+        self.keys = keys;
+        self.downloaded_at = now;
+    }
+
+    /// Check-ins that still have to be pushed to the server.
+    pub fn pending(&self) -> Vec<OfflineScan> {
+        self.scans.iter()
+            .filter(|scan| scan.admitted && scan.sync.is_none())
+            .filter_map(|scan| scan.invitation_id.map(|invitation_id| OfflineScan {
+                id: scan.id,
+                invitation_id,
+                scanned_at: scan.scanned_at,
+            }))
+            .collect()
+    }
+
+    /// Records how the server took pushed check-ins.
+    pub fn apply_sync(&mut self, synced: &[SyncedScan], now: NaiveDateTime) -> Vec<LocalScan> {
+        let mut updated = vec![];
+        for result in synced {
+            if let Some(scan) = self.scans.iter_mut().find(|scan| scan.id == result.id) {
+                scan.sync = Some(result.status);
+                scan.synced_at = Some(now);
+                if result.reason.is_some() {
+                    scan.reason = result.reason.clone();
+                }
+                updated.push(scan.clone());
+            }
+        }
+        updated
+    }
+
+    /// Validates a code against the local invitation list and records the attempt.
+    ///
+    /// A code is admitted while its invitation has admissions left, counting
+    /// the check-ins the server knew of at download time and the ones made
+    /// here since.
+    pub fn check_in(&mut self, code: &str, scanner: Option<String>, now: NaiveDateTime) -> LocalScan {
+        let (invitation_id, rejection) = match self.admission(code, now) {
+            Ok(invitation_id) => (Some(invitation_id), None),
+            Err((invitation_id, reason)) => (invitation_id, Some(reason)),
+        };
+        let scan = LocalScan {
+            id: Uuid::new_v4(),
+            code: code.trim().to_string(),
+            invitation_id,
+            scanned_at: now,
+            scanner,
+            admitted: rejection.is_none(),
+            reason: rejection,
+            sync: None,
+            synced_at: None,
+        };
+        self.scans.push(scan.clone());
+        scan
+    }
+
+    // Works out the invitation of a code and whether it may be admitted.
+    fn admission(&self, code: &str, now: NaiveDateTime) -> Result<Uuid, (Option<Uuid>, String)> {
+        let code = ScannedCode::parse(code).map_err(|e| (None, e.to_string()))?;
+        let (invitation_id, signed) = match code {
+            ScannedCode::Invitation(id) => (id, false),
+            ScannedCode::ShortUrl(url) => {
+                let invitation = self.invitations.iter()
+                    .find(|invitation| invitation.short_url == url)
+                    .ok_or_else(|| (None, format!("{} is not a code of this appointment.", url)))?;
+                (invitation.id, false)
+            }
+            ScannedCode::Ticket(ticket) => {
+                let verifier = TicketVerifier::new(&self.keys).map_err(|e| (None, e.to_string()))?;
+                let claims = verifier.verify(&ticket, now).map_err(|e| (None, e.to_string()))?;
+                if claims.appointment_id != self.appointment_id {
+                    return Err((Some(claims.invitation_id), "The ticket is for another appointment.".to_string()));
+                }
+                (claims.invitation_id, true)
+            }
+        };
+
+        // A valid signature vouches for invitations issued after the download,
+        // which admit one person unless the list says otherwise.
+        let (max_admissions, admitted) = match self.invitations.iter().find(|invitation| invitation.id == invitation_id) {
+            Some(invitation) if invitation.status != InvitationStatus::ACTIVE => {
+                return Err((Some(invitation_id), format!("invitation has been {:?}", invitation.status).to_lowercase()));
+            }
+            Some(invitation) => (invitation.max_admissions, invitation.admitted),
+            None if signed => (1, 0),
+            None => return Err((Some(invitation_id), format!("Invitation {} is not on the list of this appointment.", invitation_id))),
+        };
+
+        let admitted = admitted as usize + self.scans.iter()
+            .filter(|scan| scan.invitation_id == Some(invitation_id) && scan.admitted)
+            .filter(|scan| match (scan.sync, scan.synced_at) {
+                (None, _) => true,
+                // Accepted after the download, so not in the downloaded count yet.
+                (Some(SyncStatus::ACCEPTED), Some(synced_at)) => synced_at > self.downloaded_at,
+                _ => false,
+            })
+            .count();
+        if admitted >= max_admissions as usize {
+            return Err((Some(invitation_id), format!(
+                "invitation has already been used, {} of {} admitted",
+                max_admissions, max_admissions
+            )));
+        }
+        Ok(invitation_id)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+    use secrecy::Secret;
+    use url::Url;
+    use shared::domain::RsvpStatus;
+    use shared::ticket::TicketSigner;
+
+    fn invitation(appointment_id: Uuid, max_admissions: i32, status: InvitationStatus) -> Invitation {
+        let id = Uuid::new_v4();
+        Invitation {
+            id,
+            appointment_id,
+            used: false,
+            short_url: Url::parse(&format!("https://short.url/{}", id.simple())).unwrap(),
+            attendee_id: None,
+            status,
+            rsvp: RsvpStatus::PENDING,
+            claim_deadline: None,
+            max_admissions,
+            admitted: 0,
+        }
+    }
+
+    #[test]
+    fn test_duplicate_scans_are_rejected_locally() {
+        let appointment_id = Uuid::new_v4();
+        let group = invitation(appointment_id, 2, InvitationStatus::ACTIVE);
+        let revoked = invitation(appointment_id, 1, InvitationStatus::REVOKED);
+        let mut store = ScannerStore::new(appointment_id);
+        let now = Utc::now().naive_utc();
+        store.refresh(vec![group.clone(), revoked.clone()], vec![], now);
+
+        assert!(store.check_in(group.short_url.as_str(), None, now).admitted);
+        assert!(store.check_in(&group.id.to_string(), None, now).admitted);
+        let duplicate = store.check_in(&group.id.to_string(), None, now);
+        assert!(!duplicate.admitted);
+        assert_eq!(duplicate.reason.unwrap(), "invitation has already been used, 2 of 2 admitted");
+
+        assert_eq!(store.check_in(&revoked.id.to_string(), None, now).reason.unwrap(), "invitation has been revoked");
+        assert!(!store.check_in(&Uuid::new_v4().to_string(), None, now).admitted);
+        assert_eq!(store.pending().len(), 2);
+    }
+
+    #[test]
+    fn test_signed_ticket_issued_after_download_is_admitted_once() {
+        let appointment_id = Uuid::new_v4();
+        let signer = TicketSigner::new("test".to_string(), &Secret::new(base64_seed())).unwrap();
+        let now = Utc::now().naive_utc();
+        let mut store = ScannerStore::new(appointment_id);
+        store.refresh(vec![], vec![signer.public_key()], now);
+
+        let ticket = signer.sign(Uuid::new_v4(), appointment_id, now - Duration::hours(1), now + Duration::hours(1));
+        let other = signer.sign(Uuid::new_v4(), Uuid::new_v4(), now - Duration::hours(1), now + Duration::hours(1));
+
+        assert!(store.check_in(&ticket, None, now).admitted);
+        assert!(!store.check_in(&ticket, None, now).admitted);
+        assert_eq!(store.check_in(&other, None, now).reason.unwrap(), "The ticket is for another appointment.");
+    }
+
+    #[test]
+    fn test_synced_check_ins_are_counted_once() {
+        let appointment_id = Uuid::new_v4();
+        let group = invitation(appointment_id, 2, InvitationStatus::ACTIVE);
+        let mut store = ScannerStore::new(appointment_id);
+        let now = Utc::now().naive_utc();
+        store.refresh(vec![group.clone()], vec![], now);
+
+        let scan = store.check_in(&group.id.to_string(), None, now);
+        store.apply_sync(&[SyncedScan { id: scan.id, invitation_id: group.id, status: SyncStatus::ACCEPTED, reason: None }], now + Duration::seconds(1));
+        assert!(store.pending().is_empty());
+
+        // The server now counts the check-in.
+        let mut refreshed = group.clone();
+        refreshed.admitted = 1;
+        store.refresh(vec![refreshed], vec![], now + Duration::seconds(2));
+
+        assert!(store.check_in(&group.id.to_string(), None, now).admitted);
+        assert!(!store.check_in(&group.id.to_string(), None, now).admitted);
+    }
+
+    fn base64_seed() -> String {
+        // Base64 of 32 zero bytes.
+        "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".to_string()
+    }
+}
@@ -0,0 +1,138 @@
+use anyhow::{anyhow, Error};
+use uuid::Uuid;
+use shared::configuration::get_configuration;
+use shared::domain::{NewInvitation, WaitlistEntry};
+use crate::error::ensure_success;
+use crate::output::{render, OutputFormat, Tabular};
+
+impl Tabular for WaitlistEntry {
+    fn headers() -> Vec<&'static str> {
+        vec!["position", "id", "name", "email", "phone"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.position.to_string(),
+            self.id.to_string(),
+            self.name.clone().unwrap_or_default(),
+            self.email.as_ref().to_string(),
+            self.phone.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+impl Tabular for NewInvitation {
+    fn headers() -> Vec<&'static str> {
+        vec!["id", "appointment_id", "short_url", "claim_deadline"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.appointment_id.to_string(),
+            self.short_url.to_string(),
+            self.claim_deadline.map(|deadline| deadline.format("%Y/%m/%d %H:%M").to_string()).unwrap_or_default(),
+        ]
+    }
+}
+
+// Fetches the people waiting for a seat of an appointment, in queue order.
+pub(crate) async fn fetch_waitlist(
+    web_url: &str,
+    appt_id: Uuid
+) -> Result<Vec<WaitlistEntry>, Error> {
+    let client = reqwest::Client::new();
+
+    let response = client.get(format!("{}/api/appointment/{}/waitlist", web_url, appt_id))
+        .send()
+        .await?;
+
+    Ok(ensure_success(response).await?.json::<Vec<WaitlistEntry>>().await?)
+}
+
+// Asks the server to hand free seats to the head of the waitlist.
+async fn send_promote_waitlist(
+    web_url: &str,
+    appt_id: Uuid
+) -> Result<Vec<NewInvitation>, Error> {
+    let client = reqwest::Client::new();
+
+    let response = client.post(format!("{}/api/appointment/{}/waitlist/promote", web_url, appt_id))
+        .send()
+        .await?;
+
+    Ok(ensure_success(response).await?.json::<Vec<NewInvitation>>().await?)
+}
+
+/// Asynchronously lists the waitlist of an appointment.
+///
+/// # Parameters
+///
+/// - `appt_id`: The UUID of the appointment.
+/// - `output`: The format the waitlist is rendered in.
+///
+/// # Returns
+///
+/// - A `Result<String, Error>` with the rendered waitlist or an error.
+pub async fn list_waitlist_handler(
+    appt_id: Uuid,
+    output: OutputFormat
+) -> Result<String, Error> {
+    let configuration = get_configuration()
+        .map_err(|e| anyhow!("Failed to get configuration: {}", e))?;
+
+    let entries = fetch_waitlist(&configuration.console_cli.web_url, appt_id).await?;
+
+    render(&entries, output)
+}
+
+/// Asynchronously promotes people from the waitlist to the free seats.
+///
+/// Promoted invitations whose claim deadline passed are declined first, so
+/// running it periodically keeps the queue moving.
+///
+/// # Parameters
+///
+/// - `appt_id`: The UUID of the appointment.
+/// - `output`: The format the issued invitations are rendered in.
+///
+/// # Returns
+///
+/// - A `Result<String, Error>` with the rendered invitations or an error.
+pub async fn promote_waitlist_handler(
+    appt_id: Uuid,
+    output: OutputFormat
+) -> Result<String, Error> {
+    let configuration = get_configuration()
+        .map_err(|e| anyhow!("Failed to get configuration: {}", e))?;
+
+    let invitations = send_promote_waitlist(&configuration.console_cli.web_url, appt_id).await?;
+
+    render(&invitations, output)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{mock, server_url};
+    use crate::appointment::ENV_VAR_LOCK_TEST;
+
+    #[tokio::test]
+    async fn test_fetch_waitlist() {
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
+
+        let appt_id = Uuid::parse_str("6ba7b812-9dad-11d1-80b4-00c04fd430c9").unwrap();
+        let _mock = mock("GET", format!("/api/appointment/{}/waitlist", appt_id).as_str())
+            .with_status(200)
+            .with_body(r#"[{"id":"6ba7b810-9dad-11d1-80b4-00c04fd430c8","appointment_id":"6ba7b812-9dad-11d1-80b4-00c04fd430c9","position":1,"name":"Ada","email":"ada@example.com","phone":null,"custom_fields":{},"invitation_id":null,"promoted_at":null}]"#)
+            .create();
+
+        let entries = fetch_waitlist(&server_url(), appt_id).await.unwrap();
+
+        assert_eq!(
+            render(&entries, OutputFormat::Csv).unwrap(),
+            "position,id,name,email,phone\n1,6ba7b810-9dad-11d1-80b4-00c04fd430c8,Ada,ada@example.com,"
+        );
+    }
+}
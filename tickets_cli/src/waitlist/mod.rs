@@ -0,0 +1,5 @@
+pub mod list;
+pub mod reorder;
+
+pub use list::*;
+pub use reorder::*;
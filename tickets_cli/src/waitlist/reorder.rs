@@ -0,0 +1,75 @@
+use anyhow::{anyhow, Error};
+use uuid::Uuid;
+use shared::configuration::get_configuration;
+use shared::domain::{WaitlistEntry, WaitlistPosition};
+use crate::error::ensure_success;
+use crate::output::{render, OutputFormat};
+
+// Sends a request to the server to move a waitlist entry,
+// returning the reordered waitlist.
+async fn send_move_entry(
+    web_url: &str,
+    appt_id: Uuid,
+    entry_id: Uuid,
+    position: i32
+) -> Result<Vec<WaitlistEntry>, Error> {
+    let client = reqwest::Client::new();
+
+    let response = client.put(format!("{}/api/appointment/{}/waitlist/{}", web_url, appt_id, entry_id))
+        .json(&WaitlistPosition { position })
+        .send()
+        .await?;
+
+    Ok(ensure_success(response).await?.json::<Vec<WaitlistEntry>>().await?)
+}
+
+/// Asynchronously moves a person to another place of the waitlist.
+///
+/// # Parameters
+///
+/// - `appt_id`: The UUID of the appointment.
+/// - `entry_id`: The UUID of the waitlist entry to move.
+/// - `position`: The new 1-based place; positions past the end move the entry last.
+/// - `output`: The format the reordered waitlist is rendered in.
+///
+/// # Returns
+///
+/// - A `Result<String, Error>` with the rendered waitlist or an error.
+pub async fn move_waitlist_entry_handler(
+    appt_id: Uuid,
+    entry_id: Uuid,
+    position: i32,
+    output: OutputFormat
+) -> Result<String, Error> {
+    let configuration = get_configuration()
+        .map_err(|e| anyhow!("Failed to get configuration: {}", e))?;
+
+    let entries = send_move_entry(&configuration.console_cli.web_url, appt_id, entry_id, position).await?;
+
+    render(&entries, output)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{mock, server_url, Matcher};
+    use crate::appointment::ENV_VAR_LOCK_TEST;
+
+    #[tokio::test]
+    async fn test_move_missing_entry_fails() {
+        let _guard = ENV_VAR_LOCK_TEST.lock().await;
+
+        let appt_id = Uuid::new_v4();
+        let entry_id = Uuid::new_v4();
+        let _mock = mock("PUT", format!("/api/appointment/{}/waitlist/{}", appt_id, entry_id).as_str())
+            .match_body(Matcher::Json(serde_json::json!({"position": 2})))
+            .with_status(404)
+            .with_body(format!("Not found for {}", entry_id))
+            .create();
+
+        let error = send_move_entry(&server_url(), appt_id, entry_id, 2).await.unwrap_err();
+
+        assert!(error.to_string().contains("Not found"));
+    }
+}
@@ -0,0 +1,347 @@
+//! ZIP archives streamed to the client while their entries are rendered.
+//!
+//! Entries are written one at a time on a [`BlockingPool`] and every finished
+//! entry is sent as one chunk, so a large archive never sits in memory as a
+//! whole. Given the same entries, the archive is the same byte for byte, which
+//! lets clients resume a download with a `Range` request. Finished archives
+//! are kept in an [`ArchiveCache`], so that resuming doesn't render them again.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use actix_web::web::Bytes;
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use futures::{Stream, StreamExt};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::mpsc;
+use tracing::Instrument;
+use uuid::Uuid;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, DateTime, ZipWriter};
+use shared::blocking::BlockingPool;
+
+/// A file of an archive.
+pub struct ArchiveEntry {
+    pub name: String,
+    pub content: Vec<u8>,
+    /// Whether to deflate the content, pointless for PNGs, JPEGs and PDFs.
+    pub compress: bool,
+}
+
+type ChunkSender = mpsc::Sender<Result<Bytes, io::Error>>;
+
+/// Streams the entries as a ZIP archive, every one of them dated `modified`.
+///
+/// Every entry is compressed and written on the `pool`, which bounds the
+/// archives written at the same time. When one of the entries fails, the
+/// stream ends with the error and the archive is left truncated.
+///
+/// With a cache, the archive is also written to it under `etag`, and finished
+/// even when the client stops reading, as it's likely to resume the download.
+pub fn stream_zip<S>(
+    entries: S,
+    modified: NaiveDateTime,
+    pool: BlockingPool,
+    cache: Option<(Arc<ArchiveCache>, String)>
+) -> impl Stream<Item = Result<Bytes, io::Error>>
+    where
+        S: Stream<Item = Result<ArchiveEntry, anyhow::Error>> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel(4);
+    tokio::spawn(
+        write_archive(entries, modified, pool, cache, sender)
+            .instrument(tracing::Span::current())
+    );
+
+    futures::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    })
+}
+
+async fn write_archive<S>(
+    entries: S,
+    modified: NaiveDateTime,
+    pool: BlockingPool,
+    cache: Option<(Arc<ArchiveCache>, String)>,
+    sender: ChunkSender
+) where
+    S: Stream<Item = Result<ArchiveEntry, anyhow::Error>> + Send + 'static,
+{
+    let part = match cache.clone() {
+        Some((cache, etag)) => match pool.run(move || cache.create_part(&etag)).await.and_then(|part| Ok(part?)) {
+            Ok(part) => Some(part),
+            Err(e) => {
+                tracing::error!("Failed to cache a ZIP archive: {:?}", e);
+                None
+            }
+        },
+        None => None,
+    };
+    let (part_path, copy) = part.unzip();
+    let caching = copy.is_some();
+    let (chunk_sender, chunks) = mpsc::unbounded_channel();
+    let mut client = Some(sender);
+    let written = write_zip(
+        ChunkWriter::new(chunk_sender, copy),
+        entries,
+        zip_date_time(modified),
+        &pool,
+        Chunks { chunks, client: &mut client, caching }
+    ).await;
+
+    if let (Some((cache, etag)), Some(part_path)) = (cache, part_path) {
+        let finished = written.is_ok();
+        let stored = pool.run(move || match finished {
+            true => cache.store(&part_path, &etag),
+            false => std::fs::remove_file(&part_path),
+        }).await.and_then(|stored| Ok(stored?));
+        if let Err(e) = stored {
+            tracing::error!("Failed to cache a ZIP archive: {:?}", e);
+        }
+    }
+    if let Err(e) = written {
+        let Some(sender) = client else {
+            tracing::info!("The client stopped downloading a ZIP archive");
+            return;
+        };
+        tracing::error!("Failed to write a ZIP archive: {:?}", e);
+        let _ = sender.send(Err(io::Error::other(e.to_string()))).await;
+    }
+}
+
+fn zip_date_time(modified: NaiveDateTime) -> DateTime {
+    DateTime::from_date_and_time(
+        modified.year().clamp(1980, 2107) as u16,
+        modified.month() as u8,
+        modified.day() as u8,
+        modified.hour() as u8,
+        modified.minute() as u8,
+        // ZIP timestamps have a two seconds resolution.
+        modified.second().min(58) as u8,
+    ).unwrap_or_default()
+}
+
+/// The chunks written on the pool, on their way to the client.
+struct Chunks<'a> {
+    chunks: mpsc::UnboundedReceiver<Bytes>,
+    /// `None` once the client went away.
+    client: &'a mut Option<ChunkSender>,
+    caching: bool,
+}
+
+impl Chunks<'_> {
+    /// Sends the chunks written so far, waiting for the client to read them.
+    ///
+    /// Once the client went away, the chunks only go to the copy in the
+    /// cache. Without one, the archive is stopped.
+    async fn forward(&mut self) -> Result<(), anyhow::Error> {
+        while let Ok(chunk) = self.chunks.try_recv() {
+            let Some(client) = self.client.as_ref() else {
+                continue;
+            };
+            if client.send(Ok(chunk)).await.is_err() {
+                *self.client = None;
+            }
+        }
+        match self.client.is_none() && !self.caching {
+            true => Err(anyhow::anyhow!("The client stopped reading the archive")),
+            false => Ok(()),
+        }
+    }
+}
+
+async fn write_zip<S>(
+    writer: ChunkWriter,
+    entries: S,
+    modified: DateTime,
+    pool: &BlockingPool,
+    mut chunks: Chunks<'_>
+) -> Result<(), anyhow::Error>
+    where
+        S: Stream<Item = Result<ArchiveEntry, anyhow::Error>>,
+{
+    let mut zip = ZipWriter::new(writer);
+    zip.set_flush_on_finish_file(true);
+    futures::pin_mut!(entries);
+    while let Some(entry) = entries.next().await {
+        let entry = entry?;
+        zip = pool.run(move || write_entry(zip, entry, modified)).await??;
+        chunks.forward().await?;
+    }
+    pool.run(move || -> Result<(), anyhow::Error> {
+        let mut writer = zip.finish()?;
+        writer.flush()?;
+        Ok(())
+    }).await??;
+    chunks.forward().await
+}
+
+fn write_entry(
+    mut zip: ZipWriter<ChunkWriter>,
+    entry: ArchiveEntry,
+    modified: DateTime
+) -> Result<ZipWriter<ChunkWriter>, anyhow::Error> {
+    let method = match entry.compress {
+        true => CompressionMethod::Deflated,
+        false => CompressionMethod::Stored,
+    };
+    let options = SimpleFileOptions::default()
+        .compression_method(method)
+        .last_modified_time(modified)
+        .unix_permissions(0o644);
+    zip.start_file(entry.name, options)?;
+    zip.write_all(&entry.content)?;
+    Ok(zip)
+}
+
+/// Hands what is written over in chunks, on every flush, and copies them to
+/// a file when given one.
+///
+/// The ZIP writer seeks back into the entry it is writing to fill in its size
+/// and checksum, so the unsent bytes are kept until the entry is finished and
+/// only seeking into bytes already sent is refused.
+struct ChunkWriter {
+    chunks: mpsc::UnboundedSender<Bytes>,
+    copy: Option<File>,
+    buffer: Vec<u8>,
+    /// Number of bytes sent, the offset of the buffer in the archive.
+    sent: u64,
+    position: u64,
+}
+
+impl ChunkWriter {
+    fn new(chunks: mpsc::UnboundedSender<Bytes>, copy: Option<File>) -> Self {
+        Self { chunks, copy, buffer: vec![], sent: 0, position: 0 }
+    }
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let offset = (self.position - self.sent) as usize;
+        let overwritten = data.len().min(self.buffer.len().saturating_sub(offset));
+        self.buffer[offset..offset + overwritten].copy_from_slice(&data[..overwritten]);
+        self.buffer.extend_from_slice(&data[overwritten..]);
+        self.position += data.len() as u64;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let chunk = Bytes::from(std::mem::take(&mut self.buffer));
+        self.sent += chunk.len() as u64;
+        self.position = self.sent;
+        if let Some(copy) = &mut self.copy {
+            copy.write_all(&chunk)?;
+        }
+        // Chunks are taken from the channel until the archive is written.
+        let _ = self.chunks.send(chunk);
+        Ok(())
+    }
+}
+
+// Only needed to copy entries within an archive, which is never done here.
+impl Read for ChunkWriter {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "Can't read an archive being sent"))
+    }
+}
+
+impl Seek for ChunkWriter {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let end = self.sent + self.buffer.len() as u64;
+        let target = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+            SeekFrom::End(delta) => end.checked_add_signed(delta),
+        };
+        match target {
+            Some(target) if (self.sent..=end).contains(&target) => {
+                self.position = target;
+                Ok(target)
+            }
+            _ => Err(io::Error::new(io::ErrorKind::Unsupported, "Can't seek into bytes already sent")),
+        }
+    }
+}
+
+/// Finished archives kept on disk by their ETag, so that resumed downloads
+/// are read from the file instead of rendering the archive again.
+///
+/// Only the `capacity` archives written last are kept.
+pub struct ArchiveCache {
+    directory: PathBuf,
+    capacity: usize,
+}
+
+/// An archive of the cache, opened.
+pub struct CachedArchive {
+    file: tokio::fs::File,
+    pub length: u64,
+}
+
+impl ArchiveCache {
+    pub fn new(directory: PathBuf, capacity: usize) -> Self {
+        Self { directory, capacity }
+    }
+
+    fn path(&self, etag: &str) -> PathBuf {
+        self.directory.join(format!("{}.zip", etag.trim_matches('"')))
+    }
+
+    /// Opens the archive tagged `etag`, if it's cached.
+    pub async fn open(&self, etag: &str) -> Option<CachedArchive> {
+        let file = tokio::fs::File::open(self.path(etag)).await.ok()?;
+        let length = file.metadata().await.ok()?.len();
+        Some(CachedArchive { file, length })
+    }
+
+    /// A new file to write the archive tagged `etag` to. Concurrent requests
+    /// write their own, and the last one finished wins.
+    fn create_part(&self, etag: &str) -> io::Result<(PathBuf, File)> {
+        std::fs::create_dir_all(&self.directory)?;
+        let path = self.directory.join(format!("{}.{}.part", etag.trim_matches('"'), Uuid::new_v4().simple()));
+        let file = File::create(&path)?;
+        Ok((path, file))
+    }
+
+    /// Puts a finished archive in place, and drops the oldest ones beyond the capacity.
+    fn store(&self, part: &Path, etag: &str) -> io::Result<()> {
+        std::fs::rename(part, self.path(etag))?;
+
+        let mut archives = vec![];
+        for entry in std::fs::read_dir(&self.directory)? {
+            let entry = entry?;
+            if entry.path().extension().is_some_and(|extension| extension == "zip") {
+                archives.push((entry.metadata()?.modified()?, entry.path()));
+            }
+        }
+        archives.sort_by(|(first, _), (second, _)| second.cmp(first));
+        for (_, path) in archives.into_iter().skip(self.capacity) {
+            // Another request may have dropped it already.
+            let _ = std::fs::remove_file(path);
+        }
+        Ok(())
+    }
+}
+
+impl CachedArchive {
+    /// Streams the bytes from `first` to `last`, both included.
+    pub async fn stream_range(mut self, first: u64, last: u64) -> io::Result<impl Stream<Item = Result<Bytes, io::Error>>> {
+        const CHUNK_SIZE: usize = 64 * 1024;
+
+        self.file.seek(SeekFrom::Start(first)).await?;
+        let remaining = self.file.take(last + 1 - first);
+        Ok(futures::stream::try_unfold(remaining, |mut remaining| async move {
+            let mut chunk = vec![0; CHUNK_SIZE];
+            let read = remaining.read(&mut chunk).await?;
+            if read == 0 {
+                return Ok(None);
+            }
+            chunk.truncate(read);
+            Ok(Some((Bytes::from(chunk), remaining)))
+        }))
+    }
+}
@@ -4,8 +4,12 @@ use actix_web::{
     HttpResponse,
 };
 use actix_web::error::QueryPayloadError;
+use actix_web::http::header::RETRY_AFTER;
 use thiserror::Error;
 
+/// Seconds clients are asked to wait before retrying a request still in progress.
+const RETRY_IN_PROGRESS_SECONDS: u64 = 1;
+
 #[derive(Debug, Error)]
 #[allow(unused)]
 pub enum CustomError {
@@ -28,13 +32,51 @@ pub enum CustomError {
     #[error("{0}")]
     Forbidden(String),
     #[error("{0}")]
-    NotFound(String)
+    NotFound(String),
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("{0}")]
+    CapacityExceeded(String),
+    #[error("{0}")]
+    Conflict(String),
+    /// The same request is still being handled, answered with `Retry-After`.
+    #[error("{0}")]
+    InProgress(String),
+    #[error("{0}")]
+    TooManyRequests(String),
+    /// Raised before anything was committed, so a request under an idempotency
+    /// key can start over with the same key.
+    #[error("{0}")]
+    NothingPersisted(Box<CustomError>)
+}
+
+#[allow(unused)]
+impl CustomError {
+    /// Marks an error raised before anything was committed.
+    pub fn nothing_persisted(self) -> Self {
+        match self {
+            CustomError::NothingPersisted(_) => self,
+            e => CustomError::NothingPersisted(Box::new(e)),
+        }
+    }
+
+    /// The error itself, whether or not it was marked.
+    pub fn cause(&self) -> &CustomError {
+        match self {
+            CustomError::NothingPersisted(e) => e.cause(),
+            e => e,
+        }
+    }
 }
 
 
 impl error::ResponseError for CustomError {
     fn error_response(&self) -> HttpResponse {
-        HttpResponse::build(self.status_code())
+        let mut response = HttpResponse::build(self.status_code());
+        if let CustomError::InProgress(_) = self.cause() {
+            response.insert_header((RETRY_AFTER, RETRY_IN_PROGRESS_SECONDS.to_string()));
+        }
+        response
             .insert_header(ContentType::html())
             .body(self.to_string())
     }
@@ -50,7 +92,13 @@ impl error::ResponseError for CustomError {
             CustomError::Duplicate(_) => StatusCode::CONFLICT,
             CustomError::Forbidden(_) => StatusCode::FORBIDDEN,
             CustomError::NotFound(_) => StatusCode::NOT_FOUND,
-            CustomError::InvalidCredentials(_) => StatusCode::UNAUTHORIZED
+            CustomError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            CustomError::CapacityExceeded(_) => StatusCode::CONFLICT,
+            CustomError::Conflict(_) => StatusCode::CONFLICT,
+            CustomError::InProgress(_) => StatusCode::CONFLICT,
+            CustomError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            CustomError::InvalidCredentials(_) => StatusCode::UNAUTHORIZED,
+            CustomError::NothingPersisted(e) => error::ResponseError::status_code(e.as_ref())
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,162 @@
+//! Replay of requests sent with an `Idempotency-Key` header.
+//!
+//! The first request with a key is handled as usual, and its response stored
+//! along with a hash of the request. Retries with the same key get the stored
+//! response back instead of creating, and emailing, everything once more.
+//! Reusing a key for another request is rejected, and a retry while the first
+//! request is still being handled is asked to come back with `Retry-After`.
+
+use std::future::Future;
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use crate::error::CustomError;
+use crate::repository::{claim_idempotency_key, release_idempotency_key, renew_idempotency_key, save_idempotent_response};
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+/// Set on responses replayed from an earlier request.
+pub const IDEMPOTENT_REPLAYED_HEADER: &str = "Idempotent-Replayed";
+
+const MAX_KEY_LENGTH: usize = 255;
+/// How long a response is replayed, the key can be used again afterwards.
+const KEY_EXPIRY_SECONDS: i64 = 24 * 60 * 60;
+/// A request whose key wasn't renewed for that long was dropped, usually by a
+/// restart of the server, and its key can be claimed again.
+const STALE_KEY_SECONDS: i64 = 60;
+/// Interval at which the key of a request being handled is renewed.
+const KEY_HEARTBEAT_SECONDS: u64 = 15;
+
+#[derive(Debug)]
+pub struct IdempotencyKey(String);
+
+impl IdempotencyKey {
+    /// The key of the request, if it has one.
+    pub fn from_request(request: &HttpRequest) -> Result<Option<Self>, CustomError> {
+        let Some(value) = request.headers().get(IDEMPOTENCY_KEY_HEADER) else {
+            return Ok(None);
+        };
+        let key = value.to_str().unwrap_or_default().trim();
+        if key.is_empty() || key.len() > MAX_KEY_LENGTH {
+            return Err(CustomError::BadRequest(format!(
+                "{} must be between 1 and {} visible ASCII characters",
+                IDEMPOTENCY_KEY_HEADER, MAX_KEY_LENGTH
+            )));
+        }
+        Ok(Some(IdempotencyKey(key.to_string())))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Hash of everything that makes up a request, to tell a retry from another
+/// request sent with the same key.
+pub fn request_hash(request: &impl Serialize) -> String {
+    let request = serde_json::to_vec(request).expect("Failed to serialize request");
+    format!("{:x}", Sha256::digest(request))
+}
+
+/// Responds with what `handler` returns as JSON, or, when the request is a
+/// retry of one with the same `key` within `scope`, with the stored response.
+///
+/// # Errors
+///
+/// - `CustomError::Duplicate` when the key was used for another request.
+/// - `CustomError::InProgress` when the first request with the key is still being handled.
+/// - Any error of `handler`. The key can be used again after a
+///   `CustomError::NothingPersisted`, any other error is answered to the
+///   retries as well, as the request may have stored something before it.
+pub async fn idempotent<T, F>(
+    pool: &PgPool,
+    key: Option<IdempotencyKey>,
+    scope: &str,
+    request_hash: String,
+    handler: F
+) -> Result<HttpResponse, CustomError>
+    where
+        T: Serialize,
+        F: Future<Output = Result<T, CustomError>>,
+{
+    let Some(key) = key else {
+        return Ok(HttpResponse::Ok().json(handler.await?));
+    };
+
+    let stored = claim_idempotency_key(
+        pool, scope, key.as_str(), &request_hash, KEY_EXPIRY_SECONDS, STALE_KEY_SECONDS
+    ).await?;
+    if let Some(stored) = stored {
+        if stored.request_hash != request_hash {
+            return Err(CustomError::Duplicate(format!(
+                "{} {} was already used for another request",
+                IDEMPOTENCY_KEY_HEADER, key.as_str()
+            )));
+        }
+        return match (stored.response_status, stored.response_body) {
+            (Some(status), Some(body)) => {
+                let status = StatusCode::from_u16(status as u16).unwrap_or(StatusCode::OK);
+                let mut response = HttpResponse::build(status);
+                response.insert_header((IDEMPOTENT_REPLAYED_HEADER, "true"));
+                // Errors are stored as their message, answered like the error was.
+                match (status.is_success(), body.0) {
+                    (false, serde_json::Value::String(message)) => Ok(
+                        response.insert_header(ContentType::html()).body(message)
+                    ),
+                    (_, body) => Ok(response.json(body)),
+                }
+            }
+            _ => Err(CustomError::InProgress(format!(
+                "A request with {} {} is still being handled, retry later",
+                IDEMPOTENCY_KEY_HEADER, key.as_str()
+            ))),
+        };
+    }
+
+    let heartbeat = renew_key_while_handled(pool, scope, &key);
+    tokio::pin!(handler, heartbeat);
+    let result = tokio::select! {
+        result = &mut handler => result,
+        () = &mut heartbeat => unreachable!("The key is renewed until the handler returns"),
+    };
+    let response = match result {
+        Ok(response) => response,
+        Err(e @ CustomError::NothingPersisted(_)) => {
+            // Nothing was created, so a retry gets another chance.
+            if let Err(release_error) = release_idempotency_key(pool, scope, key.as_str()).await {
+                tracing::error!("Failed to release {} {}: {}", IDEMPOTENCY_KEY_HEADER, key.as_str(), release_error);
+            }
+            return Err(e);
+        }
+        Err(e) => {
+            // Something may have been created before the error, so a retry
+            // gets the error instead of creating it again.
+            let body = serde_json::Value::String(e.to_string());
+            if let Err(save_error) = save_idempotent_response(pool, scope, key.as_str(), e.status_code().as_u16() as i16, &body).await {
+                tracing::error!("Failed to save the error of {} {}: {}", IDEMPOTENCY_KEY_HEADER, key.as_str(), save_error);
+            }
+            return Err(e);
+        }
+    };
+    let body = serde_json::to_value(&response)
+        .map_err(|e| anyhow::anyhow!("Failed to serialize response: {}", e))?;
+    save_idempotent_response(pool, scope, key.as_str(), StatusCode::OK.as_u16() as i16, &body).await?;
+
+    Ok(HttpResponse::Ok().json(body))
+}
+
+/// Renews the key every heartbeat, so that retries keep waiting for the
+/// request however long it takes. Never returns.
+async fn renew_key_while_handled(pool: &PgPool, scope: &str, key: &IdempotencyKey) {
+    let mut heartbeat = tokio::time::interval(std::time::Duration::from_secs(KEY_HEARTBEAT_SECONDS));
+    // The key was just claimed.
+    heartbeat.tick().await;
+    loop {
+        heartbeat.tick().await;
+        if let Err(e) = renew_idempotency_key(pool, scope, key.as_str()).await {
+            tracing::error!("Failed to renew {} {}: {}", IDEMPOTENCY_KEY_HEADER, key.as_str(), e);
+        }
+    }
+}
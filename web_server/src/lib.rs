@@ -2,4 +2,7 @@ pub mod telemetry;
 pub mod error;
 pub mod startup;
 pub mod routes;
-pub mod repository;
\ No newline at end of file
+pub mod repository;
+pub mod rate_limit;
+pub mod archive;
+pub mod idempotency;
@@ -9,7 +9,7 @@ use shared::email_client::EmailClient;
 use shared::qr_client::QRClient;
 
 pub fn convert_error<E: ToString>(err: E) -> std::io::Error {
-    std::io::Error::new(std::io::ErrorKind::Other, err.to_string())
+    std::io::Error::other(err.to_string())
 }
 
 
@@ -24,6 +24,13 @@ async fn main() -> std::io::Result<()> {
 
 
     let configuration = get_configuration().map_err(convert_error)?;
+    // Tickets can't be signed without a key, refuse to start rather than fail on the first one.
+    let ticket_keys = configuration.qr_client.ticket_keys.clone()
+        .ok_or_else(|| convert_error("No ticket signing key is configured, set `qr_client.ticket_keys`."))?;
+    let ticket_signer = ticket_keys.signer().map_err(convert_error)?;
+    if configuration.application.scanners.is_empty() {
+        return Err(convert_error("No door scanner is configured, set `application.scanners`."));
+    }
     let address = format!(
         "{}:{}",
         configuration.application.host, configuration.application.port
@@ -39,7 +46,9 @@ async fn main() -> std::io::Result<()> {
         configuration.qr_client.base_url.clone(),
         configuration.qr_client.base_image_path.clone(),
         configuration.qr_client.timeout()
-    );
+    ).with_limits(&configuration.qr_client.limits)
+        .with_rendering(&configuration.qr_client.rendering)
+        .with_ticket_keys(ticket_signer, ticket_keys.retired_keys);
 
     let sender_email = configuration
         .email_client
@@ -51,9 +60,9 @@ async fn main() -> std::io::Result<()> {
         sender_email,
         configuration.email_client.authorization_token,
         timeout,
-    );
+    ).with_limits(&configuration.email_client.limits);
 
-    run(listener, db_connection_pool, qr_client, email_client)
+    run(listener, db_connection_pool, qr_client, email_client, configuration.application)
         .map_err(convert_error)?
         .await
         .map_err(convert_error)?;
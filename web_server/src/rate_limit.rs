@@ -0,0 +1,119 @@
+//! In-memory rate limiting of public endpoints.
+
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use actix_web::HttpRequest;
+
+/// Allows at most `max_hits` hits per key within a sliding `window`.
+///
+/// State lives in the process, so every web server instance limits on its own.
+pub struct RateLimiter {
+    max_hits: usize,
+    window: Duration,
+    hits: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl RateLimiter {
+    pub fn new(max_hits: usize, window: Duration) -> Self {
+        RateLimiter {
+            max_hits,
+            window,
+            hits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records a hit for `key`, returning `false` when the limit is already reached.
+    pub fn check(&self, key: &str) -> bool {
+        self.check_at(key, Instant::now())
+    }
+
+    fn check_at(&self, key: &str, now: Instant) -> bool {
+        let mut hits = self.hits.lock().expect("Rate limiter lock is poisoned");
+        // Forget keys without recent hits so the map doesn't grow forever.
+        hits.retain(|_, times| times.back().is_some_and(|last| now.duration_since(*last) < self.window));
+
+        let times = hits.entry(key.to_string()).or_default();
+        while times.front().is_some_and(|first| now.duration_since(*first) >= self.window) {
+            times.pop_front();
+        }
+        if times.len() >= self.max_hits {
+            return false;
+        }
+        times.push_back(now);
+        true
+    }
+}
+
+/// Address a request is limited by: the address of the peer, or the one
+/// forwarded by the peer when it is a trusted proxy, as anyone can send an
+/// `X-Forwarded-For` header.
+///
+/// Proxies append the address they got the request from to `X-Forwarded-For`,
+/// after whatever the client sent. So the header is read from the right, and
+/// the first address that isn't one of the trusted proxies is the client.
+pub fn client_address(request: &HttpRequest, trusted_proxies: &[IpAddr]) -> String {
+    let Some(peer) = request.peer_addr().map(|peer| peer.ip()) else {
+        return String::new();
+    };
+    if !trusted_proxies.contains(&peer) {
+        return peer.to_string();
+    }
+
+    let forwarded: Vec<&str> = request.headers().get_all("X-Forwarded-For")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+    forwarded.into_iter()
+        .rev()
+        .find(|address| address.parse::<IpAddr>().map_or(true, |address| !trusted_proxies.contains(&address)))
+        .unwrap_or(&peer.to_string())
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn hits_beyond_the_limit_are_refused_until_the_window_passes() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60));
+        let start = Instant::now();
+
+        assert!(limiter.check_at("1.2.3.4", start));
+        assert!(limiter.check_at("1.2.3.4", start + Duration::from_secs(1)));
+        assert!(!limiter.check_at("1.2.3.4", start + Duration::from_secs(2)));
+        assert!(limiter.check_at("5.6.7.8", start + Duration::from_secs(2)));
+        assert!(limiter.check_at("1.2.3.4", start + Duration::from_secs(61)));
+    }
+
+    #[test]
+    fn forwarded_address_is_only_trusted_from_a_trusted_proxy() {
+        let request = |peer: &str| TestRequest::default()
+            .peer_addr(format!("{}:4000", peer).parse().unwrap())
+            .insert_header(("X-Forwarded-For", "9.9.9.9"))
+            .to_http_request();
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+
+        assert_eq!(client_address(&request("1.2.3.4"), &[proxy]), "1.2.3.4");
+        assert_eq!(client_address(&request("10.0.0.1"), &[proxy]), "9.9.9.9");
+        assert_eq!(client_address(&request("10.0.0.1"), &[]), "10.0.0.1");
+    }
+
+    #[test]
+    fn spoofed_forwarded_addresses_are_ignored() {
+        let proxies: Vec<IpAddr> = vec!["10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()];
+        let request = |forwarded: &str| TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .insert_header(("X-Forwarded-For", forwarded.to_string()))
+            .to_http_request();
+
+        // The client sent the first entry, the proxies appended the others.
+        assert_eq!(client_address(&request("6.6.6.6, 9.9.9.9"), &proxies), "9.9.9.9");
+        assert_eq!(client_address(&request("6.6.6.6, 9.9.9.9, 10.0.0.2"), &proxies), "9.9.9.9");
+        assert_eq!(client_address(&request("10.0.0.2"), &proxies), "10.0.0.1");
+    }
+}
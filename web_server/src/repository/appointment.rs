@@ -1,9 +1,44 @@
 use anyhow::Context;
-use sqlx::{PgPool, Postgres, Row, Transaction};
+use std::sync::LazyLock;
+use sqlx::{PgPool, Postgres, QueryBuilder, Row, Transaction};
 use uuid::Uuid;
-use shared::domain::{Appointment, DBAppointment, NewAppointment};
+use shared::domain::{Appointment, AppointmentFilter, DBAppointment, NewAppointment};
+use shared::qr_render::QrRenderOptions;
 use crate::error::CustomError;
 
+/// Seats held by the invitations of an appointment: every admission of the active
+/// invitations, and the people already admitted with the others.
+const SEATS_TAKEN: &str = "
+    SELECT COALESCE(SUM(CASE WHEN invitation.status = 'ACTIVE' THEN invitation.max_admissions ELSE invitation.admitted END), 0)
+    FROM invitation
+    WHERE invitation.appointment_id = appointment.id
+";
+
+/// Appointment columns, with the seats left computed from the invitations.
+static APPOINTMENT_COLUMNS: LazyLock<String> = LazyLock::new(|| format!("
+    id, title, description, format, address, link, date, duration, capacity, registration_open, qr_options,
+    CASE WHEN capacity IS NOT NULL THEN GREATEST(capacity - ({})::INTEGER, 0) END AS remaining_seats
+", SEATS_TAKEN));
+
+/// Seats of an appointment, as seen while its row is locked.
+#[derive(Debug)]
+pub(crate) struct AppointmentSeats {
+    pub capacity: Option<i32>,
+    pub issued: i64,
+}
+
+impl AppointmentSeats {
+    /// Seats left, `None` when the appointment is unlimited.
+    pub fn free(&self) -> Option<i64> {
+        self.capacity.map(|capacity| (capacity as i64 - self.issued).max(0))
+    }
+
+    /// Whether `count` more seats fit in the capacity.
+    pub fn can_issue(&self, count: i64) -> bool {
+        self.capacity.is_none_or(|capacity| self.issued + count <= capacity as i64)
+    }
+}
+
 #[tracing::instrument(
 name = "Preserve new appointment in DB",
 skip(transaction),
@@ -16,8 +51,8 @@ pub(crate) async fn preserve_new_appointment(
     let link = new_appointment.link.map(|link| link.to_string());
     let resp = sqlx::query(
         r#"
-            INSERT INTO appointment (title, description, format, address, link, date, duration)
-            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            INSERT INTO appointment (title, description, format, address, link, date, duration, capacity, registration_open, qr_options)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
             RETURNING id;
         "#
     )
@@ -28,6 +63,9 @@ pub(crate) async fn preserve_new_appointment(
         .bind(link)
         .bind(new_appointment.date)
         .bind(new_appointment.duration.as_secs() as i32)
+        .bind(new_appointment.capacity)
+        .bind(new_appointment.registration_open)
+        .bind(new_appointment.qr_options.map(sqlx::types::Json))
         .fetch_one(&mut **transaction)
         .await
         .context("Failed to insert a new appointment into the database.")?;
@@ -45,10 +83,10 @@ pub(crate) async fn get_stored_appointments(
     pool: &PgPool,
     appointment_id: Option<Uuid>
 ) -> Result<Vec<Appointment>, CustomError> {
-    let mut query = "
-        SELECT id, title, description, format, address, link, date, duration
+    let mut query = format!("
+        SELECT {}
         FROM appointment
-    ".to_string();
+    ", *APPOINTMENT_COLUMNS);
 
     let records: Vec<DBAppointment> = if let Some(id) = appointment_id {
         query.push_str(" WHERE id = $1 ");
@@ -67,6 +105,82 @@ pub(crate) async fn get_stored_appointments(
     Ok(result)
 }
 
+#[tracing::instrument(
+name = "Get appointment QR options from DB",
+skip(pool),
+)]
+pub(crate) async fn get_appointment_qr_options(
+    pool: &PgPool,
+    appointment_id: Uuid
+) -> Result<QrRenderOptions, CustomError> {
+    let options: Option<Option<sqlx::types::Json<QrRenderOptions>>> = sqlx::query_scalar(
+        "SELECT qr_options FROM appointment WHERE id = $1"
+    )
+        .bind(appointment_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(options.flatten().map(|options| options.0).unwrap_or_default())
+}
+
+#[tracing::instrument(
+name = "Get filtered appointments from DB",
+skip(pool),
+)]
+pub(crate) async fn get_filtered_appointments(
+    pool: &PgPool,
+    filter: &AppointmentFilter
+) -> Result<Vec<Appointment>, CustomError> {
+    let mut query_builder = QueryBuilder::new(format!("
+        SELECT {}
+        FROM appointment
+        WHERE TRUE
+    ", *APPOINTMENT_COLUMNS));
+
+    if let Some(from) = filter.from {
+        query_builder.push(" AND date >= ").push_bind(from);
+    }
+    if let Some(to) = filter.to {
+        query_builder.push(" AND date <= ").push_bind(to);
+    }
+    if let Some(format) = &filter.format {
+        query_builder.push(" AND format = ").push_bind(format.clone());
+    }
+    query_builder.push(" ORDER BY date ");
+
+    let records: Vec<DBAppointment> = query_builder
+        .build_query_as::<DBAppointment>()
+        .fetch_all(pool)
+        .await?;
+
+    Ok(records.into_iter().map(Appointment::from).collect())
+}
+
+#[tracing::instrument(
+name = "Lock appointment seats in DB",
+skip(transaction)
+)]
+pub(crate) async fn lock_appointment_seats(
+    transaction: &mut Transaction<'_, Postgres>,
+    appointment_id: Uuid
+) -> Result<Option<AppointmentSeats>, CustomError> {
+    // Locking the appointment row serializes concurrent invitation requests,
+    // so the count below stays valid until the transaction ends.
+    let record = sqlx::query(&format!("
+        SELECT capacity, ({})::BIGINT AS issued
+        FROM appointment
+        WHERE id = $1
+        FOR UPDATE
+    ", SEATS_TAKEN))
+        .bind(appointment_id)
+        .fetch_optional(&mut **transaction)
+        .await?;
+
+    Ok(record.map(|record| AppointmentSeats {
+        capacity: record.get("capacity"),
+        issued: record.get("issued"),
+    }))
+}
 
 #[tracing::instrument(
 name = "Remove appointment stored in DB",
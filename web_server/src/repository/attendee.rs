@@ -0,0 +1,268 @@
+use chrono::NaiveDateTime;
+use sqlx::{PgExecutor, PgPool, Postgres, QueryBuilder, Transaction};
+use url::Url;
+use uuid::Uuid;
+use shared::domain::{Attendee, AttendeeWithInvitation, DBAttendee, Email, NewAttendee, NewInvitation};
+use crate::error::CustomError;
+
+/// Name of the unique index on the address of the attendees of an appointment.
+const ATTENDEE_EMAIL_INDEX: &str = "attendee_appointment_email_idx";
+
+/// An attendee already invited to an appointment, with their active
+/// invitation unless it was declined or revoked.
+#[derive(Debug, sqlx::FromRow)]
+pub(crate) struct KnownRecipient {
+    pub attendee_id: Uuid,
+    pub email: String,
+    pub invitation_id: Option<Uuid>,
+    pub appointment_id: Uuid,
+    pub short_url: Option<String>,
+    pub claim_deadline: Option<NaiveDateTime>,
+    pub max_admissions: Option<i32>,
+}
+
+impl KnownRecipient {
+    pub fn invitation(&self) -> Option<NewInvitation> {
+        Some(NewInvitation {
+            id: self.invitation_id?,
+            appointment_id: self.appointment_id,
+            short_url: Url::parse(self.short_url.as_deref()?).ok()?,
+            attendee_id: Some(self.attendee_id),
+            claim_deadline: self.claim_deadline,
+            max_admissions: self.max_admissions?,
+        })
+    }
+}
+
+#[tracing::instrument(
+name = "Preserve new attendees in DB",
+skip(transaction, attendees),
+)]
+pub(crate) async fn preserve_new_attendees(
+    transaction: &mut Transaction<'_, Postgres>,
+    appointment_id: Uuid,
+    attendees: &[(Uuid, NewAttendee)],
+) -> Result<(), CustomError> {
+    if attendees.is_empty() {
+        return Ok(());
+    }
+
+    let mut query_builder = QueryBuilder::new("INSERT INTO attendee (id, appointment_id, name, email, phone, custom_fields) ");
+
+    query_builder.push_values(attendees, |mut b, (id, attendee)| {
+        b
+            .push_bind(*id)
+            .push_bind(appointment_id)
+            .push_bind(attendee.name.clone())
+            .push_bind(attendee.email.as_ref().to_string())
+            .push_bind(attendee.phone.clone())
+            .push_bind(attendee.custom_fields.clone());
+    });
+
+    query_builder.build().execute(&mut **transaction).await.map_err(|e| match &e {
+        sqlx::Error::Database(error) if error.constraint() == Some(ATTENDEE_EMAIL_INDEX) => CustomError::Duplicate(
+            "An attendee was invited to the appointment at the same time, please retry".to_string()
+        ),
+        _ => CustomError::from(e),
+    })?;
+
+    Ok(())
+}
+
+/// An active invitation whose holder hasn't checked in yet, with the holder.
+#[derive(Debug, sqlx::FromRow)]
+pub(crate) struct PendingRecipient {
+    #[sqlx(flatten)]
+    pub attendee: DBAttendee,
+    pub invitation_id: Uuid,
+    pub short_url: String,
+    pub claim_deadline: Option<NaiveDateTime>,
+    pub max_admissions: i32,
+}
+
+impl PendingRecipient {
+    pub fn attendee(&self) -> Result<NewAttendee, CustomError> {
+        let attendee = Attendee::try_from(self.attendee.clone()).map_err(|e| anyhow::anyhow!(e))?;
+        Ok(NewAttendee {
+            name: attendee.name,
+            email: attendee.email,
+            phone: attendee.phone,
+            custom_fields: attendee.custom_fields,
+        })
+    }
+
+    pub fn invitation(&self) -> Result<NewInvitation, CustomError> {
+        let short_url = Url::parse(&self.short_url)
+            .map_err(|e| anyhow::anyhow!("Invalid short URL of invitation {}: {}", self.invitation_id, e))?;
+        Ok(NewInvitation {
+            id: self.invitation_id,
+            appointment_id: self.attendee.appointment_id,
+            short_url,
+            attendee_id: Some(self.attendee.id),
+            claim_deadline: self.claim_deadline,
+            max_admissions: self.max_admissions,
+        })
+    }
+}
+
+#[tracing::instrument(
+name = "Update attendee email in DB",
+skip(transaction),
+)]
+pub(crate) async fn update_attendee_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    attendee_id: Uuid,
+    email: &Email,
+) -> Result<(), CustomError> {
+    sqlx::query("UPDATE attendee SET email = $2 WHERE id = $1")
+        .bind(attendee_id)
+        .bind(email.as_ref())
+        .execute(&mut **transaction)
+        .await
+        .map_err(|e| match &e {
+            sqlx::Error::Database(error) if error.constraint() == Some(ATTENDEE_EMAIL_INDEX) => CustomError::Duplicate(
+                format!("{} is already an attendee of the appointment", email.as_ref())
+            ),
+            _ => CustomError::from(e),
+        })?;
+
+    Ok(())
+}
+
+#[tracing::instrument(
+name = "Get pending recipients from DB",
+skip(pool),
+)]
+pub(crate) async fn get_pending_recipients(
+    pool: &PgPool,
+    appointment_id: Uuid,
+    idempotency_key: Option<&str>
+) -> Result<Vec<PendingRecipient>, CustomError> {
+    // Holders who joined online took part as well. Invitations resent under the
+    // same idempotency key were resent by an earlier try of the request.
+    let recipients = sqlx::query_as::<_, PendingRecipient>("
+        SELECT attendee.id, attendee.appointment_id, attendee.name, attendee.email, attendee.phone,
+        attendee.custom_fields, invitation.id AS invitation_id, invitation.short_url,
+        invitation.claim_deadline, invitation.max_admissions
+        FROM invitation
+        JOIN attendee ON attendee.id = invitation.attendee_id
+        WHERE invitation.appointment_id = $1 AND invitation.status = 'ACTIVE'
+        AND NOT invitation.used AND invitation.admitted = 0
+        AND NOT EXISTS (SELECT 1 FROM online_joins WHERE online_joins.invitation_id = invitation.id)
+        AND NOT EXISTS (
+            SELECT 1 FROM invitation_history
+            WHERE invitation_history.invitation_id = invitation.id AND invitation_history.action = 'RESENT'
+            AND invitation_history.details->>'idempotency_key' = $2
+        )
+        ORDER BY attendee.email
+    ")
+        .bind(appointment_id)
+        .bind(idempotency_key)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(recipients)
+}
+
+#[tracing::instrument(
+name = "Get known recipients from DB",
+skip(executor, emails),
+)]
+pub(crate) async fn get_known_recipients<'e>(
+    executor: impl PgExecutor<'e>,
+    appointment_id: Uuid,
+    emails: &[String]
+) -> Result<Vec<KnownRecipient>, CustomError> {
+    if emails.is_empty() {
+        return Ok(vec![]);
+    }
+    let emails: Vec<String> = emails.iter().map(|email| email.to_lowercase()).collect();
+
+    let recipients = sqlx::query_as::<_, KnownRecipient>("
+        SELECT DISTINCT ON (lower(attendee.email)) attendee.id AS attendee_id, attendee.email,
+        attendee.appointment_id, invitation.id AS invitation_id, invitation.short_url,
+        invitation.claim_deadline, invitation.max_admissions
+        FROM attendee
+        LEFT JOIN invitation ON invitation.attendee_id = attendee.id AND invitation.status = 'ACTIVE'
+        WHERE attendee.appointment_id = $1 AND lower(attendee.email) = ANY($2)
+        ORDER BY lower(attendee.email), invitation.created_at DESC NULLS LAST
+    ")
+        .bind(appointment_id)
+        .bind(&emails)
+        .fetch_all(executor)
+        .await?;
+
+    Ok(recipients)
+}
+
+#[derive(sqlx::FromRow)]
+struct DBAttendeeWithInvitation {
+    #[sqlx(flatten)]
+    attendee: DBAttendee,
+    invitation_id: Option<Uuid>,
+    used: Option<bool>,
+}
+
+#[tracing::instrument(
+name = "Get appointment attendees from DB",
+skip(pool),
+)]
+pub(crate) async fn get_appointment_attendees(
+    pool: &PgPool,
+    appointment_id: Uuid,
+    search: Option<&str>
+) -> Result<Vec<AttendeeWithInvitation>, CustomError> {
+    let mut query_builder = QueryBuilder::new("
+        SELECT attendee.id, attendee.appointment_id, attendee.name, attendee.email, attendee.phone,
+        attendee.custom_fields, invitation.id AS invitation_id, invitation.used
+        FROM attendee
+        LEFT JOIN invitation ON invitation.attendee_id = attendee.id
+        WHERE attendee.appointment_id = ");
+    query_builder.push_bind(appointment_id);
+
+    if let Some(search) = search {
+        let pattern = format!("%{}%", search);
+        query_builder
+            .push(" AND (attendee.name ILIKE ").push_bind(pattern.clone())
+            .push(" OR attendee.email ILIKE ").push_bind(pattern)
+            .push(")");
+    }
+    query_builder.push(" ORDER BY attendee.name, attendee.email ");
+
+    let records: Vec<DBAttendeeWithInvitation> = query_builder
+        .build_query_as::<DBAttendeeWithInvitation>()
+        .fetch_all(pool)
+        .await?;
+
+    let result = records.into_iter()
+        .map(|record| Ok(AttendeeWithInvitation {
+            attendee: Attendee::try_from(record.attendee).map_err(|e| anyhow::anyhow!(e))?,
+            invitation_id: record.invitation_id,
+            used: record.used,
+        }))
+        .collect::<Result<Vec<_>, CustomError>>()?;
+    Ok(result)
+}
+
+#[tracing::instrument(
+name = "Get invitation attendee from DB",
+skip(pool),
+)]
+pub(crate) async fn get_invitation_attendee(
+    pool: &PgPool,
+    invitation_id: Uuid
+) -> Result<Option<Attendee>, CustomError> {
+    let record: Option<DBAttendee> = sqlx::query_as::<_, DBAttendee>("
+        SELECT attendee.id, attendee.appointment_id, attendee.name, attendee.email, attendee.phone, attendee.custom_fields
+        FROM attendee
+        JOIN invitation ON invitation.attendee_id = attendee.id
+        WHERE invitation.id = $1
+    ")
+        .bind(invitation_id)
+        .fetch_optional(pool)
+        .await?;
+
+    record.map(Attendee::try_from)
+        .transpose()
+        .map_err(|e| anyhow::anyhow!(e).into())
+}
@@ -0,0 +1,48 @@
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+use shared::domain::{DBInvitationHistory, InvitationAction, InvitationHistory};
+use crate::error::CustomError;
+
+#[tracing::instrument(
+name = "Preserve invitation history in DB",
+skip(transaction),
+)]
+pub(crate) async fn preserve_invitation_history(
+    transaction: &mut Transaction<'_, Postgres>,
+    invitation_id: Uuid,
+    action: InvitationAction,
+    details: serde_json::Value
+) -> Result<(), CustomError> {
+    sqlx::query("
+        INSERT INTO invitation_history (invitation_id, action, details)
+        VALUES ($1, $2, $3)
+    ")
+        .bind(invitation_id)
+        .bind(action)
+        .bind(details)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(
+name = "Get invitation history from DB",
+skip(pool),
+)]
+pub(crate) async fn get_invitation_history(
+    pool: &PgPool,
+    invitation_id: Uuid
+) -> Result<Vec<InvitationHistory>, CustomError> {
+    let records: Vec<DBInvitationHistory> = sqlx::query_as::<_, DBInvitationHistory>("
+        SELECT id, invitation_id, action, details, created_at
+        FROM invitation_history
+        WHERE invitation_id = $1
+        ORDER BY id
+    ")
+        .bind(invitation_id)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(records.into_iter().map(InvitationHistory::from).collect())
+}
@@ -0,0 +1,132 @@
+use sqlx::PgPool;
+use sqlx::types::Json;
+use crate::error::CustomError;
+
+/// A key as stored by a previous request.
+#[derive(Debug, sqlx::FromRow)]
+pub(crate) struct StoredIdempotencyKey {
+    pub request_hash: String,
+    pub response_status: Option<i16>,
+    pub response_body: Option<Json<serde_json::Value>>,
+}
+
+#[tracing::instrument(
+name = "Claim idempotency key in DB",
+skip(pool),
+)]
+pub(crate) async fn claim_idempotency_key(
+    pool: &PgPool,
+    scope: &str,
+    key: &str,
+    request_hash: &str,
+    expire_after_seconds: i64,
+    stale_after_seconds: i64
+) -> Result<Option<StoredIdempotencyKey>, CustomError> {
+    // Inserted, or taken over once expired or left without a response nor a
+    // heartbeat: nothing to replay.
+    let claimed = sqlx::query("
+        INSERT INTO idempotency_keys (scope, idempotency_key, request_hash)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (scope, idempotency_key) DO UPDATE
+        SET request_hash = EXCLUDED.request_hash, response_status = NULL, response_body = NULL,
+            created_at = CURRENT_TIMESTAMP AT TIME ZONE 'UTC', heartbeat_at = CURRENT_TIMESTAMP AT TIME ZONE 'UTC'
+        WHERE idempotency_keys.created_at < CURRENT_TIMESTAMP AT TIME ZONE 'UTC' - make_interval(secs => $4)
+            OR (
+                idempotency_keys.response_status IS NULL
+                AND idempotency_keys.heartbeat_at < CURRENT_TIMESTAMP AT TIME ZONE 'UTC' - make_interval(secs => $5)
+            )
+    ")
+        .bind(scope)
+        .bind(key)
+        .bind(request_hash)
+        .bind(expire_after_seconds as f64)
+        .bind(stale_after_seconds as f64)
+        .execute(pool)
+        .await?
+        .rows_affected() > 0;
+    if claimed {
+        return Ok(None);
+    }
+
+    let stored = sqlx::query_as::<_, StoredIdempotencyKey>("
+        SELECT request_hash, response_status, response_body
+        FROM idempotency_keys
+        WHERE scope = $1 AND idempotency_key = $2
+    ")
+        .bind(scope)
+        .bind(key)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(Some(stored))
+}
+
+/// Tells that the request holding a key is still being handled.
+#[tracing::instrument(
+name = "Renew idempotency key in DB",
+skip(pool),
+)]
+pub(crate) async fn renew_idempotency_key(
+    pool: &PgPool,
+    scope: &str,
+    key: &str
+) -> Result<(), CustomError> {
+    sqlx::query("
+        UPDATE idempotency_keys
+        SET heartbeat_at = CURRENT_TIMESTAMP AT TIME ZONE 'UTC'
+        WHERE scope = $1 AND idempotency_key = $2 AND response_status IS NULL
+    ")
+        .bind(scope)
+        .bind(key)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(
+name = "Save idempotent response in DB",
+skip(pool, response_body),
+)]
+pub(crate) async fn save_idempotent_response(
+    pool: &PgPool,
+    scope: &str,
+    key: &str,
+    response_status: i16,
+    response_body: &serde_json::Value
+) -> Result<(), CustomError> {
+    sqlx::query("
+        UPDATE idempotency_keys
+        SET response_status = $3, response_body = $4
+        WHERE scope = $1 AND idempotency_key = $2
+    ")
+        .bind(scope)
+        .bind(key)
+        .bind(response_status)
+        .bind(Json(response_body))
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(
+name = "Release idempotency key in DB",
+skip(pool),
+)]
+pub(crate) async fn release_idempotency_key(
+    pool: &PgPool,
+    scope: &str,
+    key: &str
+) -> Result<(), CustomError> {
+    sqlx::query("
+        DELETE FROM idempotency_keys
+        WHERE scope = $1 AND idempotency_key = $2 AND response_status IS NULL
+    ")
+        .bind(scope)
+        .bind(key)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
@@ -1,18 +1,22 @@
-use sqlx::{PgPool, Postgres, QueryBuilder, Transaction};
+use sqlx::{PgExecutor, PgPool, Postgres, QueryBuilder, Transaction};
 use uuid::Uuid;
-use shared::domain::{DBInvitation, Invitation, NewInvitation};
+use shared::domain::{DBInvitation, Email, Invitation, InvitationCounts, NewInvitation};
 use crate::error::CustomError;
 
+pub(crate) const INVITATION_COLUMNS: &str = "
+    id, appointment_id, used, short_url, attendee_id, status, rsvp, claim_deadline, max_admissions, admitted
+";
+
 pub(crate) async fn get_stored_invitations(
     pool: &PgPool,
-    appointment_id: Option<Uuid>
+    invitation_id: Option<Uuid>
 ) -> Result<Vec<Invitation>, CustomError> {
-    let mut query = "
-        SELECT id, appointment_id, used, short_url
+    let mut query = format!("
+        SELECT {}
         FROM invitation
-    ".to_string();
+    ", INVITATION_COLUMNS);
 
-    let records: Vec<DBInvitation> = if let Some(id) = appointment_id {
+    let records: Vec<DBInvitation> = if let Some(id) = invitation_id {
         query.push_str(" WHERE id = $1 ");
         sqlx::query_as::<_, DBInvitation>(&query)
             .bind(id)
@@ -26,6 +30,27 @@ pub(crate) async fn get_stored_invitations(
     Ok(result)
 }
 
+#[tracing::instrument(
+name = "Get appointment invitations from DB",
+skip(pool),
+)]
+pub(crate) async fn get_appointment_invitations(
+    pool: &PgPool,
+    appointment_id: Uuid
+) -> Result<Vec<Invitation>, CustomError> {
+    let records: Vec<DBInvitation> = sqlx::query_as::<_, DBInvitation>(&format!("
+        SELECT {}
+        FROM invitation
+        WHERE appointment_id = $1
+        ORDER BY created_at
+    ", INVITATION_COLUMNS))
+        .bind(appointment_id)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(records.into_iter().map(Invitation::from).collect())
+}
+
 #[tracing::instrument(
 name = "Preserve new invitations in DB",
 skip(transaction),
@@ -34,14 +59,20 @@ pub(crate) async fn preserve_new_invitations(
     transaction: &mut Transaction<'_, Postgres>,
     invitations: &Vec<NewInvitation>,
 ) -> Result<(), CustomError> {
+    if invitations.is_empty() {
+        return Ok(());
+    }
 
-    let mut query_builder = QueryBuilder::new("INSERT INTO invitation (id, appointment_id, short_url) ");
+    let mut query_builder = QueryBuilder::new("INSERT INTO invitation (id, appointment_id, short_url, attendee_id, claim_deadline, max_admissions) ");
 
     query_builder.push_values(invitations, |mut b, new_invitation| {
         b
             .push_bind(new_invitation.id)
             .push_bind(new_invitation.appointment_id)
-            .push_bind(new_invitation.short_url.as_str().to_string());
+            .push_bind(new_invitation.short_url.as_str().to_string())
+            .push_bind(new_invitation.attendee_id)
+            .push_bind(new_invitation.claim_deadline)
+            .push_bind(new_invitation.max_admissions);
     });
 
     let query = query_builder.build();
@@ -49,4 +80,211 @@ pub(crate) async fn preserve_new_invitations(
     let _ = query.execute(&mut **transaction).await?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[tracing::instrument(
+name = "Count appointment invitations in DB",
+skip(pool),
+)]
+pub(crate) async fn get_invitation_counts(
+    pool: &PgPool,
+    appointment_id: Uuid
+) -> Result<InvitationCounts, CustomError> {
+    let counts: InvitationCounts = sqlx::query_as::<_, InvitationCounts>("
+        SELECT COUNT(*) AS total,
+        COUNT(*) FILTER (WHERE used) AS used,
+        COUNT(*) FILTER (WHERE NOT used AND status <> 'DECLINED') AS unused,
+        COUNT(*) FILTER (WHERE status = 'DECLINED') AS declined
+        FROM invitation
+        WHERE appointment_id = $1
+    ")
+        .bind(appointment_id)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(counts)
+}
+
+#[tracing::instrument(
+name = "Preserve sent emails in DB",
+skip(transaction),
+)]
+pub(crate) async fn preserve_sent_emails(
+    transaction: &mut Transaction<'_, Postgres>,
+    sent_emails: &[(Uuid, Email)],
+) -> Result<(), CustomError> {
+    if sent_emails.is_empty() {
+        return Ok(());
+    }
+
+    let mut query_builder = QueryBuilder::new("INSERT INTO emails (invitation_id, receiver) ");
+
+    query_builder.push_values(sent_emails, |mut b, (invitation_id, receiver)| {
+        b
+            .push_bind(*invitation_id)
+            .push_bind(receiver.as_ref().to_string());
+    });
+
+    let _ = query_builder.build().execute(&mut **transaction).await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(
+name = "Get appointment recipients from DB",
+skip(pool),
+)]
+pub(crate) async fn get_appointment_recipients(
+    pool: &PgPool,
+    appointment_id: Uuid
+) -> Result<Vec<Email>, CustomError> {
+    let receivers: Vec<String> = sqlx::query_scalar("
+        SELECT DISTINCT attendee.email
+        FROM attendee
+        JOIN invitation ON invitation.attendee_id = attendee.id
+        WHERE invitation.appointment_id = $1
+        ORDER BY attendee.email
+    ")
+        .bind(appointment_id)
+        .fetch_all(pool)
+        .await?;
+
+    let result = receivers.into_iter()
+        .map(|receiver| Email::parse(receiver).map_err(|e| anyhow::anyhow!(e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(result)
+}
+
+#[tracing::instrument(
+name = "Decline invitation in DB",
+skip(transaction),
+)]
+pub(crate) async fn decline_stored_invitation(
+    transaction: &mut Transaction<'_, Postgres>,
+    invitation_id: Uuid
+) -> Result<Option<Invitation>, CustomError> {
+    let record: Option<DBInvitation> = sqlx::query_as::<_, DBInvitation>(&format!("
+        UPDATE invitation
+        SET status = 'DECLINED', rsvp = 'DECLINED', claim_deadline = NULL, updated_at = CURRENT_TIMESTAMP
+        WHERE id = $1 AND status = 'ACTIVE' AND NOT used
+        RETURNING {}
+    ", INVITATION_COLUMNS))
+        .bind(invitation_id)
+        .fetch_optional(&mut **transaction)
+        .await?;
+
+    Ok(record.map(Invitation::from))
+}
+
+/// Accepts an active invitation, which also claims it when it was promoted
+/// from the waitlist and the claim deadline has not passed.
+#[tracing::instrument(
+name = "Accept invitation in DB",
+skip(transaction),
+)]
+pub(crate) async fn accept_stored_invitation(
+    transaction: &mut Transaction<'_, Postgres>,
+    invitation_id: Uuid
+) -> Result<Option<Invitation>, CustomError> {
+    let record: Option<DBInvitation> = sqlx::query_as::<_, DBInvitation>(&format!("
+        UPDATE invitation
+        SET rsvp = 'ACCEPTED', claim_deadline = NULL, updated_at = CURRENT_TIMESTAMP
+        WHERE id = $1 AND status = 'ACTIVE'
+        AND (claim_deadline IS NULL OR claim_deadline >= timezone('utc', now()))
+        RETURNING {}
+    ", INVITATION_COLUMNS))
+        .bind(invitation_id)
+        .fetch_optional(&mut **transaction)
+        .await?;
+
+    Ok(record.map(Invitation::from))
+}
+
+#[tracing::instrument(
+name = "Decline expired invitation claims in DB",
+skip(transaction),
+)]
+pub(crate) async fn decline_expired_claims(
+    transaction: &mut Transaction<'_, Postgres>,
+    appointment_id: Uuid
+) -> Result<u64, CustomError> {
+    let result = sqlx::query("
+        WITH declined AS (
+            UPDATE invitation
+            SET status = 'DECLINED', rsvp = 'DECLINED', claim_deadline = NULL, updated_at = CURRENT_TIMESTAMP
+            WHERE appointment_id = $1 AND status = 'ACTIVE' AND claim_deadline < timezone('utc', now())
+            RETURNING id
+        )
+        INSERT INTO invitation_history (invitation_id, action, details)
+        SELECT id, 'DECLINED', '{\"reason\": \"claim deadline passed\"}' FROM declined
+    ")
+        .bind(appointment_id)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+#[tracing::instrument(
+name = "Revoke invitation in DB",
+skip(transaction),
+)]
+pub(crate) async fn revoke_stored_invitation(
+    transaction: &mut Transaction<'_, Postgres>,
+    invitation_id: Uuid
+) -> Result<Option<Invitation>, CustomError> {
+    let record: Option<DBInvitation> = sqlx::query_as::<_, DBInvitation>(&format!("
+        UPDATE invitation
+        SET status = 'REVOKED', claim_deadline = NULL, updated_at = CURRENT_TIMESTAMP
+        WHERE id = $1 AND status = 'ACTIVE' AND NOT used
+        RETURNING {}
+    ", INVITATION_COLUMNS))
+        .bind(invitation_id)
+        .fetch_optional(&mut **transaction)
+        .await?;
+
+    Ok(record.map(Invitation::from))
+}
+
+#[tracing::instrument(
+name = "Lock invitation in DB",
+skip(transaction),
+)]
+pub(crate) async fn lock_invitation(
+    transaction: &mut Transaction<'_, Postgres>,
+    invitation_id: Uuid
+) -> Result<bool, CustomError> {
+    // Held until the transaction ends, so that scans of one invitation are
+    // applied one after the other.
+    let locked: Option<Uuid> = sqlx::query_scalar("SELECT id FROM invitation WHERE id = $1 FOR UPDATE")
+        .bind(invitation_id)
+        .fetch_optional(&mut **transaction)
+        .await?;
+
+    Ok(locked.is_some())
+}
+
+#[tracing::instrument(
+name = "Admit invitation holder in DB",
+skip(executor),
+)]
+pub(crate) async fn admit_stored_invitation<'e>(
+    executor: impl PgExecutor<'e>,
+    invitation_id: Uuid
+) -> Result<bool, CustomError> {
+    // A single conditional update, so that concurrent scans of a group
+    // invitation can't admit more people than it allows, nor an unclaimed
+    // promotion once its deadline passed.
+    let rows_affected = sqlx::query("
+        UPDATE invitation
+        SET admitted = admitted + 1, used = admitted + 1 >= max_admissions, updated_at = CURRENT_TIMESTAMP
+        WHERE id = $1 AND status = 'ACTIVE' AND admitted < max_admissions
+        AND (claim_deadline IS NULL OR claim_deadline >= timezone('utc', now()))
+    ")
+        .bind(invitation_id)
+        .execute(executor)
+        .await?
+        .rows_affected();
+
+    Ok(rows_affected > 0)
+}
@@ -0,0 +1,300 @@
+use sqlx::{PgPool, Postgres, Transaction};
+use sqlx::types::Json;
+use uuid::Uuid;
+use shared::domain::{DBInvitation, Invitation, InvitationJob, JobEmailFailure, NewAttendee};
+use crate::error::CustomError;
+use super::INVITATION_COLUMNS;
+
+const JOB_COLUMNS: &str = "
+    id, appointment_id, status, total, processed, max_admissions, errors, email_failures, created_at, updated_at
+";
+
+/// A job claimed by a worker. Only the worker holding the lease records
+/// the progress and the outcome of the job.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct JobLease {
+    pub job_id: Uuid,
+    pub worker_id: Uuid,
+}
+
+#[tracing::instrument(
+name = "Preserve new invitation job in DB",
+skip(pool, recipients),
+)]
+pub(crate) async fn preserve_new_job(
+    pool: &PgPool,
+    appointment_id: Uuid,
+    total: i32,
+    max_admissions: i32,
+    recipients: Option<Vec<NewAttendee>>
+) -> Result<InvitationJob, CustomError> {
+    // The job and its recipients are stored at once.
+    let job = sqlx::query_as::<_, InvitationJob>(&format!("
+        WITH job AS (
+            INSERT INTO invitation_jobs (id, appointment_id, total, max_admissions)
+            VALUES ($1, $2, $3, $4)
+            RETURNING {}
+        ), recipients AS (
+            INSERT INTO invitation_job_recipients (job_id, position, recipient)
+            SELECT $1, recipient.position, recipient.value
+            FROM jsonb_array_elements($5) WITH ORDINALITY AS recipient(value, position)
+        )
+        SELECT * FROM job
+    ", JOB_COLUMNS))
+        .bind(Uuid::new_v4())
+        .bind(appointment_id)
+        .bind(total)
+        .bind(max_admissions)
+        .bind(recipients.map(Json))
+        .fetch_one(pool)
+        .await?;
+
+    Ok(job)
+}
+
+#[tracing::instrument(
+name = "Get invitation job from DB",
+skip(pool),
+)]
+pub(crate) async fn get_job(
+    pool: &PgPool,
+    job_id: Uuid
+) -> Result<Option<InvitationJob>, CustomError> {
+    let job = sqlx::query_as::<_, InvitationJob>(&format!("
+        SELECT {}
+        FROM invitation_jobs
+        WHERE id = $1
+    ", JOB_COLUMNS))
+        .bind(job_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(job)
+}
+
+#[tracing::instrument(
+name = "Claim invitation job in DB",
+skip(pool),
+)]
+pub(crate) async fn claim_job(
+    pool: &PgPool,
+    lease: JobLease,
+    lease_seconds: i64
+) -> Result<Option<InvitationJob>, CustomError> {
+    // A job still RUNNING whose lease expired lost its worker, usually to a
+    // restart of the server.
+    let job = sqlx::query_as::<_, InvitationJob>(&format!("
+        UPDATE invitation_jobs
+        SET status = 'RUNNING',
+            worker_id = $2,
+            lease_expires_at = CURRENT_TIMESTAMP AT TIME ZONE 'UTC' + make_interval(secs => $3),
+            updated_at = CURRENT_TIMESTAMP AT TIME ZONE 'UTC'
+        WHERE id = $1 AND (
+            status IN ('PENDING', 'FAILED')
+            OR (status = 'RUNNING' AND (lease_expires_at IS NULL OR lease_expires_at < CURRENT_TIMESTAMP AT TIME ZONE 'UTC'))
+        )
+        RETURNING {}
+    ", JOB_COLUMNS))
+        .bind(lease.job_id)
+        .bind(lease.worker_id)
+        .bind(lease_seconds as f64)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(job)
+}
+
+/// Extends the lease of a running job, unless another worker took it over.
+#[tracing::instrument(
+name = "Renew invitation job lease in DB",
+skip(pool),
+)]
+pub(crate) async fn renew_job_lease(
+    pool: &PgPool,
+    lease: JobLease,
+    lease_seconds: i64
+) -> Result<bool, CustomError> {
+    let renewed = sqlx::query("
+        UPDATE invitation_jobs
+        SET lease_expires_at = CURRENT_TIMESTAMP AT TIME ZONE 'UTC' + make_interval(secs => $3)
+        WHERE id = $1 AND worker_id = $2 AND status = 'RUNNING'
+    ")
+        .bind(lease.job_id)
+        .bind(lease.worker_id)
+        .bind(lease_seconds as f64)
+        .execute(pool)
+        .await?
+        .rows_affected() > 0;
+
+    Ok(renewed)
+}
+
+#[tracing::instrument(
+name = "Get invitation job recipients from DB",
+skip(pool),
+)]
+pub(crate) async fn get_job_recipients(
+    pool: &PgPool,
+    job_id: Uuid,
+    offset: i32,
+    limit: i32
+) -> Result<Vec<NewAttendee>, CustomError> {
+    let recipients: Vec<Json<NewAttendee>> = sqlx::query_scalar("
+        SELECT recipient
+        FROM invitation_job_recipients
+        WHERE job_id = $1 AND position > $2
+        ORDER BY position
+        LIMIT $3
+    ")
+        .bind(job_id)
+        .bind(offset)
+        .bind(limit as i64)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(recipients.into_iter().map(|recipient| recipient.0).collect())
+}
+
+/// Moves the progress of a job from `processed_before` on by `processed`.
+///
+/// Fails with a conflict when the worker lost the job, or another one
+/// recorded the batch first, so that the batch is rolled back.
+#[tracing::instrument(
+name = "Record invitation job progress in DB",
+skip(transaction, invitation_ids),
+)]
+pub(crate) async fn record_job_progress(
+    transaction: &mut Transaction<'_, Postgres>,
+    lease: JobLease,
+    processed_before: i32,
+    processed: i32,
+    invitation_ids: &[Uuid]
+) -> Result<(), CustomError> {
+    let recorded = sqlx::query("
+        UPDATE invitation_jobs
+        SET processed = processed + $4, updated_at = CURRENT_TIMESTAMP AT TIME ZONE 'UTC'
+        WHERE id = $1 AND worker_id = $2 AND processed = $3 AND status = 'RUNNING'
+    ")
+        .bind(lease.job_id)
+        .bind(lease.worker_id)
+        .bind(processed_before)
+        .bind(processed)
+        .execute(&mut **transaction)
+        .await?
+        .rows_affected() > 0;
+    if !recorded {
+        return Err(CustomError::Conflict(format!(
+            "Invitation job {} was taken over by another worker", lease.job_id
+        )));
+    }
+
+    // Recipients already invited keep the invitation they hold, which isn't
+    // part of the job, so there may be fewer invitations than recipients.
+    sqlx::query("
+        UPDATE invitation
+        SET job_id = $1, job_position = $2 + batch.position
+        FROM unnest($3::UUID[]) WITH ORDINALITY AS batch(id, position)
+        WHERE invitation.id = batch.id
+    ")
+        .bind(lease.job_id)
+        .bind(processed_before)
+        .bind(invitation_ids)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(())
+}
+
+/// Records the emails of a batch that couldn't be sent, unless the worker
+/// lost the job meanwhile.
+#[tracing::instrument(
+name = "Record invitation job email failures in DB",
+skip(pool, failures),
+)]
+pub(crate) async fn record_job_email_failures(
+    pool: &PgPool,
+    lease: JobLease,
+    failures: &[JobEmailFailure]
+) -> Result<(), CustomError> {
+    if failures.is_empty() {
+        return Ok(());
+    }
+    sqlx::query("
+        UPDATE invitation_jobs
+        SET email_failures = email_failures || $3, updated_at = CURRENT_TIMESTAMP AT TIME ZONE 'UTC'
+        WHERE id = $1 AND worker_id = $2
+    ")
+        .bind(lease.job_id)
+        .bind(lease.worker_id)
+        .bind(Json(failures))
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(
+name = "Complete invitation job in DB",
+skip(pool),
+)]
+pub(crate) async fn complete_job(
+    pool: &PgPool,
+    lease: JobLease
+) -> Result<(), CustomError> {
+    sqlx::query("
+        UPDATE invitation_jobs
+        SET status = 'COMPLETED', lease_expires_at = NULL, updated_at = CURRENT_TIMESTAMP AT TIME ZONE 'UTC'
+        WHERE id = $1 AND worker_id = $2
+    ")
+        .bind(lease.job_id)
+        .bind(lease.worker_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(
+name = "Fail invitation job in DB",
+skip(pool),
+)]
+pub(crate) async fn fail_job(
+    pool: &PgPool,
+    lease: JobLease,
+    error: &str
+) -> Result<(), CustomError> {
+    sqlx::query("
+        UPDATE invitation_jobs
+        SET status = 'FAILED', errors = array_append(errors, $3), lease_expires_at = NULL,
+            updated_at = CURRENT_TIMESTAMP AT TIME ZONE 'UTC'
+        WHERE id = $1 AND worker_id = $2
+    ")
+        .bind(lease.job_id)
+        .bind(lease.worker_id)
+        .bind(error)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(
+name = "Get invitation job invitations from DB",
+skip(pool),
+)]
+pub(crate) async fn get_job_invitations(
+    pool: &PgPool,
+    job_id: Uuid
+) -> Result<Vec<Invitation>, CustomError> {
+    let records: Vec<DBInvitation> = sqlx::query_as::<_, DBInvitation>(&format!("
+        SELECT {}
+        FROM invitation
+        WHERE job_id = $1
+        ORDER BY job_position
+    ", INVITATION_COLUMNS))
+        .bind(job_id)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(records.into_iter().map(Invitation::from).collect())
+}
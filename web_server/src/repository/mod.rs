@@ -1,5 +1,19 @@
+pub mod attendee;
 pub mod invitation;
 pub mod appointment;
+pub mod waitlist;
+pub mod registration;
+pub mod history;
+pub mod scan;
+pub mod job;
+pub mod idempotency;
 
+pub(crate) use attendee::*;
 pub(crate) use invitation::*;
-pub(crate) use appointment::*;
\ No newline at end of file
+pub(crate) use appointment::*;
+pub(crate) use waitlist::*;
+pub(crate) use registration::*;
+pub(crate) use history::*;
+pub(crate) use scan::*;
+pub(crate) use job::*;
+pub(crate) use idempotency::*;
@@ -0,0 +1,118 @@
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+use shared::domain::{DBRegistration, NewAttendee, Registration};
+use crate::error::CustomError;
+
+const REGISTRATION_COLUMNS: &str = "
+    id, appointment_id, name, email, created_at, confirmed_at, invitation_id, waitlist_id
+";
+
+#[tracing::instrument(
+name = "Preserve new registration in DB",
+skip(transaction, attendee, token),
+)]
+pub(crate) async fn preserve_new_registration(
+    transaction: &mut Transaction<'_, Postgres>,
+    appointment_id: Uuid,
+    attendee: &NewAttendee,
+    token: &str
+) -> Result<Registration, CustomError> {
+    let record: DBRegistration = sqlx::query_as::<_, DBRegistration>(&format!("
+        INSERT INTO registration (appointment_id, name, email, token)
+        VALUES ($1, $2, $3, $4)
+        RETURNING {}
+    ", REGISTRATION_COLUMNS))
+        .bind(appointment_id)
+        .bind(attendee.display_name())
+        .bind(attendee.email.as_ref())
+        .bind(token)
+        .fetch_one(&mut **transaction)
+        .await?;
+
+    Ok(Registration::from(record))
+}
+
+#[tracing::instrument(
+name = "Get registration by token from DB",
+skip(pool, token),
+)]
+pub(crate) async fn get_registration_by_token(
+    pool: &PgPool,
+    token: &str
+) -> Result<Option<Registration>, CustomError> {
+    let record: Option<DBRegistration> = sqlx::query_as::<_, DBRegistration>(&format!("
+        SELECT {}
+        FROM registration
+        WHERE token = $1
+    ", REGISTRATION_COLUMNS))
+        .bind(token)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(record.map(Registration::from))
+}
+
+/// Marks a registration confirmed, returning `false` when it already was.
+///
+/// Only the request that flips the flag goes on to issue the invitation, so
+/// opening the confirmation link twice doesn't issue two of them.
+#[tracing::instrument(
+name = "Start registration confirmation in DB",
+skip(pool),
+)]
+pub(crate) async fn start_registration_confirmation(
+    pool: &PgPool,
+    registration_id: Uuid
+) -> Result<bool, CustomError> {
+    let result = sqlx::query("
+        UPDATE registration
+        SET confirmed_at = timezone('utc', now())
+        WHERE id = $1 AND confirmed_at IS NULL
+    ")
+        .bind(registration_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+/// Makes a registration confirmable again after issuing its invitation failed.
+#[tracing::instrument(
+name = "Reset registration confirmation in DB",
+skip(pool),
+)]
+pub(crate) async fn reset_registration_confirmation(
+    pool: &PgPool,
+    registration_id: Uuid
+) -> Result<(), CustomError> {
+    sqlx::query("UPDATE registration SET confirmed_at = NULL WHERE id = $1")
+        .bind(registration_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(
+name = "Complete registration in DB",
+skip(pool),
+)]
+pub(crate) async fn complete_registration(
+    pool: &PgPool,
+    registration_id: Uuid,
+    invitation_id: Option<Uuid>,
+    waitlist_id: Option<Uuid>
+) -> Result<(), CustomError> {
+    sqlx::query("
+        UPDATE registration
+        SET invitation_id = $2, waitlist_id = $3
+        WHERE id = $1
+    ")
+        .bind(registration_id)
+        .bind(invitation_id)
+        .bind(waitlist_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,192 @@
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+use shared::domain::{DBScanEvent, NewScanEvent, Occupancy, OnlineAttendance, ScanEvent, ScanResult};
+use crate::error::CustomError;
+
+#[tracing::instrument(
+name = "Preserve scan event in DB",
+skip(executor),
+)]
+pub(crate) async fn preserve_scan_event<'e>(
+    executor: impl PgExecutor<'e>,
+    event: &NewScanEvent
+) -> Result<(), CustomError> {
+    sqlx::query("
+        INSERT INTO scan_events (invitation_id, appointment_id, direction, result, reason, scanner, scanned_at, client_scan_id)
+        VALUES ($1, $2, $3, $4, $5, $6, COALESCE($7, CURRENT_TIMESTAMP AT TIME ZONE 'UTC'), $8)
+    ")
+        .bind(event.invitation_id)
+        .bind(event.appointment_id)
+        .bind(event.direction)
+        .bind(event.result)
+        .bind(&event.reason)
+        .bind(&event.scanner)
+        .bind(event.scanned_at)
+        .bind(event.client_scan_id)
+        .execute(executor)
+        .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(
+name = "Claim offline scan in DB",
+skip(transaction),
+)]
+pub(crate) async fn claim_offline_scan(
+    transaction: &mut Transaction<'_, Postgres>,
+    event: &NewScanEvent
+) -> Result<Option<i32>, CustomError> {
+    // Stored before anything is admitted, so that a scan pushed twice at the
+    // same time waits for the first push and is then skipped as a duplicate.
+    let id: Option<i32> = sqlx::query_scalar("
+        INSERT INTO scan_events (invitation_id, appointment_id, direction, result, reason, scanner, scanned_at, client_scan_id)
+        VALUES ($1, $2, $3, $4, $5, $6, COALESCE($7, CURRENT_TIMESTAMP AT TIME ZONE 'UTC'), $8)
+        ON CONFLICT (client_scan_id) DO NOTHING
+        RETURNING id
+    ")
+        .bind(event.invitation_id)
+        .bind(event.appointment_id)
+        .bind(event.direction)
+        .bind(event.result)
+        .bind(&event.reason)
+        .bind(&event.scanner)
+        .bind(event.scanned_at)
+        .bind(event.client_scan_id)
+        .fetch_optional(&mut **transaction)
+        .await?;
+
+    Ok(id)
+}
+
+#[tracing::instrument(
+name = "Record scan result in DB",
+skip(transaction),
+)]
+pub(crate) async fn record_scan_result(
+    transaction: &mut Transaction<'_, Postgres>,
+    scan_id: i32,
+    result: ScanResult,
+    reason: Option<&str>
+) -> Result<(), CustomError> {
+    sqlx::query("UPDATE scan_events SET result = $2, reason = $3 WHERE id = $1")
+        .bind(scan_id)
+        .bind(result)
+        .bind(reason)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(
+name = "Get invitation scans from DB",
+skip(pool),
+)]
+pub(crate) async fn get_invitation_scans(
+    pool: &PgPool,
+    invitation_id: Uuid
+) -> Result<Vec<ScanEvent>, CustomError> {
+    let records: Vec<DBScanEvent> = sqlx::query_as::<_, DBScanEvent>("
+        SELECT id, invitation_id, appointment_id, direction, result, reason, scanner, scanned_at
+        FROM scan_events
+        WHERE invitation_id = $1
+        ORDER BY id
+    ")
+        .bind(invitation_id)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(records.into_iter().map(ScanEvent::from).collect())
+}
+
+#[tracing::instrument(
+name = "Count people inside with an invitation in DB",
+skip(executor),
+)]
+pub(crate) async fn count_inside_with_invitation<'e>(
+    executor: impl PgExecutor<'e>,
+    invitation_id: Uuid
+) -> Result<i64, CustomError> {
+    let inside: i64 = sqlx::query_scalar("
+        SELECT COALESCE(SUM(CASE WHEN direction = 'IN' THEN 1 ELSE -1 END), 0)
+        FROM scan_events
+        WHERE invitation_id = $1 AND result = 'ADMITTED'
+    ")
+        .bind(invitation_id)
+        .fetch_one(executor)
+        .await?;
+
+    Ok(inside)
+}
+
+#[tracing::instrument(
+name = "Get appointment occupancy from DB",
+skip(pool),
+)]
+pub(crate) async fn get_appointment_occupancy(
+    pool: &PgPool,
+    appointment_id: Uuid
+) -> Result<Occupancy, CustomError> {
+    let occupancy: Occupancy = sqlx::query_as::<_, Occupancy>("
+        SELECT $1 AS appointment_id,
+        COUNT(*) FILTER (WHERE direction = 'IN') - COUNT(*) FILTER (WHERE direction = 'OUT') AS inside,
+        COUNT(*) FILTER (WHERE direction = 'IN') AS checked_in,
+        COUNT(*) FILTER (WHERE direction = 'OUT') AS checked_out
+        FROM scan_events
+        WHERE appointment_id = $1 AND result = 'ADMITTED'
+    ")
+        .bind(appointment_id)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(occupancy)
+}
+
+#[tracing::instrument(
+name = "Preserve online join in DB",
+skip(pool),
+)]
+pub(crate) async fn preserve_online_join(
+    pool: &PgPool,
+    invitation_id: Uuid,
+    appointment_id: Uuid,
+    user_agent: Option<&str>
+) -> Result<(), CustomError> {
+    sqlx::query("
+        INSERT INTO online_joins (invitation_id, appointment_id, user_agent)
+        VALUES ($1, $2, $3)
+    ")
+        .bind(invitation_id)
+        .bind(appointment_id)
+        .bind(user_agent)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(
+name = "Get online attendance of an appointment from DB",
+skip(pool),
+)]
+pub(crate) async fn get_online_attendance(
+    pool: &PgPool,
+    appointment_id: Uuid
+) -> Result<Vec<OnlineAttendance>, CustomError> {
+    let attendance: Vec<OnlineAttendance> = sqlx::query_as::<_, OnlineAttendance>("
+        SELECT online_joins.invitation_id, attendee.name AS attendee_name, attendee.email AS attendee_email,
+        COUNT(*) AS joins, MIN(online_joins.joined_at) AS first_joined_at, MAX(online_joins.joined_at) AS last_joined_at
+        FROM online_joins
+        JOIN invitation ON invitation.id = online_joins.invitation_id
+        LEFT JOIN attendee ON attendee.id = invitation.attendee_id
+        WHERE online_joins.appointment_id = $1
+        GROUP BY online_joins.invitation_id, attendee.name, attendee.email
+        ORDER BY first_joined_at
+    ")
+        .bind(appointment_id)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(attendance)
+}
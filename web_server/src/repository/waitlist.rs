@@ -0,0 +1,175 @@
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+use shared::domain::{DBWaitlistEntry, NewAttendee, WaitlistEntry};
+use crate::error::CustomError;
+
+const WAITLIST_COLUMNS: &str = "
+    id, appointment_id, position, name, email, phone, custom_fields, invitation_id, promoted_at
+";
+
+#[tracing::instrument(
+name = "Preserve new waitlist entry in DB",
+skip(transaction, attendee),
+)]
+pub(crate) async fn preserve_waitlist_entry(
+    transaction: &mut Transaction<'_, Postgres>,
+    appointment_id: Uuid,
+    attendee: &NewAttendee,
+) -> Result<WaitlistEntry, CustomError> {
+    let record: DBWaitlistEntry = sqlx::query_as::<_, DBWaitlistEntry>(&format!("
+        INSERT INTO waitlist (appointment_id, position, name, email, phone, custom_fields)
+        VALUES ($1, (
+            SELECT COALESCE(MAX(position), 0) + 1 FROM waitlist
+            WHERE appointment_id = $1 AND promoted_at IS NULL
+        ), $2, $3, $4, $5)
+        RETURNING {}
+    ", WAITLIST_COLUMNS))
+        .bind(appointment_id)
+        .bind(attendee.name.clone())
+        .bind(attendee.email.as_ref().to_string())
+        .bind(attendee.phone.clone())
+        .bind(attendee.custom_fields.clone())
+        .fetch_one(&mut **transaction)
+        .await?;
+
+    Ok(WaitlistEntry::from(record))
+}
+
+#[tracing::instrument(
+name = "Get appointment waitlist from DB",
+skip(pool),
+)]
+pub(crate) async fn get_appointment_waitlist(
+    pool: &PgPool,
+    appointment_id: Uuid
+) -> Result<Vec<WaitlistEntry>, CustomError> {
+    let records: Vec<DBWaitlistEntry> = sqlx::query_as::<_, DBWaitlistEntry>(&format!("
+        SELECT {}
+        FROM waitlist
+        WHERE appointment_id = $1 AND promoted_at IS NULL
+        ORDER BY position
+    ", WAITLIST_COLUMNS))
+        .bind(appointment_id)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(records.into_iter().map(WaitlistEntry::from).collect())
+}
+
+#[tracing::instrument(
+name = "Get waitlist entry from DB",
+skip(pool),
+)]
+pub(crate) async fn get_waitlist_entry(
+    pool: &PgPool,
+    entry_id: Uuid
+) -> Result<Option<WaitlistEntry>, CustomError> {
+    let record: Option<DBWaitlistEntry> = sqlx::query_as::<_, DBWaitlistEntry>(&format!("
+        SELECT {}
+        FROM waitlist
+        WHERE id = $1
+    ", WAITLIST_COLUMNS))
+        .bind(entry_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(record.map(WaitlistEntry::from))
+}
+
+#[tracing::instrument(
+name = "Take next waitlist entries from DB",
+skip(transaction),
+)]
+pub(crate) async fn take_next_waitlist_entries(
+    transaction: &mut Transaction<'_, Postgres>,
+    appointment_id: Uuid,
+    limit: Option<i64>
+) -> Result<Vec<WaitlistEntry>, CustomError> {
+    let records: Vec<DBWaitlistEntry> = sqlx::query_as::<_, DBWaitlistEntry>(&format!("
+        SELECT {}
+        FROM waitlist
+        WHERE appointment_id = $1 AND promoted_at IS NULL
+        ORDER BY position
+        LIMIT $2
+        FOR UPDATE
+    ", WAITLIST_COLUMNS))
+        .bind(appointment_id)
+        .bind(limit)
+        .fetch_all(&mut **transaction)
+        .await?;
+
+    Ok(records.into_iter().map(WaitlistEntry::from).collect())
+}
+
+#[tracing::instrument(
+name = "Mark waitlist entries promoted in DB",
+skip(transaction),
+)]
+pub(crate) async fn mark_waitlist_entries_promoted(
+    transaction: &mut Transaction<'_, Postgres>,
+    promoted: &[(Uuid, Uuid)],
+) -> Result<(), CustomError> {
+    if promoted.is_empty() {
+        return Ok(());
+    }
+
+    let (entry_ids, invitation_ids): (Vec<Uuid>, Vec<Uuid>) = promoted.iter().cloned().unzip();
+
+    sqlx::query("
+        UPDATE waitlist
+        SET invitation_id = promoted.invitation_id,
+            promoted_at = timezone('utc', now()),
+            updated_at = CURRENT_TIMESTAMP
+        FROM UNNEST($1::UUID[], $2::UUID[]) AS promoted(entry_id, invitation_id)
+        WHERE waitlist.id = promoted.entry_id
+    ")
+        .bind(entry_ids)
+        .bind(invitation_ids)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(
+name = "Move waitlist entry in DB",
+skip(transaction),
+)]
+pub(crate) async fn move_waitlist_entry(
+    transaction: &mut Transaction<'_, Postgres>,
+    appointment_id: Uuid,
+    entry_id: Uuid,
+    position: i32
+) -> Result<bool, CustomError> {
+    let mut queue: Vec<Uuid> = sqlx::query_scalar("
+        SELECT id
+        FROM waitlist
+        WHERE appointment_id = $1 AND promoted_at IS NULL
+        ORDER BY position
+        FOR UPDATE
+    ")
+        .bind(appointment_id)
+        .fetch_all(&mut **transaction)
+        .await?;
+
+    let Some(current) = queue.iter().position(|id| *id == entry_id) else {
+        return Ok(false);
+    };
+    queue.remove(current);
+    let index = (position.max(1) as usize - 1).min(queue.len());
+    queue.insert(index, entry_id);
+
+    let positions: Vec<i32> = (1..=queue.len() as i32).collect();
+    sqlx::query("
+        UPDATE waitlist
+        SET position = queued.position, updated_at = CURRENT_TIMESTAMP
+        FROM UNNEST($1::UUID[], $2::INTEGER[]) AS queued(id, position)
+        WHERE waitlist.id = queued.id
+    ")
+        .bind(queue)
+        .bind(positions)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(true)
+}
@@ -1,14 +1,21 @@
 use super::*;
-use shared::domain::{NewAppointment, NewInvitation, InvitationParams, SendAppointmentEmails};
+use actix_web::HttpRequest;
+use std::collections::HashMap;
+use shared::domain::{Appointment, AppointmentFilter, AttendeeSearch, Email, InvitationAction, IssueOutcome, IssuedInvitation, NewAppointment, NewAttendee, NewInvitation, InvitationParams, SendAppointmentEmails};
 use shared::qr_client::QRClient;
-use futures::future::join_all;
+use futures::{stream, StreamExt, TryFutureExt};
+use sqlx::PgExecutor;
 use url::Url;
+use shared::configuration::ApplicationSettings;
 use shared::email_client::EmailClient;
-use crate::repository::{delete_stored_appointment, get_stored_appointments, preserve_new_appointment, preserve_new_invitations};
+use serde_json::json;
+use crate::idempotency::{idempotent, request_hash, IdempotencyKey};
+use crate::repository::{delete_stored_appointment, get_appointment_attendees, get_appointment_invitations, get_appointment_occupancy, get_appointment_recipients, get_online_attendance, get_filtered_appointments, get_invitation_counts, get_known_recipients, get_pending_recipients, get_stored_appointments, KnownRecipient, lock_appointment_seats, preserve_invitation_history, preserve_new_appointment, preserve_new_attendees, preserve_new_invitations, preserve_sent_emails, record_job_progress, JobLease};
 
 pub fn appointment_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::resource("/appointment")
+            .route(web::get().to(get_appointments))
             .route(web::post().to(add_appointment))
     )
         .service(
@@ -18,109 +25,563 @@ pub fn appointment_routes(cfg: &mut web::ServiceConfig) {
         )
         .service(
             web::resource("/appointment/{id}/invitation")
+                .route(web::get().to(get_invitations_by_appointment_id))
                 .route(web::post().to(add_invitation))
+        )
+        .service(
+            web::resource("/appointment/{id}/invitation/counts")
+                .route(web::get().to(get_invitation_counts_by_appointment_id))
+        )
+        .service(
+            web::resource("/appointment/{id}/invitation/resend")
+                .route(web::post().to(resend_pending_invitations))
+        )
+        .service(
+            web::resource("/appointment/{id}/attendee")
+                .route(web::get().to(get_attendees_by_appointment_id))
+        )
+        .service(
+            web::resource("/appointment/{id}/recipients")
+                .route(web::get().to(get_recipients_by_appointment_id))
+        )
+        .service(
+            web::resource("/appointment/{id}/occupancy")
+                .route(web::get().to(get_occupancy_by_appointment_id))
+        )
+        .service(
+            web::resource("/appointment/{id}/attendance")
+                .route(web::get().to(get_attendance_by_appointment_id))
         );
 }
 
+/// Upper bound of invitations issued by a single request, larger batches go
+/// through an invitation job.
+pub(crate) const MAX_INVITATIONS_PER_REQUEST: i32 = 1000;
+
+/// Invitations emailed again, and logged, at a time by a bulk resend.
+const RESEND_BATCH_SIZE: usize = 100;
 
 
 #[tracing::instrument(
     name = "Add invitation service handler",
-    skip(pool, qr_client, email_client),
+    skip(request, pool, qr_client, email_client, application),
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn add_invitation(
+    request: HttpRequest,
     appointment_id: web::Path<Uuid>,
     query_params: web::Query<InvitationParams>,
     send_appointment_emails: web::Json<SendAppointmentEmails>,
     pool: Data<PgPool>,
     qr_client: Data<QRClient>,
-    email_client: Data<EmailClient>
+    email_client: Data<EmailClient>,
+    application: Data<ApplicationSettings>
 ) -> Result<HttpResponse, CustomError> {
-    let send_appointment_emails = send_appointment_emails.into_inner();
-    let mut payload: Vec<NewInvitation>  = vec![];
-    let appt_id = appointment_id.into_inner();
-    let qr_client = qr_client.into_inner();
-    let email_client = email_client.into_inner();
-
-    let count = match &send_appointment_emails.email {
+    let idempotency_key = IdempotencyKey::from_request(&request)?;
+    let appointment_id = appointment_id.into_inner();
+    let hash = request_hash(&json!({
+        "appointment_id": appointment_id,
+        "count": query_params.count,
+        "max_admissions": query_params.max_admissions,
+        "resend_existing": query_params.resend_existing,
+        "body": &send_appointment_emails.0,
+    }));
+    let recipients = send_appointment_emails.into_inner().recipients();
+    let count = match &recipients {
         None => { query_params.count.unwrap_or(1) }
-        Some(emails) => {
-            emails.len() as i32
+        Some(attendees) => {
+            attendees.len() as i32
         }
     };
 
-    let futures: Vec<_> = (0..count).map(|_| {
-        let id = Uuid::new_v4();
-        let qr_client_clone = qr_client.clone();
-        async move {
-            qr_client_clone.get_short_url(id.to_string()).await.map(|res| (id, res.short_url))
-        }
-    }).collect();
-
-    let results: Vec<Result<(Uuid, Url), anyhow::Error>> = join_all(futures).await;
-    for result in results {
-        let (id, short_url) = result?;
-        payload.push(NewInvitation {
-            id,
-            appointment_id: appt_id,
-            short_url
-        });
+    idempotent(&pool.clone(), idempotency_key, "add_invitation", hash, issue_invitations(
+        pool,
+        &qr_client,
+        &email_client,
+        &application.base_url,
+        appointment_id,
+        count,
+        query_params.max_admissions.unwrap_or(1),
+        recipients,
+        query_params.resend_existing
+    )).await
+}
+
+/// Issues `count` invitations admitting `max_admissions` people each for an
+/// appointment within its capacity.
+///
+/// When `recipients` are given, one invitation is issued per attendee and
+/// emailed to them with its QR code and RSVP links. Addresses already holding
+/// an invitation for the appointment get it back instead, emailed again with
+/// `resend_existing`.
+///
+/// # Errors
+///
+/// - `CustomError::BadRequest` when `count` or `max_admissions` is out of bounds.
+/// - `CustomError::NotFound` when the appointment doesn't exist.
+/// - `CustomError::CapacityExceeded` when not enough seats are left.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn issue_invitations(
+    pool: Data<PgPool>,
+    qr_client: &QRClient,
+    email_client: &EmailClient,
+    base_url: &str,
+    appt_id: Uuid,
+    count: i32,
+    max_admissions: i32,
+    recipients: Option<Vec<NewAttendee>>,
+    resend_existing: bool
+) -> Result<Vec<IssuedInvitation>, CustomError> {
+    check_invitation_bounds(count, MAX_INVITATIONS_PER_REQUEST, max_admissions)
+        .map_err(CustomError::nothing_persisted)?;
+    let appointment = get_stored_appointments(pool.as_ref(), Some(appt_id)).await
+        .and_then(|appointments| appointments.into_iter()
+            .next()
+            .ok_or_else(|| CustomError::NotFound(format!("Not found for {}", appt_id))))
+        .map_err(CustomError::nothing_persisted)?;
+
+    issue_invitation_batch(
+        pool,
+        qr_client,
+        email_client,
+        base_url,
+        &appointment.qr_options.unwrap_or_default(),
+        appt_id,
+        count,
+        max_admissions,
+        recipients.unwrap_or_default(),
+        resend_existing,
+        None
+    ).await
+}
+
+/// Checks the bounds of an invitation request and that the appointment has
+/// the seats for it, returning the appointment.
+///
+/// The capacity is checked again under the row lock once the invitations are
+/// about to be stored, this only fails fast before calling the shortener.
+pub(crate) async fn check_invitation_request(
+    pool: &PgPool,
+    appt_id: Uuid,
+    count: i32,
+    max_count: i32,
+    max_admissions: i32
+) -> Result<Appointment, CustomError> {
+    check_invitation_bounds(count, max_count, max_admissions)?;
+    check_remaining_seats(pool, appt_id, count as i64 * max_admissions as i64).await
+}
+
+fn check_invitation_bounds(count: i32, max_count: i32, max_admissions: i32) -> Result<(), CustomError> {
+    if !(1..=max_count).contains(&count) {
+        return Err(CustomError::BadRequest(format!(
+            "Invitation count must be between 1 and {}, got {}",
+            max_count, count
+        )));
     }
+    if max_admissions < 1 {
+        return Err(CustomError::BadRequest(format!(
+            "An invitation must admit at least one person, got {}",
+            max_admissions
+        )));
+    }
+    Ok(())
+}
+
+async fn check_remaining_seats(pool: &PgPool, appt_id: Uuid, seats: i64) -> Result<Appointment, CustomError> {
+    let appointment = get_stored_appointments(pool, Some(appt_id)).await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| CustomError::NotFound(format!("Not found for {}", appt_id)))?;
+    if appointment.remaining_seats.is_some_and(|remaining| seats > remaining as i64) {
+        return Err(capacity_exceeded(appt_id, seats, appointment.remaining_seats.unwrap_or(0) as i64));
+    }
+    Ok(appointment)
+}
+
+/// What a recipient of a batch gets.
+enum RecipientPlan {
+    /// A new invitation, for a new attendee or for one whose invitations were
+    /// all declined or revoked.
+    Create { attendee_id: Uuid, known_attendee: bool },
+    /// The active invitation they already hold.
+    Existing(NewInvitation),
+    /// The invitation of the earlier recipient at that position, with the same address.
+    Repeat(usize),
+}
+
+// Tells the recipients already invited to the appointment from the new ones.
+async fn plan_recipients<'e>(
+    executor: impl PgExecutor<'e>,
+    appt_id: Uuid,
+    recipients: &[NewAttendee]
+) -> Result<Vec<RecipientPlan>, CustomError> {
+    let emails: Vec<String> = recipients.iter().map(|recipient| recipient.email.as_ref().to_string()).collect();
+    let known: HashMap<String, KnownRecipient> = get_known_recipients(executor, appt_id, &emails).await?
+        .into_iter()
+        .map(|recipient| (recipient.email.to_lowercase(), recipient))
+        .collect();
+
+    let mut first_positions: HashMap<String, usize> = HashMap::new();
+    let plans = emails.iter().enumerate()
+        .map(|(position, email)| {
+            let email = email.to_lowercase();
+            if let Some(first) = first_positions.get(&email) {
+                return RecipientPlan::Repeat(*first);
+            }
+            first_positions.insert(email.clone(), position);
+            match known.get(&email) {
+                Some(known) => match known.invitation() {
+                    Some(invitation) => RecipientPlan::Existing(invitation),
+                    None => RecipientPlan::Create { attendee_id: known.attendee_id, known_attendee: true },
+                },
+                None => RecipientPlan::Create { attendee_id: Uuid::new_v4(), known_attendee: false },
+            }
+        })
+        .collect();
+    Ok(plans)
+}
+
+// Invitations to create: `count` without recipients, one per new recipient otherwise.
+fn created_count(count: i32, plans: &[RecipientPlan]) -> i32 {
+    match plans.is_empty() {
+        true => count,
+        false => plans.iter().filter(|plan| matches!(plan, RecipientPlan::Create { .. })).count() as i32,
+    }
+}
+
+// Short URLs for `count` new invitations, within the limits of the shortener.
+async fn request_short_urls(qr_client: &QRClient, count: i32) -> Result<Vec<(Uuid, Url)>, CustomError> {
+    let results: Vec<Result<(Uuid, Url), anyhow::Error>> = stream::iter(0..count)
+        .map(|_| {
+            let id = Uuid::new_v4();
+            async move {
+                qr_client.get_short_url(id.to_string()).await.map(|res| (id, res.short_url))
+            }
+        })
+        .buffered(qr_client.max_concurrent_requests())
+        .collect()
+        .await;
+    Ok(results.into_iter().collect::<Result<_, _>>()?)
+}
+
+/// Issues `count` invitations in one transaction, emailing one to each of the
+/// `recipients` when there are any.
+///
+/// Recipients already holding an invitation for the appointment, or listed
+/// twice, get the invitation they hold back instead of a new one, emailed again
+/// with `resend_existing`. The invitations are returned in the order of the recipients.
+///
+/// Short URLs are requested within the limits of the shortener before the
+/// seats are locked, and the emails sent as one batch once the invitations are
+/// committed, so neither holds the lock. The recipients are planned again once
+/// the seats are locked, as a concurrent request may have invited them meanwhile. With the lease of a job and its
+/// progress so far, the invitations are recorded as progress of that job in
+/// the same transaction.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn issue_invitation_batch(
+    pool: Data<PgPool>,
+    qr_client: &QRClient,
+    email_client: &EmailClient,
+    base_url: &str,
+    qr_options: &QrRenderOptions,
+    appt_id: Uuid,
+    count: i32,
+    max_admissions: i32,
+    recipients: Vec<NewAttendee>,
+    resend_existing: bool,
+    job: Option<(JobLease, i32)>
+) -> Result<Vec<IssuedInvitation>, CustomError> {
+    // Nothing is kept of a batch failing before its commit, so a retry may
+    // start over.
+    let (mut issued, emailed) = async {
+        let expected = created_count(count, &plan_recipients(pool.as_ref(), appt_id, &recipients).await?);
+        if expected > 0 {
+            check_remaining_seats(pool.as_ref(), appt_id, expected as i64 * max_admissions as i64).await?;
+        }
+        let mut short_urls = request_short_urls(qr_client, expected).await?;
+
+        let mut transaction = open_transaction(pool.clone()).await?;
+
+        let appointment_seats = lock_appointment_seats(&mut transaction, appt_id).await?
+            .ok_or_else(|| CustomError::NotFound(format!("Not found for {}", appt_id)))?;
+        let plans = plan_recipients(&mut *transaction, appt_id, &recipients).await?;
+        let created = created_count(count, &plans);
+        let seats = created as i64 * max_admissions as i64;
+        if !appointment_seats.can_issue(seats) {
+            return Err(capacity_exceeded(appt_id, seats, appointment_seats.free().unwrap_or_default()));
+        }
+        // Only short of URLs when a recipient's invitation was revoked meanwhile,
+        // which is rare enough to request them under the lock.
+        if created as usize > short_urls.len() {
+            short_urls.extend(request_short_urls(qr_client, created - short_urls.len() as i32).await?);
+        }
+        short_urls.truncate(created as usize);
+
+        let mut payload: Vec<NewInvitation> = short_urls.into_iter()
+            .map(|(id, short_url)| NewInvitation {
+                id,
+                appointment_id: appt_id,
+                short_url,
+                attendee_id: None,
+                claim_deadline: None,
+                max_admissions
+            })
+            .collect();
 
-    let mut futures = vec![];
-
-    if send_appointment_emails.email.is_some() {
-        let email_invitation_composition = send_appointment_emails.email.as_ref()
-            .expect("Can't extract email").iter().zip(payload.iter());
-        for (email, invitation) in email_invitation_composition {
-            let future = async {
-                let image_string = qr_client.generate_qr_code_base64(invitation.short_url.clone()).await?;
-                let email_template = render_template("email").await?;
-                let html_body = email_template.replace("{IMAGE_STRING}", image_string.as_ref());
-                let plain_body = "Hello dear customer.";
-                email_client.send_email(
-                    email,
-                    "Your invitation",
-                    &html_body,
-                    plain_body
-                ).await.map_err(|e| anyhow::anyhow!(e))
+        // The new invitations go to the recipients planned for one, in order.
+        let mut attendees: Vec<(Uuid, NewAttendee)> = vec![];
+        let mut emailed: Vec<((Uuid, NewAttendee), NewInvitation)> = vec![];
+        let mut issued: Vec<IssuedInvitation> = vec![];
+        let mut new_invitations = payload.iter_mut();
+        for (recipient, plan) in recipients.iter().zip(&plans) {
+            let issued_invitation = match plan {
+                RecipientPlan::Create { attendee_id, known_attendee } => {
+                    let invitation = new_invitations.next().expect("A short URL is requested per new invitation");
+                    invitation.attendee_id = Some(*attendee_id);
+                    if !known_attendee {
+                        attendees.push((*attendee_id, recipient.clone()));
+                    }
+                    emailed.push(((*attendee_id, recipient.clone()), invitation.clone()));
+                    IssuedInvitation { invitation: invitation.clone(), email: Some(recipient.email.clone()), outcome: IssueOutcome::CREATED, email_error: None }
+                }
+                RecipientPlan::Existing(invitation) => {
+                    if resend_existing {
+                        emailed.push(((invitation.attendee_id.unwrap_or_default(), recipient.clone()), invitation.clone()));
+                    }
+                    IssuedInvitation { invitation: invitation.clone(), email: Some(recipient.email.clone()), outcome: IssueOutcome::EXISTING, email_error: None }
+                }
+                RecipientPlan::Repeat(first) => IssuedInvitation { outcome: IssueOutcome::EXISTING, ..issued[*first].clone() },
             };
-            futures.push(future);
+            issued.push(issued_invitation);
+        }
+        if recipients.is_empty() {
+            issued = payload.iter()
+                .map(|invitation| IssuedInvitation { invitation: invitation.clone(), email: None, outcome: IssueOutcome::CREATED, email_error: None })
+                .collect();
+        }
+
+        preserve_new_attendees(&mut transaction, appt_id, &attendees).await?;
+        preserve_new_invitations(&mut transaction, &payload).await?;
+
+        if let Some((lease, processed_before)) = job {
+            let invitation_ids: Vec<Uuid> = payload.iter().map(|invitation| invitation.id).collect();
+            let processed = recipients.len().max(payload.len()) as i32;
+            record_job_progress(&mut transaction, lease, processed_before, processed, &invitation_ids).await?;
+        }
+
+        commit_transaction(transaction, "Failed to commit SQL transaction to store a new course.")
+            .await?;
+
+        Ok((issued, emailed))
+    }.await.map_err(CustomError::nothing_persisted)?;
+
+    // The invitations are committed, so emails that couldn't be sent are
+    // reported along with their invitation instead of failing the request.
+    let (emailed_attendees, emailed_invitations): (Vec<_>, Vec<_>) = emailed.into_iter().unzip();
+    let outcomes = send_invitation_emails(qr_client, email_client, base_url, qr_options, &emailed_attendees, &emailed_invitations).await;
+
+    let mut sent_emails: Vec<(Uuid, Email)> = vec![];
+    for (((_, attendee), invitation), outcome) in emailed_attendees.iter().zip(&emailed_invitations).zip(outcomes) {
+        match outcome {
+            Ok(()) => sent_emails.push((invitation.id, attendee.email.clone())),
+            Err(error) => {
+                tracing::warn!("Failed to email invitation {}: {}", invitation.id, error);
+                for issued_invitation in issued.iter_mut().filter(|issued| issued.invitation.id == invitation.id) {
+                    issued_invitation.email_error = Some(error.clone());
+                }
+            }
         }
     }
+    log_sent_emails(pool, &sent_emails).await?;
 
-    for result in join_all(futures).await {
-        result?;
+    Ok(issued)
+}
+
+/// Emails every attendee their invitation, with a single request to Postmark's
+/// batch endpoint when there are several.
+///
+/// The QR codes are rendered within the limits of the QR client. The outcome
+/// of each email is returned, in order, as Postmark accepts or rejects every
+/// message of a batch on its own.
+async fn send_invitation_emails(
+    qr_client: &QRClient,
+    email_client: &EmailClient,
+    base_url: &str,
+    qr_options: &QrRenderOptions,
+    attendees: &[(Uuid, NewAttendee)],
+    invitations: &[NewInvitation]
+) -> Vec<Result<(), String>> {
+    if let ([(_, attendee)], [invitation]) = (attendees, invitations) {
+        let outcome = send_invitation_email(qr_client, email_client, base_url, qr_options, attendee, invitation, "Your invitation").await;
+        return vec![outcome.map_err(|e| e.to_string())];
     }
 
-    let mut transaction = open_transaction(pool).await?;
+    let renders: Vec<_> = attendees.iter().zip(invitations)
+        .map(|((_, attendee), invitation)| invitation_email(qr_client, base_url, qr_options, attendee, invitation, "Your invitation"))
+        .collect();
+    let rendered: Vec<Result<OutgoingEmail, CustomError>> = stream::iter(renders)
+        .buffered(qr_client.max_concurrent_requests())
+        .collect()
+        .await;
+    let mut outcomes: Vec<Result<(), String>> = Vec::with_capacity(rendered.len());
+    let mut emails: Vec<OutgoingEmail> = vec![];
+    for email in rendered {
+        match email {
+            Ok(email) => {
+                emails.push(email);
+                outcomes.push(Ok(()));
+            }
+            Err(e) => outcomes.push(Err(e.to_string())),
+        }
+    }
+    if emails.is_empty() {
+        return outcomes;
+    }
 
-    preserve_new_invitations(&mut transaction, &payload).await?;
+    let mut sent = email_client.send_email_batch(&emails).await.into_iter();
+    for outcome in outcomes.iter_mut().filter(|outcome| outcome.is_ok()) {
+        *outcome = sent.next().unwrap_or_else(|| Err("no answer from the email service".to_string()));
+    }
+    outcomes
+}
 
-    commit_transaction(transaction, "Failed to commit SQL transaction to store a new course.")
-        .await?;
+/// Emails every active invitation of an appointment whose holder hasn't
+/// checked in or joined online yet once more, with its current QR code.
+///
+/// Batches are logged as they are sent, so a failure leaves the ones already
+/// sent in the history of their invitations. Emails that couldn't be sent are
+/// reported with their invitation and left out of the history.
+///
+/// With an `Idempotency-Key`, a retry gets the answer of the first request,
+/// or is asked to come back while it runs. Each resend is logged with the key,
+/// so a retry after the first request failed only resends the rest.
+#[tracing::instrument(
+    name = "Resend pending invitations",
+    skip(request, pool, qr_client, email_client, application)
+)]
+pub async fn resend_pending_invitations(
+    request: HttpRequest,
+    appointment_id: web::Path<Uuid>,
+    pool: Data<PgPool>,
+    qr_client: Data<QRClient>,
+    email_client: Data<EmailClient>,
+    application: Data<ApplicationSettings>
+) -> Result<HttpResponse, CustomError> {
+    let appointment_id = appointment_id.into_inner();
+    let appointment = get_stored_appointments(pool.as_ref(), Some(appointment_id)).await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| CustomError::NotFound(format!("Not found for {}", appointment_id)))?;
+    let qr_options = appointment.qr_options.unwrap_or_default();
+    let idempotency_key = IdempotencyKey::from_request(&request)?;
+    let key = idempotency_key.as_ref().map(|key| key.as_str().to_string());
+    let hash = request_hash(&appointment_id);
 
-    Ok(
-        HttpResponse::Ok()
-            .json(payload)
-    )
+    // The invitations already resent are logged with the key, so a retry
+    // that starts over with it skips them.
+    idempotent(&pool.clone(), idempotency_key, "resend_pending_invitations", hash, async {
+        let pending = get_pending_recipients(pool.as_ref(), appointment_id, key.as_deref()).await?;
+        let mut resent = Vec::with_capacity(pending.len());
+        for batch in pending.chunks(RESEND_BATCH_SIZE) {
+            let attendees: Vec<(Uuid, NewAttendee)> = batch.iter()
+                .map(|recipient| Ok((recipient.attendee.id, recipient.attendee()?)))
+                .collect::<Result<_, CustomError>>()?;
+            let invitations: Vec<NewInvitation> = batch.iter()
+                .map(|recipient| recipient.invitation())
+                .collect::<Result<_, CustomError>>()?;
+            let outcomes = send_invitation_emails(&qr_client, &email_client, &application.base_url, &qr_options, &attendees, &invitations).await;
+
+            let mut sent_emails: Vec<(Uuid, Email)> = vec![];
+            for (((_, attendee), invitation), outcome) in attendees.iter().zip(&invitations).zip(&outcomes) {
+                if outcome.is_ok() {
+                    sent_emails.push((invitation.id, attendee.email.clone()));
+                }
+            }
+
+            let mut transaction = open_transaction(pool.clone()).await?;
+            preserve_sent_emails(&mut transaction, &sent_emails).await?;
+            for (invitation_id, email) in &sent_emails {
+                let mut details = json!({ "to": email.as_ref() });
+                if let Some(key) = &key {
+                    details["idempotency_key"] = json!(key);
+                }
+                preserve_invitation_history(&mut transaction, *invitation_id, InvitationAction::RESENT, details).await?;
+            }
+            commit_transaction(transaction, "Failed to commit SQL transaction to resend invitations.")
+                .await?;
+
+            resent.extend(invitations.into_iter().zip(attendees).zip(outcomes).map(|((invitation, (_, attendee)), outcome)| IssuedInvitation {
+                invitation,
+                email: Some(attendee.email),
+                outcome: IssueOutcome::EXISTING,
+                email_error: outcome.err(),
+            }));
+            tracing::info!("Resent {} of {} pending invitations of appointment {}", resent.len(), pending.len(), appointment_id);
+        }
+
+        Ok(resent)
+    }.map_err(CustomError::nothing_persisted)).await
+}
+
+fn capacity_exceeded(appointment_id: Uuid, requested: i64, remaining: i64) -> CustomError {
+    CustomError::CapacityExceeded(format!(
+        "Appointment {} has {} seat(s) left, {} seat(s) requested",
+        appointment_id, remaining, requested
+    ))
 }
 
 #[tracing::instrument(
     name = "Add new appointment",
-    skip(pool),
+    skip(request, pool),
 )]
 pub async fn add_appointment(
+    request: HttpRequest,
     new_appointment: web::Json<NewAppointment>,
     pool: Data<PgPool>
 ) -> Result<HttpResponse, CustomError> {
-    let mut transaction = open_transaction(pool).await?;
+    new_appointment.validate().map_err(CustomError::BadRequest)?;
+    let idempotency_key = IdempotencyKey::from_request(&request)?;
+    let hash = request_hash(&new_appointment.0);
 
-    let response = preserve_new_appointment(&mut transaction, new_appointment.0).await?;
+    idempotent(&pool.clone(), idempotency_key, "add_appointment", hash, async {
+        let mut transaction = open_transaction(pool).await?;
 
-    commit_transaction(transaction, "Failed to commit SQL transaction to store a new course.")
-        .await?;
+        let response = preserve_new_appointment(&mut transaction, new_appointment.0).await?;
+
+        commit_transaction(transaction, "Failed to commit SQL transaction to store a new course.")
+            .await?;
 
+        Ok(response)
+    }.map_err(CustomError::nothing_persisted)).await
+}
+
+#[tracing::instrument(
+    name = "Get appointment by id",
+    skip(pool)
+)]
+pub async fn get_appointment_by_id(
+    pool: Data<PgPool>,
+    appointment_id: web::Path<Uuid>
+) -> Result<HttpResponse, CustomError> {
+    let appointment_id = appointment_id.into_inner();
+    let response = get_stored_appointments(pool.as_ref(), Some(appointment_id)).await?;
+    let appointment = response.first()
+        .ok_or_else(|| CustomError::NotFound(format!("Not found for {}", appointment_id)))?;
+    Ok(
+        HttpResponse::Ok()
+            .json(appointment)
+    )
+}
+
+#[tracing::instrument(
+    name = "Get appointments",
+    skip(pool)
+)]
+pub async fn get_appointments(
+    pool: Data<PgPool>,
+    filter: web::Query<AppointmentFilter>
+) -> Result<HttpResponse, CustomError> {
+    let response = get_filtered_appointments(pool.as_ref(), &filter).await?;
     Ok(
         HttpResponse::Ok()
             .json(response)
@@ -128,17 +589,22 @@ pub async fn add_appointment(
 }
 
 #[tracing::instrument(
-    name = "Get appointment by id",
+    name = "Get invitations by appointment id",
     skip(pool)
 )]
-pub async fn get_appointment_by_id(
+pub async fn get_invitations_by_appointment_id(
     pool: Data<PgPool>,
     appointment_id: web::Path<Uuid>
 ) -> Result<HttpResponse, CustomError> {
-    let response = get_stored_appointments(pool.as_ref(), Some(appointment_id.into_inner())).await?;
+    let appointment_id = appointment_id.into_inner();
+    if get_stored_appointments(pool.as_ref(), Some(appointment_id)).await?.is_empty() {
+        return Err(CustomError::NotFound(format!("Not found for {}", appointment_id)));
+    }
+
+    let response = get_appointment_invitations(pool.as_ref(), appointment_id).await?;
     Ok(
         HttpResponse::Ok()
-            .json(response.first())
+            .json(response)
     )
 }
 
@@ -163,4 +629,105 @@ pub async fn delete_appointment(
         HttpResponse::Ok()
             .json(response)
     )
-}
\ No newline at end of file
+}
+
+#[tracing::instrument(
+    name = "Get recipients by appointment id",
+    skip(pool)
+)]
+pub async fn get_recipients_by_appointment_id(
+    pool: Data<PgPool>,
+    appointment_id: web::Path<Uuid>
+) -> Result<HttpResponse, CustomError> {
+    let appointment_id = appointment_id.into_inner();
+    if get_stored_appointments(pool.as_ref(), Some(appointment_id)).await?.is_empty() {
+        return Err(CustomError::NotFound(format!("Not found for {}", appointment_id)));
+    }
+
+    let response = get_appointment_recipients(pool.as_ref(), appointment_id).await?;
+    Ok(
+        HttpResponse::Ok()
+            .json(response)
+    )
+}
+
+#[tracing::instrument(
+    name = "Get attendees by appointment id",
+    skip(pool)
+)]
+pub async fn get_attendees_by_appointment_id(
+    pool: Data<PgPool>,
+    appointment_id: web::Path<Uuid>,
+    query_params: web::Query<AttendeeSearch>
+) -> Result<HttpResponse, CustomError> {
+    let appointment_id = appointment_id.into_inner();
+    if get_stored_appointments(pool.as_ref(), Some(appointment_id)).await?.is_empty() {
+        return Err(CustomError::NotFound(format!("Not found for {}", appointment_id)));
+    }
+
+    let response = get_appointment_attendees(pool.as_ref(), appointment_id, query_params.q.as_deref()).await?;
+    Ok(
+        HttpResponse::Ok()
+            .json(response)
+    )
+}
+
+#[tracing::instrument(
+    name = "Get invitation counts by appointment id",
+    skip(pool)
+)]
+pub async fn get_invitation_counts_by_appointment_id(
+    pool: Data<PgPool>,
+    appointment_id: web::Path<Uuid>
+) -> Result<HttpResponse, CustomError> {
+    let appointment_id = appointment_id.into_inner();
+    if get_stored_appointments(pool.as_ref(), Some(appointment_id)).await?.is_empty() {
+        return Err(CustomError::NotFound(format!("Not found for {}", appointment_id)));
+    }
+
+    let response = get_invitation_counts(pool.as_ref(), appointment_id).await?;
+    Ok(
+        HttpResponse::Ok()
+            .json(response)
+    )
+}
+
+#[tracing::instrument(
+    name = "Get occupancy by appointment id",
+    skip(pool)
+)]
+pub async fn get_occupancy_by_appointment_id(
+    pool: Data<PgPool>,
+    appointment_id: web::Path<Uuid>
+) -> Result<HttpResponse, CustomError> {
+    let appointment_id = appointment_id.into_inner();
+    if get_stored_appointments(pool.as_ref(), Some(appointment_id)).await?.is_empty() {
+        return Err(CustomError::NotFound(format!("Not found for {}", appointment_id)));
+    }
+
+    let response = get_appointment_occupancy(pool.as_ref(), appointment_id).await?;
+    Ok(
+        HttpResponse::Ok()
+            .json(response)
+    )
+}
+
+#[tracing::instrument(
+    name = "Get online attendance by appointment id",
+    skip(pool)
+)]
+pub async fn get_attendance_by_appointment_id(
+    pool: Data<PgPool>,
+    appointment_id: web::Path<Uuid>
+) -> Result<HttpResponse, CustomError> {
+    let appointment_id = appointment_id.into_inner();
+    if get_stored_appointments(pool.as_ref(), Some(appointment_id)).await?.is_empty() {
+        return Err(CustomError::NotFound(format!("Not found for {}", appointment_id)));
+    }
+
+    let response = get_online_attendance(pool.as_ref(), appointment_id).await?;
+    Ok(
+        HttpResponse::Ok()
+            .json(response)
+    )
+}
@@ -1,8 +1,15 @@
 use super::*;
 
+use actix_web::{http, HttpRequest};
 use uuid::Uuid;
+use serde::Deserialize;
+use serde_json::json;
+use shared::configuration::ApplicationSettings;
+use shared::domain::{EditInvitation, Invitation, InvitationAction, InvitationStatus, IssueOutcome, IssuedInvitation, NewAttendee, NewInvitation, QrParams, ResendInvitation, RsvpForm, TransferInvitation};
+use shared::email_client::EmailClient;
 use shared::qr_client::QRClient;
-use crate::repository::get_stored_invitations;
+use shared::qr_render::QrFormat;
+use crate::repository::{accept_stored_invitation, decline_stored_invitation, get_appointment_qr_options, get_invitation_attendee, get_invitation_history, get_known_recipients, get_invitation_scans, get_stored_appointments, get_stored_invitations, preserve_invitation_history, preserve_new_attendees, preserve_new_invitations, preserve_sent_emails, revoke_stored_invitation, update_attendee_email};
 
 
 pub fn invitation_routes(cfg: &mut web::ServiceConfig) {
@@ -13,15 +20,88 @@ pub fn invitation_routes(cfg: &mut web::ServiceConfig) {
         .service(
             web::resource("/invitation/{id}/qr")
                 .route(web::get().to(get_invitation_qr))
+        )
+        .service(
+            web::resource("/invitation/{id}/decline")
+                .route(web::post().to(decline_invitation))
+        )
+        .service(
+            web::resource("/invitation/{id}/claim")
+                .route(web::post().to(claim_invitation))
+        )
+        .service(
+            web::resource("/invitation/{id}/rsvp")
+                .route(web::get().to(get_rsvp_page))
+                .route(web::post().to(submit_rsvp))
+        )
+        .service(
+            web::resource("/invitation/{id}/revoke")
+                .route(web::post().to(revoke_invitation))
+        )
+        .service(
+            web::resource("/invitation/{id}/reissue")
+                .route(web::post().to(reissue_invitation))
+        )
+        .service(
+            web::resource("/invitation/{id}/transfer")
+                .route(web::post().to(transfer_invitation))
+        )
+        .service(
+            web::resource("/invitation/{id}/resend")
+                .route(web::post().to(resend_invitation))
+        )
+        .service(
+            web::resource("/invitation/{id}/history")
+                .route(web::get().to(get_invitation_history_by_id))
+        )
+        .service(
+            web::resource("/invitation/{id}/scans")
+                .route(web::get().to(get_invitation_scans_by_id))
         );
 }
 
+/// Image format a QR code request asks for in its `Accept` header,
+/// `None` when it is after the QR page. `image/*` gets `default`.
+fn negotiate_qr_format(request: &HttpRequest, default: QrFormat) -> Option<QrFormat> {
+    let accept = request.headers()
+        .get(http::header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())?;
+
+    let mut ranges: Vec<(&str, f32)> = accept.split(',')
+        .map(|range| {
+            let mut parts = range.split(';').map(str::trim);
+            let media_type = parts.next().unwrap_or_default();
+            let quality = parts
+                .find_map(|param| param.strip_prefix("q="))
+                .and_then(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            (media_type, quality)
+        })
+        .filter(|(_, quality)| *quality > 0.0)
+        .collect();
+    // Stable, so equally ranked types keep the order of the header.
+    ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    ranges.into_iter()
+        .find_map(|(media_type, _)| match media_type.to_lowercase().as_str() {
+            "text/html" | "*/*" => Some(None),
+            "image/png" => Some(Some(QrFormat::Png)),
+            "image/svg+xml" => Some(Some(QrFormat::Svg)),
+            "image/jpeg" => Some(Some(QrFormat::Jpeg)),
+            "image/*" => Some(Some(default)),
+            _ => None,
+        })
+        .flatten()
+}
+
 #[tracing::instrument(
     name = "Get invitation QR",
-    skip(pool, qr_client)
+    skip(request, pool, qr_client)
 )]
 async fn get_invitation_qr(
+    request: HttpRequest,
     invitation_id: web::Path<Uuid>,
+    query_params: web::Query<QrParams>,
     pool: Data<PgPool>,
     qr_client: Data<QRClient>
 ) -> Result<HttpResponse, CustomError> {
@@ -38,12 +118,41 @@ async fn get_invitation_qr(
 
     let invitation = response.first().unwrap();
 
-    let image_string = qr_client.generate_qr_code_base64(invitation.short_url.clone()).await?;
+    // An explicit `format` always gets the image, otherwise the Accept header decides.
+    let query_params = query_params.into_inner();
+    let requested_format = query_params.format;
+    let mut qr_options = query_params.apply(get_appointment_qr_options(pool.as_ref(), invitation.appointment_id).await?);
+    qr_options.validate().map_err(CustomError::BadRequest)?;
+    let image_format = requested_format.or_else(|| negotiate_qr_format(&request, qr_options.format));
+
+    if let Some(format) = image_format {
+        qr_options.format = format;
+        let qr_code = qr_client.render_qr_code(&invitation.short_url, &qr_options).await?;
+        return Ok(
+            HttpResponse::Ok()
+                .content_type(qr_code.content_type())
+                .insert_header((http::header::VARY, "Accept"))
+                .body(qr_code.bytes)
+        );
+    }
+
+    let attendee = get_invitation_attendee(pool.as_ref(), invitation_id).await?;
+    let attendee_name = attendee.as_ref().map(|attendee| attendee.display_name()).unwrap_or("guest");
+
+    let qr_code = qr_client.render_qr_code(&invitation.short_url, &qr_options).await?;
     let qr_template  = render_template("qr").await?;
-    let formatted_html = qr_template.replace("{IMAGE_STRING}", &image_string);
+    let formatted_html = qr_template
+        .replace("{IMAGE_TYPE}", qr_code.content_type())
+        .replace("{IMAGE_STRING}", &qr_code.to_base64())
+        .replace("{ATTENDEE_NAME}", &escape_html(attendee_name))
+        .replace("{ADMISSIONS}", &format!(
+            "Admits {} of {} people",
+            invitation.remaining_admissions(), invitation.max_admissions
+        ));
     Ok(
         HttpResponse::Ok()
         .content_type("text/html; charset=utf-8")
+        .insert_header((http::header::VARY, "Accept"))
         .body(formatted_html)
     )
 }
@@ -56,9 +165,503 @@ async fn get_invitation_by_id(
     invitation_id: web::Path<Uuid>,
     pool: Data<PgPool>
 ) -> Result<HttpResponse, CustomError> {
-    let response = get_stored_invitations(pool.as_ref(), Some(invitation_id.into_inner())).await?;
+    let invitation_id = invitation_id.into_inner();
+    let response = get_stored_invitations(pool.as_ref(), Some(invitation_id)).await?;
+    let invitation = response.first()
+        .ok_or_else(|| CustomError::NotFound(format!("Not found for {}", invitation_id)))?;
+    Ok(
+        HttpResponse::Ok()
+            .json(invitation)
+    )
+}
+
+/// Declines an invitation and hands its seat to the waitlist.
+async fn decline_and_release(
+    pool: Data<PgPool>,
+    qr_client: &QRClient,
+    email_client: &EmailClient,
+    base_url: &str,
+    invitation_id: Uuid
+) -> Result<Invitation, CustomError> {
+    if get_stored_invitations(pool.as_ref(), Some(invitation_id)).await?.is_empty() {
+        return Err(CustomError::NotFound(format!("Not found for {}", invitation_id)));
+    }
+
+    let mut transaction = open_transaction(pool.clone()).await?;
+
+    let invitation = decline_stored_invitation(&mut transaction, invitation_id).await?
+        .ok_or_else(|| CustomError::Conflict(format!("Invitation {} is already used or declined", invitation_id)))?;
+    preserve_invitation_history(&mut transaction, invitation_id, InvitationAction::DECLINED, json!({})).await?;
+
+    commit_transaction(transaction, "Failed to commit SQL transaction to decline an invitation.")
+        .await?;
+
+    promote_waitlist_after_release(pool, qr_client, email_client, base_url, invitation.appointment_id).await;
+
+    Ok(invitation)
+}
+
+/// Accepts an invitation, claiming it when it was promoted from the waitlist.
+async fn accept(pool: Data<PgPool>, invitation_id: Uuid) -> Result<Invitation, CustomError> {
+    if get_stored_invitations(pool.as_ref(), Some(invitation_id)).await?.is_empty() {
+        return Err(CustomError::NotFound(format!("Not found for {}", invitation_id)));
+    }
+
+    let mut transaction = open_transaction(pool).await?;
+
+    let invitation = accept_stored_invitation(&mut transaction, invitation_id).await?
+        .ok_or_else(|| CustomError::Conflict(format!("Invitation {} can no longer be accepted", invitation_id)))?;
+    preserve_invitation_history(&mut transaction, invitation_id, InvitationAction::ACCEPTED, json!({})).await?;
+
+    commit_transaction(transaction, "Failed to commit SQL transaction to accept an invitation.")
+        .await?;
+
+    Ok(invitation)
+}
+
+#[tracing::instrument(
+    name = "Decline invitation",
+    skip(pool, qr_client, email_client, application)
+)]
+async fn decline_invitation(
+    invitation_id: web::Path<Uuid>,
+    pool: Data<PgPool>,
+    qr_client: Data<QRClient>,
+    email_client: Data<EmailClient>,
+    application: Data<ApplicationSettings>
+) -> Result<HttpResponse, CustomError> {
+    let invitation = decline_and_release(
+        pool,
+        &qr_client,
+        &email_client,
+        &application.base_url,
+        invitation_id.into_inner()
+    ).await?;
+
     Ok(
         HttpResponse::Ok()
-            .json(response.first())
+            .json(invitation)
     )
-}
\ No newline at end of file
+}
+
+#[tracing::instrument(
+    name = "Claim invitation",
+    skip(pool)
+)]
+async fn claim_invitation(
+    invitation_id: web::Path<Uuid>,
+    pool: Data<PgPool>
+) -> Result<HttpResponse, CustomError> {
+    let invitation = accept(pool, invitation_id.into_inner()).await?;
+
+    Ok(
+        HttpResponse::Ok()
+            .json(invitation)
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct RsvpParams {
+    response: Option<String>,
+}
+
+#[tracing::instrument(
+    name = "Get RSVP page",
+    skip(pool)
+)]
+async fn get_rsvp_page(
+    invitation_id: web::Path<Uuid>,
+    query_params: web::Query<RsvpParams>,
+    pool: Data<PgPool>
+) -> Result<HttpResponse, CustomError> {
+    let invitation_id = invitation_id.into_inner();
+    let invitation = get_stored_invitations(pool.as_ref(), Some(invitation_id)).await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| CustomError::NotFound(format!("Not found for {}", invitation_id)))?;
+    let appointment = get_stored_appointments(pool.as_ref(), Some(invitation.appointment_id)).await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| CustomError::NotFound(format!("Not found for {}", invitation.appointment_id)))?;
+    let attendee = get_invitation_attendee(pool.as_ref(), invitation_id).await?;
+    let attendee_name = attendee.as_ref().map(|attendee| attendee.display_name()).unwrap_or("guest");
+
+    // The email links only preselect the answer; it is stored once the form is posted.
+    let responses: &[(&str, &str)] = match query_params.response.as_deref() {
+        Some("accept") => &[("accept", "I will attend")],
+        Some("decline") => &[("decline", "I can't attend")],
+        _ => &[("accept", "I will attend"), ("decline", "I can't attend")],
+    };
+    let forms: String = responses.iter()
+        .map(|(response, label)| format!(
+            r#"<form method="post"><input type="hidden" name="response" value="{}"><button type="submit">{}</button></form>"#,
+            response, label
+        ))
+        .collect();
+
+    let rsvp_template = render_template("rsvp").await?;
+    let formatted_html = rsvp_template
+        .replace("{ATTENDEE_NAME}", &escape_html(attendee_name))
+        .replace("{TITLE}", &escape_html(&appointment.title))
+        .replace("{DATE}", &appointment.date.format("%Y/%m/%d %H:%M").to_string())
+        .replace("{RSVP_STATUS}", &format!("{:?}", invitation.rsvp))
+        .replace("{FORMS}", &forms);
+    Ok(
+        HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(formatted_html)
+    )
+}
+
+#[tracing::instrument(
+    name = "Submit RSVP",
+    skip(pool, qr_client, email_client, application)
+)]
+async fn submit_rsvp(
+    invitation_id: web::Path<Uuid>,
+    form: web::Form<RsvpForm>,
+    pool: Data<PgPool>,
+    qr_client: Data<QRClient>,
+    email_client: Data<EmailClient>,
+    application: Data<ApplicationSettings>
+) -> Result<HttpResponse, CustomError> {
+    let invitation_id = invitation_id.into_inner();
+    let message = match form.response.as_str() {
+        "accept" => {
+            accept(pool, invitation_id).await?;
+            "Thank you, your attendance is confirmed."
+        }
+        "decline" => {
+            decline_and_release(pool, &qr_client, &email_client, &application.base_url, invitation_id).await?;
+            "Thank you for letting us know, your seat has been released."
+        }
+        other => return Err(CustomError::BadRequest(format!("{} is not a valid response, use accept or decline", other))),
+    };
+
+    render_message("Your answer", message).await
+}
+
+#[tracing::instrument(
+    name = "Revoke invitation",
+    skip(pool, qr_client, email_client, application)
+)]
+async fn revoke_invitation(
+    invitation_id: web::Path<Uuid>,
+    body: Option<web::Json<EditInvitation>>,
+    pool: Data<PgPool>,
+    qr_client: Data<QRClient>,
+    email_client: Data<EmailClient>,
+    application: Data<ApplicationSettings>
+) -> Result<HttpResponse, CustomError> {
+    let invitation_id = invitation_id.into_inner();
+    let reason = body.and_then(|body| body.into_inner().reason);
+    if get_stored_invitations(pool.as_ref(), Some(invitation_id)).await?.is_empty() {
+        return Err(CustomError::NotFound(format!("Not found for {}", invitation_id)));
+    }
+
+    let mut transaction = open_transaction(pool.clone()).await?;
+
+    let invitation = revoke_stored_invitation(&mut transaction, invitation_id).await?
+        .ok_or_else(|| CustomError::Conflict(format!("Invitation {} is not active", invitation_id)))?;
+    preserve_invitation_history(&mut transaction, invitation_id, InvitationAction::REVOKED, json!({ "reason": reason })).await?;
+
+    commit_transaction(transaction, "Failed to commit SQL transaction to revoke an invitation.")
+        .await?;
+
+    promote_waitlist_after_release(pool, &qr_client, &email_client, &application.base_url, invitation.appointment_id).await;
+
+    Ok(
+        HttpResponse::Ok()
+            .json(invitation)
+    )
+}
+
+/// Revokes an invitation and issues a fresh code in its place.
+///
+/// With `new_holder` the replacement goes to that attendee (a transfer),
+/// otherwise to the attendee of the revoked invitation (a reissue). The
+/// seat is kept, so the capacity and the waitlist are left untouched.
+///
+/// # Returns
+///
+/// The revoked invitation, the replacement and the attendee of the replacement.
+async fn replace_invitation(
+    pool: Data<PgPool>,
+    qr_client: &QRClient,
+    email_client: &EmailClient,
+    base_url: &str,
+    invitation_id: Uuid,
+    new_holder: Option<NewAttendee>,
+    reason: Option<String>
+) -> Result<(Invitation, NewInvitation, Option<NewAttendee>), CustomError> {
+    if get_stored_invitations(pool.as_ref(), Some(invitation_id)).await?.is_empty() {
+        return Err(CustomError::NotFound(format!("Not found for {}", invitation_id)));
+    }
+
+    let id = Uuid::new_v4();
+    let short_url = qr_client.get_short_url(id.to_string()).await?.short_url;
+
+    let mut transaction = open_transaction(pool.clone()).await?;
+
+    let revoked = revoke_stored_invitation(&mut transaction, invitation_id).await?
+        .ok_or_else(|| CustomError::Conflict(format!("Invitation {} is not active", invitation_id)))?;
+
+    let (attendee_id, holder, action) = match new_holder {
+        Some(holder) => {
+            // An address is an attendee of the appointment once, holding one active invitation at most.
+            let known = get_known_recipients(&mut *transaction, revoked.appointment_id, &[holder.email.as_ref().to_string()]).await?
+                .into_iter()
+                .next();
+            let attendee_id = match known {
+                Some(known) if known.invitation_id.is_some() => return Err(CustomError::Duplicate(format!(
+                    "{} already holds an invitation for the appointment", holder.email.as_ref()
+                ))),
+                Some(known) => known.attendee_id,
+                None => {
+                    let attendee_id = Uuid::new_v4();
+                    preserve_new_attendees(&mut transaction, revoked.appointment_id, &[(attendee_id, holder.clone())]).await?;
+                    attendee_id
+                }
+            };
+            (Some(attendee_id), Some(holder), InvitationAction::TRANSFERRED)
+        }
+        None => {
+            let holder = get_invitation_attendee(pool.as_ref(), invitation_id).await?
+                .map(|attendee| NewAttendee {
+                    name: attendee.name,
+                    email: attendee.email,
+                    phone: attendee.phone,
+                    custom_fields: attendee.custom_fields,
+                });
+            (revoked.attendee_id, holder, InvitationAction::REISSUED)
+        }
+    };
+
+    let replacement = NewInvitation {
+        id,
+        appointment_id: revoked.appointment_id,
+        short_url,
+        attendee_id,
+        claim_deadline: None,
+        // People already admitted keep their seats on the revoked invitation.
+        max_admissions: revoked.remaining_admissions()
+    };
+    preserve_new_invitations(&mut transaction, &vec![replacement.clone()]).await?;
+
+    preserve_invitation_history(&mut transaction, invitation_id, action, json!({
+        "reason": reason,
+        "replaced_by": id,
+        "to": holder.as_ref().map(|holder| holder.email.as_ref().to_string()),
+    })).await?;
+    preserve_invitation_history(&mut transaction, id, InvitationAction::REPLACEMENT, json!({
+        "replaces": invitation_id,
+    })).await?;
+
+    commit_transaction(transaction, "Failed to commit SQL transaction to replace an invitation.")
+        .await?;
+
+    if let Some(holder) = &holder {
+        let qr_options = get_appointment_qr_options(pool.as_ref(), revoked.appointment_id).await?;
+        send_invitation_email(qr_client, email_client, base_url, &qr_options, holder, &replacement, "Your new invitation").await?;
+        log_sent_emails(pool, &[(id, holder.email.clone())]).await?;
+    }
+
+    Ok((revoked, replacement, holder))
+}
+
+#[tracing::instrument(
+    name = "Reissue invitation",
+    skip(pool, qr_client, email_client, application)
+)]
+async fn reissue_invitation(
+    invitation_id: web::Path<Uuid>,
+    body: Option<web::Json<EditInvitation>>,
+    pool: Data<PgPool>,
+    qr_client: Data<QRClient>,
+    email_client: Data<EmailClient>,
+    application: Data<ApplicationSettings>
+) -> Result<HttpResponse, CustomError> {
+    let reason = body.and_then(|body| body.into_inner().reason);
+    let (_, replacement, _) = replace_invitation(
+        pool,
+        &qr_client,
+        &email_client,
+        &application.base_url,
+        invitation_id.into_inner(),
+        None,
+        reason
+    ).await?;
+
+    Ok(
+        HttpResponse::Ok()
+            .json(replacement)
+    )
+}
+
+#[tracing::instrument(
+    name = "Transfer invitation",
+    skip(pool, qr_client, email_client, application)
+)]
+async fn transfer_invitation(
+    invitation_id: web::Path<Uuid>,
+    body: web::Json<TransferInvitation>,
+    pool: Data<PgPool>,
+    qr_client: Data<QRClient>,
+    email_client: Data<EmailClient>,
+    application: Data<ApplicationSettings>
+) -> Result<HttpResponse, CustomError> {
+    let invitation_id = invitation_id.into_inner();
+    let TransferInvitation { email, name, reason } = body.into_inner();
+    let previous_holder = get_invitation_attendee(pool.as_ref(), invitation_id).await?;
+    let new_holder = NewAttendee {
+        name,
+        ..NewAttendee::from(email)
+    };
+
+    let (_, replacement, new_holder) = replace_invitation(
+        pool,
+        &qr_client,
+        &email_client,
+        &application.base_url,
+        invitation_id,
+        Some(new_holder),
+        reason
+    ).await?;
+
+    // The transfer is stored at this point, a failed notice doesn't undo it.
+    if let (Some(previous_holder), Some(new_holder)) = (previous_holder, new_holder) {
+        let template = render_template("transfer_email").await?;
+        let html_body = template
+            .replace("{ATTENDEE_NAME}", &escape_html(previous_holder.display_name()))
+            .replace("{NEW_HOLDER}", &escape_html(new_holder.display_name()));
+        let plain_body = format!(
+            "Hello {}. Your invitation has been transferred to {}. Its QR code is no longer valid.",
+            previous_holder.display_name(), new_holder.display_name()
+        );
+        if let Err(e) = email_client.send_email(
+            &previous_holder.email,
+            "Your invitation has been transferred",
+            &html_body,
+            &plain_body
+        ).await {
+            tracing::error!("Failed to notify {} about the transfer of invitation {}: {:?}", previous_holder.email.as_ref(), invitation_id, e);
+        }
+    }
+
+    Ok(
+        HttpResponse::Ok()
+            .json(replacement)
+    )
+}
+
+/// Emails an invitation again with its current QR code, for attendees who
+/// lost the first email. A corrected address replaces the one on file.
+#[tracing::instrument(
+    name = "Resend invitation",
+    skip(pool, qr_client, email_client, application)
+)]
+async fn resend_invitation(
+    invitation_id: web::Path<Uuid>,
+    body: Option<web::Json<ResendInvitation>>,
+    pool: Data<PgPool>,
+    qr_client: Data<QRClient>,
+    email_client: Data<EmailClient>,
+    application: Data<ApplicationSettings>
+) -> Result<HttpResponse, CustomError> {
+    let invitation_id = invitation_id.into_inner();
+    let corrected_email = body.and_then(|body| body.into_inner().email);
+    let invitation = get_stored_invitations(pool.as_ref(), Some(invitation_id)).await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| CustomError::NotFound(format!("Not found for {}", invitation_id)))?;
+    if invitation.status != InvitationStatus::ACTIVE {
+        return Err(CustomError::Conflict(format!("Invitation {} is not active", invitation_id)));
+    }
+    let attendee = get_invitation_attendee(pool.as_ref(), invitation_id).await?
+        .ok_or_else(|| CustomError::Conflict(format!(
+            "Invitation {} has no attendee to resend it to, transfer it instead", invitation_id
+        )))?;
+
+    let previous_email = attendee.email.clone();
+    let holder = NewAttendee {
+        name: attendee.name,
+        email: corrected_email.unwrap_or(attendee.email),
+        phone: attendee.phone,
+        custom_fields: attendee.custom_fields,
+    };
+    let corrected = holder.email.as_ref() != previous_email.as_ref();
+    let resent = NewInvitation {
+        id: invitation.id,
+        appointment_id: invitation.appointment_id,
+        short_url: invitation.short_url,
+        attendee_id: invitation.attendee_id,
+        claim_deadline: invitation.claim_deadline,
+        max_admissions: invitation.max_admissions,
+    };
+
+    if corrected {
+        let mut transaction = open_transaction(pool.clone()).await?;
+        update_attendee_email(&mut transaction, attendee.id, &holder.email).await?;
+        commit_transaction(transaction, "Failed to commit SQL transaction to correct an email address.")
+            .await?;
+    }
+    let qr_options = get_appointment_qr_options(pool.as_ref(), invitation.appointment_id).await?;
+    send_invitation_email(&qr_client, &email_client, &application.base_url, &qr_options, &holder, &resent, "Your invitation").await?;
+
+    let mut transaction = open_transaction(pool.clone()).await?;
+    preserve_sent_emails(&mut transaction, &[(invitation_id, holder.email.clone())]).await?;
+    preserve_invitation_history(&mut transaction, invitation_id, InvitationAction::RESENT, json!({
+        "to": holder.email.as_ref(),
+        "corrected_from": corrected.then(|| previous_email.as_ref().to_string()),
+    })).await?;
+
+    commit_transaction(transaction, "Failed to commit SQL transaction to resend an invitation.")
+        .await?;
+
+    Ok(
+        HttpResponse::Ok()
+            .json(IssuedInvitation {
+                invitation: resent,
+                email: Some(holder.email),
+                outcome: IssueOutcome::EXISTING,
+                email_error: None,
+            })
+    )
+}
+
+#[tracing::instrument(
+    name = "Get invitation history",
+    skip(pool)
+)]
+async fn get_invitation_history_by_id(
+    invitation_id: web::Path<Uuid>,
+    pool: Data<PgPool>
+) -> Result<HttpResponse, CustomError> {
+    let invitation_id = invitation_id.into_inner();
+    if get_stored_invitations(pool.as_ref(), Some(invitation_id)).await?.is_empty() {
+        return Err(CustomError::NotFound(format!("Not found for {}", invitation_id)));
+    }
+
+    let response = get_invitation_history(pool.as_ref(), invitation_id).await?;
+    Ok(
+        HttpResponse::Ok()
+            .json(response)
+    )
+}
+
+#[tracing::instrument(
+    name = "Get invitation scans",
+    skip(pool)
+)]
+async fn get_invitation_scans_by_id(
+    invitation_id: web::Path<Uuid>,
+    pool: Data<PgPool>
+) -> Result<HttpResponse, CustomError> {
+    // Scans of codes that don't belong to any invitation are kept as well,
+    // so an unknown id is answered with its attempts rather than a 404.
+    let response = get_invitation_scans(pool.as_ref(), invitation_id.into_inner()).await?;
+    Ok(
+        HttpResponse::Ok()
+            .json(response)
+    )
+}
@@ -0,0 +1,258 @@
+use super::*;
+use actix_web::http::header::LOCATION;
+use shared::configuration::ApplicationSettings;
+use shared::domain::{InvitationJob, InvitationParams, IssueOutcome, JobEmailFailure, SendAppointmentEmails};
+use crate::repository::{claim_job, complete_job, fail_job, get_job, get_job_invitations, get_job_recipients, get_stored_appointments, JobLease, preserve_new_job, record_job_email_failures, renew_job_lease};
+
+pub fn job_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/appointment/{id}/invitation/jobs")
+            .route(web::post().to(add_invitation_job))
+    )
+        .service(
+            web::resource("/jobs/{id}")
+                .route(web::get().to(get_job_by_id))
+        )
+        .service(
+            web::resource("/jobs/{id}/resume")
+                .route(web::post().to(resume_job))
+        )
+        .service(
+            web::resource("/jobs/{id}/invitations")
+                .route(web::get().to(get_invitations_by_job_id))
+        );
+}
+
+/// Upper bound of invitations issued by a single job.
+const MAX_INVITATIONS_PER_JOB: i32 = 100_000;
+
+/// Invitations issued, and committed, at a time by a job.
+const JOB_BATCH_SIZE: i32 = 100;
+
+/// A RUNNING job whose lease wasn't renewed for that long lost its worker and can be resumed.
+const JOB_LEASE_SECONDS: i64 = 60;
+
+/// Interval at which a worker renews the lease of its job.
+const JOB_HEARTBEAT_SECONDS: u64 = 15;
+
+#[tracing::instrument(
+    name = "Add invitation job",
+    skip(pool, qr_client, email_client, application),
+)]
+pub async fn add_invitation_job(
+    appointment_id: web::Path<Uuid>,
+    query_params: web::Query<InvitationParams>,
+    send_appointment_emails: web::Json<SendAppointmentEmails>,
+    pool: Data<PgPool>,
+    qr_client: Data<QRClient>,
+    email_client: Data<EmailClient>,
+    application: Data<ApplicationSettings>
+) -> Result<HttpResponse, CustomError> {
+    let appointment_id = appointment_id.into_inner();
+    let recipients = send_appointment_emails.into_inner().recipients();
+    let count = match &recipients {
+        None => query_params.count.unwrap_or(1),
+        Some(attendees) => attendees.len() as i32,
+    };
+    let max_admissions = query_params.max_admissions.unwrap_or(1);
+
+    check_invitation_request(pool.as_ref(), appointment_id, count, MAX_INVITATIONS_PER_JOB, max_admissions).await?;
+
+    let job = preserve_new_job(pool.as_ref(), appointment_id, count, max_admissions, recipients).await?;
+    let job = start_job(job.id, pool, qr_client, email_client, application).await?
+        .ok_or_else(|| CustomError::Conflict(format!("Job {} is already running", job.id)))?;
+
+    Ok(job_accepted(job))
+}
+
+#[tracing::instrument(
+    name = "Get invitation job by id",
+    skip(pool)
+)]
+pub async fn get_job_by_id(
+    job_id: web::Path<Uuid>,
+    pool: Data<PgPool>
+) -> Result<HttpResponse, CustomError> {
+    let job_id = job_id.into_inner();
+    let job = get_job(pool.as_ref(), job_id).await?
+        .ok_or_else(|| CustomError::NotFound(format!("Not found for {}", job_id)))?;
+
+    Ok(
+        HttpResponse::Ok()
+            .json(job)
+    )
+}
+
+#[tracing::instrument(
+    name = "Resume invitation job",
+    skip(pool, qr_client, email_client, application)
+)]
+pub async fn resume_job(
+    job_id: web::Path<Uuid>,
+    pool: Data<PgPool>,
+    qr_client: Data<QRClient>,
+    email_client: Data<EmailClient>,
+    application: Data<ApplicationSettings>
+) -> Result<HttpResponse, CustomError> {
+    let job_id = job_id.into_inner();
+    let job = get_job(pool.as_ref(), job_id).await?
+        .ok_or_else(|| CustomError::NotFound(format!("Not found for {}", job_id)))?;
+
+    match start_job(job_id, pool, qr_client, email_client, application).await? {
+        Some(job) => Ok(job_accepted(job)),
+        None => Err(CustomError::Conflict(format!(
+            "Job {} is {:?}, only failed jobs can be resumed",
+            job_id, job.status
+        ))),
+    }
+}
+
+#[tracing::instrument(
+    name = "Get invitations issued by a job",
+    skip(pool)
+)]
+pub async fn get_invitations_by_job_id(
+    job_id: web::Path<Uuid>,
+    pool: Data<PgPool>
+) -> Result<HttpResponse, CustomError> {
+    let job_id = job_id.into_inner();
+    get_job(pool.as_ref(), job_id).await?
+        .ok_or_else(|| CustomError::NotFound(format!("Not found for {}", job_id)))?;
+
+    let invitations = get_job_invitations(pool.as_ref(), job_id).await?;
+    Ok(
+        HttpResponse::Ok()
+            .json(invitations)
+    )
+}
+
+fn job_accepted(job: InvitationJob) -> HttpResponse {
+    HttpResponse::Accepted()
+        .insert_header((LOCATION, format!("/api/jobs/{}", job.id)))
+        .json(job)
+}
+
+/// Claims a job and runs it in the background, from the invitations it
+/// already issued on.
+///
+/// Returns `None` when the job is running under a live lease, or completed.
+async fn start_job(
+    job_id: Uuid,
+    pool: Data<PgPool>,
+    qr_client: Data<QRClient>,
+    email_client: Data<EmailClient>,
+    application: Data<ApplicationSettings>
+) -> Result<Option<InvitationJob>, CustomError> {
+    let lease = JobLease { job_id, worker_id: Uuid::new_v4() };
+    let job = claim_job(pool.as_ref(), lease, JOB_LEASE_SECONDS).await?;
+    if let Some(job) = &job {
+        tokio::spawn(run_invitation_job(job.clone(), lease, pool, qr_client, email_client, application));
+    }
+    Ok(job)
+}
+
+#[tracing::instrument(
+    name = "Run invitation job",
+    skip_all,
+    fields(job_id = %job.id, processed = job.processed, total = job.total)
+)]
+async fn run_invitation_job(
+    job: InvitationJob,
+    lease: JobLease,
+    pool: Data<PgPool>,
+    qr_client: Data<QRClient>,
+    email_client: Data<EmailClient>,
+    application: Data<ApplicationSettings>
+) {
+    let process = process_invitation_job(&job, lease, pool.clone(), &qr_client, &email_client, &application.base_url);
+    tokio::pin!(process);
+    let heartbeat = renew_lease_until_lost(pool.as_ref(), lease);
+    tokio::pin!(heartbeat);
+    // A worker that lost its lease finishes the batch at hand, whose progress
+    // is then refused, so the processing isn't cut short in the middle of one.
+    let result = tokio::select! {
+        result = &mut process => result,
+        () = &mut heartbeat => process.await,
+    };
+    let outcome = match result {
+        Ok(()) => complete_job(pool.as_ref(), lease).await,
+        Err(e) => {
+            tracing::error!("Invitation job {} failed: {}", job.id, e);
+            fail_job(pool.as_ref(), lease, &e.to_string()).await
+        }
+    };
+    if let Err(e) = outcome {
+        tracing::error!("Failed to record the outcome of invitation job {}: {}", job.id, e);
+    }
+}
+
+/// Renews the lease of a job every heartbeat, and returns once another
+/// worker took the job over.
+async fn renew_lease_until_lost(pool: &PgPool, lease: JobLease) {
+    let mut heartbeat = tokio::time::interval(std::time::Duration::from_secs(JOB_HEARTBEAT_SECONDS));
+    // The lease was just taken with the claim.
+    heartbeat.tick().await;
+    loop {
+        heartbeat.tick().await;
+        match renew_job_lease(pool, lease, JOB_LEASE_SECONDS).await {
+            Ok(true) => {}
+            Ok(false) => {
+                tracing::warn!("Invitation job {} was taken over by another worker", lease.job_id);
+                return;
+            }
+            // The lease may still be renewed on the next heartbeat.
+            Err(e) => tracing::error!("Failed to renew the lease of invitation job {}: {}", lease.job_id, e),
+        }
+    }
+}
+
+async fn process_invitation_job(
+    job: &InvitationJob,
+    lease: JobLease,
+    pool: Data<PgPool>,
+    qr_client: &QRClient,
+    email_client: &EmailClient,
+    base_url: &str
+) -> Result<(), CustomError> {
+    let appointment = get_stored_appointments(pool.as_ref(), Some(job.appointment_id)).await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| CustomError::NotFound(format!("Appointment {} was deleted", job.appointment_id)))?;
+    let qr_options = appointment.qr_options.unwrap_or_default();
+
+    let mut processed = job.processed;
+    while processed < job.total {
+        let count = JOB_BATCH_SIZE.min(job.total - processed);
+        // Empty for jobs without recipients.
+        let recipients = get_job_recipients(pool.as_ref(), job.id, processed, count).await?;
+
+        let issued = issue_invitation_batch(
+            pool.clone(),
+            qr_client,
+            email_client,
+            base_url,
+            &qr_options,
+            job.appointment_id,
+            count,
+            job.max_admissions,
+            recipients,
+            false,
+            Some((lease, processed))
+        ).await?;
+        // The invitations are issued either way, so the job carries on and
+        // reports the emails that couldn't be sent. Only new invitations are
+        // emailed, the recipients listed twice get theirs once.
+        let failures: Vec<JobEmailFailure> = issued.into_iter()
+            .filter(|issued| issued.outcome == IssueOutcome::CREATED)
+            .filter_map(|issued| Some(JobEmailFailure {
+                invitation_id: issued.invitation.id,
+                email: issued.email?,
+                error: issued.email_error?,
+            }))
+            .collect();
+        record_job_email_failures(pool.as_ref(), lease, &failures).await?;
+        processed += count;
+        tracing::info!("Invitation job {} issued {} of {} invitations", job.id, processed, job.total);
+    }
+    Ok(())
+}
@@ -1,6 +1,10 @@
 mod appointment;
 mod validation;
 mod invitation;
+mod waitlist;
+mod registration;
+mod ticket;
+mod job;
 
 use crate::error::CustomError;
 use actix_web::{HttpResponse, Result, web};
@@ -8,10 +12,18 @@ use actix_web::web::Data;
 use sqlx::{PgPool, Postgres, Transaction};
 use anyhow::Context;
 use uuid::Uuid;
+use shared::domain::{Email, NewAttendee, NewInvitation};
+use shared::email_client::{EmailClient, OutgoingEmail};
+use shared::qr_client::QRClient;
+use shared::qr_render::QrRenderOptions;
 
 pub use appointment::*;
 pub use validation::*;
 pub use invitation::*;
+pub use waitlist::*;
+pub use registration::*;
+pub use ticket::*;
+pub use job::*;
 
 
 pub(crate) async fn open_transaction(pool: Data<PgPool>) -> Result<Transaction<'static, Postgres>, CustomError> {
@@ -34,7 +46,7 @@ pub(crate) async fn commit_transaction(transaction: Transaction<'_, Postgres>, m
 /// Asynchronously reads and returns the content of a specified HTML template.
 ///
 /// Given the name of a template (without the `.html` extension), this function
-/// will search for the template in the `./../templates/` directory, read its
+/// will search for the template in the `./src/templates/` directory, read its
 /// content, and return it as a `String`.
 ///
 /// # Arguments
@@ -62,13 +74,103 @@ pub(crate) async fn commit_transaction(transaction: Transaction<'_, Postgres>, m
 ///
 /// # Note
 ///
-/// This function expects the templates to be located in `./../templates/` relative
-/// to the binary's location and assumes a `.html` file extension for the templates.
+/// This function expects the templates to be located in `./src/templates/` relative
+/// to the working directory, which is the `web_server` crate like for the
+/// `../configuration` lookup, and assumes a `.html` file extension for the templates.
 pub async fn render_template(template_name: &str) -> Result<String, tokio::io::Error> {
     tokio::fs::read_to_string(
-        format!("./../templates/{}.html",
+        format!("./src/templates/{}.html",
                 template_name
         )
     ).await
 }
 
+/// Renders a plain HTML page telling the visitor the outcome of their action.
+pub(crate) async fn render_message(title: &str, message: &str) -> Result<HttpResponse, CustomError> {
+    let message_template = render_template("message").await?;
+    let formatted_html = message_template
+        .replace("{TITLE}", &escape_html(title))
+        .replace("{MESSAGE}", &escape_html(message));
+    Ok(
+        HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(formatted_html)
+    )
+}
+
+/// Logs emails sent for invitations committed before sending them, so no
+/// transaction is held while the email provider is called.
+pub(crate) async fn log_sent_emails(pool: Data<PgPool>, sent_emails: &[(Uuid, Email)]) -> Result<(), CustomError> {
+    if sent_emails.is_empty() {
+        return Ok(());
+    }
+    let mut transaction = open_transaction(pool).await?;
+    crate::repository::preserve_sent_emails(&mut transaction, sent_emails).await?;
+    commit_transaction(transaction, "Failed to commit SQL transaction to log sent emails.")
+        .await
+}
+
+/// Emails an invitation with its QR code, rendered with the options of its
+/// appointment, and RSVP links to its attendee.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn send_invitation_email(
+    qr_client: &QRClient,
+    email_client: &EmailClient,
+    base_url: &str,
+    qr_options: &QrRenderOptions,
+    attendee: &NewAttendee,
+    invitation: &NewInvitation,
+    subject: &str
+) -> Result<(), CustomError> {
+    let email = invitation_email(qr_client, base_url, qr_options, attendee, invitation, subject).await?;
+    email_client.send_email(
+        &email.recipient,
+        &email.subject,
+        &email.html_body,
+        &email.text_body
+    ).await.map_err(|e| anyhow::anyhow!(e))?;
+    Ok(())
+}
+
+/// Renders the email of an invitation, see `send_invitation_email`.
+pub(crate) async fn invitation_email(
+    qr_client: &QRClient,
+    base_url: &str,
+    qr_options: &QrRenderOptions,
+    attendee: &NewAttendee,
+    invitation: &NewInvitation,
+    subject: &str
+) -> Result<OutgoingEmail, CustomError> {
+    let qr_code = qr_client.render_qr_code(&invitation.short_url, &qr_options.for_email()).await?;
+    let email_template = render_template("email").await?;
+    let html_body = email_template
+        .replace("{IMAGE_TYPE}", qr_code.content_type())
+        .replace("{IMAGE_STRING}", &qr_code.to_base64())
+        .replace("{ATTENDEE_NAME}", &escape_html(attendee.display_name()))
+        .replace("{ACCEPT_URL}", &rsvp_url(base_url, invitation.id, "accept"))
+        .replace("{DECLINE_URL}", &rsvp_url(base_url, invitation.id, "decline"));
+    let text_body = format!(
+        "Hello {}. Let us know if you will attend: {}",
+        attendee.display_name(), rsvp_url(base_url, invitation.id, "accept")
+    );
+    Ok(OutgoingEmail {
+        recipient: attendee.email.clone(),
+        subject: subject.to_string(),
+        html_body,
+        text_body,
+    })
+}
+
+/// Link to the RSVP page of an invitation, preselecting `response`.
+pub(crate) fn rsvp_url(base_url: &str, invitation_id: Uuid, response: &str) -> String {
+    format!("{}/api/invitation/{}/rsvp?response={}", base_url.trim_end_matches('/'), invitation_id, response)
+}
+
+/// Escapes text so that it can be inserted into an HTML template.
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
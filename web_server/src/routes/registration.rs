@@ -0,0 +1,241 @@
+use super::*;
+use std::time::Duration;
+use actix_web::HttpRequest;
+use chrono::Utc;
+use shared::configuration::ApplicationSettings;
+use shared::domain::{Appointment, Email, RegistrationForm};
+use shared::email_client::EmailClient;
+use shared::qr_client::QRClient;
+use crate::rate_limit::{client_address, RateLimiter};
+use crate::repository::{complete_registration, get_registration_by_token, get_stored_appointments, preserve_new_registration, reset_registration_confirmation, start_registration_confirmation};
+
+/// Registrations accepted per client address and per email address within [`REGISTRATION_WINDOW`].
+pub const REGISTRATIONS_PER_WINDOW: usize = 5;
+pub const REGISTRATION_WINDOW: Duration = Duration::from_secs(10 * 60);
+/// Hours the confirmation link stays valid.
+const CONFIRMATION_WINDOW_HOURS: i64 = 24;
+
+pub fn registration_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/appointment/{id}/register")
+            .route(web::get().to(get_registration_page))
+            .route(web::post().to(register))
+    )
+        .service(
+            web::resource("/registration/{token}/confirm")
+                .route(web::get().to(get_confirmation_page))
+                .route(web::post().to(confirm_registration))
+        );
+}
+
+// Only appointments that opted in can be registered to.
+async fn get_open_appointment(pool: &PgPool, appointment_id: Uuid) -> Result<Appointment, CustomError> {
+    get_stored_appointments(pool, Some(appointment_id)).await?
+        .into_iter()
+        .next()
+        .filter(|appointment| appointment.registration_open)
+        .ok_or_else(|| CustomError::NotFound(format!("Not found for {}", appointment_id)))
+}
+
+#[tracing::instrument(
+    name = "Get registration page",
+    skip(pool)
+)]
+async fn get_registration_page(
+    appointment_id: web::Path<Uuid>,
+    pool: Data<PgPool>
+) -> Result<HttpResponse, CustomError> {
+    let appointment = get_open_appointment(pool.as_ref(), appointment_id.into_inner()).await?;
+
+    let seats = match appointment.remaining_seats {
+        Some(0) => "The appointment is full, registering puts you on the waitlist.".to_string(),
+        Some(remaining) => format!("{} seat(s) left.", remaining),
+        None => String::new(),
+    };
+    let registration_template = render_template("registration").await?;
+    let formatted_html = registration_template
+        .replace("{TITLE}", &escape_html(&appointment.title))
+        .replace("{DESCRIPTION}", &escape_html(&appointment.description))
+        .replace("{DATE}", &appointment.date.format("%Y/%m/%d %H:%M").to_string())
+        .replace("{SEATS}", &seats);
+    Ok(
+        HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(formatted_html)
+    )
+}
+
+#[tracing::instrument(
+    name = "Register to appointment",
+    skip(request, form, pool, email_client, application, limiter)
+)]
+async fn register(
+    request: HttpRequest,
+    appointment_id: web::Path<Uuid>,
+    form: web::Form<RegistrationForm>,
+    pool: Data<PgPool>,
+    email_client: Data<EmailClient>,
+    application: Data<ApplicationSettings>,
+    limiter: Data<RateLimiter>
+) -> Result<HttpResponse, CustomError> {
+    let check_email = "Please check your inbox and open the link we sent you to confirm your registration.";
+
+    let client_address = client_address(&request, &application.trusted_proxies);
+    if !limiter.check(&client_address) {
+        return Err(CustomError::TooManyRequests("Too many registrations, please try again later.".to_string()));
+    }
+    // Bots get the same answer as people, so they can't tell they were caught.
+    if form.is_spam() {
+        tracing::warn!(%client_address, "Registration honeypot was filled in");
+        return render_message("Almost there", check_email).await;
+    }
+
+    let attendee = form.parse().map_err(CustomError::BadRequest)?;
+    if !limiter.check(&format!("email:{}", attendee.email.as_ref().to_lowercase())) {
+        return Err(CustomError::TooManyRequests("Too many registrations, please try again later.".to_string()));
+    }
+    let appointment = get_open_appointment(pool.as_ref(), appointment_id.into_inner()).await?;
+
+    let token = Uuid::new_v4().simple().to_string();
+    let mut transaction = open_transaction(pool).await?;
+
+    preserve_new_registration(&mut transaction, appointment.id, &attendee, &token).await?;
+
+    commit_transaction(transaction, "Failed to commit SQL transaction to store a new registration.")
+        .await?;
+
+    // Emailed once committed, so the link always leads to a registration. One
+    // whose email couldn't be sent stays unconfirmed, and the person can
+    // register again.
+    let confirm_url = format!(
+        "{}/api/registration/{}/confirm",
+        application.base_url.trim_end_matches('/'),
+        token
+    );
+    send_confirmation_email(&email_client, &attendee.email, attendee.display_name(), &appointment.title, &confirm_url).await?;
+
+    render_message("Almost there", check_email).await
+}
+
+async fn send_confirmation_email(
+    email_client: &EmailClient,
+    email: &Email,
+    name: &str,
+    title: &str,
+    confirm_url: &str
+) -> Result<(), CustomError> {
+    let email_template = render_template("registration_email").await?;
+    let html_body = email_template
+        .replace("{ATTENDEE_NAME}", &escape_html(name))
+        .replace("{TITLE}", &escape_html(title))
+        .replace("{CONFIRM_URL}", confirm_url);
+    let plain_body = format!(
+        "Hello {}. Please confirm your registration to {}: {}",
+        name, title, confirm_url
+    );
+    email_client.send_email(
+        email,
+        "Confirm your registration",
+        &html_body,
+        &plain_body
+    ).await.map_err(|e| anyhow::anyhow!(e))?;
+    Ok(())
+}
+
+// Opening the link only shows a form; link scanners of mail providers
+// follow links, and must not confirm a registration by doing so.
+#[tracing::instrument(
+    name = "Get registration confirmation page",
+    skip(token, pool)
+)]
+async fn get_confirmation_page(
+    token: web::Path<String>,
+    pool: Data<PgPool>
+) -> Result<HttpResponse, CustomError> {
+    let registration = get_registration_by_token(pool.as_ref(), &token).await?
+        .ok_or_else(|| CustomError::NotFound("Unknown registration link".to_string()))?;
+    if registration.confirmed_at.is_some() {
+        return render_message("Registration confirmed", "Your registration is already confirmed.").await;
+    }
+    let expires_at = registration.created_at + chrono::Duration::hours(CONFIRMATION_WINDOW_HOURS);
+    if expires_at < Utc::now().naive_utc() {
+        return Err(CustomError::Forbidden("The registration link has expired, please register again.".to_string()));
+    }
+    let appointment = get_stored_appointments(pool.as_ref(), Some(registration.appointment_id)).await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| CustomError::NotFound(format!("Not found for {}", registration.appointment_id)))?;
+
+    let confirm_template = render_template("registration_confirm").await?;
+    let formatted_html = confirm_template
+        .replace("{TITLE}", &escape_html(&appointment.title))
+        .replace("{ATTENDEE_NAME}", &escape_html(&registration.name));
+    Ok(
+        HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(formatted_html)
+    )
+}
+
+#[tracing::instrument(
+    name = "Confirm registration",
+    skip(token, pool, qr_client, email_client, application)
+)]
+async fn confirm_registration(
+    token: web::Path<String>,
+    pool: Data<PgPool>,
+    qr_client: Data<QRClient>,
+    email_client: Data<EmailClient>,
+    application: Data<ApplicationSettings>
+) -> Result<HttpResponse, CustomError> {
+    let registration = get_registration_by_token(pool.as_ref(), &token).await?
+        .ok_or_else(|| CustomError::NotFound("Unknown registration link".to_string()))?;
+
+    let expires_at = registration.created_at + chrono::Duration::hours(CONFIRMATION_WINDOW_HOURS);
+    if registration.confirmed_at.is_none() && expires_at < Utc::now().naive_utc() {
+        return Err(CustomError::Forbidden("The registration link has expired, please register again.".to_string()));
+    }
+    if registration.confirmed_at.is_some() || !start_registration_confirmation(pool.as_ref(), registration.id).await? {
+        return render_message("Registration confirmed", "Your registration is already confirmed.").await;
+    }
+
+    let attendee = registration.attendee();
+    let issued = issue_invitations(
+        pool.clone(),
+        &qr_client,
+        &email_client,
+        &application.base_url,
+        registration.appointment_id,
+        1,
+        1,
+        Some(vec![attendee.clone()]),
+        // Someone already invited gets their invitation once more.
+        true
+    ).await;
+
+    let message = match issued {
+        Ok(invitations) => {
+            let invitation_id = invitations.first().map(|issued| issued.invitation.id);
+            complete_registration(pool.as_ref(), registration.id, invitation_id, None).await?;
+            "Your registration is confirmed, your invitation is on its way to your inbox."
+        }
+        Err(e) if matches!(e.cause(), CustomError::CapacityExceeded(_)) => {
+            let entry = add_to_waitlist(
+                pool.clone(),
+                &qr_client,
+                &email_client,
+                &application.base_url,
+                registration.appointment_id,
+                &attendee
+            ).await?;
+            complete_registration(pool.as_ref(), registration.id, entry.invitation_id, Some(entry.id)).await?;
+            "The appointment is full, you have been put on the waitlist. We will email you when a seat is free."
+        }
+        Err(e) => {
+            reset_registration_confirmation(pool.as_ref(), registration.id).await?;
+            return Err(e);
+        }
+    };
+
+    render_message("Registration confirmed", message).await
+}
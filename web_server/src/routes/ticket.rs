@@ -0,0 +1,379 @@
+use super::*;
+
+use std::collections::HashMap;
+use actix_web::{http, HttpRequest, HttpResponseBuilder};
+use chrono::{Duration, Utc};
+use futures::{stream, Stream, StreamExt, TryStreamExt};
+use sha2::{Digest, Sha256};
+use shared::domain::{Appointment, Attendee, Invitation, InvitationStatus, QrArchiveParams};
+use shared::qr_client::QRClient;
+use shared::domain::{AppointmentFormat, AppointmentWithInvitation};
+use shared::qr_render::{QrFormat, QrRenderOptions};
+use shared::ticket::{IssuedTicket, TicketClaims};
+use shared::ticket_pdf::PrintableTicket;
+use crate::archive::{stream_zip, ArchiveCache, ArchiveEntry};
+use crate::repository::{get_appointment_attendees, get_appointment_invitations, get_stored_appointments, get_stored_invitations};
+use crate::routes::get_stored_invitation;
+
+pub fn ticket_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/ticket/keys")
+            .route(web::get().to(get_ticket_keys))
+    )
+        .service(
+            web::resource("/invitation/{id}/ticket")
+                .route(web::get().to(get_invitation_ticket))
+        )
+        .service(
+            web::resource("/invitation/{id}/ticket.pdf")
+                .route(web::get().to(get_invitation_ticket_pdf))
+        )
+        .service(
+            web::resource("/appointment/{id}/qr.zip")
+                .route(web::get().to(get_appointment_qr_archive))
+        );
+}
+
+/// Where a ticket sends people: the address, or `Online`.
+fn ticket_location(format: &AppointmentFormat, address: Option<&str>) -> String {
+    match format {
+        AppointmentFormat::OFFLINE => address.unwrap_or_default().to_string(),
+        AppointmentFormat::ONLINE => "Online".to_string(),
+    }
+}
+
+#[tracing::instrument(
+    name = "Get ticket keys",
+    skip(qr_client)
+)]
+async fn get_ticket_keys(
+    qr_client: Data<QRClient>
+) -> Result<HttpResponse, CustomError> {
+    Ok(
+        HttpResponse::Ok()
+            .json(qr_client.public_ticket_keys())
+    )
+}
+
+#[tracing::instrument(
+    name = "Get invitation ticket",
+    skip(pool, qr_client)
+)]
+async fn get_invitation_ticket(
+    invitation_id: web::Path<Uuid>,
+    pool: Data<PgPool>,
+    qr_client: Data<QRClient>
+) -> Result<HttpResponse, CustomError> {
+    let invitation_id = invitation_id.into_inner();
+    let invitation = get_stored_invitations(pool.as_ref(), Some(invitation_id)).await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| CustomError::NotFound(format!("Not found for {}", invitation_id)))?;
+    if invitation.status != InvitationStatus::ACTIVE {
+        return Err(CustomError::Conflict(format!("Invitation {} is not active", invitation_id)));
+    }
+    let appointment = get_stored_appointments(pool.as_ref(), Some(invitation.appointment_id)).await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| CustomError::NotFound(format!("Not found for {}", invitation.appointment_id)))?;
+
+    // Like the online validation, the ticket is valid until the end of the
+    // day after the appointment.
+    let not_before = Utc::now().naive_utc();
+    let not_after = (appointment.date.date() + Duration::days(2))
+        .and_hms_opt(0, 0, 0)
+        .expect("Failed to create midnight time");
+
+    let ticket = qr_client.sign_ticket(invitation.id, invitation.appointment_id, not_before, not_after)?;
+    let claims = TicketClaims::read_unverified(&ticket)
+        .map_err(|e| anyhow::anyhow!(e))?;
+    let qr_code = qr_client.generate_ticket_qr_code_base64(&ticket).await?;
+
+    Ok(
+        HttpResponse::Ok()
+            .json(IssuedTicket { ticket, claims, qr_code })
+    )
+}
+
+#[tracing::instrument(
+    name = "Get invitation ticket PDF",
+    skip(pool, qr_client)
+)]
+async fn get_invitation_ticket_pdf(
+    invitation_id: web::Path<Uuid>,
+    pool: Data<PgPool>,
+    qr_client: Data<QRClient>
+) -> Result<HttpResponse, CustomError> {
+    let invitation_id = invitation_id.into_inner();
+    let invitation = get_stored_invitation(pool.as_ref(), invitation_id).await?
+        .map(AppointmentWithInvitation::try_from)
+        .transpose()
+        .map_err(|e| anyhow::anyhow!(e))?
+        .ok_or_else(|| CustomError::NotFound(format!("Not found for {}", invitation_id)))?;
+    if invitation.status != InvitationStatus::ACTIVE {
+        return Err(CustomError::Conflict(format!("Invitation {} is not active", invitation_id)));
+    }
+
+    let pdf = qr_client.render_ticket_pdf(&PrintableTicket {
+        invitation_id,
+        location: ticket_location(&invitation.format, invitation.address.as_deref()),
+        title: invitation.title,
+        date: invitation.date,
+        attendee_name: invitation.attendee.as_ref().map(|attendee| attendee.display_name().to_string()),
+        max_admissions: invitation.max_admissions,
+        qr_content: invitation.short_url.to_string(),
+    }).await?;
+
+    Ok(
+        HttpResponse::Ok()
+            .content_type("application/pdf")
+            .insert_header(("Content-Disposition", format!("inline; filename=\"ticket-{}.pdf\"", invitation_id)))
+            .body(pdf)
+    )
+}
+
+/// Number of QR archives kept on disk for resumed downloads.
+pub const CACHED_ARCHIVES: usize = 16;
+
+/// What the archive holds for every invitation.
+#[derive(Debug, Clone)]
+enum ArchiveContent {
+    QrCodes(QrRenderOptions),
+    Tickets,
+}
+
+impl ArchiveContent {
+    fn file_name(&self, invitation_id: Uuid) -> String {
+        match self {
+            ArchiveContent::QrCodes(options) => format!("{}.{}", invitation_id, options.format.extension()),
+            ArchiveContent::Tickets => format!("ticket-{}.pdf", invitation_id),
+        }
+    }
+}
+
+/// Renders the QR codes or tickets of the invitations with the QR client, as
+/// many at a time as it renders, and then the manifest listing them.
+fn archive_entries(
+    qr_client: Data<QRClient>,
+    appointment: Appointment,
+    content: ArchiveContent,
+    invitations: Vec<(Invitation, Option<Attendee>)>
+) -> impl Stream<Item = Result<ArchiveEntry, anyhow::Error>> + Send + 'static {
+    let manifest = archive_manifest(&content, &invitations);
+    let renders = qr_client.render_pool().size();
+
+    stream::iter(invitations)
+        .map(move |(invitation, attendee)| {
+            let (qr_client, appointment, content) = (qr_client.clone(), appointment.clone(), content.clone());
+            async move {
+                let name = content.file_name(invitation.id);
+                let (content, compress) = match &content {
+                    ArchiveContent::QrCodes(options) => {
+                        let qr_code = qr_client.render_qr_code(&invitation.short_url, options).await?;
+                        (qr_code.bytes, options.format == QrFormat::Svg)
+                    }
+                    ArchiveContent::Tickets => {
+                        let pdf = qr_client.render_ticket_pdf(&PrintableTicket {
+                            invitation_id: invitation.id,
+                            title: appointment.title,
+                            date: appointment.date,
+                            location: ticket_location(&appointment.format, appointment.address.as_deref()),
+                            attendee_name: attendee.as_ref().map(|attendee| attendee.display_name().to_string()),
+                            max_admissions: invitation.max_admissions,
+                            qr_content: invitation.short_url.to_string(),
+                        }).await?;
+                        (pdf, false)
+                    }
+                };
+                Ok(ArchiveEntry { name, content, compress })
+            }
+        })
+        .buffered(renders)
+        // The manifest comes last, as the archive is only complete with it.
+        .chain(stream::once(futures::future::ready(manifest)))
+}
+
+/// `manifest.csv`, mapping every file of the archive to its invitation and attendee.
+fn archive_manifest(content: &ArchiveContent, invitations: &[(Invitation, Option<Attendee>)]) -> Result<ArchiveEntry, anyhow::Error> {
+    let mut manifest = csv::Writer::from_writer(vec![]);
+    manifest.write_record(["file", "invitation_id", "short_url", "max_admissions", "attendee_name", "attendee_email"])?;
+    for (invitation, attendee) in invitations {
+        manifest.write_record([
+            content.file_name(invitation.id),
+            invitation.id.to_string(),
+            invitation.short_url.to_string(),
+            invitation.max_admissions.to_string(),
+            attendee.as_ref().and_then(|attendee| attendee.name.clone()).unwrap_or_default(),
+            attendee.as_ref().map(|attendee| attendee.email.as_ref().to_string()).unwrap_or_default(),
+        ])?;
+    }
+    Ok(ArchiveEntry {
+        name: "manifest.csv".to_string(),
+        content: manifest.into_inner().map_err(|e| anyhow::anyhow!(e.to_string()))?,
+        compress: true,
+    })
+}
+
+/// Fingerprint of everything the archive is made of: the archive is the same
+/// byte for byte as long as it doesn't change.
+fn archive_etag(appointment: &Appointment, content: &ArchiveContent, invitations: &[(Invitation, Option<Attendee>)]) -> String {
+    let mut hasher = Sha256::new();
+    match content {
+        ArchiveContent::QrCodes(options) => hasher.update(serde_json::to_vec(options).unwrap_or_default()),
+        ArchiveContent::Tickets => hasher.update(format!(
+            "tickets|{}|{}|{:?}|{:?}", appointment.title, appointment.date, appointment.format, appointment.address
+        )),
+    }
+    for (invitation, attendee) in invitations {
+        hasher.update(format!(
+            "|{}|{}|{}|{:?}|{:?}",
+            invitation.id,
+            invitation.short_url,
+            invitation.max_admissions,
+            attendee.as_ref().map(|attendee| &attendee.name),
+            attendee.as_ref().map(|attendee| attendee.email.as_ref()),
+        ));
+    }
+    let digest = hasher.finalize();
+    format!("\"{}\"", digest[..16].iter().map(|byte| format!("{:02x}", byte)).collect::<String>())
+}
+
+/// First and last byte of a `Range: bytes=first-[last]` request, when it
+/// applies to the archive tagged `etag`. Other ranges get the whole archive.
+fn requested_range(request: &HttpRequest, etag: &str) -> Option<(u64, Option<u64>)> {
+    let header = |name| request.headers().get(name).and_then(|value: &http::header::HeaderValue| value.to_str().ok());
+    if header(http::header::IF_RANGE).is_some_and(|if_range| if_range != etag) {
+        return None;
+    }
+    let (first, last) = header(http::header::RANGE)?
+        .strip_prefix("bytes=")?
+        .split_once('-')?;
+    let first = first.trim().parse().ok()?;
+    let last = match last.trim() {
+        "" => None,
+        last => Some(last.parse().ok()?),
+    };
+    Some((first, last))
+}
+
+/// Streams a ZIP of the QR codes, or the PDF tickets, of the active invitations
+/// of an appointment, with a `manifest.csv` mapping every file to its
+/// invitation and attendee.
+///
+/// The archive only changes with its invitations, so downloads can be
+/// resumed with `Range` and `If-Range` requests, which are answered from the
+/// archive cache.
+#[tracing::instrument(
+    name = "Get appointment QR archive",
+    skip(request, pool, qr_client, cache)
+)]
+async fn get_appointment_qr_archive(
+    request: HttpRequest,
+    appointment_id: web::Path<Uuid>,
+    query_params: web::Query<QrArchiveParams>,
+    pool: Data<PgPool>,
+    qr_client: Data<QRClient>,
+    cache: Data<ArchiveCache>
+) -> Result<HttpResponse, CustomError> {
+    let appointment_id = appointment_id.into_inner();
+    let appointment = get_stored_appointments(pool.as_ref(), Some(appointment_id)).await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| CustomError::NotFound(format!("Not found for {}", appointment_id)))?;
+
+    let qr_options = appointment.qr_options.clone().unwrap_or_default();
+    let content = match query_params.into_inner().format.as_deref() {
+        Some(format) if format.eq_ignore_ascii_case("pdf") => ArchiveContent::Tickets,
+        Some(format) => ArchiveContent::QrCodes(QrRenderOptions {
+            format: format.parse().map_err(CustomError::BadRequest)?,
+            ..qr_options
+        }),
+        None => ArchiveContent::QrCodes(qr_options),
+    };
+
+    let mut attendees: HashMap<Uuid, Attendee> = get_appointment_attendees(pool.as_ref(), appointment_id, None).await?
+        .into_iter()
+        .filter_map(|attendee| attendee.invitation_id.map(|id| (id, attendee.attendee)))
+        .collect();
+    let mut invitations: Vec<(Invitation, Option<Attendee>)> = get_appointment_invitations(pool.as_ref(), appointment_id).await?
+        .into_iter()
+        .filter(|invitation| invitation.status == InvitationStatus::ACTIVE)
+        .map(|invitation| {
+            let attendee = attendees.remove(&invitation.id);
+            (invitation, attendee)
+        })
+        .collect();
+    // A stable order keeps the archive the same between requests.
+    invitations.sort_by_key(|(invitation, _)| invitation.id);
+
+    let etag = archive_etag(&appointment, &content, &invitations);
+    let range = requested_range(&request, &etag);
+    let modified = appointment.date;
+
+    let cached = match cache.open(&etag).await {
+        Some(cached) => cached,
+        None if range.is_none() => {
+            let pool = qr_client.render_pool().clone();
+            let archive = stream_zip(
+                archive_entries(qr_client, appointment, content, invitations),
+                modified,
+                pool,
+                Some((cache.into_inner(), etag.clone()))
+            );
+            return Ok(
+                archive_response(HttpResponse::Ok(), appointment_id, etag)
+                    .streaming(archive)
+            );
+        }
+        None => {
+            // The length is only known once the whole archive is written,
+            // which goes to the cache rather than memory.
+            let pool = qr_client.render_pool().clone();
+            stream_zip(
+                archive_entries(qr_client, appointment, content, invitations),
+                modified,
+                pool,
+                Some((cache.clone().into_inner(), etag.clone()))
+            )
+                .try_for_each(|_| futures::future::ready(Ok(())))
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to write the archive: {}", e))?;
+            cache.open(&etag).await
+                .ok_or_else(|| anyhow::anyhow!("The archive {} left the cache before it was read", etag))?
+        }
+    };
+
+    let length = cached.length;
+    let (mut response, first, last) = match range {
+        None => (archive_response(HttpResponse::Ok(), appointment_id, etag), 0, length.saturating_sub(1)),
+        Some((first, last)) => {
+            let last = last.unwrap_or(u64::MAX).min(length.saturating_sub(1));
+            if first >= length || first > last {
+                return Ok(
+                    HttpResponse::RangeNotSatisfiable()
+                        .insert_header((http::header::CONTENT_RANGE, format!("bytes */{}", length)))
+                        .finish()
+                );
+            }
+            let mut response = archive_response(HttpResponse::PartialContent(), appointment_id, etag);
+            response.insert_header((http::header::CONTENT_RANGE, format!("bytes {}-{}/{}", first, last, length)));
+            (response, first, last)
+        }
+    };
+    let archive = cached.stream_range(first, last).await
+        .map_err(|e| anyhow::anyhow!("Failed to read the cached archive: {}", e))?;
+    Ok(
+        response
+            .no_chunking(last + 1 - first)
+            .streaming(archive)
+    )
+}
+
+fn archive_response(mut response: HttpResponseBuilder, appointment_id: Uuid, etag: String) -> HttpResponseBuilder {
+    response
+        .content_type("application/zip")
+        .insert_header((http::header::ETAG, etag))
+        .insert_header((http::header::ACCEPT_RANGES, "bytes"))
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"qr-{}.zip\"", appointment_id)));
+    response
+}
@@ -1,30 +1,305 @@
-use actix_web::http;
+use actix_web::{http, HttpRequest};
+use anyhow::anyhow;
 use chrono::{NaiveDateTime, Utc};
-use shared::domain::{AppointmentFormat, AppointmentWithInvitation, DBAppointmentWithInvitation};
+use secrecy::ExposeSecret;
+use sha2::{Digest, Sha256};
+use sqlx::PgExecutor;
+use subtle::ConstantTimeEq;
+use shared::configuration::ApplicationSettings;
+use shared::domain::{AppointmentFormat, AppointmentWithInvitation, DBAppointmentWithInvitation, InvitationStatus, NewScanEvent, OfflineScan, ScanDirection, ScanParams, ScanResult, SyncStatus, SyncedScan, ValidationPreview};
 use super::*;
 use crate::error::CustomError;
+use crate::repository::{admit_stored_invitation, claim_offline_scan, count_inside_with_invitation, lock_invitation, preserve_online_join, preserve_scan_event, record_scan_result};
 
 pub fn validation_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::resource("/validations/{id}")
             .route(web::get().to(validate_invitation_by_id))
-    );
+            .route(web::post().to(join_meeting_by_id))
+    )
+        .service(
+            web::resource("/validations/{id}/check-in")
+                .route(web::post().to(check_in_invitation_by_id))
+        )
+        .service(
+            web::resource("/appointment/{id}/scans")
+                .route(web::post().to(sync_offline_scans))
+        );
 }
 
+/// Shows what a validation link is about, without checking anyone in.
+///
+/// Phone cameras, link previews and email scanners open this URL on their
+/// own, so it never changes anything, and ONLINE invitations are only
+/// joined by posting to it. Browsers get a page, other clients a
+/// `ValidationPreview`.
 #[tracing::instrument(
     name = "Validate invitation by id",
-    skip(pool),
+    skip(request, pool),
 )]
 pub(crate) async fn validate_invitation_by_id(
+    request: HttpRequest,
     invitation_id: web::Path<Uuid>,
     pool: Data<PgPool>
 ) -> Result<HttpResponse, CustomError> {
-    let response = get_stored_invitation(pool.as_ref(), invitation_id.into_inner()).await?;
+    let invitation_id = invitation_id.into_inner();
+    let invitation = get_stored_invitation(pool.as_ref(), invitation_id).await?
+        .map(AppointmentWithInvitation::try_from)
+        .transpose()
+        .map_err(|e| anyhow::anyhow!(e))?
+        .ok_or_else(|| CustomError::NotFound(format!("Not found for {}", invitation_id)))?;
+
+    let now = Utc::now().naive_utc();
+    let mut rejection = check_admissible(&invitation).err();
+    let mut opens_at = None;
+    if matches!(invitation.format, AppointmentFormat::ONLINE) && rejection.is_none() {
+        rejection = check_joinable(&invitation, now).err();
+        let (opens, _) = join_window(&invitation);
+        if now < opens {
+            opens_at = Some(opens);
+        }
+    }
+    if rejection.is_none() && invitation.remaining_admissions < 1 {
+        rejection = Some(used_up(&invitation));
+    }
 
-    let invitation = AppointmentWithInvitation::from(response);
+    let preview = ValidationPreview {
+        invitation_id,
+        appointment_id: invitation.appointment_id,
+        title: invitation.title,
+        format: invitation.format,
+        address: invitation.address,
+        date: invitation.date,
+        status: invitation.status,
+        admission: invitation.admission,
+        remaining_admissions: invitation.remaining_admissions,
+        attendee_name: invitation.attendee.as_ref().map(|attendee| attendee.display_name().to_string()),
+        valid: rejection.is_none(),
+        reason: rejection.map(|e| e.to_string()),
+    };
+
+    let mut response = HttpResponse::Ok();
+    // The state changes with every check-in, so a cached preview would lie.
+    response.insert_header((http::header::CACHE_CONTROL, "no-store"));
+    if !prefers_html(&request) {
+        return Ok(response.json(preview));
+    }
 
-    if invitation.used {
-        return Err(CustomError::Forbidden("invitation has already been used".to_string()));
+    if let Some(opens_at) = opens_at {
+        let seconds = (opens_at - now).num_seconds();
+        let waiting_template = render_template("waiting").await?;
+        let formatted_html = waiting_template
+            .replace("{TITLE}", &escape_html(&preview.title))
+            .replace("{ATTENDEE_NAME}", &escape_html(preview.attendee_name.as_deref().unwrap_or("guest")))
+            .replace("{OPENS_AT}", &opens_at.format("%Y/%m/%d %H:%M").to_string())
+            .replace("{COUNTDOWN}", &format!("{}h {}m {}s", seconds / 3600, seconds % 3600 / 60, seconds % 60))
+            .replace("{SECONDS}", &seconds.to_string());
+        return Ok(
+            response
+                .content_type("text/html; charset=utf-8")
+                .body(formatted_html)
+        );
+    }
+
+    if matches!(preview.format, AppointmentFormat::ONLINE) && preview.valid {
+        let join_template = render_template("join").await?;
+        let formatted_html = join_template
+            .replace("{TITLE}", &escape_html(&preview.title))
+            .replace("{ATTENDEE_NAME}", &escape_html(preview.attendee_name.as_deref().unwrap_or("guest")))
+            .replace("{DATE}", &preview.date.format("%Y/%m/%d %H:%M").to_string());
+        return Ok(
+            response
+                .content_type("text/html; charset=utf-8")
+                .body(formatted_html)
+        );
+    }
+
+    let validation_template = render_template("validation").await?;
+    let status = match &preview.reason {
+        None => "Valid".to_string(),
+        Some(reason) => format!("Not valid: {}", reason),
+    };
+    let formatted_html = validation_template
+        .replace("{TITLE}", &escape_html(&preview.title))
+        .replace("{DATE}", &preview.date.format("%Y/%m/%d %H:%M").to_string())
+        .replace("{LOCATION}", &escape_html(preview.address.as_deref().unwrap_or("online")))
+        .replace("{ATTENDEE_NAME}", &escape_html(preview.attendee_name.as_deref().unwrap_or("guest")))
+        .replace("{ADMISSIONS}", &escape_html(&preview.admission))
+        .replace("{STATUS}", &escape_html(&status));
+    Ok(
+        response
+            .content_type("text/html; charset=utf-8")
+            .body(formatted_html)
+    )
+}
+
+/// Minutes before the start of an ONLINE appointment its meeting can be joined.
+const JOIN_OPENS_MINUTES_EARLY: i64 = 15;
+
+/// When the meeting of an ONLINE appointment can be joined: from shortly
+/// before its start until its end.
+fn join_window(invitation: &AppointmentWithInvitation) -> (NaiveDateTime, NaiveDateTime) {
+    let duration = chrono::Duration::seconds(invitation.duration.as_secs() as i64);
+    (invitation.date - chrono::Duration::minutes(JOIN_OPENS_MINUTES_EARLY), invitation.date + duration)
+}
+
+/// Tells why the meeting of an ONLINE invitation can't be joined right now, if it can't.
+fn check_joinable(invitation: &AppointmentWithInvitation, now: NaiveDateTime) -> Result<(), CustomError> {
+    let (opens, closes) = join_window(invitation);
+    if now < opens {
+        return Err(CustomError::Forbidden(format!("the meeting opens at {} UTC", opens.format("%Y/%m/%d %H:%M"))));
+    }
+    if now > closes {
+        return Err(CustomError::Forbidden("the meeting has ended".to_string()));
+    }
+    Ok(())
+}
+
+/// Sends the holder of an ONLINE invitation to the meeting and records the join.
+///
+/// Only a deliberate POST, such as the button of the validation page, joins,
+/// so link previews don't show up as attendance. The redirect is never
+/// cached, so the meeting link stays behind the invitation and its event window.
+#[tracing::instrument(
+    name = "Join meeting by id",
+    skip(request, pool),
+)]
+pub(crate) async fn join_meeting_by_id(
+    request: HttpRequest,
+    invitation_id: web::Path<Uuid>,
+    pool: Data<PgPool>
+) -> Result<HttpResponse, CustomError> {
+    let invitation_id = invitation_id.into_inner();
+    let invitation = get_stored_invitation(pool.as_ref(), invitation_id).await?
+        .map(AppointmentWithInvitation::try_from)
+        .transpose()
+        .map_err(|e| anyhow::anyhow!(e))?
+        .ok_or_else(|| CustomError::NotFound(format!("Not found for {}", invitation_id)))?;
+
+    if !matches!(invitation.format, AppointmentFormat::ONLINE) {
+        return Err(CustomError::BadRequest(
+            "only ONLINE appointments have a meeting to join, scanners check people in at the door".to_string()
+        ));
+    }
+    check_admissible(&invitation)?;
+    check_joinable(&invitation, Utc::now().naive_utc())?;
+
+    let user_agent = request.headers()
+        .get(http::header::USER_AGENT)
+        .and_then(|user_agent| user_agent.to_str().ok());
+    preserve_online_join(pool.as_ref(), invitation.id, invitation.appointment_id, user_agent).await?;
+
+    Ok(
+        HttpResponse::SeeOther()
+            .insert_header((http::header::CACHE_CONTROL, "no-store"))
+            .append_header((
+                http::header::LOCATION,
+                invitation.link
+                    .expect("Can't extract invitation url")
+                    .to_string()
+            ))
+            .finish()
+    )
+}
+
+/// Checks someone in or out at the door, for authenticated scanners only.
+///
+/// Every attempt is recorded as a scan event.
+#[tracing::instrument(
+    name = "Check in invitation by id",
+    skip(request, pool, application),
+)]
+pub(crate) async fn check_in_invitation_by_id(
+    request: HttpRequest,
+    invitation_id: web::Path<Uuid>,
+    scan: Option<web::Json<ScanParams>>,
+    pool: Data<PgPool>,
+    application: Data<ApplicationSettings>
+) -> Result<HttpResponse, CustomError> {
+    let scanner = authorize_scanner(&request, &application)?;
+    let invitation_id = invitation_id.into_inner();
+    let ScanParams { direction } = scan.map(|scan| scan.into_inner()).unwrap_or_default();
+
+    // The people inside are counted from the scans, so scans of an invitation
+    // are decided and recorded one at a time.
+    let mut transaction = open_transaction(pool.clone()).await?;
+    lock_invitation(&mut transaction, invitation_id).await?;
+    let stored = get_stored_invitation(&mut *transaction, invitation_id).await?;
+    let appointment_id = stored.as_ref().map(|stored| stored.appointment_id);
+
+    let outcome = match stored {
+        None => Err(CustomError::NotFound(format!("Not found for {}", invitation_id))),
+        Some(stored) => {
+            let invitation = AppointmentWithInvitation::try_from(stored).map_err(|e| anyhow::anyhow!(e))?;
+            match direction {
+                ScanDirection::IN => check_in(&mut transaction, invitation).await,
+                ScanDirection::OUT => check_out(&mut transaction, invitation).await,
+            }
+        }
+    };
+
+    // Every attempt is kept, so that disputed entries can be audited.
+    let (result, reason) = match &outcome {
+        Ok(_) => (ScanResult::ADMITTED, None),
+        Err(e) => (ScanResult::REJECTED, Some(e.to_string())),
+    };
+    preserve_scan_event(&mut *transaction, &NewScanEvent {
+        invitation_id,
+        appointment_id,
+        direction,
+        result,
+        reason,
+        scanner: Some(scanner),
+        scanned_at: None,
+        client_scan_id: None,
+    }).await?;
+
+    commit_transaction(transaction, "Failed to commit SQL transaction to record a scan.")
+        .await?;
+
+    outcome
+}
+
+/// Rejects requests that don't carry the bearer token of a door scanner,
+/// and tells which scanner sent the others.
+fn authorize_scanner(request: &HttpRequest, application: &ApplicationSettings) -> Result<String, CustomError> {
+    let token = request.headers()
+        .get(http::header::AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .ok_or_else(|| CustomError::InvalidCredentials(anyhow!("Missing scanner token")))?;
+
+    // Digests have the same length whatever the tokens, and every known token
+    // is compared, so the response time doesn't tell how much of a guess matched.
+    let digest = Sha256::digest(token.as_bytes());
+    let mut scanner = None;
+    for known in &application.scanners {
+        let matches = Sha256::digest(known.token.expose_secret().as_bytes()).ct_eq(&digest);
+        if bool::from(matches) {
+            scanner = Some(known.name.clone());
+        }
+    }
+    scanner.ok_or_else(|| CustomError::InvalidCredentials(anyhow!("Unknown scanner token")))
+}
+
+/// Whether the client is a browser asking for a page rather than an API client.
+fn prefers_html(request: &HttpRequest) -> bool {
+    request.headers()
+        .get(http::header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"))
+}
+
+/// Tells why an invitation can't be used to get in right now, if it can't.
+fn check_admissible(invitation: &AppointmentWithInvitation) -> Result<(), CustomError> {
+    match invitation.status {
+        InvitationStatus::DECLINED => {
+            return Err(CustomError::Forbidden("invitation has been declined".to_string()));
+        }
+        InvitationStatus::REVOKED => {
+            return Err(CustomError::Forbidden("invitation has been revoked".to_string()));
+        }
+        InvitationStatus::ACTIVE => {}
     }
 
     let now = Utc::now().naive_utc();
@@ -41,44 +316,168 @@ pub(crate) async fn validate_invitation_by_id(
     if invitation.date < yesterday {
         return Err(CustomError::Forbidden("The invitation date is outdated.".to_string()));
     }
+    if invitation.claim_deadline.is_some_and(|deadline| deadline < now) {
+        return Err(CustomError::Forbidden("invitation was not claimed before its deadline".to_string()));
+    }
+    if matches!(invitation.format, AppointmentFormat::ONLINE) && invitation.used {
+        return Err(used_up(invitation));
+    }
+    Ok(())
+}
+
+/// Lets the holder of an invitation in, consuming one of its admissions
+/// unless someone who checked out with it comes back.
+async fn check_in(
+    transaction: &mut Transaction<'_, Postgres>,
+    invitation: AppointmentWithInvitation
+) -> Result<HttpResponse, CustomError> {
+    check_admissible(&invitation)?;
+    if matches!(invitation.format, AppointmentFormat::ONLINE) {
+        return Err(CustomError::BadRequest(
+            "ONLINE appointments are joined through the invitation link, there is no door to check in at".to_string()
+        ));
+    }
+
+    let re_entry = count_inside_with_invitation(&mut **transaction, invitation.id).await? < invitation.admitted as i64;
+    if !re_entry && (invitation.used || !admit_stored_invitation(&mut **transaction, invitation.id).await?) {
+        return Err(used_up(&invitation));
+    }
+    let response = get_stored_invitation(&mut **transaction, invitation.id).await?
+        .ok_or_else(|| CustomError::NotFound(format!("Not found for {}", invitation.id)))?;
+
+    Ok(
+        HttpResponse::Ok()
+            .json(AppointmentWithInvitation::try_from(response).map_err(|e| anyhow::anyhow!(e))?)
+    )
+}
+
+/// Lets someone who checked in with an invitation out.
+async fn check_out(
+    transaction: &mut Transaction<'_, Postgres>,
+    invitation: AppointmentWithInvitation
+) -> Result<HttpResponse, CustomError> {
+    if count_inside_with_invitation(&mut **transaction, invitation.id).await? < 1 {
+        return Err(CustomError::Conflict("nobody is checked in with this invitation".to_string()));
+    }
+
+    Ok(
+        HttpResponse::Ok()
+            .json(invitation)
+    )
+}
+
+#[tracing::instrument(
+    name = "Sync offline scans",
+    skip(request, pool, scans, application),
+)]
+pub(crate) async fn sync_offline_scans(
+    request: HttpRequest,
+    appointment_id: web::Path<Uuid>,
+    scans: web::Json<Vec<OfflineScan>>,
+    pool: Data<PgPool>,
+    application: Data<ApplicationSettings>
+) -> Result<HttpResponse, CustomError> {
+    let scanner = authorize_scanner(&request, &application)?;
+    let appointment_id = appointment_id.into_inner();
+    let mut scans = scans.into_inner();
+    // When offline scanners admitted the same code too often, the earliest
+    // check-ins win and the later ones are reported as conflicts.
+    scans.sort_by_key(|scan| scan.scanned_at);
+
+    let mut response = vec![];
+    for scan in scans {
+        response.push(sync_offline_scan(pool.clone(), appointment_id, &scanner, scan).await?);
+    }
+
+    Ok(
+        HttpResponse::Ok()
+            .json(response)
+    )
+}
 
-    let http_response = match invitation.format {
+async fn sync_offline_scan(
+    pool: Data<PgPool>,
+    appointment_id: Uuid,
+    scanner: &str,
+    scan: OfflineScan
+) -> Result<SyncedScan, CustomError> {
+    let mut transaction = open_transaction(pool).await?;
 
-        AppointmentFormat::ONLINE => {
-            HttpResponse::PermanentRedirect()
-                .append_header((
-                    http::header::LOCATION,
-                    invitation.link
-                        .expect("Can't extract invitation url")
-                        .to_string()
-                ))
-                .finish()
+    let claimed = claim_offline_scan(&mut transaction, &NewScanEvent {
+        invitation_id: scan.invitation_id,
+        appointment_id: Some(appointment_id),
+        direction: ScanDirection::IN,
+        result: ScanResult::REJECTED,
+        reason: None,
+        scanner: Some(scanner.to_string()),
+        scanned_at: Some(scan.scanned_at),
+        client_scan_id: Some(scan.id),
+    }).await?;
+    let Some(scan_id) = claimed else {
+        return Ok(SyncedScan { id: scan.id, invitation_id: scan.invitation_id, status: SyncStatus::DUPLICATE, reason: None });
+    };
+
+    lock_invitation(&mut transaction, scan.invitation_id).await?;
+    let stored = get_stored_invitation(&mut *transaction, scan.invitation_id).await?
+        .filter(|stored| stored.appointment_id == appointment_id);
+    let (status, reason) = match stored {
+        None => (SyncStatus::REJECTED, Some(format!("Not found for {}", scan.invitation_id))),
+        Some(stored) if stored.status != InvitationStatus::ACTIVE => {
+            (SyncStatus::REJECTED, Some(format!("invitation is {:?}", stored.status).to_lowercase()))
         }
-        AppointmentFormat::OFFLINE => {
-            HttpResponse::Ok()
-                .json(invitation)
+        Some(stored) => {
+            if admit_stored_invitation(&mut *transaction, scan.invitation_id).await? {
+                (SyncStatus::ACCEPTED, None)
+            } else {
+                (SyncStatus::CONFLICT, Some(format!(
+                    "all {} admission(s) were used by other scans first",
+                    stored.max_admissions
+                )))
+            }
         }
     };
 
-    Ok(http_response)
+    let result = if status == SyncStatus::ACCEPTED { ScanResult::ADMITTED } else { ScanResult::REJECTED };
+    record_scan_result(&mut transaction, scan_id, result, reason.as_deref()).await?;
+
+    commit_transaction(transaction, "Failed to commit SQL transaction to sync an offline scan.")
+        .await?;
+
+    Ok(SyncedScan { id: scan.id, invitation_id: scan.invitation_id, status, reason })
+}
+
+fn used_up(invitation: &AppointmentWithInvitation) -> CustomError {
+    if invitation.max_admissions > 1 {
+        CustomError::Forbidden(format!(
+            "invitation has already been used, {} of {} admitted",
+            invitation.max_admissions, invitation.max_admissions
+        ))
+    } else {
+        CustomError::Forbidden("invitation has already been used".to_string())
+    }
 }
 
-pub(crate) async fn get_stored_invitation(
-    pool: &PgPool,
+pub(crate) async fn get_stored_invitation<'e>(
+    executor: impl PgExecutor<'e>,
     invitation_id: Uuid
-) -> Result<DBAppointmentWithInvitation, CustomError> {
+) -> Result<Option<DBAppointmentWithInvitation>, CustomError> {
     let query = "
-        SELECT invitation.id, invitation.appointment_id, invitation.used, invitation.short_url,
-        appointment.link, appointment.format, appointment.address, appointment.date
+        SELECT invitation.id, invitation.appointment_id, appointment.title, invitation.used, invitation.status,
+        invitation.max_admissions, invitation.admitted, invitation.claim_deadline, invitation.short_url,
+        appointment.link, appointment.format, appointment.address, appointment.date, appointment.duration,
+        attendee.id AS attendee_id, attendee.name AS attendee_name, attendee.email AS attendee_email,
+        attendee.phone AS attendee_phone, attendee.custom_fields AS attendee_custom_fields
         FROM invitation
         LEFT JOIN appointment
         ON invitation.appointment_id = appointment.id
+        LEFT JOIN attendee
+        ON invitation.attendee_id = attendee.id
         WHERE invitation.id = $1
     ".to_string();
 
-    let result: DBAppointmentWithInvitation = sqlx::query_as::<_, DBAppointmentWithInvitation>(&query)
+    let result: Option<DBAppointmentWithInvitation> = sqlx::query_as::<_, DBAppointmentWithInvitation>(&query)
         .bind(invitation_id)
-        .fetch_one(pool)
+        .fetch_optional(executor)
         .await?;
 
     Ok(result)
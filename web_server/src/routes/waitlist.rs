@@ -0,0 +1,326 @@
+use super::*;
+use std::collections::HashMap;
+use chrono::Utc;
+use futures::future::join_all;
+use url::Url;
+use shared::domain::{Email, NewAttendee, NewInvitation, WaitlistEntry, WaitlistPosition};
+use shared::configuration::ApplicationSettings;
+use shared::email_client::EmailClient;
+use shared::qr_client::QRClient;
+use crate::repository::{decline_expired_claims, get_appointment_qr_options, get_appointment_waitlist, get_known_recipients, get_stored_appointments, get_waitlist_entry, KnownRecipient, lock_appointment_seats, mark_waitlist_entries_promoted, move_waitlist_entry, preserve_new_attendees, preserve_new_invitations, preserve_waitlist_entry, take_next_waitlist_entries};
+
+/// Hours a promoted person has to claim their invitation.
+const CLAIM_WINDOW_HOURS: i64 = 48;
+
+pub fn waitlist_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/appointment/{id}/waitlist")
+            .route(web::get().to(get_waitlist))
+            .route(web::post().to(join_waitlist))
+    )
+        .service(
+            web::resource("/appointment/{id}/waitlist/promote")
+                .route(web::post().to(promote_waitlist_handler))
+        )
+        .service(
+            web::resource("/appointment/{id}/waitlist/{entry_id}")
+                .route(web::put().to(reorder_waitlist))
+        );
+}
+
+/// Issues invitations to the people at the head of the waitlist while seats are free.
+///
+/// Promoted invitations that were not claimed in time are declined first, so
+/// their seats go to the next people in line. Each promoted person gets an
+/// email with their QR code and the claim deadline, unless they hold an
+/// invitation already, which they keep.
+///
+/// Short URLs are requested for the people in line before the seats are
+/// locked, and the emails sent once the promotion is committed. People a
+/// concurrent promotion took meanwhile are left to it.
+///
+/// # Returns
+///
+/// The invitations issued, empty when no seat is free or nobody is waiting.
+#[tracing::instrument(
+    name = "Promote waitlist",
+    skip(pool, qr_client, email_client),
+)]
+pub(crate) async fn promote_waitlist(
+    pool: Data<PgPool>,
+    qr_client: &QRClient,
+    email_client: &EmailClient,
+    base_url: &str,
+    appointment_id: Uuid
+) -> Result<Vec<NewInvitation>, CustomError> {
+    let qr_options = get_appointment_qr_options(pool.as_ref(), appointment_id).await?.for_email();
+    let mut transaction = open_transaction(pool.clone()).await?;
+
+    decline_expired_claims(&mut transaction, appointment_id).await?;
+    let seats = lock_appointment_seats(&mut transaction, appointment_id).await?
+        .ok_or_else(|| CustomError::NotFound(format!("Not found for {}", appointment_id)))?;
+    let waiting = match seats.free() {
+        Some(0) => 0,
+        free => take_next_waitlist_entries(&mut transaction, appointment_id, free).await?.len(),
+    };
+
+    commit_transaction(transaction, "Failed to commit SQL transaction to decline expired claims.")
+        .await?;
+    if waiting == 0 {
+        return Ok(vec![]);
+    }
+
+    let futures: Vec<_> = (0..waiting).map(|_| {
+        let id = Uuid::new_v4();
+        async move {
+            qr_client.get_short_url(id.to_string()).await.map(|res| (id, res.short_url))
+        }
+    }).collect();
+    let short_urls = join_all(futures).await
+        .into_iter()
+        .collect::<Result<Vec<(Uuid, Url)>, anyhow::Error>>()?;
+
+    let mut transaction = open_transaction(pool.clone()).await?;
+
+    let seats = lock_appointment_seats(&mut transaction, appointment_id).await?
+        .ok_or_else(|| CustomError::NotFound(format!("Not found for {}", appointment_id)))?;
+    let limit = seats.free().map_or(waiting as i64, |free| free.min(waiting as i64));
+    let entries = match limit {
+        0 => vec![],
+        limit => take_next_waitlist_entries(&mut transaction, appointment_id, Some(limit)).await?,
+    };
+    if entries.is_empty() {
+        return Ok(vec![]);
+    }
+
+    // People invited before, e.g. who declined, stay the same attendee, and
+    // those who hold an invitation already keep it.
+    let emails: Vec<String> = entries.iter().map(|entry| entry.attendee().email.as_ref().to_string()).collect();
+    let known: HashMap<String, KnownRecipient> = get_known_recipients(&mut *transaction, appointment_id, &emails).await?
+        .into_iter()
+        .map(|recipient| (recipient.email.to_lowercase(), recipient))
+        .collect();
+
+    let claim_deadline = Utc::now().naive_utc() + chrono::Duration::hours(CLAIM_WINDOW_HOURS);
+    let mut invitations: Vec<NewInvitation> = vec![];
+    let mut attendees: Vec<(Uuid, NewAttendee)> = vec![];
+    let mut promoted: Vec<(Uuid, Uuid)> = vec![];
+    let mut new_attendees: Vec<(Uuid, NewAttendee)> = vec![];
+    for (entry, (id, short_url)) in entries.iter().zip(short_urls) {
+        let attendee = entry.attendee();
+        let attendee_id = match known.get(&attendee.email.as_ref().to_lowercase()) {
+            Some(KnownRecipient { invitation_id: Some(invitation_id), .. }) => {
+                promoted.push((entry.id, *invitation_id));
+                continue;
+            }
+            Some(known) => known.attendee_id,
+            None => {
+                let attendee_id = Uuid::new_v4();
+                new_attendees.push((attendee_id, attendee.clone()));
+                attendee_id
+            }
+        };
+        attendees.push((attendee_id, attendee));
+        invitations.push(NewInvitation {
+            id,
+            appointment_id,
+            short_url,
+            attendee_id: Some(attendee_id),
+            claim_deadline: Some(claim_deadline),
+            max_admissions: 1
+        });
+        promoted.push((entry.id, id));
+    }
+
+    preserve_new_attendees(&mut transaction, appointment_id, &new_attendees).await?;
+    preserve_new_invitations(&mut transaction, &invitations).await?;
+    mark_waitlist_entries_promoted(&mut transaction, &promoted).await?;
+
+    commit_transaction(transaction, "Failed to commit SQL transaction to promote the waitlist.")
+        .await?;
+
+    let deadline = claim_deadline.format("%Y/%m/%d %H:%M").to_string();
+    let futures: Vec<_> = attendees.iter().zip(invitations.iter()).map(|((_, attendee), invitation)| {
+        let deadline = &deadline;
+        let qr_options = &qr_options;
+        async move {
+            let qr_code = qr_client.render_qr_code(&invitation.short_url, qr_options).await?;
+            let email_template = render_template("waitlist_email").await?;
+            let html_body = email_template
+                .replace("{IMAGE_TYPE}", qr_code.content_type())
+                .replace("{IMAGE_STRING}", &qr_code.to_base64())
+                .replace("{ATTENDEE_NAME}", &escape_html(attendee.display_name()))
+                .replace("{CLAIM_DEADLINE}", deadline)
+                .replace("{ACCEPT_URL}", &rsvp_url(base_url, invitation.id, "accept"))
+                .replace("{DECLINE_URL}", &rsvp_url(base_url, invitation.id, "decline"));
+            let plain_body = format!(
+                "Hello {}. A seat is available for you, please accept it before {} UTC: {}",
+                attendee.display_name(), deadline, rsvp_url(base_url, invitation.id, "accept")
+            );
+            email_client.send_email(
+                &attendee.email,
+                "A seat is available",
+                &html_body,
+                &plain_body
+            ).await.map_err(|e| anyhow::anyhow!(e))
+        }
+    }).collect();
+
+    for result in join_all(futures).await {
+        result?;
+    }
+
+    let sent_emails: Vec<(Uuid, Email)> = attendees.iter()
+        .zip(invitations.iter())
+        .map(|((_, attendee), invitation)| (invitation.id, attendee.email.clone()))
+        .collect();
+    log_sent_emails(pool, &sent_emails).await?;
+
+    Ok(invitations)
+}
+
+/// Runs [`promote_waitlist`] after a seat was freed, logging failures
+/// instead of failing the request that freed the seat.
+pub(crate) async fn promote_waitlist_after_release(
+    pool: Data<PgPool>,
+    qr_client: &QRClient,
+    email_client: &EmailClient,
+    base_url: &str,
+    appointment_id: Uuid
+) {
+    if let Err(e) = promote_waitlist(pool, qr_client, email_client, base_url, appointment_id).await {
+        tracing::error!(error = %e, %appointment_id, "Failed to promote the waitlist");
+    }
+}
+
+/// Adds a person at the end of the waitlist, then promotes the head of the
+/// waitlist in case seats are free already, e.g. when the appointment is not full yet.
+///
+/// # Returns
+///
+/// The entry as stored after the promotion, with its invitation when it was promoted.
+pub(crate) async fn add_to_waitlist(
+    pool: Data<PgPool>,
+    qr_client: &QRClient,
+    email_client: &EmailClient,
+    base_url: &str,
+    appointment_id: Uuid,
+    attendee: &NewAttendee
+) -> Result<WaitlistEntry, CustomError> {
+    let mut transaction = open_transaction(pool.clone()).await?;
+
+    let entry = preserve_waitlist_entry(&mut transaction, appointment_id, attendee).await?;
+
+    commit_transaction(transaction, "Failed to commit SQL transaction to join the waitlist.")
+        .await?;
+
+    promote_waitlist(pool.clone(), qr_client, email_client, base_url, appointment_id).await?;
+
+    get_waitlist_entry(pool.as_ref(), entry.id).await?
+        .ok_or_else(|| CustomError::NotFound(format!("Not found for {}", entry.id)))
+}
+
+#[tracing::instrument(
+    name = "Join waitlist",
+    skip(pool, qr_client, email_client, application),
+)]
+async fn join_waitlist(
+    appointment_id: web::Path<Uuid>,
+    attendee: web::Json<NewAttendee>,
+    pool: Data<PgPool>,
+    qr_client: Data<QRClient>,
+    email_client: Data<EmailClient>,
+    application: Data<ApplicationSettings>
+) -> Result<HttpResponse, CustomError> {
+    let appointment_id = appointment_id.into_inner();
+    if get_stored_appointments(pool.as_ref(), Some(appointment_id)).await?.is_empty() {
+        return Err(CustomError::NotFound(format!("Not found for {}", appointment_id)));
+    }
+
+    let entry = add_to_waitlist(
+        pool,
+        &qr_client,
+        &email_client,
+        &application.base_url,
+        appointment_id,
+        &attendee
+    ).await?;
+    Ok(
+        HttpResponse::Ok()
+            .json(entry)
+    )
+}
+
+#[tracing::instrument(
+    name = "Get waitlist",
+    skip(pool),
+)]
+async fn get_waitlist(
+    appointment_id: web::Path<Uuid>,
+    pool: Data<PgPool>
+) -> Result<HttpResponse, CustomError> {
+    let appointment_id = appointment_id.into_inner();
+    if get_stored_appointments(pool.as_ref(), Some(appointment_id)).await?.is_empty() {
+        return Err(CustomError::NotFound(format!("Not found for {}", appointment_id)));
+    }
+
+    let response = get_appointment_waitlist(pool.as_ref(), appointment_id).await?;
+    Ok(
+        HttpResponse::Ok()
+            .json(response)
+    )
+}
+
+#[tracing::instrument(
+    name = "Reorder waitlist",
+    skip(pool),
+)]
+async fn reorder_waitlist(
+    path: web::Path<(Uuid, Uuid)>,
+    body: web::Json<WaitlistPosition>,
+    pool: Data<PgPool>
+) -> Result<HttpResponse, CustomError> {
+    let (appointment_id, entry_id) = path.into_inner();
+    if body.position < 1 {
+        return Err(CustomError::BadRequest("position must be greater than zero.".to_string()));
+    }
+
+    let mut transaction = open_transaction(pool.clone()).await?;
+
+    if !move_waitlist_entry(&mut transaction, appointment_id, entry_id, body.position).await? {
+        return Err(CustomError::NotFound(format!("Not found for {}", entry_id)));
+    }
+
+    commit_transaction(transaction, "Failed to commit SQL transaction to reorder the waitlist.")
+        .await?;
+
+    let response = get_appointment_waitlist(pool.as_ref(), appointment_id).await?;
+    Ok(
+        HttpResponse::Ok()
+            .json(response)
+    )
+}
+
+#[tracing::instrument(
+    name = "Promote waitlist handler",
+    skip(pool, qr_client, email_client, application),
+)]
+async fn promote_waitlist_handler(
+    appointment_id: web::Path<Uuid>,
+    pool: Data<PgPool>,
+    qr_client: Data<QRClient>,
+    email_client: Data<EmailClient>,
+    application: Data<ApplicationSettings>
+) -> Result<HttpResponse, CustomError> {
+    let response = promote_waitlist(
+        pool,
+        &qr_client,
+        &email_client,
+        &application.base_url,
+        appointment_id.into_inner()
+    ).await?;
+    Ok(
+        HttpResponse::Ok()
+            .json(response)
+    )
+}
@@ -1,18 +1,30 @@
-use crate::routes::{appointment_routes, invitation_routes, validation_routes};
+use crate::archive::ArchiveCache;
+use crate::rate_limit::RateLimiter;
+use crate::routes::{appointment_routes, invitation_routes, job_routes, registration_routes, ticket_routes, validation_routes, waitlist_routes, CACHED_ARCHIVES, REGISTRATIONS_PER_WINDOW, REGISTRATION_WINDOW};
 use crate::error::CustomError;
 use actix_web::dev::Server;
 use actix_web::{web, App, HttpResponse, HttpServer, Result};
 use std::net::TcpListener;
 use sqlx::{Pool, Postgres};
 use tracing_actix_web::TracingLogger;
+use shared::configuration::ApplicationSettings;
 use shared::email_client::EmailClient;
 use shared::qr_client::QRClient;
 
-pub fn run(listener: TcpListener, pool: Pool<Postgres>, qrclient: QRClient, email_client: EmailClient ) -> Result<Server, CustomError> {
+pub fn run(
+    listener: TcpListener,
+    pool: Pool<Postgres>,
+    qrclient: QRClient,
+    email_client: EmailClient,
+    application: ApplicationSettings
+) -> Result<Server, CustomError> {
     let address_msg = format!("Server started on {:?}", &listener.local_addr()?);
     let db_pool = web::Data::new(pool);
     let qr_client = web::Data::new(qrclient);
     let email_client = web::Data::new(email_client);
+    let application = web::Data::new(application);
+    let registration_limiter = web::Data::new(RateLimiter::new(REGISTRATIONS_PER_WINDOW, REGISTRATION_WINDOW));
+    let archive_cache = web::Data::new(ArchiveCache::new(std::env::temp_dir().join("qr-archives"), CACHED_ARCHIVES));
     let server = HttpServer::new(move || {
         App::new()
             .wrap(TracingLogger::default())
@@ -23,10 +35,17 @@ pub fn run(listener: TcpListener, pool: Pool<Postgres>, qrclient: QRClient, emai
                 .configure(appointment_routes)
                 .configure(validation_routes)
                 .configure(invitation_routes)
+                .configure(waitlist_routes)
+                .configure(registration_routes)
+                .configure(ticket_routes)
+                .configure(job_routes)
             )
             .app_data(db_pool.clone())
             .app_data(qr_client.clone())
+            .app_data(archive_cache.clone())
             .app_data(email_client.clone())
+            .app_data(application.clone())
+            .app_data(registration_limiter.clone())
     })
     .listen(listener)?
     .run();
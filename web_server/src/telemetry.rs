@@ -5,6 +5,8 @@ use tracing_log::LogTracer;
 use tracing_subscriber::fmt::MakeWriter;
 use tracing_subscriber::{layer::SubscriberExt, EnvFilter, Registry};
 
+pub use shared::blocking::spawn_blocking_with_tracing;
+
 pub fn setup_subscriber<Sink>(
     name: String,
     env_filter: String,
@@ -27,12 +29,3 @@ pub fn init_subscriber(subscriber: impl Subscriber + Sync + Send) {
     LogTracer::init().expect("Failed to set logger");
     set_global_default(subscriber).expect("Failed to set subscriber");
 }
-
-pub fn spawn_blocking_with_tracing<F, R>(f: F) -> actix_web::rt::task::JoinHandle<R>
-    where
-        F: FnOnce() -> R + Send + 'static,
-        R: Send + 'static,
-{
-    let current_span = tracing::Span::current();
-    actix_web::rt::task::spawn_blocking(move || current_span.in_scope(f))
-}
\ No newline at end of file
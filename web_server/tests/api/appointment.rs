@@ -7,7 +7,9 @@ mod appointment_tests {
     use reqwest::Client;
     use serde_json::json;
     use uuid::Uuid;
-    use shared::domain::{Appointment, NewInvitation};
+    use shared::domain::{Appointment, Invitation, InvitationCounts, IssueOutcome, IssuedInvitation, NewInvitation};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, ResponseTemplate};
     use super::*;
 
     fn get_appointment_data() -> serde_json::Value {
@@ -34,7 +36,7 @@ mod appointment_tests {
 
         let new_appoinment = get_appointment_data();
 
-        let response = client.post(&format!("{}/api/appointment", &application.address))
+        let response = client.post(format!("{}/api/appointment", &application.address))
             .json(&new_appoinment)
             .send()
             .await
@@ -58,7 +60,7 @@ mod appointment_tests {
 
         let new_appoinment = get_appointment_data();
 
-        let response = client.post(&format!("{}/api/appointment", &application.address))
+        let response = client.post(format!("{}/api/appointment", &application.address))
             .json(&new_appoinment)
             .send()
             .await
@@ -66,7 +68,7 @@ mod appointment_tests {
 
         let appointment_id: Uuid = response.json().await.expect("Failed to parse response");
 
-        let response = client.get(&format!("{}/api/appointment/{}", application.address, appointment_id.to_string()))
+        let response = client.get(format!("{}/api/appointment/{}", application.address, appointment_id))
             .send()
             .await
             .expect("Failed to fetch appointment");
@@ -88,7 +90,7 @@ mod appointment_tests {
 
         let new_appoinment = get_appointment_data();
 
-        let response = client.post(&format!("{}/api/appointment", &application.address))
+        let response = client.post(format!("{}/api/appointment", &application.address))
             .json(&new_appoinment)
             .send()
             .await
@@ -96,7 +98,7 @@ mod appointment_tests {
 
         let appointment_id: Uuid = response.json().await.expect("Failed to parse response");
 
-        let response = client.delete(&format!("{}/api/appointment/{}", application.address, appointment_id.to_string()))
+        let response = client.delete(format!("{}/api/appointment/{}", application.address, appointment_id))
             .send()
             .await
             .expect("Failed to delete student");
@@ -118,7 +120,7 @@ mod appointment_tests {
 
         let new_appoinment = get_appointment_data();
 
-        let response = client.post(&format!("{}/api/appointment", &application.address))
+        let response = client.post(format!("{}/api/appointment", &application.address))
             .json(&new_appoinment)
             .send()
             .await
@@ -126,9 +128,11 @@ mod appointment_tests {
 
         let appointment_id: Uuid = response.json().await.expect("Failed to parse response");
 
+        application.mock_short_urls().await;
+
         let count = 1;
 
-        let response = client.post(&format!("{}/api/appointment/{}/invitation?count={}", application.address, appointment_id.to_string(), count))
+        let response = client.post(format!("{}/api/appointment/{}/invitation?count={}", application.address, appointment_id, count))
             .json(&json!({}))
             .send()
             .await
@@ -140,4 +144,730 @@ mod appointment_tests {
 
         assert_eq!(new_invitation.len(), count);
     }
+
+    #[actix_web::test]
+    async fn test_get_missing_appointment_returns_404() {
+        let application = spawn_app().await;
+
+        let response = Client::new()
+            .get(format!("{}/api/appointment/{}", application.address, Uuid::new_v4()))
+            .send()
+            .await
+            .expect("Failed to fetch appointment");
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_list_appointments_with_filters() {
+        let application = spawn_app().await;
+        let client = Client::new();
+
+        let mut offline_appointment = get_appointment_data();
+        offline_appointment["title"] = json!("Offline appointment");
+        offline_appointment["format"] = json!("OFFLINE");
+        offline_appointment["date"] = json!("2024-12-10T10:10:00");
+
+        for appointment in [get_appointment_data(), offline_appointment] {
+            client.post(format!("{}/api/appointment", &application.address))
+                .json(&appointment)
+                .send()
+                .await
+                .expect("Failed to add new appointment");
+        }
+
+        let all: Vec<Appointment> = client.get(format!("{}/api/appointment", &application.address))
+            .send()
+            .await
+            .expect("Failed to list appointments")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        assert_eq!(all.len(), 2);
+
+        let response = client.get(format!("{}/api/appointment?format=OFFLINE&from=2024-11-01T00:00:00", &application.address))
+            .send()
+            .await
+            .expect("Failed to list appointments");
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let filtered: Vec<Appointment> = response.json().await.expect("Failed to parse response");
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered[0].title, "Offline appointment");
+    }
+
+    #[actix_web::test]
+    async fn test_get_invitations_by_appointment_id() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        let client = Client::new();
+
+        let appointment_id: Uuid = client.post(format!("{}/api/appointment", &application.address))
+            .json(&get_appointment_data())
+            .send()
+            .await
+            .expect("Failed to add new appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+
+        client.post(format!("{}/api/appointment/{}/invitation?count=3", application.address, appointment_id))
+            .json(&json!({}))
+            .send()
+            .await
+            .expect("Failed to add invitation to appointment");
+
+        let response = client.get(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .send()
+            .await
+            .expect("Failed to list invitations");
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let invitations: Vec<Invitation> = response.json().await.expect("Failed to parse response");
+        assert_eq!(invitations.len(), 3);
+        assert!(invitations.iter().all(|invitation| invitation.appointment_id == appointment_id && !invitation.used));
+
+        let counts: InvitationCounts = client.get(format!("{}/api/appointment/{}/invitation/counts", application.address, appointment_id))
+            .send()
+            .await
+            .expect("Failed to count invitations")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        assert_eq!((counts.total, counts.used, counts.unused, counts.declined), (3, 0, 3, 0));
+    }
+
+    #[actix_web::test]
+    async fn test_sent_emails_are_listed_as_recipients() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        application.mock_emails().await;
+        let client = Client::new();
+
+        let appointment_id: Uuid = client.post(format!("{}/api/appointment", &application.address))
+            .json(&get_appointment_data())
+            .send()
+            .await
+            .expect("Failed to add new appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+
+        let response = client.post(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .json(&json!({ "email": ["first@example.com", "second@example.com"] }))
+            .send()
+            .await
+            .expect("Failed to add invitation to appointment");
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = client.get(format!("{}/api/appointment/{}/recipients", application.address, appointment_id))
+            .send()
+            .await
+            .expect("Failed to list recipients");
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let recipients: Vec<String> = response.json().await.expect("Failed to parse response");
+        assert_eq!(recipients, vec!["first@example.com", "second@example.com"]);
+    }
+
+    #[actix_web::test]
+    async fn test_invitations_are_kept_when_emailing_them_fails() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        Mock::given(method("POST"))
+            .and(path("/email"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&application.email_server)
+            .await;
+        let client = Client::new();
+
+        let appointment_id: Uuid = client.post(format!("{}/api/appointment", &application.address))
+            .json(&get_appointment_data())
+            .send()
+            .await
+            .expect("Failed to add new appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+
+        let response = client.post(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .json(&json!({ "email": ["first@example.com"] }))
+            .send()
+            .await
+            .expect("Failed to add invitation to appointment");
+        assert_eq!(response.status(), StatusCode::OK);
+        let issued: Vec<IssuedInvitation> = response.json().await.expect("Failed to parse response");
+        assert!(issued[0].email_error.is_some());
+
+        // The seat was taken before emailing, the invitation can be resent.
+        let invitations: Vec<Invitation> = client.get(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .send()
+            .await
+            .expect("Failed to list invitations")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        assert_eq!(invitations.len(), 1);
+        assert!(invitations[0].attendee_id.is_some());
+    }
+
+    #[actix_web::test]
+    async fn test_rejected_emails_of_a_batch_are_reported_per_recipient() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        Mock::given(method("POST"))
+            .and(path("/email/batch"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+                { "ErrorCode": 0, "Message": "OK", "To": "first@example.com" },
+                { "ErrorCode": 406, "Message": "Inactive recipient", "To": "second@example.com" }
+            ])))
+            .mount(&application.email_server)
+            .await;
+        let client = Client::new();
+
+        let appointment_id: Uuid = client.post(format!("{}/api/appointment", &application.address))
+            .json(&get_appointment_data())
+            .send()
+            .await
+            .expect("Failed to add new appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+
+        let response = client.post(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .json(&json!({ "email": ["first@example.com", "second@example.com"] }))
+            .send()
+            .await
+            .expect("Failed to add invitation to appointment");
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let issued: Vec<IssuedInvitation> = response.json().await.expect("Failed to parse response");
+        assert_eq!(issued.len(), 2);
+        assert_eq!(issued[0].email_error, None);
+        assert!(issued[1].email_error.as_deref().unwrap().contains("Inactive recipient"));
+
+        // Only the email that was sent is logged.
+        let receivers: Vec<String> = sqlx::query_scalar("SELECT receiver FROM emails")
+            .fetch_all(&application.db_pool)
+            .await
+            .expect("Failed to list sent emails");
+        assert_eq!(receivers, vec!["first@example.com"]);
+    }
+
+    #[actix_web::test]
+    async fn test_invitations_beyond_capacity_are_rejected() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        let client = Client::new();
+
+        let mut new_appointment = get_appointment_data();
+        new_appointment["capacity"] = json!(3);
+        let appointment_id: Uuid = client.post(format!("{}/api/appointment", &application.address))
+            .json(&new_appointment)
+            .send()
+            .await
+            .expect("Failed to add new appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+
+        let response = client.post(format!("{}/api/appointment/{}/invitation?count=2", application.address, appointment_id))
+            .json(&json!({}))
+            .send()
+            .await
+            .expect("Failed to add invitation to appointment");
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = client.post(format!("{}/api/appointment/{}/invitation?count=2", application.address, appointment_id))
+            .json(&json!({}))
+            .send()
+            .await
+            .expect("Failed to add invitation to appointment");
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert!(response.text().await.unwrap().contains("1 seat(s) left"));
+
+        let appointment: Appointment = client.get(format!("{}/api/appointment/{}", application.address, appointment_id))
+            .send()
+            .await
+            .expect("Failed to fetch appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        assert_eq!(appointment.capacity, Some(3));
+        assert_eq!(appointment.remaining_seats, Some(1));
+    }
+
+    #[actix_web::test]
+    async fn test_invalid_invitation_count_is_rejected() {
+        let application = spawn_app().await;
+        let client = Client::new();
+
+        let appointment_id: Uuid = client.post(format!("{}/api/appointment", &application.address))
+            .json(&get_appointment_data())
+            .send()
+            .await
+            .expect("Failed to add new appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+
+        for count in ["0", "-5", "100000"] {
+            let response = client.post(format!("{}/api/appointment/{}/invitation?count={}", application.address, appointment_id, count))
+                .json(&json!({}))
+                .send()
+                .await
+                .expect("Failed to add invitation to appointment");
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+        assert!(application.shortener_server.received_requests().await.unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_invitation_for_missing_appointment_returns_404() {
+        let application = spawn_app().await;
+        let client = Client::new();
+
+        let response = client.post(format!("{}/api/appointment/{}/invitation?count=2", application.address, Uuid::new_v4()))
+            .json(&json!({}))
+            .send()
+            .await
+            .expect("Failed to add invitation to appointment");
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert!(application.shortener_server.received_requests().await.unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_invalid_appointment_is_rejected() {
+        let application = spawn_app().await;
+        let client = Client::new();
+
+        let mut new_appointment = get_appointment_data();
+        new_appointment["capacity"] = json!(0);
+        let response = client.post(format!("{}/api/appointment", &application.address))
+            .json(&new_appointment)
+            .send()
+            .await
+            .expect("Failed to add new appointment");
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_qr_archive_lists_active_invitations_and_resumes() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        application.mock_emails().await;
+        let client = Client::new();
+
+        let appointment_id: Uuid = client.post(format!("{}/api/appointment", &application.address))
+            .json(&get_appointment_data())
+            .send()
+            .await
+            .expect("Failed to add new appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        let invitations: Vec<NewInvitation> = client.post(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .json(&json!({ "attendees": [
+                { "name": "Ada Lovelace", "email": "ada@example.com" },
+                { "email": "grace@example.com" },
+                { "email": "alan@example.com" }
+            ] }))
+            .send()
+            .await
+            .expect("Failed to add invitations to appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        client.post(format!("{}/api/invitation/{}/revoke", application.address, invitations[2].id))
+            .send()
+            .await
+            .expect("Failed to revoke invitation");
+
+        let archive_url = format!("{}/api/appointment/{}/qr.zip", application.address, appointment_id);
+        let response = client.get(&archive_url)
+            .send()
+            .await
+            .expect("Failed to download archive");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "application/zip");
+        let etag = response.headers()["etag"].to_str().unwrap().to_string();
+        let archive = response.bytes().await.unwrap();
+
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(archive.to_vec())).unwrap();
+        assert_eq!(zip.len(), 3);
+        let mut manifest = String::new();
+        std::io::Read::read_to_string(&mut zip.by_name("manifest.csv").unwrap(), &mut manifest).unwrap();
+        let lines: Vec<&str> = manifest.lines().collect();
+        assert_eq!(lines[0], "file,invitation_id,short_url,max_admissions,attendee_name,attendee_email");
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().any(|line| line.starts_with(&format!("{}.png,{}", invitations[0].id, invitations[0].id)) && line.ends_with("Ada Lovelace,ada@example.com")));
+        assert!(!manifest.contains(&invitations[2].id.to_string()));
+        assert!(zip.by_name(&format!("{}.png", invitations[1].id)).is_ok());
+
+        let response = client.get(&archive_url)
+            .header("Range", "bytes=100-")
+            .header("If-Range", &etag)
+            .send()
+            .await
+            .expect("Failed to resume archive");
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            response.headers()["content-range"].to_str().unwrap(),
+            format!("bytes 100-{}/{}", archive.len() - 1, archive.len())
+        );
+        assert_eq!(response.bytes().await.unwrap(), archive[100..]);
+
+        let response = client.get(&archive_url)
+            .header("Range", "bytes=100-")
+            .header("If-Range", "\"outdated\"")
+            .send()
+            .await
+            .expect("Failed to resume archive");
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = client.get(format!("{}?format=pdf", archive_url))
+            .send()
+            .await
+            .expect("Failed to download archive");
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(response.bytes().await.unwrap().to_vec())).unwrap();
+        assert!(zip.by_name(&format!("ticket-{}.pdf", invitations[0].id)).is_ok());
+
+        // A range of an archive never downloaded before is rendered into the cache first.
+        let svg_url = format!("{}?format=svg", archive_url);
+        let response = client.get(&svg_url)
+            .header("Range", "bytes=0-99")
+            .send()
+            .await
+            .expect("Failed to download archive");
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        let etag = response.headers()["etag"].to_str().unwrap().to_string();
+        let start = response.bytes().await.unwrap();
+        let response = client.get(&svg_url)
+            .header("Range", "bytes=100-")
+            .header("If-Range", &etag)
+            .send()
+            .await
+            .expect("Failed to resume archive");
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        let archive = [start.to_vec(), response.bytes().await.unwrap().to_vec()].concat();
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(archive)).unwrap();
+        assert!(zip.by_name(&format!("{}.svg", invitations[0].id)).is_ok());
+
+        let response = client.get(format!("{}?format=gif", archive_url))
+            .send()
+            .await
+            .expect("Failed to download archive");
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_retried_appointment_with_idempotency_key_is_created_once() {
+        let application = spawn_app().await;
+        let client = Client::new();
+
+        let mut ids = vec![];
+        for _ in 0..2 {
+            let response = client.post(format!("{}/api/appointment", &application.address))
+                .header("Idempotency-Key", "create-conference")
+                .json(&get_appointment_data())
+                .send()
+                .await
+                .expect("Failed to add new appointment");
+            assert_eq!(response.status(), StatusCode::OK);
+            ids.push(response.json::<Uuid>().await.expect("Failed to parse response"));
+        }
+        assert_eq!(ids[0], ids[1]);
+
+        let appointments: Vec<Appointment> = client.get(format!("{}/api/appointment", &application.address))
+            .send()
+            .await
+            .expect("Failed to list appointments")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        assert_eq!(appointments.len(), 1);
+
+        let mut other = get_appointment_data();
+        other["title"] = json!("Another appointment");
+        let response = client.post(format!("{}/api/appointment", &application.address))
+            .header("Idempotency-Key", "create-conference")
+            .json(&other)
+            .send()
+            .await
+            .expect("Failed to add new appointment");
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+
+    #[actix_web::test]
+    async fn test_retried_invitation_with_idempotency_key_is_replayed() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        application.mock_emails().await;
+        let client = Client::new();
+
+        let appointment_id: Uuid = client.post(format!("{}/api/appointment", &application.address))
+            .json(&get_appointment_data())
+            .send()
+            .await
+            .expect("Failed to add new appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+
+        let send = |key: &'static str, emails: Vec<&'static str>| client
+            .post(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .header("Idempotency-Key", key)
+            .json(&json!({ "email": emails }))
+            .send();
+
+        let first = send("batch-1", vec!["first@example.com", "second@example.com"]).await.unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+        assert!(first.headers().get("Idempotent-Replayed").is_none());
+        let first: Vec<NewInvitation> = first.json().await.unwrap();
+
+        let retry = send("batch-1", vec!["first@example.com", "second@example.com"]).await.unwrap();
+        assert_eq!(retry.status(), StatusCode::OK);
+        assert_eq!(retry.headers().get("Idempotent-Replayed").unwrap(), "true");
+        let retry: Vec<NewInvitation> = retry.json().await.unwrap();
+
+        assert_eq!(
+            first.iter().map(|invitation| invitation.id).collect::<Vec<_>>(),
+            retry.iter().map(|invitation| invitation.id).collect::<Vec<_>>()
+        );
+        assert_eq!(application.sent_emails().await.len(), 2);
+
+        let reused = send("batch-1", vec!["third@example.com"]).await.unwrap();
+        assert_eq!(reused.status(), StatusCode::CONFLICT);
+        // Not worth retrying, unlike a request still in progress.
+        assert!(reused.headers().get("Retry-After").is_none());
+
+        let invitations: Vec<Invitation> = client.get(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(invitations.len(), 2);
+    }
+
+    #[actix_web::test]
+    async fn test_retry_during_the_first_request_is_asked_to_come_back() {
+        let application = spawn_app().await;
+        application.mock_emails().await;
+        // A slow shortener keeps the first request running.
+        Mock::given(method("POST"))
+            .and(path("/short_url/hash"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "hash": "testhash",
+                    "short_url": "https://short.url/testhash",
+                    "long_url": "https://www.example.com"
+                }))
+                .set_delay(Duration::from_millis(1000)))
+            .mount(&application.shortener_server)
+            .await;
+        let client = Client::new();
+
+        let appointment_id: Uuid = client.post(format!("{}/api/appointment", &application.address))
+            .json(&get_appointment_data())
+            .send()
+            .await
+            .expect("Failed to add new appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+
+        let send = || client
+            .post(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .header("Idempotency-Key", "slow-batch")
+            .json(&json!({ "email": ["first@example.com"] }))
+            .send();
+
+        let first = send();
+        let retry = async {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            send().await
+        };
+        let (first, retry) = tokio::join!(first, retry);
+        assert_eq!(first.unwrap().status(), StatusCode::OK);
+        let retry = retry.unwrap();
+        assert_eq!(retry.status(), StatusCode::CONFLICT);
+        assert_eq!(retry.headers().get("Retry-After").unwrap(), "1");
+
+        let replayed = send().await.unwrap();
+        assert_eq!(replayed.status(), StatusCode::OK);
+        assert_eq!(replayed.headers().get("Idempotent-Replayed").unwrap(), "true");
+    }
+
+    #[actix_web::test]
+    async fn test_failed_request_releases_its_idempotency_key() {
+        let application = spawn_app().await;
+        let client = Client::new();
+
+        let response = client.post(format!("{}/api/appointment/{}/invitation?count=1", application.address, Uuid::new_v4()))
+            .header("Idempotency-Key", "missing-appointment")
+            .json(&json!({}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // Not a conflict: the key was released along with the error.
+        let response = client.post(format!("{}/api/appointment/{}/invitation?count=1", application.address, Uuid::new_v4()))
+            .header("Idempotency-Key", "missing-appointment")
+            .json(&json!({}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_request_failing_after_its_commit_keeps_its_idempotency_key() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        application.mock_emails().await;
+        let client = Client::new();
+
+        let appointment_id: Uuid = client.post(format!("{}/api/appointment", &application.address))
+            .json(&get_appointment_data())
+            .send()
+            .await
+            .expect("Failed to add new appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+
+        // The invitations are committed and emailed, but logging the emails fails.
+        sqlx::query("ALTER TABLE Emails ADD CONSTRAINT no_emails CHECK (false) NOT VALID")
+            .execute(&application.db_pool)
+            .await
+            .unwrap();
+
+        let send = || client
+            .post(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .header("Idempotency-Key", "unlogged-batch")
+            .json(&json!({ "email": ["first@example.com"] }))
+            .send();
+
+        let first = send().await.unwrap();
+        assert_eq!(first.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(first.headers().get("Idempotent-Replayed").is_none());
+        let first = first.text().await.unwrap();
+
+        // The retry gets the same error instead of inviting and emailing again.
+        let retry = send().await.unwrap();
+        assert_eq!(retry.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(retry.headers().get("Idempotent-Replayed").unwrap(), "true");
+        assert_eq!(retry.text().await.unwrap(), first);
+        assert_eq!(application.sent_emails().await.len(), 1);
+
+        let invitations: Vec<Invitation> = client.get(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(invitations.len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn test_known_recipients_keep_their_invitation() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        application.mock_emails().await;
+        let client = Client::new();
+
+        let mut new_appointment = get_appointment_data();
+        new_appointment["capacity"] = json!(2);
+        let appointment_id: Uuid = client.post(format!("{}/api/appointment", &application.address))
+            .json(&new_appointment)
+            .send()
+            .await
+            .expect("Failed to add new appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+
+        let first: Vec<IssuedInvitation> = client.post(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .json(&json!({ "email": ["first@example.com", "second@example.com"] }))
+            .send()
+            .await
+            .expect("Failed to add invitation to appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        assert!(first.iter().all(|issued| issued.outcome == IssueOutcome::CREATED));
+
+        // The appointment is full, but nobody new is invited.
+        let response = client.post(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .json(&json!({ "email": ["Second@Example.com", "first@example.com"] }))
+            .send()
+            .await
+            .expect("Failed to add invitation to appointment");
+        assert_eq!(response.status(), StatusCode::OK);
+        let second: Vec<IssuedInvitation> = response.json().await.expect("Failed to parse response");
+        assert!(second.iter().all(|issued| issued.outcome == IssueOutcome::EXISTING));
+        assert_eq!(second[0].invitation.id, first[1].invitation.id);
+        assert_eq!(second[1].invitation.id, first[0].invitation.id);
+        assert_eq!(application.sent_emails().await.len(), 2);
+
+        let resent: Vec<IssuedInvitation> = client.post(format!("{}/api/appointment/{}/invitation?resend_existing=true", application.address, appointment_id))
+            .json(&json!({ "email": ["first@example.com"] }))
+            .send()
+            .await
+            .expect("Failed to add invitation to appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        assert_eq!(resent[0].outcome, IssueOutcome::EXISTING);
+        assert_eq!(resent[0].invitation.id, first[0].invitation.id);
+        let emails = application.sent_emails().await;
+        assert_eq!(emails.len(), 3);
+        assert_eq!(emails[2]["To"], "first@example.com");
+
+        let invitations: Vec<Invitation> = client.get(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .send()
+            .await
+            .expect("Failed to fetch invitations")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        assert_eq!(invitations.len(), 2);
+    }
+
+    #[actix_web::test]
+    async fn test_repeated_email_in_one_request_is_invited_once() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        application.mock_emails().await;
+        let client = Client::new();
+
+        let appointment_id: Uuid = client.post(format!("{}/api/appointment", &application.address))
+            .json(&get_appointment_data())
+            .send()
+            .await
+            .expect("Failed to add new appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+
+        let issued: Vec<IssuedInvitation> = client.post(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .json(&json!({ "email": ["first@example.com", "FIRST@example.com"] }))
+            .send()
+            .await
+            .expect("Failed to add invitation to appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        assert_eq!(issued.len(), 2);
+        assert_eq!(issued[0].outcome, IssueOutcome::CREATED);
+        assert_eq!(issued[1].outcome, IssueOutcome::EXISTING);
+        assert_eq!(issued[0].invitation.id, issued[1].invitation.id);
+        assert_eq!(application.sent_emails().await.len(), 1);
+    }
 }
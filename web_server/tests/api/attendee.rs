@@ -0,0 +1,78 @@
+use crate::helpers::spawn_app;
+
+#[cfg(test)]
+mod attendee_tests {
+    use actix_web::http::StatusCode;
+    use chrono::{Duration, Utc};
+    use reqwest::Client;
+    use serde_json::json;
+    use uuid::Uuid;
+    use shared::domain::{AppointmentWithInvitation, AttendeeWithInvitation, NewInvitation};
+    use super::*;
+
+    fn get_offline_appointment_data() -> serde_json::Value {
+        json!({
+            "title": "Test appoinment",
+            "description": "Some test desctiption",
+            "format": "OFFLINE",
+            "address": "123 Fake St.",
+            "link": null,
+            "date": (Utc::now() + Duration::days(7)).naive_utc(),
+            "duration": 6000
+        })
+    }
+
+    #[actix_web::test]
+    async fn test_invite_attendees() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        application.mock_emails().await;
+        let client = Client::new();
+
+        let appointment_id: Uuid = client.post(format!("{}/api/appointment", &application.address))
+            .json(&get_offline_appointment_data())
+            .send()
+            .await
+            .expect("Failed to add new appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+
+        let response = client.post(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .json(&json!({ "attendees": [
+                { "name": "Ada <Lovelace>", "email": "ada@example.com", "phone": "+380000000000", "custom_fields": { "locale": "en" } },
+                { "email": "grace@example.com" }
+            ] }))
+            .send()
+            .await
+            .expect("Failed to add invitation to appointment");
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let invitations: Vec<NewInvitation> = response.json().await.expect("Failed to parse response");
+        assert!(invitations.iter().all(|invitation| invitation.attendee_id.is_some()));
+
+        // The greeting is HTML-escaped in the email body.
+        let bodies = application.sent_emails().await;
+        assert!(bodies.iter().any(|body| body["HtmlBody"].as_str().unwrap().contains("Dear Ada &lt;Lovelace&gt;,")));
+        assert!(bodies.iter().any(|body| body["TextBody"].as_str().unwrap().starts_with("Hello grace@example.com.")));
+
+        let response = client.get(format!("{}/api/appointment/{}/attendee?q=ada", application.address, appointment_id))
+            .send()
+            .await
+            .expect("Failed to list attendees");
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let attendees: Vec<AttendeeWithInvitation> = response.json().await.expect("Failed to parse response");
+        assert_eq!(attendees.len(), 1);
+        assert_eq!(attendees[0].attendee.name.as_deref(), Some("Ada <Lovelace>"));
+        assert_eq!(attendees[0].attendee.custom_fields, json!({ "locale": "en" }));
+
+        let response = application.check_in(attendees[0].invitation_id.unwrap(), json!({})).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let validated: AppointmentWithInvitation = response.json().await.expect("Failed to parse response");
+        let attendee = validated.attendee.expect("Attendee is missing");
+        assert_eq!(attendee.email.as_ref(), "ada@example.com");
+        assert_eq!(attendee.phone.as_deref(), Some("+380000000000"));
+    }
+}
@@ -1,13 +1,16 @@
 use std::net::TcpListener;
 use sqlx::{Connection, PgConnection, PgPool, Executor};
 use uuid::Uuid;
-use shared::configuration::{DatabaseSettings, get_configuration};
+use secrecy::Secret;
+use shared::configuration::{DatabaseSettings, ScannerSettings, get_configuration};
 use web_server::startup::run;
 use once_cell::sync::Lazy;
 use tracing::metadata::LevelFilter;
 use shared::email_client::EmailClient;
 use shared::qr_client::QRClient;
 use web_server::telemetry::{setup_subscriber, init_subscriber};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
 
 static APP_TRACING: Lazy<()> = Lazy::new(|| {
     let default_filter_level = LevelFilter::DEBUG.to_string();
@@ -23,7 +26,83 @@ static APP_TRACING: Lazy<()> = Lazy::new(|| {
 
 pub struct TestApp {
     pub address: String,
+    #[allow(dead_code)]
     pub db_pool: PgPool,
+    /// Stands in for the Postmark API.
+    #[allow(dead_code)]
+    pub email_server: MockServer,
+    /// Stands in for the apilayer URL shortener.
+    pub shortener_server: MockServer,
+    /// Bearer token of the door scanner named `north-gate`.
+    #[allow(dead_code)]
+    pub scanner_token: String,
+}
+
+impl TestApp {
+    /// Makes the mocked shortener answer every request with a short URL.
+    pub async fn mock_short_urls(&self) {
+        Mock::given(method("POST"))
+            .and(path("/short_url/hash"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "hash": "testhash",
+                "short_url": "https://short.url/testhash",
+                "long_url": "https://www.example.com"
+            })))
+            .mount(&self.shortener_server)
+            .await;
+    }
+
+    /// Checks someone in or out with an invitation, as a door scanner.
+    #[allow(dead_code)]
+    pub async fn check_in(&self, invitation_id: Uuid, scan: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/api/validations/{}/check-in", self.address, invitation_id))
+            .bearer_auth(&self.scanner_token)
+            .json(&scan)
+            .send()
+            .await
+            .expect("Failed to check in invitation")
+    }
+
+    /// Makes the mocked Postmark API accept every email, sent alone or in a batch.
+    #[allow(dead_code)]
+    pub async fn mock_emails(&self) {
+        Mock::given(method("POST"))
+            .and(path("/email"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&self.email_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/email/batch"))
+            .respond_with(AcceptBatch)
+            .mount(&self.email_server)
+            .await;
+    }
+
+    /// Bodies of the emails sent, those of batches included, in order.
+    #[allow(dead_code)]
+    pub async fn sent_emails(&self) -> Vec<serde_json::Value> {
+        self.email_server.received_requests().await.unwrap()
+            .iter()
+            .flat_map(|request| match serde_json::from_slice(&request.body).unwrap() {
+                serde_json::Value::Array(emails) => emails,
+                email => vec![email],
+            })
+            .collect()
+    }
+}
+
+/// Answers a Postmark batch with a success for every message of it.
+struct AcceptBatch;
+
+impl Respond for AcceptBatch {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let emails: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap_or_default();
+        let results: Vec<serde_json::Value> = emails.iter()
+            .map(|email| serde_json::json!({ "ErrorCode": 0, "Message": "OK", "To": email["To"] }))
+            .collect();
+        ResponseTemplate::new(200).set_body_json(results)
+    }
 }
 
 pub async fn spawn_app() -> TestApp {
@@ -33,8 +112,22 @@ pub async fn spawn_app() -> TestApp {
     let port = listener.local_addr().unwrap().port();
     let address = format!("http://127.0.0.1:{}", port);
 
+    let email_server = MockServer::start().await;
+    let shortener_server = MockServer::start().await;
+
     let mut configuration = get_configuration().expect("Failed to read configuration.");
     configuration.database.database_name = Uuid::new_v4().to_string();
+    configuration.application.base_url = address.clone();
+    configuration.email_client.base_url = email_server.uri();
+    configuration.qr_client.api_url = format!("{}/short_url/hash", shortener_server.uri());
+    let scanner_token = Uuid::new_v4().to_string();
+    configuration.application.scanners = vec![ScannerSettings {
+        name: "north-gate".to_string(),
+        token: Secret::new(scanner_token.clone()),
+    }];
+    // Jobs issue hundreds of invitations, the mock servers don't limit the rate.
+    configuration.qr_client.limits.requests_per_second = 0.0;
+    configuration.email_client.limits.requests_per_second = 0.0;
     let pg_pool = configure_database(&configuration.database).await;
 
     let sender_email = configuration
@@ -48,7 +141,7 @@ pub async fn spawn_app() -> TestApp {
         sender_email,
         configuration.email_client.authorization_token,
         timeout,
-    );
+    ).with_limits(&configuration.email_client.limits);
 
     let qr_client = QRClient::new(
         configuration.qr_client.api_url.clone(),
@@ -56,14 +149,25 @@ pub async fn spawn_app() -> TestApp {
         configuration.qr_client.base_url.clone(),
         configuration.qr_client.base_image_path.clone(),
         configuration.qr_client.timeout()
-    );
+    ).with_limits(&configuration.qr_client.limits)
+        .with_rendering(&configuration.qr_client.rendering);
+    let qr_client = match &configuration.qr_client.ticket_keys {
+        Some(keys) => qr_client.with_ticket_keys(
+            keys.signer().expect("Invalid ticket signing key."),
+            keys.retired_keys.clone()
+        ),
+        None => qr_client,
+    };
 
-    let server = run(listener, pg_pool.clone(), qr_client, email_client).expect("Failed to bind address");
-    let _ = tokio::spawn(server);
+    let server = run(listener, pg_pool.clone(), qr_client, email_client, configuration.application).expect("Failed to bind address");
+    tokio::spawn(server);
 
     TestApp {
         address,
         db_pool: pg_pool,
+        email_server,
+        shortener_server,
+        scanner_token,
     }
 }
 
@@ -81,7 +185,11 @@ pub async fn configure_database(config: &DatabaseSettings) -> PgPool {
         .expect("Failed to create database.");
 
     // Migrate database
-    let connection_pool = PgPool::connect_with(config.connection_options_with_db())
+    // The server of a finished test keeps running on its own threads, and its
+    // pool with it, so Postgres closes the idle connections instead.
+    let connection_options = config.connection_options_with_db()
+        .options([("idle_session_timeout", "10s")]);
+    let connection_pool = PgPool::connect_with(connection_options)
         .await
         .expect("Failed to connect to Postgres.");
 
@@ -0,0 +1,751 @@
+use crate::helpers::{spawn_app, TestApp};
+
+#[cfg(test)]
+mod invitation_tests {
+    use actix_web::http::StatusCode;
+    use chrono::{Duration, Utc};
+    use reqwest::Client;
+    use serde_json::json;
+    use sqlx::Executor;
+    use uuid::Uuid;
+    use shared::domain::{Appointment, AppointmentWithInvitation, AttendeeWithInvitation, Invitation, InvitationAction, InvitationHistory, InvitationStatus, IssueOutcome, IssuedInvitation, NewInvitation};
+    use shared::ticket::{IssuedTicket, PublicTicketKey, TicketVerifier};
+    use super::*;
+
+    async fn add_invitation(application: &TestApp, client: &Client) -> (Uuid, NewInvitation) {
+        let appointment_id: Uuid = client.post(format!("{}/api/appointment", &application.address))
+            .json(&json!({
+                "title": "Test appoinment",
+                "description": "Some test desctiption",
+                "format": "OFFLINE",
+                "address": "123 Fake St.",
+                "link": null,
+                "date": (Utc::now() + Duration::days(7)).naive_utc(),
+                "duration": 6000,
+                "capacity": 1
+            }))
+            .send()
+            .await
+            .expect("Failed to add new appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+
+        let invitations: Vec<NewInvitation> = client.post(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .json(&json!({ "email": ["first@example.com"] }))
+            .send()
+            .await
+            .expect("Failed to add invitation to appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+
+        (appointment_id, invitations.into_iter().next().unwrap())
+    }
+
+    async fn get_history(application: &TestApp, client: &Client, invitation_id: Uuid) -> Vec<InvitationHistory> {
+        client.get(format!("{}/api/invitation/{}/history", application.address, invitation_id))
+            .send()
+            .await
+            .expect("Failed to fetch invitation history")
+            .json()
+            .await
+            .expect("Failed to parse response")
+    }
+
+    async fn sent_emails(application: &TestApp) -> Vec<serde_json::Value> {
+        application.email_server.received_requests().await.unwrap()
+            .iter()
+            .map(|request| serde_json::from_slice(&request.body).unwrap())
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn test_get_missing_invitation_returns_404() {
+        let application = spawn_app().await;
+
+        let response = Client::new()
+            .get(format!("{}/api/invitation/{}", application.address, Uuid::new_v4()))
+            .send()
+            .await
+            .expect("Failed to fetch invitation");
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_revoked_invitation_fails_validation() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        application.mock_emails().await;
+        let client = Client::new();
+
+        let (_, invitation) = add_invitation(&application, &client).await;
+
+        let response = client.post(format!("{}/api/invitation/{}/revoke", application.address, invitation.id))
+            .json(&json!({ "reason": "ticket was shared publicly" }))
+            .send()
+            .await
+            .expect("Failed to revoke invitation");
+        assert_eq!(response.status(), StatusCode::OK);
+        let revoked: Invitation = response.json().await.expect("Failed to parse response");
+        assert_eq!(revoked.status, InvitationStatus::REVOKED);
+
+        let response = application.check_in(invitation.id, json!({})).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(response.text().await.unwrap().contains("revoked"));
+
+        let history = get_history(&application, &client, invitation.id).await;
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].action, InvitationAction::REVOKED);
+        assert_eq!(history[0].details["reason"], "ticket was shared publicly");
+
+        let response = client.post(format!("{}/api/invitation/{}/revoke", application.address, invitation.id))
+            .send()
+            .await
+            .expect("Failed to revoke invitation");
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+
+    #[actix_web::test]
+    async fn test_reissued_invitation_replaces_old_code() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        application.mock_emails().await;
+        let client = Client::new();
+
+        let (_, invitation) = add_invitation(&application, &client).await;
+
+        let response = client.post(format!("{}/api/invitation/{}/reissue", application.address, invitation.id))
+            .send()
+            .await
+            .expect("Failed to reissue invitation");
+        assert_eq!(response.status(), StatusCode::OK);
+        let replacement: NewInvitation = response.json().await.expect("Failed to parse response");
+        assert_ne!(replacement.id, invitation.id);
+        assert_eq!(replacement.attendee_id, invitation.attendee_id);
+
+        let response = application.check_in(invitation.id, json!({})).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = application.check_in(replacement.id, json!({})).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let emails = sent_emails(&application).await;
+        assert_eq!(emails.len(), 2);
+        assert_eq!(emails[1]["To"], "first@example.com");
+        assert_eq!(emails[1]["Subject"], "Your new invitation");
+
+        let history = get_history(&application, &client, invitation.id).await;
+        assert_eq!(history[0].action, InvitationAction::REISSUED);
+        assert_eq!(history[0].details["replaced_by"], replacement.id.to_string());
+        let history = get_history(&application, &client, replacement.id).await;
+        assert_eq!(history[0].action, InvitationAction::REPLACEMENT);
+    }
+
+    #[actix_web::test]
+    async fn test_transferred_invitation_notifies_both_parties() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        application.mock_emails().await;
+        let client = Client::new();
+
+        let (appointment_id, invitation) = add_invitation(&application, &client).await;
+
+        let response = client.post(format!("{}/api/invitation/{}/transfer", application.address, invitation.id))
+            .json(&json!({ "email": "second@example.com", "name": "Grace", "reason": "first can't make it" }))
+            .send()
+            .await
+            .expect("Failed to transfer invitation");
+        assert_eq!(response.status(), StatusCode::OK);
+        let replacement: NewInvitation = response.json().await.expect("Failed to parse response");
+
+        let emails = sent_emails(&application).await;
+        let recipients: Vec<&str> = emails.iter().map(|email| email["To"].as_str().unwrap()).collect();
+        assert_eq!(recipients, vec!["first@example.com", "second@example.com", "first@example.com"]);
+        assert_eq!(emails[2]["Subject"], "Your invitation has been transferred");
+        assert!(emails[2]["TextBody"].as_str().unwrap().contains("transferred to Grace"));
+
+        let attendees: Vec<AttendeeWithInvitation> = client.get(format!("{}/api/appointment/{}/attendee?q=second", application.address, appointment_id))
+            .send()
+            .await
+            .expect("Failed to fetch attendees")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        assert_eq!(attendees.len(), 1);
+        assert_eq!(replacement.attendee_id, Some(attendees[0].attendee.id));
+        assert_eq!(attendees[0].invitation_id, Some(replacement.id));
+
+        let history = get_history(&application, &client, invitation.id).await;
+        assert_eq!(history[0].action, InvitationAction::TRANSFERRED);
+        assert_eq!(history[0].details["to"], "second@example.com");
+        assert_eq!(history[0].details["reason"], "first can't make it");
+    }
+
+    #[actix_web::test]
+    async fn test_invitation_is_not_transferred_to_another_holder() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        application.mock_emails().await;
+        let client = Client::new();
+
+        let appointment_id: Uuid = client.post(format!("{}/api/appointment", &application.address))
+            .json(&json!({
+                "title": "Test appoinment",
+                "description": "Some test desctiption",
+                "format": "OFFLINE",
+                "address": "123 Fake St.",
+                "link": null,
+                "date": (Utc::now() + Duration::days(7)).naive_utc(),
+                "duration": 6000
+            }))
+            .send()
+            .await
+            .expect("Failed to add new appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        let invitations: Vec<NewInvitation> = client.post(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .json(&json!({ "email": ["first@example.com", "second@example.com"] }))
+            .send()
+            .await
+            .expect("Failed to add invitation to appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+
+        let response = client.post(format!("{}/api/invitation/{}/transfer", application.address, invitations[0].id))
+            .json(&json!({ "email": "Second@example.com", "name": "Grace" }))
+            .send()
+            .await
+            .expect("Failed to transfer invitation");
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        // Nothing was revoked along the way.
+        let invitation: Invitation = client.get(format!("{}/api/invitation/{}", application.address, invitations[0].id))
+            .send()
+            .await
+            .expect("Failed to fetch invitation")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        assert_eq!(invitation.status, InvitationStatus::ACTIVE);
+    }
+
+    #[actix_web::test]
+    async fn test_group_invitation_admits_up_to_its_limit() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        let client = Client::new();
+
+        let appointment_id: Uuid = client.post(format!("{}/api/appointment", &application.address))
+            .json(&json!({
+                "title": "Family day",
+                "description": "Some test desctiption",
+                "format": "OFFLINE",
+                "address": "123 Fake St.",
+                "link": null,
+                "date": (Utc::now() + Duration::days(7)).naive_utc(),
+                "duration": 6000,
+                "capacity": 4
+            }))
+            .send()
+            .await
+            .expect("Failed to add new appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+
+        let invitations: Vec<NewInvitation> = client.post(format!("{}/api/appointment/{}/invitation?max_admissions=3", application.address, appointment_id))
+            .json(&json!({}))
+            .send()
+            .await
+            .expect("Failed to add invitation to appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        let invitation = &invitations[0];
+        assert_eq!(invitation.max_admissions, 3);
+
+        let appointment: Appointment = client.get(format!("{}/api/appointment/{}", application.address, appointment_id))
+            .send()
+            .await
+            .expect("Failed to fetch appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        assert_eq!(appointment.remaining_seats, Some(1));
+
+        let response = client.post(format!("{}/api/appointment/{}/invitation?max_admissions=2", application.address, appointment_id))
+            .json(&json!({}))
+            .send()
+            .await
+            .expect("Failed to add invitation to appointment");
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        for admitted in 1..=3 {
+            let response = application.check_in(invitation.id, json!({})).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let scan: AppointmentWithInvitation = response.json().await.expect("Failed to parse response");
+            assert_eq!(scan.admission, format!("{} of 3 admitted", admitted));
+            assert_eq!(scan.remaining_admissions, 3 - admitted);
+        }
+
+        let response = application.check_in(invitation.id, json!({})).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(response.text().await.unwrap().contains("3 of 3 admitted"));
+
+        let page = client.get(format!("{}/api/invitation/{}/qr", application.address, invitation.id))
+            .send()
+            .await
+            .expect("Failed to fetch QR page")
+            .text()
+            .await
+            .unwrap();
+        assert!(page.contains("Admits 0 of 3 people"));
+    }
+
+    #[actix_web::test]
+    async fn test_signed_ticket_verifies_with_public_key_only() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        application.mock_emails().await;
+        let client = Client::new();
+
+        let (appointment_id, invitation) = add_invitation(&application, &client).await;
+
+        let response = client.get(format!("{}/api/invitation/{}/ticket", application.address, invitation.id))
+            .send()
+            .await
+            .expect("Failed to fetch ticket");
+        assert_eq!(response.status(), StatusCode::OK);
+        let issued: IssuedTicket = response.json().await.expect("Failed to parse response");
+        assert!(!issued.qr_code.is_empty());
+
+        let keys: Vec<PublicTicketKey> = client.get(format!("{}/api/ticket/keys", application.address))
+            .send()
+            .await
+            .expect("Failed to fetch ticket keys")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        let verifier = TicketVerifier::new(&keys).unwrap();
+        let claims = verifier.verify(&issued.ticket, Utc::now().naive_utc()).unwrap();
+        assert_eq!(claims.invitation_id, invitation.id);
+        assert_eq!(claims.appointment_id, appointment_id);
+        assert!(verifier.verify(&issued.ticket, Utc::now().naive_utc() + Duration::days(10)).is_err());
+
+        client.post(format!("{}/api/invitation/{}/revoke", application.address, invitation.id))
+            .send()
+            .await
+            .expect("Failed to revoke invitation");
+        let response = client.get(format!("{}/api/invitation/{}/ticket", application.address, invitation.id))
+            .send()
+            .await
+            .expect("Failed to fetch ticket");
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+
+    #[actix_web::test]
+    async fn test_ticket_pdf_is_printable_for_active_invitations() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        application.mock_emails().await;
+        let client = Client::new();
+
+        let (_, invitation) = add_invitation(&application, &client).await;
+
+        let response = client.get(format!("{}/api/invitation/{}/ticket.pdf", application.address, invitation.id))
+            .send()
+            .await
+            .expect("Failed to fetch ticket PDF");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "application/pdf");
+        let pdf = response.bytes().await.expect("Failed to read ticket PDF");
+        assert!(pdf.starts_with(b"%PDF"));
+
+        client.post(format!("{}/api/invitation/{}/revoke", application.address, invitation.id))
+            .send()
+            .await
+            .expect("Failed to revoke invitation");
+        let response = client.get(format!("{}/api/invitation/{}/ticket.pdf", application.address, invitation.id))
+            .send()
+            .await
+            .expect("Failed to fetch ticket PDF");
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let response = client.get(format!("{}/api/invitation/{}/ticket.pdf", application.address, Uuid::new_v4()))
+            .send()
+            .await
+            .expect("Failed to fetch ticket PDF");
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_qr_code_follows_appointment_options_and_accept_header() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        let client = Client::new();
+
+        let appointment = json!({
+            "title": "Styled codes",
+            "description": "Some test desctiption",
+            "format": "OFFLINE",
+            "address": "123 Fake St.",
+            "link": null,
+            "date": (Utc::now() + Duration::days(7)).naive_utc(),
+            "duration": 6000,
+            "qr_options": { "format": "svg", "foreground": "#1A237E", "background": "#FFFDE7", "quiet_zone": 2 }
+        });
+        let mut unreadable = appointment.clone();
+        unreadable["qr_options"]["foreground"] = json!("#FFFFFF");
+        let response = client.post(format!("{}/api/appointment", &application.address))
+            .json(&unreadable)
+            .send()
+            .await
+            .expect("Failed to add new appointment");
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let appointment_id: Uuid = client.post(format!("{}/api/appointment", &application.address))
+            .json(&appointment)
+            .send()
+            .await
+            .expect("Failed to add new appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        let invitations: Vec<NewInvitation> = client.post(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .json(&json!({}))
+            .send()
+            .await
+            .expect("Failed to add invitation to appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        let qr_url = format!("{}/api/invitation/{}/qr", application.address, invitations[0].id);
+
+        let page = client.get(&qr_url)
+            .header("Accept", "text/html,application/xhtml+xml,image/webp,*/*;q=0.8")
+            .send()
+            .await
+            .expect("Failed to fetch QR page")
+            .text()
+            .await
+            .unwrap();
+        assert!(page.contains("data:image/svg+xml;base64,"));
+
+        let response = client.get(&qr_url)
+            .header("Accept", "image/svg+xml")
+            .send()
+            .await
+            .expect("Failed to fetch QR code");
+        assert_eq!(response.headers()["content-type"], "image/svg+xml");
+        let svg = response.text().await.unwrap();
+        assert!(svg.contains(r##"fill="#1A237E""##));
+
+        let response = client.get(format!("{}?format=png&size=256", qr_url))
+            .header("Accept", "text/html")
+            .send()
+            .await
+            .expect("Failed to fetch QR code");
+        assert_eq!(response.headers()["content-type"], "image/png");
+        assert!(response.bytes().await.unwrap().starts_with(b"\x89PNG"));
+
+        let response = client.get(format!("{}?format=jpeg&foreground=%23FFFFFF", qr_url))
+            .send()
+            .await
+            .expect("Failed to fetch QR code");
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = client.get(format!("{}?format=gif", qr_url))
+            .send()
+            .await
+            .expect("Failed to fetch QR code");
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_invitation_is_resent_with_the_same_code() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        application.mock_emails().await;
+        let client = Client::new();
+
+        let (appointment_id, invitation) = add_invitation(&application, &client).await;
+
+        let response = client.post(format!("{}/api/invitation/{}/resend", application.address, invitation.id))
+            .send()
+            .await
+            .expect("Failed to resend invitation");
+        assert_eq!(response.status(), StatusCode::OK);
+        let resent: IssuedInvitation = response.json().await.expect("Failed to parse response");
+        assert_eq!(resent.invitation.id, invitation.id);
+        assert_eq!(resent.invitation.short_url, invitation.short_url);
+        assert_eq!(resent.outcome, IssueOutcome::EXISTING);
+
+        let response = client.post(format!("{}/api/invitation/{}/resend", application.address, invitation.id))
+            .json(&json!({ "email": "corrected@example.com" }))
+            .send()
+            .await
+            .expect("Failed to resend invitation");
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let emails = sent_emails(&application).await;
+        let recipients: Vec<&str> = emails.iter().map(|email| email["To"].as_str().unwrap()).collect();
+        assert_eq!(recipients, vec!["first@example.com", "first@example.com", "corrected@example.com"]);
+        assert_eq!(emails[2]["Subject"], "Your invitation");
+
+        let attendees: Vec<AttendeeWithInvitation> = client.get(format!("{}/api/appointment/{}/attendee", application.address, appointment_id))
+            .send()
+            .await
+            .expect("Failed to fetch attendees")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        assert_eq!(attendees.len(), 1);
+        assert_eq!(attendees[0].attendee.email.as_ref(), "corrected@example.com");
+
+        let history = get_history(&application, &client, invitation.id).await;
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].action, InvitationAction::RESENT);
+        assert_eq!(history[0].details["to"], "first@example.com");
+        assert!(history[0].details["corrected_from"].is_null());
+        assert_eq!(history[1].details["to"], "corrected@example.com");
+        assert_eq!(history[1].details["corrected_from"], "first@example.com");
+    }
+
+    #[actix_web::test]
+    async fn test_revoked_invitation_is_not_resent() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        application.mock_emails().await;
+        let client = Client::new();
+
+        let (_, invitation) = add_invitation(&application, &client).await;
+        client.post(format!("{}/api/invitation/{}/revoke", application.address, invitation.id))
+            .send()
+            .await
+            .expect("Failed to revoke invitation");
+
+        let response = client.post(format!("{}/api/invitation/{}/resend", application.address, invitation.id))
+            .send()
+            .await
+            .expect("Failed to resend invitation");
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let response = client.post(format!("{}/api/invitation/{}/resend", application.address, Uuid::new_v4()))
+            .send()
+            .await
+            .expect("Failed to resend invitation");
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(sent_emails(&application).await.len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn test_invitations_are_resent_to_everyone_not_checked_in() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        application.mock_emails().await;
+        let client = Client::new();
+
+        let appointment_id: Uuid = client.post(format!("{}/api/appointment", &application.address))
+            .json(&json!({
+                "title": "Test appoinment",
+                "description": "Some test desctiption",
+                "format": "OFFLINE",
+                "address": "123 Fake St.",
+                "link": null,
+                "date": (Utc::now() + Duration::days(7)).naive_utc(),
+                "duration": 6000
+            }))
+            .send()
+            .await
+            .expect("Failed to add new appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        let invitations: Vec<NewInvitation> = client.post(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .json(&json!({ "email": ["first@example.com", "second@example.com", "third@example.com", "fourth@example.com"] }))
+            .send()
+            .await
+            .expect("Failed to add invitation to appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        // Invitations issued without an attendee have nobody to be resent to.
+        client.post(format!("{}/api/appointment/{}/invitation?count=2", application.address, appointment_id))
+            .json(&json!({}))
+            .send()
+            .await
+            .expect("Failed to add invitation to appointment");
+
+        sqlx::query("UPDATE invitation SET admitted = 1 WHERE id = $1")
+            .bind(invitations[0].id)
+            .execute(&application.db_pool)
+            .await
+            .unwrap();
+        client.post(format!("{}/api/invitation/{}/revoke", application.address, invitations[1].id))
+            .send()
+            .await
+            .expect("Failed to revoke invitation");
+        sqlx::query("INSERT INTO online_joins (invitation_id, appointment_id) VALUES ($1, $2)")
+            .bind(invitations[3].id)
+            .bind(appointment_id)
+            .execute(&application.db_pool)
+            .await
+            .unwrap();
+
+        let response = client.post(format!("{}/api/appointment/{}/invitation/resend", application.address, appointment_id))
+            .send()
+            .await
+            .expect("Failed to resend invitations");
+        assert_eq!(response.status(), StatusCode::OK);
+        let resent: Vec<IssuedInvitation> = response.json().await.expect("Failed to parse response");
+        assert_eq!(resent.len(), 1);
+        assert_eq!(resent[0].invitation.id, invitations[2].id);
+        assert_eq!(resent[0].email.as_ref().unwrap().as_ref(), "third@example.com");
+
+        let emails = application.sent_emails().await;
+        assert_eq!(emails.len(), 5);
+        assert_eq!(emails[4]["To"], "third@example.com");
+        let history = get_history(&application, &client, invitations[2].id).await;
+        assert_eq!(history[0].action, InvitationAction::RESENT);
+
+        let response = client.post(format!("{}/api/appointment/{}/invitation/resend", application.address, Uuid::new_v4()))
+            .send()
+            .await
+            .expect("Failed to resend invitations");
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_retried_resend_only_emails_invitations_not_resent_yet() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        application.mock_emails().await;
+        let client = Client::new();
+
+        let appointment_id: Uuid = client.post(format!("{}/api/appointment", &application.address))
+            .json(&json!({
+                "title": "Test appoinment",
+                "description": "Some test desctiption",
+                "format": "OFFLINE",
+                "address": "123 Fake St.",
+                "link": null,
+                "date": (Utc::now() + Duration::days(7)).naive_utc(),
+                "duration": 6000
+            }))
+            .send()
+            .await
+            .expect("Failed to add new appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        let invitations: Vec<NewInvitation> = client.post(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .json(&json!({ "email": ["first@example.com", "second@example.com"] }))
+            .send()
+            .await
+            .expect("Failed to add invitation to appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        // As if a first try of the request resent one invitation before it failed.
+        sqlx::query("INSERT INTO invitation_history (invitation_id, action, details) VALUES ($1, 'RESENT', $2)")
+            .bind(invitations[0].id)
+            .bind(json!({ "to": "first@example.com", "idempotency_key": "resend-1" }))
+            .execute(&application.db_pool)
+            .await
+            .unwrap();
+
+        let resend = || client.post(format!("{}/api/appointment/{}/invitation/resend", application.address, appointment_id))
+            .header("Idempotency-Key", "resend-1")
+            .send();
+        let response = resend().await.expect("Failed to resend invitations");
+        assert_eq!(response.status(), StatusCode::OK);
+        let resent: Vec<IssuedInvitation> = response.json().await.expect("Failed to parse response");
+        assert_eq!(resent.iter().map(|issued| issued.invitation.id).collect::<Vec<_>>(), vec![invitations[1].id]);
+
+        let response = resend().await.expect("Failed to resend invitations");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get("Idempotent-Replayed").unwrap(), "true");
+        let replayed: Vec<IssuedInvitation> = response.json().await.expect("Failed to parse response");
+        assert_eq!(replayed.len(), 1);
+        assert_eq!(application.sent_emails().await.len(), 3);
+    }
+
+    #[actix_web::test]
+    async fn test_unique_active_invitation_migration_keeps_the_used_one() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        application.mock_emails().await;
+        let client = Client::new();
+
+        let appointment_id: Uuid = client.post(format!("{}/api/appointment", &application.address))
+            .json(&json!({
+                "title": "Test appoinment",
+                "description": "Some test desctiption",
+                "format": "OFFLINE",
+                "address": "123 Fake St.",
+                "link": null,
+                "date": (Utc::now() + Duration::days(7)).naive_utc(),
+                "duration": 6000
+            }))
+            .send()
+            .await
+            .expect("Failed to add new appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        let invitations: Vec<NewInvitation> = client.post(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .json(&json!({ "email": ["first@example.com", "second@example.com"] }))
+            .send()
+            .await
+            .expect("Failed to add invitation to appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        let (used, unused) = (invitations[0].id, invitations[1].id);
+        sqlx::query("UPDATE invitation SET used = true, admitted = 1 WHERE id = $1")
+            .bind(used)
+            .execute(&application.db_pool)
+            .await
+            .unwrap();
+
+        // Back to before the migration, when concurrent requests could issue
+        // another invitation to the same attendees.
+        sqlx::query("DROP INDEX invitation_active_attendee")
+            .execute(&application.db_pool)
+            .await
+            .unwrap();
+        let mut later = Vec::new();
+        for invitation_id in [used, unused] {
+            let later_id = Uuid::new_v4();
+            sqlx::query("INSERT INTO invitation (id, appointment_id, short_url, attendee_id, created_at) \
+                SELECT $2, appointment_id, short_url, attendee_id, created_at + interval '1 hour' FROM invitation WHERE id = $1")
+                .bind(invitation_id)
+                .bind(later_id)
+                .execute(&application.db_pool)
+                .await
+                .unwrap();
+            later.push(later_id);
+        }
+
+        application.db_pool
+            .execute(include_str!("../../../migrations/20240210090000_unique_active_invitation.sql"))
+            .await
+            .expect("Failed to run the migration");
+
+        let status = |invitation_id: Uuid| sqlx::query_scalar::<_, String>("SELECT status::text FROM invitation WHERE id = $1")
+            .bind(invitation_id)
+            .fetch_one(&application.db_pool);
+        // The older invitation was used, so the later one goes instead.
+        assert_eq!(status(used).await.unwrap(), "ACTIVE");
+        assert_eq!(status(later[0]).await.unwrap(), "REVOKED");
+        // Neither was, so the latest one stays.
+        assert_eq!(status(unused).await.unwrap(), "REVOKED");
+        assert_eq!(status(later[1]).await.unwrap(), "ACTIVE");
+
+        let history = get_history(&application, &client, later[0]).await;
+        assert_eq!(history[0].action, InvitationAction::REVOKED);
+    }
+}
@@ -0,0 +1,282 @@
+use crate::helpers::{spawn_app, TestApp};
+
+#[cfg(test)]
+mod job_tests {
+    use std::time::Duration;
+    use actix_web::http::StatusCode;
+    use reqwest::Client;
+    use serde_json::json;
+    use uuid::Uuid;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, ResponseTemplate};
+    use shared::domain::{Invitation, InvitationJob, JobStatus};
+    use super::*;
+
+    async fn add_appointment(application: &TestApp, client: &Client) -> Uuid {
+        client.post(format!("{}/api/appointment", &application.address))
+            .json(&json!({
+                "title": "Conference",
+                "description": "Yearly conference",
+                "format": "OFFLINE",
+                "address": "123 Fake St.",
+                "date": "2024-10-10T10:10:00",
+                "duration": 6000
+            }))
+            .send()
+            .await
+            .expect("Failed to add new appointment")
+            .json()
+            .await
+            .expect("Failed to parse appointment id")
+    }
+
+    async fn wait_for_job(application: &TestApp, client: &Client, job_id: Uuid) -> InvitationJob {
+        for _ in 0..300 {
+            let job: InvitationJob = client.get(format!("{}/api/jobs/{}", &application.address, job_id))
+                .send()
+                .await
+                .expect("Failed to get job")
+                .json()
+                .await
+                .expect("Failed to parse job");
+            if job.status.is_finished() {
+                return job;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("Job {} didn't finish in time", job_id);
+    }
+
+    #[actix_web::test]
+    async fn test_invitation_job_issues_and_emails_in_batches() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        application.mock_emails().await;
+        let client = Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap();
+        let appointment_id = add_appointment(&application, &client).await;
+
+        let attendees: Vec<_> = (0..150)
+            .map(|i| json!({"email": format!("attendee{}@example.com", i), "name": format!("Attendee {}", i)}))
+            .collect();
+        let response = client.post(format!("{}/api/appointment/{}/invitation/jobs", &application.address, appointment_id))
+            .json(&json!({"attendees": attendees}))
+            .send()
+            .await
+            .expect("Failed to add job");
+
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let job: InvitationJob = response.json().await.unwrap();
+        assert_eq!(job.total, 150);
+
+        let job = wait_for_job(&application, &client, job.id).await;
+        assert_eq!(job.status, JobStatus::COMPLETED);
+        assert_eq!(job.processed, 150);
+        assert!(job.errors.is_empty());
+        assert!(job.email_failures.is_empty());
+
+        let invitations: Vec<Invitation> = client.get(format!("{}/api/jobs/{}/invitations", &application.address, job.id))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(invitations.len(), 150);
+        assert!(invitations.iter().all(|invitation| invitation.attendee_id.is_some()));
+        // One batch request to Postmark per batch of the job.
+        assert_eq!(application.email_server.received_requests().await.unwrap().len(), 2);
+        assert_eq!(application.sent_emails().await.len(), 150);
+    }
+
+    #[actix_web::test]
+    async fn test_invitation_job_reports_rejected_emails() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        Mock::given(method("POST"))
+            .and(path("/email/batch"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+                { "ErrorCode": 0, "Message": "OK", "To": "attendee0@example.com" },
+                { "ErrorCode": 406, "Message": "Inactive recipient", "To": "attendee1@example.com" },
+                { "ErrorCode": 0, "Message": "OK", "To": "attendee2@example.com" }
+            ])))
+            .mount(&application.email_server)
+            .await;
+        let client = Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap();
+        let appointment_id = add_appointment(&application, &client).await;
+
+        let attendees: Vec<_> = (0..3)
+            .map(|i| json!({"email": format!("attendee{}@example.com", i)}))
+            .collect();
+        let job: InvitationJob = client.post(format!("{}/api/appointment/{}/invitation/jobs", &application.address, appointment_id))
+            .json(&json!({"attendees": attendees}))
+            .send()
+            .await
+            .expect("Failed to add job")
+            .json()
+            .await
+            .unwrap();
+
+        let job = wait_for_job(&application, &client, job.id).await;
+        assert_eq!(job.status, JobStatus::COMPLETED);
+        assert_eq!(job.processed, 3);
+        let invitations: Vec<Invitation> = client.get(format!("{}/api/jobs/{}/invitations", &application.address, job.id))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(job.email_failures.len(), 1);
+        assert_eq!(job.email_failures[0].invitation_id, invitations[1].id);
+        assert_eq!(job.email_failures[0].email.as_ref(), "attendee1@example.com");
+        assert!(job.email_failures[0].error.contains("Inactive recipient"));
+    }
+
+    #[actix_web::test]
+    async fn test_failed_invitation_job_is_resumed() {
+        let application = spawn_app().await;
+        let client = Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap();
+        let appointment_id = add_appointment(&application, &client).await;
+
+        Mock::given(method("POST"))
+            .and(path("/short_url/hash"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&application.shortener_server)
+            .await;
+
+        let job: InvitationJob = client.post(format!("{}/api/appointment/{}/invitation/jobs?count=250&max_admissions=2", &application.address, appointment_id))
+            .json(&json!({}))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        let job = wait_for_job(&application, &client, job.id).await;
+        assert_eq!(job.status, JobStatus::FAILED);
+        assert_eq!(job.processed, 0);
+        assert_eq!(job.errors.len(), 1);
+
+        application.shortener_server.reset().await;
+        application.mock_short_urls().await;
+
+        let response = client.post(format!("{}/api/jobs/{}/resume", &application.address, job.id))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        let job = wait_for_job(&application, &client, job.id).await;
+        assert_eq!(job.status, JobStatus::COMPLETED);
+        assert_eq!(job.processed, 250);
+        // The error stays in the report of the job.
+        assert_eq!(job.errors.len(), 1);
+
+        let invitations: Vec<Invitation> = client.get(format!("{}/api/jobs/{}/invitations", &application.address, job.id))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(invitations.len(), 250);
+        assert!(invitations.iter().all(|invitation| invitation.max_admissions == 2));
+
+        let response = client.post(format!("{}/api/jobs/{}/resume", &application.address, job.id))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+
+    #[actix_web::test]
+    async fn test_running_invitation_job_is_not_resumed_twice() {
+        let application = spawn_app().await;
+        application.mock_emails().await;
+        // A slow shortener keeps the job running while it's resumed.
+        Mock::given(method("POST"))
+            .and(path("/short_url/hash"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "hash": "testhash",
+                    "short_url": "https://short.url/testhash",
+                    "long_url": "https://www.example.com"
+                }))
+                .set_delay(Duration::from_millis(50)))
+            .mount(&application.shortener_server)
+            .await;
+        let client = Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap();
+        let appointment_id = add_appointment(&application, &client).await;
+
+        let attendees: Vec<_> = (0..300)
+            .map(|i| json!({"email": format!("attendee{}@example.com", i), "name": format!("Attendee {}", i)}))
+            .collect();
+        let job: InvitationJob = client.post(format!("{}/api/appointment/{}/invitation/jobs", &application.address, appointment_id))
+            .json(&json!({"attendees": attendees}))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        let response = client.post(format!("{}/api/jobs/{}/resume", &application.address, job.id))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let job = wait_for_job(&application, &client, job.id).await;
+        assert_eq!(job.status, JobStatus::COMPLETED);
+        assert_eq!(job.processed, 300);
+
+        let invitations: Vec<Invitation> = client.get(format!("{}/api/jobs/{}/invitations", &application.address, job.id))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(invitations.len(), 300);
+    }
+
+    #[actix_web::test]
+    async fn test_invitation_job_is_checked_before_starting() {
+        let application = spawn_app().await;
+        let client = Client::new();
+        let appointment_id = add_appointment(&application, &client).await;
+
+        let response = client.post(format!("{}/api/appointment/{}/invitation/jobs?count=0", &application.address, appointment_id))
+            .json(&json!({}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = client.post(format!("{}/api/appointment/{}/invitation/jobs?count=10", &application.address, Uuid::new_v4()))
+            .json(&json!({}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = client.get(format!("{}/api/jobs/{}", &application.address, Uuid::new_v4()))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -1,2 +1,8 @@
 mod helpers;
-mod appointment;
\ No newline at end of file
+mod appointment;
+mod attendee;
+mod invitation;
+mod waitlist;
+mod registration;
+mod scan;
+mod job;
@@ -0,0 +1,178 @@
+use crate::helpers::{spawn_app, TestApp};
+
+#[cfg(test)]
+mod registration_tests {
+    use actix_web::http::StatusCode;
+    use chrono::{Duration, Utc};
+    use reqwest::Client;
+    use serde_json::json;
+    use uuid::Uuid;
+    use shared::domain::{Invitation, InvitationStatus, NewInvitation, RsvpStatus, WaitlistEntry};
+    use super::*;
+
+    async fn add_appointment(application: &TestApp, client: &Client, registration_open: bool, capacity: i32) -> Uuid {
+        client.post(format!("{}/api/appointment", &application.address))
+            .json(&json!({
+                "title": "Rust <meetup>",
+                "description": "Some test desctiption",
+                "format": "OFFLINE",
+                "address": "123 Fake St.",
+                "link": null,
+                "date": (Utc::now() + Duration::days(7)).naive_utc(),
+                "duration": 6000,
+                "capacity": capacity,
+                "registration_open": registration_open
+            }))
+            .send()
+            .await
+            .expect("Failed to add new appointment")
+            .json()
+            .await
+            .expect("Failed to parse response")
+    }
+
+    async fn register(application: &TestApp, client: &Client, appointment_id: Uuid, name: &str, email: &str, website: &str) -> reqwest::Response {
+        client.post(format!("{}/api/appointment/{}/register", application.address, appointment_id))
+            .form(&[("name", name), ("email", email), ("website", website)])
+            .send()
+            .await
+            .expect("Failed to register")
+    }
+
+    // Extracts the confirmation link from the plain text of the last email sent.
+    async fn last_confirmation_link(application: &TestApp) -> String {
+        let requests = application.email_server.received_requests().await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&requests.last().unwrap().body).unwrap();
+        assert_eq!(body["Subject"], "Confirm your registration");
+        body["TextBody"].as_str().unwrap().rsplit(' ').next().unwrap().to_string()
+    }
+
+    #[actix_web::test]
+    async fn test_confirmed_registrations_get_invitation_then_waitlist() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        application.mock_emails().await;
+        let client = Client::new();
+        let appointment_id = add_appointment(&application, &client, true, 1).await;
+
+        let page = client.get(format!("{}/api/appointment/{}/register", application.address, appointment_id))
+            .send()
+            .await
+            .expect("Failed to get registration page");
+        assert_eq!(page.status(), StatusCode::OK);
+        assert!(page.text().await.unwrap().contains("Rust &lt;meetup&gt;"));
+
+        let response = register(&application, &client, appointment_id, "Ada", "ada@example.com", "").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let ada_link = last_confirmation_link(&application).await;
+        // Opening the link, as mail scanners do, only shows the confirmation form.
+        let page = client.get(&ada_link).send().await.expect("Failed to open confirmation link");
+        assert_eq!(page.status(), StatusCode::OK);
+        assert!(page.text().await.unwrap().contains(r#"<form method="post">"#));
+        // Nothing is issued until the email address is confirmed.
+        let invitations: Vec<Invitation> = client.get(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .send().await.unwrap().json().await.unwrap();
+        assert!(invitations.is_empty());
+
+        let response = client.post(&ada_link).send().await.expect("Failed to confirm registration");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.text().await.unwrap().contains("your invitation is on its way"));
+
+        register(&application, &client, appointment_id, "Grace", "grace@example.com", "").await;
+        let grace_link = last_confirmation_link(&application).await;
+        let response = client.post(&grace_link).send().await.expect("Failed to confirm registration");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.text().await.unwrap().contains("waitlist"));
+
+        let invitations: Vec<Invitation> = client.get(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .send().await.unwrap().json().await.unwrap();
+        assert_eq!(invitations.len(), 1);
+        let waitlist: Vec<WaitlistEntry> = client.get(format!("{}/api/appointment/{}/waitlist", application.address, appointment_id))
+            .send().await.unwrap().json().await.unwrap();
+        assert_eq!(waitlist.len(), 1);
+        assert_eq!(waitlist[0].email.as_ref(), "grace@example.com");
+
+        // Opening the link again doesn't issue another invitation.
+        let response = client.post(&ada_link).send().await.expect("Failed to confirm registration");
+        assert!(response.text().await.unwrap().contains("already confirmed"));
+        let invitations: Vec<Invitation> = client.get(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .send().await.unwrap().json().await.unwrap();
+        assert_eq!(invitations.len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn test_honeypot_and_rate_limit() {
+        let application = spawn_app().await;
+        application.mock_emails().await;
+        let client = Client::new();
+        let appointment_id = add_appointment(&application, &client, true, 10).await;
+
+        let response = register(&application, &client, appointment_id, "Bot", "bot@example.com", "http://spam.example").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(application.email_server.received_requests().await.unwrap().is_empty());
+
+        for i in 0..4 {
+            let response = register(&application, &client, appointment_id, "Ada", &format!("ada{}@example.com", i), "").await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        let response = register(&application, &client, appointment_id, "Ada", "ada9@example.com", "").await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[actix_web::test]
+    async fn test_closed_registration_returns_404() {
+        let application = spawn_app().await;
+        let client = Client::new();
+        let appointment_id = add_appointment(&application, &client, false, 10).await;
+
+        let page = client.get(format!("{}/api/appointment/{}/register", application.address, appointment_id))
+            .send()
+            .await
+            .expect("Failed to get registration page");
+        assert_eq!(page.status(), StatusCode::NOT_FOUND);
+
+        let response = register(&application, &client, appointment_id, "Ada", "ada@example.com", "").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_rsvp_links_update_status() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        application.mock_emails().await;
+        let client = Client::new();
+        let appointment_id = add_appointment(&application, &client, false, 10).await;
+
+        let invitations: Vec<NewInvitation> = client.post(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .json(&json!({ "email": ["ada@example.com"] }))
+            .send().await.unwrap().json().await.unwrap();
+        let invitation_id = invitations[0].id;
+
+        let requests = application.email_server.received_requests().await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        let accept_url = format!("{}/api/invitation/{}/rsvp?response=accept", application.address, invitation_id);
+        assert!(body["HtmlBody"].as_str().unwrap().contains(&accept_url));
+
+        let page = client.get(&accept_url).send().await.expect("Failed to get RSVP page");
+        assert_eq!(page.status(), StatusCode::OK);
+        let page = page.text().await.unwrap();
+        assert!(page.contains("I will attend") && !page.contains("I can't attend"));
+
+        let rsvp_url = format!("{}/api/invitation/{}/rsvp", application.address, invitation_id);
+        let response = client.post(&rsvp_url).form(&[("response", "accept")]).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let invitation: Invitation = client.get(format!("{}/api/invitation/{}", application.address, invitation_id))
+            .send().await.unwrap().json().await.unwrap();
+        assert_eq!(invitation.rsvp, RsvpStatus::ACCEPTED);
+
+        let response = client.post(&rsvp_url).form(&[("response", "decline")]).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let invitation: Invitation = client.get(format!("{}/api/invitation/{}", application.address, invitation_id))
+            .send().await.unwrap().json().await.unwrap();
+        assert_eq!(invitation.rsvp, RsvpStatus::DECLINED);
+        assert_eq!(invitation.status, InvitationStatus::DECLINED);
+
+        let response = client.post(&rsvp_url).form(&[("response", "accept")]).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+}
@@ -0,0 +1,452 @@
+use crate::helpers::spawn_app;
+
+#[cfg(test)]
+mod scan_tests {
+    use actix_web::http::StatusCode;
+    use chrono::{Duration, Utc};
+    use reqwest::Client;
+    use serde_json::json;
+    use uuid::Uuid;
+    use shared::domain::{AppointmentWithInvitation, NewInvitation, Occupancy, ScanDirection, ScanEvent, ScanResult, OnlineAttendance, SyncStatus, SyncedScan, ValidationPreview};
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_scans_track_occupancy_and_history() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        let client = Client::new();
+
+        let appointment_id: Uuid = client.post(format!("{}/api/appointment", &application.address))
+            .json(&json!({
+                "title": "Two day festival",
+                "description": "Some test desctiption",
+                "format": "OFFLINE",
+                "address": "123 Fake St.",
+                "link": null,
+                "date": (Utc::now() + Duration::days(7)).naive_utc(),
+                "duration": 6000
+            }))
+            .send()
+            .await
+            .expect("Failed to add new appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+
+        let invitations: Vec<NewInvitation> = client.post(format!("{}/api/appointment/{}/invitation?max_admissions=2", application.address, appointment_id))
+            .json(&json!({}))
+            .send()
+            .await
+            .expect("Failed to add invitation to appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        let invitation_id = invitations[0].id;
+
+        // Scans are recorded under the name of the token, whatever the body claims.
+        let scan = |direction: &'static str| {
+            application.check_in(invitation_id, json!({ "scanner": "south-gate", "direction": direction }))
+        };
+
+        let response = scan("OUT").await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(scan("IN").await.status(), StatusCode::OK);
+        assert_eq!(scan("OUT").await.status(), StatusCode::OK);
+
+        // Coming back doesn't use up the second admission.
+        let scanned: AppointmentWithInvitation = scan("IN").await.json().await.expect("Failed to parse response");
+        assert_eq!(scanned.admission, "1 of 2 admitted");
+        let scanned: AppointmentWithInvitation = scan("IN").await.json().await.expect("Failed to parse response");
+        assert_eq!(scanned.admission, "2 of 2 admitted");
+        assert_eq!(scan("IN").await.status(), StatusCode::FORBIDDEN);
+
+        let occupancy: Occupancy = client.get(format!("{}/api/appointment/{}/occupancy", application.address, appointment_id))
+            .send()
+            .await
+            .expect("Failed to fetch occupancy")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        assert_eq!((occupancy.inside, occupancy.checked_in, occupancy.checked_out), (2, 3, 1));
+
+        let scans: Vec<ScanEvent> = client.get(format!("{}/api/invitation/{}/scans", application.address, invitation_id))
+            .send()
+            .await
+            .expect("Failed to fetch scans")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        assert_eq!(scans.len(), 6);
+        assert!(scans.iter().all(|scan| scan.scanner.as_deref() == Some("north-gate")));
+        assert_eq!(scans[0].result, ScanResult::REJECTED);
+        assert_eq!(scans[0].direction, ScanDirection::OUT);
+        assert_eq!(scans[5].result, ScanResult::REJECTED);
+        assert!(scans[5].reason.as_deref().unwrap().contains("already been used"));
+    }
+
+    #[actix_web::test]
+    async fn test_concurrent_re_entries_admit_one_person() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        let client = Client::new();
+
+        let appointment_id: Uuid = client.post(format!("{}/api/appointment", &application.address))
+            .json(&json!({
+                "title": "Gallery opening",
+                "description": "Some test desctiption",
+                "format": "OFFLINE",
+                "address": "123 Fake St.",
+                "link": null,
+                "date": (Utc::now() + Duration::days(7)).naive_utc(),
+                "duration": 6000
+            }))
+            .send()
+            .await
+            .expect("Failed to add new appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        let invitations: Vec<NewInvitation> = client.post(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .json(&json!({}))
+            .send()
+            .await
+            .expect("Failed to add invitation to appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        let invitation_id = invitations[0].id;
+
+        assert_eq!(application.check_in(invitation_id, json!({})).await.status(), StatusCode::OK);
+        assert_eq!(application.check_in(invitation_id, json!({ "direction": "OUT" })).await.status(), StatusCode::OK);
+
+        // Both doors see one person outside, only one of them may let them back in.
+        let responses = futures::future::join_all((0..4).map(|_| application.check_in(invitation_id, json!({})))).await;
+        let admitted = responses.iter().filter(|response| response.status() == StatusCode::OK).count();
+        assert_eq!(admitted, 1);
+
+        let occupancy: Occupancy = client.get(format!("{}/api/appointment/{}/occupancy", application.address, appointment_id))
+            .send()
+            .await
+            .expect("Failed to fetch occupancy")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        assert_eq!(occupancy.inside, 1);
+    }
+
+    #[actix_web::test]
+    async fn test_scan_of_unknown_code_is_recorded() {
+        let application = spawn_app().await;
+        let client = Client::new();
+        let invitation_id = Uuid::new_v4();
+
+        let response = application.check_in(invitation_id, json!({})).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let scans: Vec<ScanEvent> = client.get(format!("{}/api/invitation/{}/scans", application.address, invitation_id))
+            .send()
+            .await
+            .expect("Failed to fetch scans")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        assert_eq!(scans.len(), 1);
+        assert_eq!(scans[0].appointment_id, None);
+        assert_eq!(scans[0].result, ScanResult::REJECTED);
+    }
+
+    #[actix_web::test]
+    async fn test_offline_scans_sync_with_conflicts_and_retries() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        let client = Client::new();
+
+        let appointment_id: Uuid = client.post(format!("{}/api/appointment", &application.address))
+            .json(&json!({
+                "title": "Festival with a flaky network",
+                "description": "Some test desctiption",
+                "format": "OFFLINE",
+                "address": "123 Fake St.",
+                "link": null,
+                "date": (Utc::now() + Duration::days(7)).naive_utc(),
+                "duration": 6000
+            }))
+            .send()
+            .await
+            .expect("Failed to add new appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+
+        let invitations: Vec<NewInvitation> = client.post(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .json(&json!({}))
+            .send()
+            .await
+            .expect("Failed to add invitation to appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        let invitation_id = invitations[0].id;
+        let now = Utc::now().naive_utc();
+
+        let sync = |scans: serde_json::Value| {
+            let client = &client;
+            let token = &application.scanner_token;
+            let url = format!("{}/api/appointment/{}/scans", application.address, appointment_id);
+            async move {
+                client.post(url)
+                    .bearer_auth(token)
+                    .json(&scans)
+                    .send()
+                    .await
+                    .expect("Failed to sync scans")
+                    .json::<Vec<SyncedScan>>()
+                    .await
+                    .expect("Failed to parse response")
+            }
+        };
+
+        // Both doors admitted the same single-use code while offline; the earlier scan wins.
+        let south = json!({"id": Uuid::new_v4(), "invitation_id": invitation_id, "scanned_at": now - Duration::minutes(5)});
+        let north = json!({"id": Uuid::new_v4(), "invitation_id": invitation_id, "scanned_at": now - Duration::minutes(10)});
+        let unknown = json!({"id": Uuid::new_v4(), "invitation_id": Uuid::new_v4(), "scanned_at": now});
+
+        let synced = sync(json!([south, north, unknown])).await;
+        let status = |id: &serde_json::Value| synced.iter().find(|scan| json!(scan.id) == *id).unwrap().status;
+        assert_eq!(status(&north["id"]), SyncStatus::ACCEPTED);
+        assert_eq!(status(&south["id"]), SyncStatus::CONFLICT);
+        assert_eq!(status(&unknown["id"]), SyncStatus::REJECTED);
+
+        // A scanner retrying after a dropped response changes nothing.
+        let synced = sync(json!([north])).await;
+        assert_eq!(synced[0].status, SyncStatus::DUPLICATE);
+
+        let scans: Vec<ScanEvent> = client.get(format!("{}/api/invitation/{}/scans", application.address, invitation_id))
+            .send()
+            .await
+            .expect("Failed to fetch scans")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        assert_eq!(scans.len(), 2);
+        assert_eq!(scans[0].scanner.as_deref(), Some("north-gate"));
+        assert_eq!(scans[0].result, ScanResult::ADMITTED);
+        assert_eq!(scans[1].result, ScanResult::REJECTED);
+
+        let occupancy: Occupancy = client.get(format!("{}/api/appointment/{}/occupancy", application.address, appointment_id))
+            .send()
+            .await
+            .expect("Failed to fetch occupancy")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        assert_eq!(occupancy.checked_in, 1);
+
+        // A push retried while the first one is still in flight admits once too.
+        let group: Vec<NewInvitation> = client.post(format!("{}/api/appointment/{}/invitation?max_admissions=3", application.address, appointment_id))
+            .json(&json!({}))
+            .send()
+            .await
+            .expect("Failed to add invitation to appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        let retried = json!({"id": Uuid::new_v4(), "invitation_id": group[0].id, "scanned_at": now});
+        let pushes = futures::future::join_all((0..3).map(|_| sync(json!([retried])))).await;
+        let mut statuses: Vec<SyncStatus> = pushes.iter().map(|synced| synced[0].status).collect();
+        statuses.sort_by_key(|status| *status == SyncStatus::DUPLICATE);
+        assert_eq!(statuses, vec![SyncStatus::ACCEPTED, SyncStatus::DUPLICATE, SyncStatus::DUPLICATE]);
+    }
+
+    #[actix_web::test]
+    async fn test_validation_link_previews_without_checking_in() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        let client = Client::new();
+
+        let appointment_id: Uuid = client.post(format!("{}/api/appointment", &application.address))
+            .json(&json!({
+                "title": "Gallery opening",
+                "description": "Some test desctiption",
+                "format": "OFFLINE",
+                "address": "123 Fake St.",
+                "link": null,
+                "date": (Utc::now() + Duration::days(7)).naive_utc(),
+                "duration": 6000
+            }))
+            .send()
+            .await
+            .expect("Failed to add new appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+
+        let invitations: Vec<NewInvitation> = client.post(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .json(&json!({}))
+            .send()
+            .await
+            .expect("Failed to add invitation to appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        let invitation_id = invitations[0].id;
+        let url = format!("{}/api/validations/{}", application.address, invitation_id);
+
+        // Link preview bots and mail scanners may open the link any number of times.
+        for _ in 0..3 {
+            let response = client.get(&url).send().await.expect("Failed to preview invitation");
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()["cache-control"], "no-store");
+            let preview: ValidationPreview = response.json().await.expect("Failed to parse response");
+            assert!(preview.valid);
+            assert_eq!(preview.title, "Gallery opening");
+            assert_eq!(preview.admission, "0 of 1 admitted");
+        }
+        let page = client.get(&url)
+            .header("Accept", "text/html,application/xhtml+xml")
+            .send()
+            .await
+            .expect("Failed to preview invitation")
+            .text()
+            .await
+            .unwrap();
+        assert!(page.contains("Gallery opening"));
+        assert!(page.contains("Valid"));
+
+        let response = client.post(format!("{}/check-in", url)).send().await.expect("Failed to check in");
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = client.post(format!("{}/check-in", url))
+            .bearer_auth("not-a-scanner")
+            .send()
+            .await
+            .expect("Failed to check in");
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        assert_eq!(application.check_in(invitation_id, json!({})).await.status(), StatusCode::OK);
+
+        let preview: ValidationPreview = client.get(&url).send().await.unwrap().json().await.unwrap();
+        assert!(!preview.valid);
+        assert_eq!(preview.reason.as_deref(), Some("invitation has already been used"));
+
+        let scans: Vec<ScanEvent> = client.get(format!("{}/api/invitation/{}/scans", application.address, invitation_id))
+            .send()
+            .await
+            .expect("Failed to fetch scans")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        assert_eq!(scans.len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn test_online_join_is_time_gated_and_recorded() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        let client = Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap();
+
+        let add_online_appointment = |date: chrono::NaiveDateTime| {
+            let client = &client;
+            let address = &application.address;
+            async move {
+                let appointment_id: Uuid = client.post(format!("{}/api/appointment", address))
+                    .json(&json!({
+                        "title": "Webinar",
+                        "description": "Some test desctiption",
+                        "format": "ONLINE",
+                        "address": null,
+                        "link": "https://meet.example.com/secret-room",
+                        "date": date,
+                        "duration": 3600
+                    }))
+                    .send()
+                    .await
+                    .expect("Failed to add new appointment")
+                    .json()
+                    .await
+                    .expect("Failed to parse response");
+                let invitations: Vec<NewInvitation> = client.post(format!("{}/api/appointment/{}/invitation", address, appointment_id))
+                    .json(&json!({}))
+                    .send()
+                    .await
+                    .expect("Failed to add invitation to appointment")
+                    .json()
+                    .await
+                    .expect("Failed to parse response");
+                (appointment_id, invitations[0].id)
+            }
+        };
+
+        let (appointment_id, invitation_id) = add_online_appointment((Utc::now() - Duration::minutes(5)).naive_utc()).await;
+        let url = format!("{}/api/validations/{}", application.address, invitation_id);
+
+        // Opening the link, as link previews do, only shows the way in.
+        let preview: ValidationPreview = client.get(&url).send().await.unwrap().json().await.unwrap();
+        assert!(preview.valid);
+        let page = client.get(&url)
+            .header("Accept", "text/html")
+            .send()
+            .await
+            .expect("Failed to preview invitation")
+            .text()
+            .await
+            .unwrap();
+        assert!(page.contains("<form method=\"post\">"));
+        assert!(!page.contains("secret-room"));
+
+        for _ in 0..2 {
+            let response = client.post(&url)
+                .header("User-Agent", "test-browser")
+                .send()
+                .await
+                .expect("Failed to join meeting");
+            assert_eq!(response.status(), StatusCode::SEE_OTHER);
+            assert_eq!(response.headers()["cache-control"], "no-store");
+            assert_eq!(response.headers()["location"], "https://meet.example.com/secret-room");
+        }
+
+        let attendance: Vec<OnlineAttendance> = client.get(format!("{}/api/appointment/{}/attendance", application.address, appointment_id))
+            .send()
+            .await
+            .expect("Failed to fetch attendance")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        assert_eq!(attendance.len(), 1);
+        assert_eq!(attendance[0].invitation_id, invitation_id);
+        assert_eq!(attendance[0].joins, 2);
+
+        let (appointment_id, invitation_id) = add_online_appointment((Utc::now() + Duration::days(1)).naive_utc()).await;
+        let url = format!("{}/api/validations/{}", application.address, invitation_id);
+
+        let response = client.get(&url).send().await.expect("Failed to preview invitation");
+        assert_eq!(response.status(), StatusCode::OK);
+        let preview: ValidationPreview = response.json().await.expect("Failed to parse response");
+        assert!(!preview.valid);
+        assert!(preview.reason.unwrap().starts_with("the meeting opens at"));
+
+        let page = client.get(&url)
+            .header("Accept", "text/html")
+            .send()
+            .await
+            .expect("Failed to preview invitation")
+            .text()
+            .await
+            .unwrap();
+        assert!(page.contains("id=\"countdown\""));
+        assert!(!page.contains("secret-room"));
+        let response = client.post(&url).send().await.expect("Failed to join meeting");
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let attendance: Vec<OnlineAttendance> = client.get(format!("{}/api/appointment/{}/attendance", application.address, appointment_id))
+            .send()
+            .await
+            .expect("Failed to fetch attendance")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        assert!(attendance.is_empty());
+    }
+}
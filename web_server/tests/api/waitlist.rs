@@ -0,0 +1,230 @@
+use crate::helpers::{spawn_app, TestApp};
+
+#[cfg(test)]
+mod waitlist_tests {
+    use actix_web::http::StatusCode;
+    use chrono::{Duration, Utc};
+    use reqwest::Client;
+    use serde_json::json;
+    use uuid::Uuid;
+    use shared::domain::{Invitation, InvitationStatus, NewInvitation, ValidationPreview, WaitlistEntry};
+    use super::*;
+
+    async fn add_full_appointment(application: &TestApp, client: &Client) -> (Uuid, NewInvitation) {
+        let appointment_id: Uuid = client.post(format!("{}/api/appointment", &application.address))
+            .json(&json!({
+                "title": "Test appoinment",
+                "description": "Some test desctiption",
+                "format": "OFFLINE",
+                "address": "123 Fake St.",
+                "link": null,
+                "date": (Utc::now() + Duration::days(7)).naive_utc(),
+                "duration": 6000,
+                "capacity": 1
+            }))
+            .send()
+            .await
+            .expect("Failed to add new appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+
+        let invitations: Vec<NewInvitation> = client.post(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .json(&json!({ "email": ["first@example.com"] }))
+            .send()
+            .await
+            .expect("Failed to add invitation to appointment")
+            .json()
+            .await
+            .expect("Failed to parse response");
+
+        (appointment_id, invitations.into_iter().next().unwrap())
+    }
+
+    async fn join_waitlist(application: &TestApp, client: &Client, appointment_id: Uuid, email: &str) -> WaitlistEntry {
+        let response = client.post(format!("{}/api/appointment/{}/waitlist", application.address, appointment_id))
+            .json(&json!({ "email": email }))
+            .send()
+            .await
+            .expect("Failed to join waitlist");
+        assert_eq!(response.status(), StatusCode::OK);
+        response.json().await.expect("Failed to parse response")
+    }
+
+    #[actix_web::test]
+    async fn test_declined_seat_goes_to_reordered_waitlist() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        application.mock_emails().await;
+        let client = Client::new();
+
+        let (appointment_id, invitation) = add_full_appointment(&application, &client).await;
+        let ada = join_waitlist(&application, &client, appointment_id, "ada@example.com").await;
+        let grace = join_waitlist(&application, &client, appointment_id, "grace@example.com").await;
+        assert_eq!((ada.position, grace.position), (1, 2));
+        assert!(ada.invitation_id.is_none());
+
+        let response = client.put(format!("{}/api/appointment/{}/waitlist/{}", application.address, appointment_id, grace.id))
+            .json(&json!({ "position": 1 }))
+            .send()
+            .await
+            .expect("Failed to reorder waitlist");
+        assert_eq!(response.status(), StatusCode::OK);
+        let waitlist: Vec<WaitlistEntry> = response.json().await.expect("Failed to parse response");
+        assert_eq!(waitlist.iter().map(|entry| entry.id).collect::<Vec<_>>(), vec![grace.id, ada.id]);
+
+        let response = client.post(format!("{}/api/invitation/{}/decline", application.address, invitation.id))
+            .send()
+            .await
+            .expect("Failed to decline invitation");
+        assert_eq!(response.status(), StatusCode::OK);
+        let declined: Invitation = response.json().await.expect("Failed to parse response");
+        assert_eq!(declined.status, InvitationStatus::DECLINED);
+
+        let waitlist: Vec<WaitlistEntry> = client.get(format!("{}/api/appointment/{}/waitlist", application.address, appointment_id))
+            .send()
+            .await
+            .expect("Failed to fetch waitlist")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        assert_eq!(waitlist.len(), 1);
+        assert_eq!(waitlist[0].id, ada.id);
+
+        let invitations: Vec<Invitation> = client.get(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .send()
+            .await
+            .expect("Failed to fetch invitations")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        let promoted = invitations.iter().find(|invitation| invitation.status == InvitationStatus::ACTIVE).unwrap();
+        assert!(promoted.claim_deadline.is_some());
+
+        let requests = application.email_server.received_requests().await.unwrap();
+        let promotion: serde_json::Value = serde_json::from_slice(&requests.last().unwrap().body).unwrap();
+        assert_eq!(promotion["To"], "grace@example.com");
+        assert_eq!(promotion["Subject"], "A seat is available");
+
+        let response = client.post(format!("{}/api/invitation/{}/decline", application.address, invitation.id))
+            .send()
+            .await
+            .expect("Failed to decline invitation");
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+
+    #[actix_web::test]
+    async fn test_unclaimed_invitation_is_passed_on() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        application.mock_emails().await;
+        let client = Client::new();
+
+        let (appointment_id, invitation) = add_full_appointment(&application, &client).await;
+        join_waitlist(&application, &client, appointment_id, "ada@example.com").await;
+        join_waitlist(&application, &client, appointment_id, "grace@example.com").await;
+
+        client.post(format!("{}/api/invitation/{}/decline", application.address, invitation.id))
+            .send()
+            .await
+            .expect("Failed to decline invitation");
+
+        let invitations: Vec<Invitation> = client.get(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .send()
+            .await
+            .expect("Failed to fetch invitations")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        let promoted = invitations.iter().find(|invitation| invitation.status == InvitationStatus::ACTIVE).unwrap();
+
+        sqlx::query("UPDATE invitation SET claim_deadline = claim_deadline - INTERVAL '3 days' WHERE id = $1")
+            .bind(promoted.id)
+            .execute(&application.db_pool)
+            .await
+            .unwrap();
+
+        let response = client.post(format!("{}/api/invitation/{}/claim", application.address, promoted.id))
+            .send()
+            .await
+            .expect("Failed to claim invitation");
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        // Until the next promotion declines it, the lapsed invitation doesn't let anyone in.
+        let preview: ValidationPreview = client.get(format!("{}/api/validations/{}", application.address, promoted.id))
+            .send()
+            .await
+            .expect("Failed to preview invitation")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        assert!(!preview.valid);
+        assert_eq!(preview.reason.as_deref(), Some("invitation was not claimed before its deadline"));
+        assert_eq!(application.check_in(promoted.id, json!({})).await.status(), StatusCode::FORBIDDEN);
+
+        let response = client.post(format!("{}/api/appointment/{}/waitlist/promote", application.address, appointment_id))
+            .send()
+            .await
+            .expect("Failed to promote waitlist");
+        assert_eq!(response.status(), StatusCode::OK);
+        let issued: Vec<NewInvitation> = response.json().await.expect("Failed to parse response");
+        assert_eq!(issued.len(), 1);
+
+        let response = client.post(format!("{}/api/invitation/{}/claim", application.address, issued[0].id))
+            .send()
+            .await
+            .expect("Failed to claim invitation");
+        assert_eq!(response.status(), StatusCode::OK);
+        let claimed: Invitation = response.json().await.expect("Failed to parse response");
+        assert!(claimed.claim_deadline.is_none());
+
+        let waitlist: Vec<WaitlistEntry> = client.get(format!("{}/api/appointment/{}/waitlist", application.address, appointment_id))
+            .send()
+            .await
+            .expect("Failed to fetch waitlist")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        assert!(waitlist.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_waiting_attendee_keeps_their_active_invitation() {
+        let application = spawn_app().await;
+        application.mock_short_urls().await;
+        application.mock_emails().await;
+        let client = Client::new();
+
+        let (appointment_id, invitation) = add_full_appointment(&application, &client).await;
+        let entry = join_waitlist(&application, &client, appointment_id, "FIRST@example.com").await;
+        sqlx::query("UPDATE appointment SET capacity = 2 WHERE id = $1")
+            .bind(appointment_id)
+            .execute(&application.db_pool)
+            .await
+            .unwrap();
+
+        let response = client.post(format!("{}/api/appointment/{}/waitlist/promote", application.address, appointment_id))
+            .send()
+            .await
+            .expect("Failed to promote waitlist");
+        assert_eq!(response.status(), StatusCode::OK);
+        let issued: Vec<NewInvitation> = response.json().await.expect("Failed to parse response");
+        assert!(issued.is_empty());
+
+        let invitations: Vec<Invitation> = client.get(format!("{}/api/appointment/{}/invitation", application.address, appointment_id))
+            .send()
+            .await
+            .expect("Failed to fetch invitations")
+            .json()
+            .await
+            .expect("Failed to parse response");
+        assert_eq!(invitations.iter().map(|invitation| invitation.id).collect::<Vec<_>>(), vec![invitation.id]);
+
+        let promoted_to: Option<Uuid> = sqlx::query_scalar("SELECT invitation_id FROM waitlist WHERE id = $1")
+            .bind(entry.id)
+            .fetch_one(&application.db_pool)
+            .await
+            .unwrap();
+        assert_eq!(promoted_to, Some(invitation.id));
+    }
+}